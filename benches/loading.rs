@@ -0,0 +1,99 @@
+//! Benchmarks of reading command definitions. The crate has no library target,
+//! so the parser modules are included directly.
+// Tests of the included modules are not compiled without the test harness
+#![allow(dead_code, unused_imports)]
+
+use std::fmt::Write;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+
+#[path = "../src/cmd.rs"]
+mod cmd;
+#[path = "../src/dialect.rs"]
+mod dialect;
+#[path = "../src/error.rs"]
+mod error;
+#[path = "../src/parser.rs"]
+mod parser;
+#[path = "../src/paths.rs"]
+mod paths;
+#[path = "../src/script.rs"]
+mod script;
+
+/// Number of generated commands
+const COMMANDS: usize = 1000;
+
+/// Commands file with a mix of value groups, flags and computed groups
+fn generate_commands() -> String {
+    let mut data = String::new();
+    for i in 0..COMMANDS {
+        writeln!(
+            data,
+            r#"
+[[commands]]
+template = "tool{i} sub\\_command [_OPTIONS_] --name=_NAME_ _PATH_ [_OUT_]"
+description = "Run *tool{i}* with a name"
+keywords = ["tool", "bench{i}"]
+groups.NAME.expect = "string"
+groups.NAME.transform = ["trim", "lower"]
+groups.PATH.expect = "path"
+groups.PATH.suggest = ["src/", "tests/"]
+groups.OUT.expect = "path"
+groups.OUT.computed = "{{PATH}}.out"
+groups.OPTIONS.flags = [
+  {{ template = "-v", description = "Verbose output" }},
+  {{ template = "--depth _N_", description = "Max depth", expect = "number" }},
+  {{ template = "--filter=_KEY_:_VALUE_", description = "Filter", expect = {{ KEY = "string", VALUE = "string" }} }},
+  {{ template = "--force", description = "Overwrite", danger = true }},
+]"#,
+            i = i
+        )
+        .unwrap();
+    }
+    data
+}
+
+fn loading(c: &mut Criterion) {
+    let data = generate_commands();
+    c.bench_function("parse_str 1000 commands", |b| {
+        b.iter(|| parser::parse_str(black_box(&data)).unwrap())
+    });
+
+    // Definitions without reading toml
+    c.bench_function("parse_defs 1000 commands", |b| {
+        b.iter_batched(
+            || toml::de::from_str(&data).unwrap(),
+            |defs| parser::parse_defs(defs).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+
+    let dir = std::env::temp_dir().join(format!("snova-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("commands.toml"), &data).unwrap();
+    let paths = paths::Paths {
+        config_dir: Some(dir.clone()),
+        data_dir: None,
+        script_dirs: vec![],
+    };
+    c.bench_function("read_all 1000 commands", |b| {
+        b.iter(|| parser::read_all(black_box(&paths), None, false).unwrap())
+    });
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let commands = parser::parse_str(&data).unwrap();
+    let mut values = std::collections::HashMap::new();
+    values.insert("NAME".to_string(), "bench".to_string());
+    values.insert("PATH".to_string(), "my dir/file.txt".to_string());
+    values.insert("OPTIONS".to_string(), "-v --depth 3".to_string());
+    c.bench_function("build 1000 commands", |b| {
+        b.iter(|| {
+            for cmd in &commands {
+                black_box((cmd.build)(black_box(&values)));
+            }
+        })
+    });
+}
+
+criterion_group!(benches, loading);
+criterion_main!(benches);
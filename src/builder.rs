@@ -0,0 +1,193 @@
+//! Builds commands in code with the same validation as definition files:
+//!
+//! ```
+//! let grep = CommandBuilder::new("grep [_OPTIONS_] _PATTERN_ _PATH_")
+//!     .description("Find lines in a file")
+//!     .group("PATTERN", ValueType::String)
+//!     .group("PATH", ValueType::Path)
+//!     .flags_group(
+//!         "OPTIONS",
+//!         vec![
+//!             FlagBuilder::new("-i", "Case insensitive matching"),
+//!             FlagBuilder::new("*-A* _NUM_", "Print lines after the match")
+//!                 .expect(ValueType::Number),
+//!         ],
+//!     )
+//!     .build()?;
+//! ```
+//!
+//! The builder assembles a definition table that is read like a command of a
+//! definition file, so errors have the same messages.
+
+use toml::value::{Table, Value};
+
+use crate::cmd::{Command, ValueType};
+use crate::error::Result;
+use crate::parser;
+
+/// Command definition assembled in code
+#[derive(Debug, Clone)]
+pub struct CommandBuilder {
+    def: Table,
+    groups: Table,
+}
+
+impl CommandBuilder {
+    pub fn new(template: &str) -> Self {
+        let mut def = Table::new();
+        def.insert("template".into(), Value::String(template.into()));
+        Self {
+            def,
+            groups: Table::new(),
+        }
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.def
+            .insert("description".into(), Value::String(description.into()));
+        self
+    }
+
+    /// Group that expects a single value
+    pub fn group(mut self, name: &str, value_type: ValueType) -> Self {
+        let mut group = Table::new();
+        group.insert("expect".into(), Value::String(value_type.name().into()));
+        self.groups.insert(name.into(), Value::Table(group));
+        self
+    }
+
+    /// Group of flags to pick from
+    pub fn flags_group(mut self, name: &str, flags: impl IntoIterator<Item = FlagBuilder>) -> Self {
+        let flags = flags
+            .into_iter()
+            .map(|flag| Value::Table(flag.def))
+            .collect();
+        let mut group = Table::new();
+        group.insert("flags".into(), Value::Array(flags));
+        self.groups.insert(name.into(), Value::Table(group));
+        self
+    }
+
+    /// Verify the definition and build the command
+    pub fn build(mut self) -> Result<Command> {
+        self.def.insert("groups".into(), Value::Table(self.groups));
+        parser::table_command(self.def, false)
+    }
+}
+
+/// Flag definition of a flags group
+#[derive(Debug, Clone)]
+pub struct FlagBuilder {
+    def: Table,
+}
+
+impl FlagBuilder {
+    pub fn new(template: &str, description: &str) -> Self {
+        let mut def = Table::new();
+        def.insert("template".into(), Value::String(template.into()));
+        def.insert("description".into(), Value::String(description.into()));
+        Self { def }
+    }
+
+    /// Type of the flag value
+    pub fn expect(mut self, value_type: ValueType) -> Self {
+        self.def
+            .insert("expect".into(), Value::String(value_type.name().into()));
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::cmd::GroupValue;
+
+    fn grep() -> CommandBuilder {
+        CommandBuilder::new("grep [_OPTIONS_] _PATTERN_ _PATH_")
+            .description("Find lines in a file (*grep*)")
+            .group("PATTERN", ValueType::String)
+            .group("PATH", ValueType::Path)
+            .flags_group(
+                "OPTIONS",
+                vec![
+                    FlagBuilder::new("-i", "Case insensitive matching"),
+                    FlagBuilder::new("-v", "Invert match (return non-matching lines)"),
+                    FlagBuilder::new("*-A* _NUM_", "Print _NUM_ lines after the matched line")
+                        .expect(ValueType::Number),
+                    FlagBuilder::new("*-B* _NUM_", "Print _NUM_ lines before the matched line")
+                        .expect(ValueType::Number),
+                    FlagBuilder::new("-r", "Search files recursively"),
+                ],
+            )
+    }
+
+    fn group_types(cmd: &Command) -> Vec<(&str, String)> {
+        cmd.groups
+            .iter()
+            .map(|group| {
+                let expect = match &group.expect {
+                    GroupValue::Single(value_type) => value_type.name().to_string(),
+                    GroupValue::Flags(flags) => flags
+                        .iter()
+                        .map(|flag| flag.template.as_str())
+                        .collect::<Vec<_>>()
+                        .join(","),
+                };
+                (group.name.as_str(), expect)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_as_builtin() {
+        let builtin = parser::builtin().unwrap();
+        let parsed = builtin
+            .iter()
+            .find(|cmd| cmd.template == "grep [_OPTIONS_] _PATTERN_ _PATH_")
+            .unwrap();
+        let built = grep().build().unwrap();
+
+        assert_eq!(parsed.description, built.description);
+        assert_eq!(group_types(parsed), group_types(&built));
+        let values: HashMap<String, String> = vec![
+            ("OPTIONS", "-i -A 3"),
+            ("PATTERN", "TODO"),
+            ("PATH", "src/main.rs"),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+        assert_eq!((parsed.build)(&values), (built.build)(&values));
+        assert_eq!("grep -i -A 3 TODO src/main.rs", (built.build)(&values));
+    }
+
+    #[test]
+    fn errors_of_definition_files() {
+        let toml_err = |data: &str| parser::parse_str(data).err().unwrap().to_string();
+        let build_err = |builder: CommandBuilder| builder.build().err().unwrap().to_string();
+
+        assert_eq!(
+            toml_err(
+                r#"
+[[commands]]
+template = "grep _PATTERN_ _PATH_"
+description = "Find lines"
+groups.PATTERN.expect = "string"
+"#
+            ),
+            build_err(
+                CommandBuilder::new("grep _PATTERN_ _PATH_")
+                    .description("Find lines")
+                    .group("PATTERN", ValueType::String)
+            )
+        );
+        // Fields that are not set are reported in the command
+        assert_eq!(
+            "In 'grep _PATTERN_': missing field `description`",
+            build_err(CommandBuilder::new("grep _PATTERN_").group("PATTERN", ValueType::String))
+        );
+        assert!(grep().build().is_ok());
+    }
+}
@@ -0,0 +1,196 @@
+//! Reads and writes the system clipboard with one of the common tools or
+//! the terminal (OSC 52)
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, Context, Result};
+use std::time::Duration;
+
+use crate::cmd::ValueType;
+use crate::subprocess::Runner;
+
+/// Clipboard tools and their arguments to print the contents
+const PASTE_TOOLS: &[(&str, &[&str])] = &[
+    ("pbpaste", &[]),
+    ("wl-paste", &["--no-newline"]),
+    ("xclip", &["-selection", "clipboard", "-o"]),
+    ("xsel", &["--clipboard", "--output"]),
+];
+/// Clipboard tools and their arguments to replace the contents from stdin
+const COPY_TOOLS: &[(&str, &[&str])] = &[
+    ("pbcopy", &[]),
+    ("wl-copy", &[]),
+    ("xclip", &["-selection", "clipboard"]),
+    ("xsel", &["--clipboard", "--input"]),
+];
+/// How long to wait for a clipboard tool
+const TIMEOUT: Duration = Duration::from_millis(500);
+/// Max size of encoded OSC 52 data, terminals drop longer sequences (xterm
+/// and tmux accept about 100KB)
+const OSC52_MAX: usize = 100_000;
+const BASE64_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Read the clipboard with the first tool that works. Only the first two
+/// lines are read as longer contents are never suggested.
+pub fn read() -> Option<String> {
+    PASTE_TOOLS.iter().find_map(|(tool, args)| {
+        let mut cmd = Command::new(tool);
+        cmd.args(*args);
+        Runner::new(TIMEOUT)
+            .max_lines(2)
+            .run(&mut cmd)
+            .ok()
+            .map(|output| output.lines.join("\n"))
+    })
+}
+
+/// Replace the clipboard contents with the first tool that is installed
+pub fn write(text: &str) -> Result<()> {
+    for (tool, args) in COPY_TOOLS {
+        let mut child = match Command::new(tool)
+            .args(*args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(_) => continue,
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(anyhow!("{} exited with {}", tool, status));
+        }
+        return Ok(());
+    }
+    Err(anyhow!("No clipboard tool found"))
+}
+
+/// Copy with a clipboard tool. Over ssh, or when no tool works, the terminal
+/// is asked to copy instead so that the text ends up in the local clipboard.
+pub fn copy(text: &str) -> Result<()> {
+    let remote = std::env::var_os("SSH_CONNECTION").is_some();
+    if !remote && write(text).is_ok() {
+        return Ok(());
+    }
+    write_terminal(text)
+}
+
+/// Send the text to the controlling terminal with an OSC 52 sequence. Stdout
+/// is not used as it could be captured.
+fn write_terminal(text: &str) -> Result<()> {
+    let sequence = osc52(text)?;
+    let mut tty = termion::get_tty().context("Open terminal")?;
+    tty.write_all(sequence.as_bytes())?;
+    tty.flush()?;
+    Ok(())
+}
+
+/// OSC 52 sequence that sets the clipboard. Text that terminals would drop
+/// is an error rather than being cut.
+fn osc52(text: &str) -> Result<String> {
+    let encoded = base64(text.as_bytes());
+    if encoded.len() > OSC52_MAX {
+        return Err(anyhow!(
+            "{} bytes are too many for the terminal clipboard (at most {})",
+            text.len(),
+            OSC52_MAX / 4 * 3
+        ));
+    }
+    Ok(format!("\x1b]52;c;{}\x07", encoded))
+}
+
+fn base64(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (n >> (18 - 6 * i)) & 0x3f;
+                encoded.push(BASE64_CHARS[index as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Clipboard contents that could be suggested: a single non-empty line
+/// that is a valid value.
+pub fn suggestion(contents: Option<String>, expect: &ValueType) -> Option<String> {
+    let contents = contents?;
+    let value = contents.trim_end_matches(&['\r', '\n'][..]);
+    if value.trim().is_empty() || value.contains(&['\r', '\n'][..]) {
+        return None;
+    }
+    expect.validate(value).ok()?;
+    Some(value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suggestion_valid() {
+        assert_eq!(
+            Some("https://example.com".to_string()),
+            suggestion(Some("https://example.com\n".into()), &ValueType::String)
+        );
+        assert_eq!(
+            Some("42".to_string()),
+            suggestion(Some("42".into()), &ValueType::Number)
+        );
+    }
+
+    #[test]
+    fn suggestion_invalid() {
+        assert_eq!(None, suggestion(None, &ValueType::String));
+        assert_eq!(None, suggestion(Some("".into()), &ValueType::String));
+        assert_eq!(None, suggestion(Some("  \n".into()), &ValueType::String));
+        assert_eq!(None, suggestion(Some("abc".into()), &ValueType::Number));
+    }
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!("", base64(b""));
+        assert_eq!("Zg==", base64(b"f"));
+        assert_eq!("Zm8=", base64(b"fo"));
+        assert_eq!("Zm9v", base64(b"foo"));
+        assert_eq!("bHMgLWxhIH4vJw==", base64(b"ls -la ~/'"));
+    }
+
+    #[test]
+    fn osc52_limit() {
+        assert_eq!("\x1b]52;c;bHM=\x07", osc52("ls").unwrap());
+        let long = "x".repeat(OSC52_MAX);
+        let err = osc52(&long).unwrap_err().to_string();
+        assert!(
+            err.contains("too many for the terminal clipboard"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn suggestion_multi_line() {
+        assert_eq!(
+            None,
+            suggestion(Some("one\ntwo".into()), &ValueType::String)
+        );
+        assert_eq!(
+            None,
+            suggestion(Some("one\r\ntwo\n".into()), &ValueType::String)
+        );
+    }
+}
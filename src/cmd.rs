@@ -1,19 +1,131 @@
-use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::{borrow::Cow, collections::HashMap};
 
-use anyhow::{anyhow, Result};
+use crate::dialect::Dialect;
+use crate::error::{Result, SnovaError};
+
+/// Builds the final command from user input
+pub type BuildFn = Box<dyn Fn(&HashMap<String, String>) -> String>;
 
 pub struct Command {
     pub template: String,
     pub description: String,
+    /// Extra words to find the command by
+    pub keywords: Vec<String>,
+    pub long_description: Option<String>,
+    pub example: Option<String>,
+    /// Complete commands with what they do
+    pub examples: Vec<Example>,
     pub groups: Vec<CmdGroup>,
-    pub build: Box<dyn Fn(&HashMap<String, String>) -> String>,
+    /// Ask user to confirm the command with this warning
+    pub confirm: Option<String>,
+    /// Hook that receives the built command instead of the global one
+    pub post_build: Option<String>,
+    /// Command is run as root, e.g with sudo when the user isn't root
+    pub needs_root: bool,
+    /// Definition problems that did not prevent building the command
+    pub warnings: Vec<String>,
+    /// Where the command is defined
+    pub source: CommandSource,
+    pub build: BuildFn,
+}
+
+/// Where a command is defined
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum CommandSource {
+    #[default]
+    Builtin,
+    /// User commands file or a file in commands.d
+    User(PathBuf),
+    Project(PathBuf),
+    /// Annotated shell script in one of the `script_dirs`
+    Imported(PathBuf),
+}
+
+impl CommandSource {
+    /// Names of the source kinds
+    pub const KINDS: &'static [&'static str] = &["builtin", "user", "project", "imported"];
+
+    /// Short name of the source, e.g `builtin`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CommandSource::Builtin => "builtin",
+            CommandSource::User(_) => "user",
+            CommandSource::Project(_) => "project",
+            CommandSource::Imported(_) => "imported",
+        }
+    }
+
+    /// File the command is defined in
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            CommandSource::Builtin => None,
+            CommandSource::User(path)
+            | CommandSource::Project(path)
+            | CommandSource::Imported(path) => Some(path),
+        }
+    }
+}
+
+/// Complete command that shows how a command is used
+#[derive(Debug, Clone, PartialEq)]
+pub struct Example {
+    pub description: String,
+    pub command: String,
 }
 
 pub struct CmdGroup {
     pub name: String,
     pub expect: GroupValue,
     pub suggest: Option<Vec<String>>,
+    /// Suggestions that are looked up when the group is asked
+    pub suggest_source: Option<SuggestSource>,
     pub optional: bool,
+    /// Value that is used when user submits an empty input
+    pub default: Option<String>,
+    /// How user input is quoted in the command
+    pub quote: QuotePolicy,
+    /// Applied in order to the submitted value
+    pub transform: Vec<Transform>,
+    pub prefix: Option<String>,
+    pub suffix: Option<String>,
+    /// Value derived from other groups, e.g "{DIR}.tar.gz"
+    pub computed: Option<String>,
+    /// Ask user to edit the computed value
+    pub editable: bool,
+    /// Value could span multiple lines
+    pub multiline: bool,
+    /// Range of number values
+    pub limits: NumberLimits,
+    /// Range of string value lengths
+    pub length: LengthLimits,
+    /// Value is never stored in history or presets
+    pub secret: bool,
+    /// Typed text could be used as a flag that is not defined
+    pub allow_custom: bool,
+    /// Picked flags are ordered as they are defined
+    pub canonical_order: bool,
+}
+
+impl CmdGroup {
+    /// Computed groups that are not editable are never asked
+    pub fn is_prompted(&self) -> bool {
+        self.computed.is_none() || self.editable
+    }
+
+    /// Apply transformations, prefix and suffix to the submitted value
+    pub fn transform_value(&self, value: &str) -> String {
+        let mut value = value.to_string();
+        for transform in &self.transform {
+            value = transform.apply(&value);
+        }
+        format!(
+            "{}{}{}",
+            self.prefix.as_deref().unwrap_or(""),
+            value,
+            self.suffix.as_deref().unwrap_or("")
+        )
+    }
 }
 
 pub enum GroupValue {
@@ -24,10 +136,18 @@ pub enum GroupValue {
 pub struct Flag {
     pub template: String,
     pub description: String,
+    pub long_description: Option<String>,
+    pub example: Option<String>,
     pub expect: Option<FlagExpectation>,
     /// Allow specifing this flag multiple times
     pub multiple: bool,
+    /// Flag is destructive and is highlighted
+    pub danger: bool,
     pub suggest: Option<Vec<String>>,
+    /// Shell command that lists more suggestions, one per line
+    pub suggest_cmd: Option<String>,
+    /// Value that is used when user submits an empty input
+    pub default: Option<String>,
 }
 
 impl PartialEq for Flag {
@@ -36,8 +156,19 @@ impl PartialEq for Flag {
     }
 }
 
+/// Builds a flag from values of its input groups
+pub type FlagBuildFn = Box<dyn Fn(&[String]) -> String>;
+
 pub struct FlagExpectation {
-    pub build: Box<dyn Fn(&str) -> String>,
+    /// Values to ask for in template order
+    pub inputs: Vec<FlagInput>,
+    /// Builds the flag from values in the same order as inputs
+    pub build: FlagBuildFn,
+}
+
+/// A single placeholder in a flag template
+pub struct FlagInput {
+    pub name: String,
     pub value_type: ValueType,
 }
 
@@ -49,19 +180,884 @@ pub enum ValueType {
 }
 
 impl ValueType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ValueType::String => "string",
+            ValueType::Path => "path",
+            ValueType::Number => "number",
+        }
+    }
+
     pub fn is_valid_char(&self, c: char) -> bool {
         match self {
             ValueType::String | ValueType::Path => true,
-            ValueType::Number => c.is_digit(10),
+            ValueType::Number => c.is_ascii_digit(),
         }
     }
 
+    /// Verify the submitted value
+    pub fn validate(&self, value: &str) -> Result<()> {
+        if value.is_empty() {
+            return Err(SnovaError::validation("Value is required"));
+        }
+        if !value.chars().all(|c| self.is_valid_char(c)) {
+            return Err(SnovaError::validation(format!(
+                "'{}' is not a valid {:?} value",
+                value, self
+            )));
+        }
+        if let ValueType::Path = self {
+            Dialect::current().validate_path(value)?;
+        }
+        Ok(())
+    }
+
     pub fn parse(v: &str) -> Result<ValueType> {
         match v {
             "string" => Ok(ValueType::String),
             "path" => Ok(ValueType::Path),
             "number" => Ok(ValueType::Number),
-            _ => Err(anyhow!("Unknown value type '{}'", v)),
+            _ => Err(SnovaError::parse(format!("Unknown value type '{}'", v))),
+        }
+    }
+}
+
+/// Where suggestions are looked up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SuggestSource {
+    /// Files tracked by git merged with the current directory entries
+    GitFiles,
+    /// Values of groups with the same name and value type in any command
+    HistoryValues,
+    /// Values of groups with the same value type in any command
+    HistoryTypeValues,
+}
+
+impl SuggestSource {
+    const NAMES: &'static [&'static str] = &["git_files", "history_values", "history_type_values"];
+
+    pub fn parse(v: &str) -> Result<SuggestSource> {
+        match v {
+            "git_files" => Ok(SuggestSource::GitFiles),
+            "history_values" => Ok(SuggestSource::HistoryValues),
+            "history_type_values" => Ok(SuggestSource::HistoryTypeValues),
+            _ => Err(SnovaError::parse(format!(
+                "Unknown suggestion source '{}' (valid: {})",
+                v,
+                SuggestSource::NAMES.join(", ")
+            ))),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SuggestSource::GitFiles => "git_files",
+            SuggestSource::HistoryValues => "history_values",
+            SuggestSource::HistoryTypeValues => "history_type_values",
+        }
+    }
+}
+
+/// Constraints on number values that are checked on submit
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NumberLimits {
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+    /// Keep leading zeros (e.g for tools that treat them as octal)
+    pub keep_leading_zeros: bool,
+}
+
+impl NumberLimits {
+    /// Parse the number and verify it is within the range.
+    /// Returns the value with redundant leading zeros stripped unless they are kept.
+    pub fn apply(&self, value: &str) -> Result<String> {
+        if value.is_empty() {
+            return Err(SnovaError::validation("Value is required"));
+        }
+        let number: u64 = value.parse().map_err(|_| {
+            SnovaError::validation(format!(
+                "'{}' is not a valid number (max {})",
+                value,
+                u64::MAX
+            ))
+        })?;
+        if let Some(min) = self.min.filter(|min| number < *min) {
+            return Err(SnovaError::validation(format!(
+                "{} is less than {}",
+                number, min
+            )));
+        }
+        if let Some(max) = self.max.filter(|max| number > *max) {
+            return Err(SnovaError::validation(format!(
+                "{} is greater than {}",
+                number, max
+            )));
+        }
+        if self.keep_leading_zeros {
+            Ok(value.to_string())
+        } else {
+            Ok(number.to_string())
+        }
+    }
+}
+
+/// Constraints on the length of string values that are checked on submit
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LengthLimits {
+    pub min: Option<usize>,
+    pub max: Option<usize>,
+}
+
+impl LengthLimits {
+    /// Verify the length of the value in chars (not bytes)
+    pub fn check(&self, value: &str) -> Result<()> {
+        let len = value.chars().count();
+        if let Some(min) = self.min.filter(|min| len < *min) {
+            return Err(SnovaError::validation(format!(
+                "Value is {} chars long, at least {} expected",
+                len, min
+            )));
+        }
+        if let Some(max) = self.max.filter(|max| len > *max) {
+            return Err(SnovaError::validation(format!(
+                "Value is {} chars long, at most {} allowed",
+                len, max
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Part of a computed value
+#[derive(Debug, Clone, PartialEq)]
+pub enum ComputedPart {
+    Text(String),
+    /// Reference to a group value
+    Ref(String),
+}
+
+/// Split a computed value into text and `{NAME}` references. Use `{{` and `}}` for braces.
+/// Errors point to the column of the offending brace.
+pub fn parse_computed(template: &str) -> Result<Vec<ComputedPart>> {
+    let mut parts = vec![];
+    let mut text = String::new();
+    let mut chars = template.chars().enumerate().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '{' if matches!(chars.peek(), Some((_, '{'))) => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if matches!(chars.peek(), Some((_, '}'))) => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '}')) => break,
+                        Some((j, '{')) => {
+                            return Err(SnovaError::parse(format!(
+                                "Unexpected '{{' at column {} in reference '{{{}'",
+                                j + 1,
+                                name
+                            )))
+                        }
+                        Some((_, c)) => name.push(c),
+                        None => {
+                            return Err(SnovaError::parse(format!(
+                                "Reference '{{{}' at column {} is not closed",
+                                name,
+                                i + 1
+                            )))
+                        }
+                    }
+                }
+                if !text.is_empty() {
+                    parts.push(ComputedPart::Text(std::mem::take(&mut text)));
+                }
+                parts.push(ComputedPart::Ref(name));
+            }
+            '}' => {
+                return Err(SnovaError::parse(format!(
+                    "Unexpected '}}' at column {} in '{}'",
+                    i + 1,
+                    template
+                )))
+            }
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        parts.push(ComputedPart::Text(text));
+    }
+    Ok(parts)
+}
+
+/// Replace `{NAME}` references with group values. Missing values are left empty.
+pub fn format_computed(template: &str, values: &HashMap<String, String>) -> Result<String> {
+    let mut result = String::with_capacity(template.len());
+    for part in parse_computed(template)? {
+        match part {
+            ComputedPart::Text(text) => result.push_str(&text),
+            ComputedPart::Ref(name) => {
+                if let Some(value) = values.get(&name) {
+                    result.push_str(value);
+                }
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Group names referenced in a computed value
+pub fn computed_refs(template: &str) -> Result<Vec<String>> {
+    Ok(parse_computed(template)?
+        .into_iter()
+        .filter_map(|part| match part {
+            ComputedPart::Ref(name) => Some(name),
+            ComputedPart::Text(_) => None,
+        })
+        .collect())
+}
+
+/// Ambient value that a template could refer to without a prompt, e.g `{date}`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContextVar {
+    /// Current directory
+    Cwd,
+    /// Today's date in the strftime format
+    Date(String),
+    User,
+    Hostname,
+}
+
+impl ContextVar {
+    const DATE_FORMAT: &'static str = "%Y-%m-%d";
+
+    /// Parse a reference like `user` or `date:%Y%m%d`. Returns None when the
+    /// name is not a context variable.
+    pub fn parse(reference: &str) -> Result<Option<ContextVar>> {
+        let (name, format) = match reference.split_once(':') {
+            Some((name, format)) => (name, Some(format)),
+            None => (reference, None),
+        };
+        let var = match name {
+            "cwd" => ContextVar::Cwd,
+            "date" => {
+                let format = format.unwrap_or(Self::DATE_FORMAT);
+                if format.is_empty() {
+                    return Err(SnovaError::parse("Date format is empty"));
+                }
+                return Ok(Some(ContextVar::Date(format.to_string())));
+            }
+            "user" => ContextVar::User,
+            "hostname" => ContextVar::Hostname,
+            _ => return Ok(None),
+        };
+        match format {
+            Some(_) => Err(SnovaError::parse(format!(
+                "Context variable '{}' takes no format",
+                name
+            ))),
+            None => Ok(Some(var)),
+        }
+    }
+
+    /// Name that a group could hide the variable with
+    pub fn name(&self) -> &'static str {
+        match self {
+            ContextVar::Cwd => "cwd",
+            ContextVar::Date(_) => "date",
+            ContextVar::User => "user",
+            ContextVar::Hostname => "hostname",
+        }
+    }
+
+    /// Current value. It is empty when it couldn't be read.
+    pub fn value(&self) -> String {
+        match self {
+            ContextVar::Cwd => std::env::current_dir()
+                .map(|dir| dir.display().to_string())
+                .unwrap_or_default(),
+            ContextVar::Date(format) => format_date(format),
+            ContextVar::User => std::env::var("USER")
+                .or_else(|_| std::env::var("LOGNAME"))
+                .unwrap_or_default(),
+            ContextVar::Hostname => {
+                let mut buf = [0u8; 256];
+                let len = unsafe {
+                    if libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) != 0 {
+                        return String::new();
+                    }
+                    libc::strlen(buf.as_ptr() as *const libc::c_char)
+                };
+                String::from_utf8_lossy(&buf[..len]).into_owned()
+            }
+        }
+    }
+}
+
+/// Format the current local time with strftime
+fn format_date(format: &str) -> String {
+    let format = match std::ffi::CString::new(format) {
+        Ok(format) => format,
+        Err(_) => return String::new(),
+    };
+    let mut buf = [0u8; 256];
+    let len = unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&now, &mut tm).is_null() {
+            return String::new();
         }
+        libc::strftime(
+            buf.as_mut_ptr() as *mut libc::c_char,
+            buf.len(),
+            format.as_ptr(),
+            &tm,
+        )
+    };
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+/// Value transformation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transform {
+    Trim,
+    Lower,
+    Upper,
+    UrlEncode,
+}
+
+impl Transform {
+    const NAMES: &'static [&'static str] = &["trim", "lower", "upper", "urlencode"];
+
+    pub fn parse(v: &str) -> Result<Transform> {
+        match v {
+            "trim" => Ok(Transform::Trim),
+            "lower" => Ok(Transform::Lower),
+            "upper" => Ok(Transform::Upper),
+            "urlencode" => Ok(Transform::UrlEncode),
+            _ => Err(SnovaError::parse(format!(
+                "Unknown transform '{}' (valid: {})",
+                v,
+                Transform::NAMES.join(", ")
+            ))),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Transform::Trim => "trim",
+            Transform::Lower => "lower",
+            Transform::Upper => "upper",
+            Transform::UrlEncode => "urlencode",
+        }
+    }
+
+    pub fn apply(&self, value: &str) -> String {
+        match self {
+            Transform::Trim => value.trim().to_string(),
+            Transform::Lower => value.to_lowercase(),
+            Transform::Upper => value.to_uppercase(),
+            Transform::UrlEncode => value
+                .bytes()
+                .map(|b| match b {
+                    b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                        (b as char).to_string()
+                    }
+                    _ => format!("%{:02X}", b),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// How user input is quoted for the shell
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum QuotePolicy {
+    /// Quote only values that the shell would otherwise split or expand
+    #[default]
+    Auto,
+    Always,
+    /// Insert values as is (e.g when input is a shell expression)
+    Never,
+}
+
+impl QuotePolicy {
+    pub fn parse(v: &str) -> Result<QuotePolicy> {
+        match v {
+            "auto" => Ok(QuotePolicy::Auto),
+            "always" => Ok(QuotePolicy::Always),
+            "never" => Ok(QuotePolicy::Never),
+            _ => Err(SnovaError::parse(format!("Unknown quote policy '{}'", v))),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            QuotePolicy::Auto => "auto",
+            QuotePolicy::Always => "always",
+            QuotePolicy::Never => "never",
+        }
+    }
+}
+
+/// How a template written on several lines is rendered
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Render {
+    /// Lines are joined with a space
+    #[default]
+    OneLine,
+    /// Lines are kept with a trailing backslash
+    MultiLine,
+}
+
+impl Render {
+    pub fn parse(v: &str) -> Result<Render> {
+        match v {
+            "oneline" => Ok(Render::OneLine),
+            "multiline" => Ok(Render::MultiLine),
+            _ => Err(SnovaError::parse(format!("Unknown render mode '{}'", v))),
+        }
+    }
+
+    /// Join template lines. A backslash at the end of a line is optional and
+    /// blank lines are dropped. Multi-line rendering keeps the indentation.
+    pub fn join_lines(&self, template: &str) -> String {
+        if !template.contains('\n') {
+            return template.to_string();
+        }
+        let lines = template
+            .lines()
+            .map(|line| line.trim_end().trim_end_matches('\\').trim_end())
+            .filter(|line| !line.trim().is_empty());
+        match self {
+            Render::OneLine => lines.map(str::trim).collect::<Vec<_>>().join(" "),
+            Render::MultiLine => {
+                let lines: Vec<&str> = lines.collect();
+                let first_indent = lines.first().map_or(0, |line| indent(line));
+                lines
+                    .iter()
+                    .map(|line| &line[indent(line).min(first_indent)..])
+                    .collect::<Vec<_>>()
+                    .join(" \\\n")
+            }
+        }
+    }
+}
+
+/// Length of the leading whitespace
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// Quote the value for the shell of the current dialect (single quotes on unix)
+pub fn quote(value: &str, policy: QuotePolicy) -> Cow<'_, str> {
+    Dialect::current().quote(value, policy)
+}
+
+/// Verify that the text could be inserted into a command as is: quotes are
+/// closed and a backslash doesn't end the line.
+pub fn check_quotes(text: &str) -> Result<()> {
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' if !chars.any(|c| c == '\'') => {
+                return Err(SnovaError::validation("Unclosed single quote"));
+            }
+            '"' => loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => {
+                        chars.next();
+                    }
+                    Some(_) => {}
+                    None => return Err(SnovaError::validation("Unclosed double quote")),
+                }
+            },
+            '\\' if chars.next().is_none() => {
+                return Err(SnovaError::validation("Trailing backslash"));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Split the command into words like a POSIX shell does: quotes are removed
+/// and escaped characters are kept. Operators (e.g `|`) are words too.
+pub fn split_words(text: &str) -> Result<Vec<String>> {
+    let mut words = vec![];
+    // Quoted empty strings are words as well
+    let mut word: Option<String> = None;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\\' => match chars.next() {
+                // Line continuation
+                Some('\n') => {}
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => return Err(SnovaError::validation("Trailing backslash")),
+            },
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(SnovaError::validation("Unclosed single quote")),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('\n') => {}
+                            Some(c) if matches!(c, '"' | '\\' | '$' | '`') => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err(SnovaError::validation("Unclosed double quote")),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err(SnovaError::validation("Unclosed double quote")),
+                    }
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn transform_values() {
+        let cases = [
+            (Transform::Trim, "  host ", "host"),
+            (Transform::Lower, "Example.COM", "example.com"),
+            (Transform::Upper, "get", "GET"),
+            (Transform::UrlEncode, "a b&c=d/é", "a%20b%26c%3Dd%2F%C3%A9"),
+            (Transform::UrlEncode, "safe-._~", "safe-._~"),
+        ];
+        for (transform, value, expected) in cases.iter() {
+            assert_eq!(*expected, transform.apply(value), "{:?}", transform);
+        }
+        assert!(Transform::parse("reverse").is_err());
+    }
+
+    #[test]
+    fn transform_group_value() {
+        let group = CmdGroup {
+            name: "BRANCH".into(),
+            expect: GroupValue::Single(ValueType::String),
+            suggest: None,
+            suggest_source: None,
+            optional: false,
+            default: None,
+            quote: QuotePolicy::Auto,
+            transform: vec![Transform::Trim, Transform::Lower],
+            prefix: Some("refs/heads/".into()),
+            suffix: None,
+            computed: None,
+            editable: false,
+            multiline: false,
+            limits: NumberLimits::default(),
+            length: LengthLimits::default(),
+            secret: false,
+            allow_custom: false,
+            canonical_order: false,
+        };
+        assert_eq!("refs/heads/main", group.transform_value(" Main "));
+    }
+
+    #[test]
+    fn custom_flag_quotes() {
+        assert!(check_quotes("--data-binary '@body.json'").is_ok());
+        assert!(check_quotes(r#"-H "X-Id: \"a b\"" --compressed"#).is_ok());
+        assert!(check_quotes(r"--name it\'s").is_ok());
+        assert!(check_quotes("-H 'X-Id: 1").is_err());
+        assert!(check_quotes(r#"-d "{\"a\": 1}"#).is_err());
+        assert!(check_quotes(r"--path a\").is_err());
+    }
+
+    #[test]
+    fn split_command_words() {
+        let words = |text: &str| split_words(text).unwrap();
+        assert_eq!(
+            vec!["grep", "-i", "foo", "./src"],
+            words("grep  -i foo ./src ")
+        );
+        assert_eq!(
+            vec!["git", "commit", "-m", "it's done"],
+            words(r#"git commit -m 'it'\''s done'"#)
+        );
+        assert_eq!(
+            vec!["echo", r#"a "b" $c \d"#, "e f"],
+            words(r#"echo "a \"b\" \$c \d" e\ f"#)
+        );
+        assert_eq!(
+            vec!["printf", "", "--x=a b"],
+            words(r#"printf '' --x="a b""#)
+        );
+        assert_eq!(
+            vec!["ps", "aux", "|", "grep", "x"],
+            words("ps aux | grep x")
+        );
+        assert_eq!(
+            vec!["docker", "run", "--rm", "alpine"],
+            words("docker run \\\n  --rm \\\n  alpine")
+        );
+        assert!(words("").is_empty());
+        assert!(split_words("echo 'a").is_err());
+        assert!(split_words(r#"echo "a"#).is_err());
+        assert!(split_words(r"echo a\").is_err());
+    }
+
+    #[test]
+    fn number_limits_overflow() {
+        let limits = NumberLimits::default();
+        assert_eq!(
+            "18446744073709551615",
+            limits.apply("18446744073709551615").unwrap()
+        );
+        assert!(limits.apply("18446744073709551616").is_err());
+        assert!(limits
+            .apply("1234567890123456789012345678901234567890")
+            .is_err());
+    }
+
+    #[test]
+    fn number_limits_leading_zeros() {
+        let limits = NumberLimits::default();
+        assert_eq!("9", limits.apply("0000000000000000009").unwrap());
+        assert_eq!("0", limits.apply("000").unwrap());
+
+        let limits = NumberLimits {
+            keep_leading_zeros: true,
+            ..NumberLimits::default()
+        };
+        assert_eq!("0755", limits.apply("0755").unwrap());
+    }
+
+    #[test]
+    fn number_limits_range() {
+        let limits = NumberLimits {
+            min: Some(1),
+            max: Some(65535),
+            keep_leading_zeros: false,
+        };
+        assert_eq!("8080", limits.apply("08080").unwrap());
+        assert_eq!(
+            "0 is less than 1",
+            limits.apply("0").unwrap_err().to_string()
+        );
+        assert_eq!(
+            "65536 is greater than 65535",
+            limits.apply("65536").unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn number_limits_empty() {
+        let limits = NumberLimits::default();
+        assert_eq!(
+            "Value is required",
+            limits.apply("").unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn length_limits() {
+        let length = LengthLimits {
+            min: Some(2),
+            max: Some(4),
+        };
+        assert_eq!(
+            "Value is 1 chars long, at least 2 expected",
+            length.check("a").unwrap_err().to_string()
+        );
+        assert!(length.check("ab").is_ok());
+        assert!(length.check("abcd").is_ok());
+        assert_eq!(
+            "Value is 5 chars long, at most 4 allowed",
+            length.check("abcde").unwrap_err().to_string()
+        );
+        // Chars are counted, not bytes
+        assert!(length.check("äöüß").is_ok());
+        assert!(LengthLimits::default().check(&"a".repeat(1000)).is_ok());
+    }
+
+    #[test]
+    fn format_computed_values() {
+        let mut values = HashMap::new();
+        values.insert("DIR".to_string(), "logs".to_string());
+        assert_eq!(
+            "logs.tar.gz",
+            format_computed("{DIR}.tar.gz", &values).unwrap()
+        );
+        assert_eq!(
+            "{logs}-",
+            format_computed("{{{DIR}}}-{MISSING}", &values).unwrap()
+        );
+        assert_eq!(vec!["DIR"], computed_refs("{{x}} {DIR}.tar.gz").unwrap());
+        assert!(computed_refs("{DIR").is_err());
+    }
+
+    #[test]
+    fn format_computed_errors() {
+        let values = HashMap::new();
+        let err = |template| format_computed(template, &values).unwrap_err().to_string();
+        assert_eq!("Reference '{DIR' at column 3 is not closed", err("a-{DIR"));
+        assert_eq!("Reference '{' at column 1 is not closed", err("{"));
+        assert_eq!(
+            "Unexpected '{' at column 4 in reference '{DI'",
+            err("{DI{R}}")
+        );
+        assert_eq!("Unexpected '}' at column 2 in 'a}b'", err("a}b"));
+    }
+
+    /// Text without braces or a reference to one of the known groups
+    fn computed_part() -> impl Strategy<Value = ComputedPart> {
+        prop_oneof![
+            "[^{}]{1,8}".prop_map(ComputedPart::Text),
+            "(A|B|MISSING)".prop_map(ComputedPart::Ref),
+            Just(ComputedPart::Text("{".to_string())),
+            Just(ComputedPart::Text("}".to_string())),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn format_computed_any_input(template in "\\PC{0,2000}") {
+            let values: HashMap<String, String> = HashMap::new();
+            if let Ok(result) = format_computed(&template, &values) {
+                // Without values the output only loses braces and references
+                prop_assert!(result.chars().count() <= template.chars().count());
+            }
+        }
+
+        #[test]
+        fn format_computed_round_trip(parts in prop::collection::vec(computed_part(), 0..12)) {
+            let mut values = HashMap::new();
+            values.insert("A".to_string(), "alpha".to_string());
+            values.insert("B".to_string(), "{B}".to_string());
+
+            let mut template = String::new();
+            let mut expected = String::new();
+            let mut refs = vec![];
+            for part in &parts {
+                match part {
+                    ComputedPart::Text(text) => {
+                        template.push_str(&text.replace('{', "{{").replace('}', "}}"));
+                        expected.push_str(text);
+                    }
+                    ComputedPart::Ref(name) => {
+                        template.push_str(&format!("{{{}}}", name));
+                        expected.push_str(values.get(name).map(String::as_str).unwrap_or(""));
+                        refs.push(name.clone());
+                    }
+                }
+            }
+            prop_assert_eq!(expected, format_computed(&template, &values).unwrap());
+            prop_assert_eq!(refs, computed_refs(&template).unwrap());
+        }
+    }
+
+    #[test]
+    fn quote_values() {
+        let cases = [
+            ("plain", QuotePolicy::Auto, "plain"),
+            (
+                "./path/to-file.txt",
+                QuotePolicy::Auto,
+                "./path/to-file.txt",
+            ),
+            ("two words", QuotePolicy::Auto, "'two words'"),
+            ("it's", QuotePolicy::Auto, "'it'\\''s'"),
+            ("say \"hi\"", QuotePolicy::Auto, "'say \"hi\"'"),
+            ("$(rm -rf ~)", QuotePolicy::Auto, "'$(rm -rf ~)'"),
+            ("{\"a\": 1}", QuotePolicy::Auto, "'{\"a\": 1}'"),
+            ("", QuotePolicy::Auto, "''"),
+            ("one\ntwo", QuotePolicy::Auto, "'one\ntwo'"),
+            ("plain", QuotePolicy::Always, "'plain'"),
+            ("two words", QuotePolicy::Never, "two words"),
+        ];
+        for (value, policy, expected) in cases.iter() {
+            assert_eq!(*expected, quote(value, *policy), "Quote {:?}", value);
+        }
+    }
+
+    #[test]
+    fn render_join_lines() {
+        let template = "tar -czf _OUT_ \\\n    --exclude _PATTERN_\n\n    _DIR_  ";
+        assert_eq!(
+            "tar -czf _OUT_ --exclude _PATTERN_ _DIR_",
+            Render::OneLine.join_lines(template)
+        );
+        assert_eq!(
+            "tar -czf _OUT_ \\\n    --exclude _PATTERN_ \\\n    _DIR_",
+            Render::MultiLine.join_lines(template)
+        );
+        // Single line templates are kept as is
+        assert_eq!("ls  _DIR_ ", Render::MultiLine.join_lines("ls  _DIR_ "));
+        assert_eq!(Render::MultiLine, Render::parse("multiline").unwrap());
+        assert!(Render::parse("wrap").is_err());
+    }
+
+    #[test]
+    fn context_var_parse() {
+        assert_eq!(Some(ContextVar::Cwd), ContextVar::parse("cwd").unwrap());
+        assert_eq!(Some(ContextVar::User), ContextVar::parse("user").unwrap());
+        assert_eq!(
+            Some(ContextVar::Hostname),
+            ContextVar::parse("hostname").unwrap()
+        );
+        assert_eq!(
+            Some(ContextVar::Date("%Y-%m-%d".into())),
+            ContextVar::parse("date").unwrap()
+        );
+        assert_eq!(
+            Some(ContextVar::Date("%Y%m%d".into())),
+            ContextVar::parse("date:%Y%m%d").unwrap()
+        );
+        // Other references are not variables
+        assert_eq!(None, ContextVar::parse("").unwrap());
+        assert_eq!(None, ContextVar::parse("DATE").unwrap());
+        assert!(ContextVar::parse("date:").is_err());
+        assert!(ContextVar::parse("cwd:short").is_err());
+    }
+
+    #[test]
+    fn context_var_values() {
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(cwd.display().to_string(), ContextVar::Cwd.value());
+        if let Ok(user) = std::env::var("USER") {
+            assert_eq!(user, ContextVar::User.value());
+        }
+        assert!(!ContextVar::Hostname.value().is_empty());
+
+        let date = ContextVar::Date("%Y-%m-%d".into()).value();
+        assert_eq!(10, date.len(), "{}", date);
+        assert_eq!(Some((4, '-')), date.char_indices().nth(4));
+        let year = ContextVar::Date("%Y".into()).value();
+        assert!(date.starts_with(&year), "{} {}", date, year);
+        assert_eq!(
+            format!("backup-{}.tar", year),
+            ContextVar::Date("backup-%Y.tar".into()).value()
+        );
     }
 }
@@ -0,0 +1,243 @@
+//! Path and quoting rules of the shell that commands are built for
+
+use std::borrow::Cow;
+
+use crate::cmd::QuotePolicy;
+use crate::error::{Result, SnovaError};
+
+/// Prefix of Windows paths that are passed to the file system as is
+const VERBATIM_PREFIX: &str = r"\\?\";
+
+/// Shell syntax of the built commands
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dialect {
+    Posix,
+    PowerShell,
+    /// Not selected yet: there is no way to tell which Windows shell reads the command
+    #[allow(dead_code)]
+    Cmd,
+}
+
+impl Dialect {
+    /// Dialect of the platform snova runs on
+    pub fn current() -> Self {
+        if cfg!(windows) {
+            Dialect::PowerShell
+        } else {
+            Dialect::Posix
+        }
+    }
+
+    fn windows_paths(self) -> bool {
+        matches!(self, Dialect::PowerShell | Dialect::Cmd)
+    }
+
+    /// Verify that the value could be a path. Windows paths could start with
+    /// a drive letter (`C:\`), be UNC paths (`\\server\share`) or verbatim
+    /// paths (`\\?\C:\`) and use either slash.
+    pub fn validate_path(self, value: &str) -> Result<()> {
+        if value.contains('\0') {
+            return Err(SnovaError::validation("Path contains a NUL char"));
+        }
+        if !self.windows_paths() {
+            return Ok(());
+        }
+        let invalid = |reason: String| {
+            SnovaError::validation(format!("'{}' is not a valid path: {}", value, reason))
+        };
+
+        let mut rest = value;
+        let verbatim = value.starts_with(VERBATIM_PREFIX);
+        if verbatim {
+            rest = &rest[VERBATIM_PREFIX.len()..];
+        } else if let Some(unc) = rest.strip_prefix(r"\\").or_else(|| rest.strip_prefix("//")) {
+            let mut parts = unc.split(['\\', '/']);
+            let server = parts.next().unwrap_or("");
+            let share = parts.next().unwrap_or("");
+            if server.is_empty() || share.is_empty() {
+                return Err(invalid("UNC paths need a server and a share".into()));
+            }
+            rest = unc;
+        }
+        let mut chars = rest.chars();
+        if let (Some(drive), Some(':')) = (chars.next(), chars.next()) {
+            if drive.is_ascii_alphabetic() {
+                rest = &rest[2..];
+            }
+        }
+        match rest
+            .chars()
+            .find(|c| c.is_control() || matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*'))
+        {
+            Some(c) => Err(invalid(format!("{:?} is not allowed", c))),
+            None => Ok(()),
+        }
+    }
+
+    /// Use the separator of the dialect. Verbatim Windows paths are kept as is
+    /// as they don't accept `/`.
+    pub fn normalize_path(self, value: &str) -> Cow<'_, str> {
+        if self.windows_paths() && !value.starts_with(VERBATIM_PREFIX) && value.contains('/') {
+            Cow::Owned(value.replace('/', "\\"))
+        } else {
+            Cow::Borrowed(value)
+        }
+    }
+
+    /// Quote the value for the shell
+    pub fn quote(self, value: &str, policy: QuotePolicy) -> Cow<'_, str> {
+        let needs_quotes = match policy {
+            QuotePolicy::Auto => value.is_empty() || !value.chars().all(|c| self.is_plain(c)),
+            QuotePolicy::Always => true,
+            QuotePolicy::Never => false,
+        };
+        if !needs_quotes {
+            return Cow::Borrowed(value);
+        }
+        Cow::Owned(match self {
+            Dialect::Posix => format!("'{}'", value.replace('\'', "'\\''")),
+            Dialect::PowerShell => format!("'{}'", value.replace('\'', "''")),
+            Dialect::Cmd => quote_cmd(value),
+        })
+    }
+
+    /// Chars that could be passed to the shell without quotes
+    fn is_plain(self, c: char) -> bool {
+        c.is_alphanumeric()
+            || match self {
+                Dialect::Posix => {
+                    matches!(
+                        c,
+                        '-' | '_' | '.' | '/' | ':' | '=' | '@' | '%' | '+' | ',' | '~'
+                    )
+                }
+                Dialect::PowerShell => {
+                    matches!(c, '-' | '_' | '.' | '/' | '\\' | ':' | '=' | '+' | '~')
+                }
+                Dialect::Cmd => matches!(c, '-' | '_' | '.' | '/' | '\\' | ':' | '+' | '~'),
+            }
+    }
+}
+
+/// Double quotes for cmd. Programs split arguments with CommandLineToArgvW
+/// rules, so backslashes before a quote (e.g a trailing one in `C:\dir\`) are
+/// doubled to not escape it.
+fn quote_cmd(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    let mut backslashes = 0;
+    for c in value.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted.push_str(&"\\".repeat(backslashes * 2));
+                quoted.push_str("\"\"");
+                backslashes = 0;
+            }
+            c => {
+                quoted.push_str(&"\\".repeat(backslashes));
+                quoted.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_posix_paths() {
+        let dialect = Dialect::Posix;
+        assert!(dialect.validate_path("./src/main.rs").is_ok());
+        // Backslashes and colons are plain chars
+        assert!(dialect.validate_path(r"C:\dir\a?.txt").is_ok());
+        assert!(dialect.validate_path("a\0b").is_err());
+    }
+
+    #[test]
+    fn validate_windows_paths() {
+        let dialect = Dialect::PowerShell;
+        for path in [
+            r"C:\Users\me\My Documents",
+            r"C:\",
+            "c:/tools/bin",
+            r"dir\file.txt",
+            r"\\server\share\file.txt",
+            "//server/share",
+            r"\\?\C:\very\long\path",
+            r"\\?\UNC\server\share",
+        ] {
+            assert!(dialect.validate_path(path).is_ok(), "{}", path);
+        }
+        assert_eq!(
+            r"'C:\a|b' is not a valid path: '|' is not allowed",
+            dialect.validate_path(r"C:\a|b").unwrap_err().to_string()
+        );
+        for path in [r"C:\a:b", r"dir\*.txt", "a\"b", r"\\server", r"\\", "a\tb"] {
+            assert!(dialect.validate_path(path).is_err(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn normalize_paths() {
+        assert_eq!("a/b", Dialect::Posix.normalize_path("a/b"));
+        assert_eq!(r"C:\a\b", Dialect::Cmd.normalize_path("C:/a/b"));
+        assert_eq!(
+            r"\\srv\share",
+            Dialect::PowerShell.normalize_path("//srv/share")
+        );
+        // Verbatim paths are kept
+        assert_eq!(r"\\?\C:\a/b", Dialect::Cmd.normalize_path(r"\\?\C:\a/b"));
+    }
+
+    #[test]
+    fn quote_powershell() {
+        let cases = [
+            (r"C:\Users\me", QuotePolicy::Auto, r"C:\Users\me"),
+            (r"C:\My Documents", QuotePolicy::Auto, r"'C:\My Documents'"),
+            ("it's", QuotePolicy::Auto, "'it''s'"),
+            ("$env:PATH", QuotePolicy::Auto, "'$env:PATH'"),
+            ("", QuotePolicy::Auto, "''"),
+            ("plain", QuotePolicy::Always, "'plain'"),
+            ("two words", QuotePolicy::Never, "two words"),
+        ];
+        for (value, policy, expected) in cases.iter() {
+            assert_eq!(*expected, Dialect::PowerShell.quote(value, *policy));
+        }
+    }
+
+    #[test]
+    fn quote_cmd_args() {
+        let cases = [
+            (r"C:\Users\me", r"C:\Users\me"),
+            (r"C:\My Documents", r#""C:\My Documents""#),
+            // Trailing backslash would escape the closing quote
+            (r"C:\My Documents\", r#""C:\My Documents\\""#),
+            (r#"say "hi""#, r#""say ""hi""""#),
+            (r#"a\"b"#, r#""a\\""b""#),
+            ("a&b", r#""a&b""#),
+            ("", r#""""#),
+        ];
+        for (value, expected) in cases.iter() {
+            assert_eq!(*expected, Dialect::Cmd.quote(value, QuotePolicy::Auto));
+        }
+    }
+
+    #[test]
+    fn quote_posix() {
+        assert_eq!(
+            "'two words'",
+            Dialect::Posix.quote("two words", QuotePolicy::Auto)
+        );
+        assert_eq!(
+            "'it'\\''s'",
+            Dialect::Posix.quote("it's", QuotePolicy::Auto)
+        );
+        assert_eq!("C:/dir", Dialect::Posix.quote("C:/dir", QuotePolicy::Auto));
+    }
+}
@@ -0,0 +1,213 @@
+//! Errors of loading command definitions and building commands
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Errors that callers could tell apart
+#[derive(Debug)]
+pub enum SnovaError {
+    /// Command definitions are invalid
+    Parse {
+        /// File the definitions were read from
+        file: Option<PathBuf>,
+        /// Template of the invalid command
+        command: Option<String>,
+        reason: String,
+    },
+    /// Value doesn't match its group
+    Validation(String),
+    /// User cancelled the prompts with Esc
+    Cancelled,
+    /// User skipped the remaining prompts with Ctrl-S
+    Finished,
+    /// User went back to picking a command with Ctrl-G
+    Restart,
+    /// User terminated input with Ctrl-C
+    Interrupted,
+    /// Choice prompt has no options to pick from
+    EmptyChoices,
+    /// Reading or writing a file or the terminal failed
+    Io {
+        path: Option<PathBuf>,
+        source: io::Error,
+    },
+}
+
+pub type Result<T, E = SnovaError> = std::result::Result<T, E>;
+
+impl SnovaError {
+    /// Definition error without a location
+    pub fn parse(reason: impl Into<String>) -> Self {
+        SnovaError::Parse {
+            file: None,
+            command: None,
+            reason: reason.into(),
+        }
+    }
+
+    pub fn validation(reason: impl Into<String>) -> Self {
+        SnovaError::Validation(reason.into())
+    }
+
+    /// I/O error of the file
+    pub fn io(path: &Path, source: io::Error) -> Self {
+        SnovaError::Io {
+            path: Some(path.to_path_buf()),
+            source,
+        }
+    }
+
+    /// Prefix the reason of definition and validation errors, e.g with the group name
+    pub fn context(self, context: impl fmt::Display) -> Self {
+        match self {
+            SnovaError::Parse {
+                file,
+                command,
+                reason,
+            } => SnovaError::Parse {
+                file,
+                command,
+                reason: format!("{}: {}", context, reason),
+            },
+            SnovaError::Validation(reason) => {
+                SnovaError::Validation(format!("{}: {}", context, reason))
+            }
+            err => err,
+        }
+    }
+
+    /// Locate the error in the command. Invalid values found in a definition
+    /// are definition errors.
+    pub fn in_command(self, template: &str) -> Self {
+        match self {
+            SnovaError::Parse {
+                file,
+                command,
+                reason,
+            } => SnovaError::Parse {
+                file,
+                command: command.or_else(|| Some(template.to_string())),
+                reason,
+            },
+            SnovaError::Validation(reason) => SnovaError::Parse {
+                file: None,
+                command: Some(template.to_string()),
+                reason,
+            },
+            err => err,
+        }
+    }
+
+    /// Locate the definition error in the file
+    pub fn in_file(self, path: &Path) -> Self {
+        match self {
+            SnovaError::Parse {
+                file,
+                command,
+                reason,
+            } => SnovaError::Parse {
+                file: file.or_else(|| Some(path.to_path_buf())),
+                command,
+                reason,
+            },
+            err => err,
+        }
+    }
+}
+
+impl fmt::Display for SnovaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnovaError::Parse {
+                file,
+                command,
+                reason,
+            } => {
+                if let Some(file) = file {
+                    write!(f, "{}: ", file.display())?;
+                }
+                if let Some(command) = command {
+                    write!(f, "In '{}': ", command)?;
+                }
+                write!(f, "{}", reason)
+            }
+            SnovaError::Validation(reason) => write!(f, "{}", reason),
+            SnovaError::Cancelled => write!(f, "Cancelled"),
+            SnovaError::Finished => write!(f, "Finished early"),
+            SnovaError::Restart => write!(f, "Back to commands"),
+            SnovaError::Interrupted => write!(f, "Terminated"),
+            SnovaError::EmptyChoices => write!(f, "Nothing to choose from"),
+            SnovaError::Io {
+                path: Some(path),
+                source,
+            } => write!(f, "{}: {}", path.display(), source),
+            SnovaError::Io { path: None, source } => write!(f, "{}", source),
+        }
+    }
+}
+
+impl std::error::Error for SnovaError {}
+
+impl From<io::Error> for SnovaError {
+    fn from(source: io::Error) -> Self {
+        SnovaError::Io { path: None, source }
+    }
+}
+
+impl From<toml::de::Error> for SnovaError {
+    fn from(err: toml::de::Error) -> Self {
+        SnovaError::parse(err.to_string())
+    }
+}
+
+/// Add context to errors the way `anyhow::Context` does
+pub trait Context<T> {
+    fn with_context<C: fmt::Display>(self, context: impl FnOnce() -> C) -> Result<T>;
+}
+
+impl<T> Context<T> for Result<T> {
+    fn with_context<C: fmt::Display>(self, context: impl FnOnce() -> C) -> Result<T> {
+        self.map_err(|err| err.context(context()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_location() {
+        let err = SnovaError::parse("Group 'PATH' is not closed")
+            .in_command("grep _PATH")
+            .in_file(Path::new("commands.toml"));
+        assert_eq!(
+            "commands.toml: In 'grep _PATH': Group 'PATH' is not closed",
+            err.to_string()
+        );
+        // The innermost location is kept
+        let err = err.in_command("ls").in_file(Path::new("other.toml"));
+        assert_eq!(
+            "commands.toml: In 'grep _PATH': Group 'PATH' is not closed",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn context_and_kinds() {
+        let err = SnovaError::validation("70000 is greater than 65535")
+            .context("In group 'PORT' default")
+            .in_command("ssh -p _PORT_");
+        assert!(matches!(err, SnovaError::Parse { .. }));
+        assert_eq!(
+            "In 'ssh -p _PORT_': In group 'PORT' default: 70000 is greater than 65535",
+            err.to_string()
+        );
+        // Only definition and validation errors have reasons
+        assert!(matches!(
+            SnovaError::Cancelled.context("Pick a flag"),
+            SnovaError::Cancelled
+        ));
+        assert_eq!("Terminated", SnovaError::Interrupted.to_string());
+    }
+}
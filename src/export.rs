@@ -0,0 +1,201 @@
+//! Machine-readable dump of the parsed commands (`snova export`)
+
+use serde::Serialize;
+
+use crate::cmd::*;
+
+#[derive(Serialize)]
+struct CommandExport<'a> {
+    template: &'a str,
+    description: &'a str,
+    keywords: &'a [String],
+    long_description: Option<&'a str>,
+    example: Option<&'a str>,
+    examples: Vec<ExampleExport<'a>>,
+    confirm: Option<&'a str>,
+    post_build: Option<&'a str>,
+    needs_root: bool,
+    source: SourceExport,
+    groups: Vec<GroupExport<'a>>,
+}
+
+#[derive(Serialize)]
+struct ExampleExport<'a> {
+    description: &'a str,
+    command: &'a str,
+}
+
+#[derive(Serialize)]
+struct SourceExport {
+    /// builtin, user, project or imported
+    kind: &'static str,
+    path: Option<String>,
+}
+
+#[derive(Serialize)]
+struct GroupExport<'a> {
+    name: &'a str,
+    /// Value type or "flags"
+    #[serde(rename = "type")]
+    value_type: &'static str,
+    optional: bool,
+    default: Option<&'a str>,
+    suggest: &'a [String],
+    suggest_source: Option<&'static str>,
+    quote: &'static str,
+    transform: Vec<&'static str>,
+    prefix: Option<&'a str>,
+    suffix: Option<&'a str>,
+    computed: Option<&'a str>,
+    editable: bool,
+    multiline: bool,
+    min: Option<u64>,
+    max: Option<u64>,
+    keep_leading_zeros: bool,
+    min_len: Option<usize>,
+    max_len: Option<usize>,
+    secret: bool,
+    allow_custom: bool,
+    canonical_order: bool,
+    flags: Vec<FlagExport<'a>>,
+}
+
+#[derive(Serialize)]
+struct FlagExport<'a> {
+    template: &'a str,
+    description: &'a str,
+    long_description: Option<&'a str>,
+    example: Option<&'a str>,
+    /// Input groups of the flag in template order
+    expect: Vec<FlagInputExport<'a>>,
+    multiple: bool,
+    danger: bool,
+    suggest: &'a [String],
+    suggest_cmd: Option<&'a str>,
+    default: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct FlagInputExport<'a> {
+    name: &'a str,
+    #[serde(rename = "type")]
+    value_type: &'static str,
+}
+
+/// Commands as pretty-printed JSON. Commands and groups keep their order and
+/// every field is present (null when not set), so the output is stable.
+pub fn to_json(commands: &[Command]) -> serde_json::Result<String> {
+    let commands: Vec<CommandExport> = commands.iter().map(command).collect();
+    serde_json::to_string_pretty(&commands)
+}
+
+fn command(cmd: &Command) -> CommandExport<'_> {
+    let kind = cmd.source.kind();
+    let path = cmd.source.path().map(|path| path.display().to_string());
+    CommandExport {
+        template: &cmd.template,
+        description: &cmd.description,
+        keywords: &cmd.keywords,
+        long_description: cmd.long_description.as_deref(),
+        example: cmd.example.as_deref(),
+        examples: cmd
+            .examples
+            .iter()
+            .map(|example| ExampleExport {
+                description: &example.description,
+                command: &example.command,
+            })
+            .collect(),
+        confirm: cmd.confirm.as_deref(),
+        post_build: cmd.post_build.as_deref(),
+        needs_root: cmd.needs_root,
+        source: SourceExport { kind, path },
+        groups: cmd.groups.iter().map(group).collect(),
+    }
+}
+
+fn group(group: &CmdGroup) -> GroupExport<'_> {
+    let (value_type, flags) = match &group.expect {
+        GroupValue::Single(value_type) => (value_type.name(), vec![]),
+        GroupValue::Flags(flags) => ("flags", flags.iter().map(flag).collect()),
+    };
+    GroupExport {
+        name: &group.name,
+        value_type,
+        optional: group.optional,
+        default: group.default.as_deref(),
+        suggest: group.suggest.as_deref().unwrap_or_default(),
+        suggest_source: group.suggest_source.map(|source| source.name()),
+        quote: group.quote.name(),
+        transform: group.transform.iter().map(Transform::name).collect(),
+        prefix: group.prefix.as_deref(),
+        suffix: group.suffix.as_deref(),
+        computed: group.computed.as_deref(),
+        editable: group.editable,
+        multiline: group.multiline,
+        min: group.limits.min,
+        max: group.limits.max,
+        keep_leading_zeros: group.limits.keep_leading_zeros,
+        min_len: group.length.min,
+        max_len: group.length.max,
+        secret: group.secret,
+        allow_custom: group.allow_custom,
+        canonical_order: group.canonical_order,
+        flags,
+    }
+}
+
+fn flag(flag: &Flag) -> FlagExport<'_> {
+    FlagExport {
+        template: &flag.template,
+        description: &flag.description,
+        long_description: flag.long_description.as_deref(),
+        example: flag.example.as_deref(),
+        expect: flag
+            .expect
+            .iter()
+            .flat_map(|expect| &expect.inputs)
+            .map(|input| FlagInputExport {
+                name: &input.name,
+                value_type: input.value_type.name(),
+            })
+            .collect(),
+        multiple: flag.multiple,
+        danger: flag.danger,
+        suggest: flag.suggest.as_deref().unwrap_or_default(),
+        suggest_cmd: flag.suggest_cmd.as_deref(),
+        default: flag.default.as_deref(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    #[test]
+    fn export_builtin() {
+        let commands = parser::builtin().unwrap();
+        insta::assert_snapshot!(to_json(&commands).unwrap());
+    }
+
+    #[test]
+    fn export_source() {
+        let mut commands = parser::builtin().unwrap();
+        commands.truncate(1);
+        commands[0].source = CommandSource::Project("/src/app/.snova.toml".into());
+        let json: serde_json::Value = serde_json::from_str(&to_json(&commands).unwrap()).unwrap();
+        assert_eq!(
+            serde_json::json!({ "kind": "project", "path": "/src/app/.snova.toml" }),
+            json[0]["source"]
+        );
+        assert_eq!("Find lines in a file (*grep*)", json[0]["description"]);
+        assert_eq!("flags", json[0]["groups"][2]["type"]);
+        let flag = &json[0]["groups"][2]["flags"][2];
+        assert_eq!("*-A* _NUM_", flag["template"]);
+        assert_eq!(
+            serde_json::json!([{ "name": "NUM", "type": "number" }]),
+            flag["expect"]
+        );
+    }
+}
@@ -0,0 +1,331 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// How many values to remember per command group
+const VALUES_LIMIT: usize = 20;
+/// How many values to remember across all commands
+const RECENT_LIMIT: usize = 200;
+const VALUES_FILE: &str = "values.toml";
+/// How many built commands to remember
+const COMMANDS_LIMIT: usize = 1000;
+const COMMANDS_FILE: &str = "history";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ValuesFile {
+    #[serde(default)]
+    values: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    recent: Vec<RecentValue>,
+}
+
+/// Value submitted for a group of any command
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecentValue {
+    pub group: String,
+    /// Value type name, e.g "path"
+    pub expect: String,
+    pub value: String,
+}
+
+/// Values previously submitted for command groups
+#[derive(Debug, Default)]
+pub struct History {
+    /// Where values are stored. Nothing is persisted if not set.
+    path: Option<PathBuf>,
+    values: HashMap<String, Vec<String>>,
+    /// Most recent first
+    recent: Vec<RecentValue>,
+}
+
+impl History {
+    /// Read history from the directory
+    pub fn open(dir: &Path) -> Result<Self> {
+        let path = dir.join(VALUES_FILE);
+        let file = if path.is_file() {
+            let data =
+                std::fs::read_to_string(&path).context(format!("Read {}", path.display()))?;
+            toml::de::from_str(&data).context("Parse values history")?
+        } else {
+            ValuesFile::default()
+        };
+        Ok(Self {
+            path: Some(path),
+            values: file.values,
+            recent: file.recent,
+        })
+    }
+
+    /// Values for the command group, most recent first
+    pub fn values(&self, template: &str, group: &str) -> &[String] {
+        self.values
+            .get(&key(template, group))
+            .map(|values| values.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Remember a value for the command group
+    pub fn add(&mut self, template: &str, group: &str, value: &str) {
+        let values = self.values.entry(key(template, group)).or_default();
+        values.retain(|v| v != value);
+        values.insert(0, value.to_string());
+        values.truncate(VALUES_LIMIT);
+    }
+
+    /// Remember a value for a group by its name and value type, so that other
+    /// commands could suggest it
+    pub fn add_recent(&mut self, group: &str, expect: &str, value: &str) {
+        let recent = RecentValue {
+            group: group.to_string(),
+            expect: expect.to_string(),
+            value: value.to_string(),
+        };
+        self.recent.retain(|v| *v != recent);
+        self.recent.insert(0, recent);
+        self.recent.truncate(RECENT_LIMIT);
+    }
+
+    /// Values of all commands that match, most recent first and without repeats
+    pub fn recent_values(&self, matches: impl Fn(&RecentValue) -> bool) -> Vec<String> {
+        let mut values: Vec<String> = vec![];
+        for recent in self.recent.iter().filter(|v| matches(v)) {
+            if !values.contains(&recent.value) {
+                values.push(recent.value.clone());
+            }
+        }
+        values
+    }
+
+    /// Write history to disk
+    pub fn save(&self) -> Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).context(format!("Create {}", dir.display()))?;
+        }
+        let file = ValuesFile {
+            values: self.values.clone(),
+            recent: self.recent.clone(),
+        };
+        let data = toml::ser::to_string(&file).context("Serialize values history")?;
+        std::fs::write(path, data).context(format!("Write {}", path.display()))
+    }
+
+    /// Remove all stored values
+    pub fn clear(dir: &Path) -> Result<()> {
+        let path = dir.join(VALUES_FILE);
+        if path.is_file() {
+            std::fs::remove_file(&path).context(format!("Remove {}", path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Command that was built
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandEntry {
+    /// Seconds since the UNIX epoch
+    pub time: u64,
+    pub command: String,
+}
+
+/// Built commands from the directory, oldest first. Every line of the file
+/// is a timestamp and a command separated by a tab.
+pub fn commands(dir: &Path) -> Result<Vec<CommandEntry>> {
+    let path = dir.join(COMMANDS_FILE);
+    if !path.is_file() {
+        return Ok(vec![]);
+    }
+    let data = std::fs::read_to_string(&path).context(format!("Read {}", path.display()))?;
+    Ok(data.lines().filter_map(parse_entry).collect())
+}
+
+/// Remember a built command. The oldest commands are dropped once there
+/// are too many.
+pub fn add_command(dir: &Path, command: &str, time: u64) -> Result<()> {
+    add_command_capped(dir, command, time, COMMANDS_LIMIT)
+}
+
+fn add_command_capped(dir: &Path, command: &str, time: u64, limit: usize) -> Result<()> {
+    let path = dir.join(COMMANDS_FILE);
+    let line = format!("{}\t{}\n", time, escape(command));
+    let mut entries = commands(dir)?;
+    if entries.len() < limit {
+        std::fs::create_dir_all(dir).context(format!("Create {}", dir.display()))?;
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .context(format!("Open {}", path.display()))?;
+        return file
+            .write_all(line.as_bytes())
+            .context(format!("Write {}", path.display()));
+    }
+    entries.drain(..entries.len() + 1 - limit);
+    let mut data: String = entries
+        .iter()
+        .map(|entry| format!("{}\t{}\n", entry.time, escape(&entry.command)))
+        .collect();
+    data.push_str(&line);
+    std::fs::write(&path, data).context(format!("Write {}", path.display()))
+}
+
+fn parse_entry(line: &str) -> Option<CommandEntry> {
+    let (time, command) = line.split_once('\t')?;
+    Some(CommandEntry {
+        time: time.parse().ok()?,
+        command: unescape(command),
+    })
+}
+
+/// Keep multiline commands on a single line
+fn escape(command: &str) -> String {
+    command.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut result = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                chars.next();
+                result.push('\n');
+            }
+            ('\\', Some('\\')) => {
+                chars.next();
+                result.push('\\');
+            }
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Stable key for a command group (FNV-1a of template and group name)
+fn key(template: &str, group: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in template.bytes().chain(Some(0)).chain(group.bytes()) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("snova-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn key_is_stable() {
+        assert_eq!(key("grep _PATTERN_", "PATTERN"), "c9bc2bce13532bad");
+        assert_ne!(
+            key("grep _PATTERN_", "PATTERN"),
+            key("grep _PATTERN", "_PATTERN")
+        );
+    }
+
+    #[test]
+    fn recent_first() {
+        let mut history = History::default();
+        history.add("grep", "PATTERN", "foo");
+        history.add("grep", "PATTERN", "bar");
+        history.add("grep", "PATTERN", "foo");
+        assert_eq!(history.values("grep", "PATTERN"), &["foo", "bar"]);
+        assert!(history.values("grep", "PATH").is_empty());
+    }
+
+    #[test]
+    fn recent_across_commands() {
+        let mut history = History::default();
+        history.add_recent("URL", "string", "https://a.example.com");
+        history.add_recent("URL", "string", "https://b.example.com");
+        history.add_recent("URL", "string", "https://a.example.com");
+        history.add_recent("FILE", "path", "a.txt");
+        assert_eq!(
+            history.recent_values(|v| v.group == "URL"),
+            &["https://a.example.com", "https://b.example.com"]
+        );
+        for i in 0..RECENT_LIMIT {
+            history.add_recent("N", "number", &i.to_string());
+        }
+        assert!(history.recent_values(|v| v.group == "URL").is_empty());
+    }
+
+    #[test]
+    fn capped() {
+        let mut history = History::default();
+        for i in 0..30 {
+            history.add("grep", "PATTERN", &i.to_string());
+        }
+        let values = history.values("grep", "PATTERN");
+        assert_eq!(values.len(), VALUES_LIMIT);
+        assert_eq!(values[0], "29");
+    }
+
+    #[test]
+    fn save_and_clear() {
+        let dir = temp_dir("history");
+        let mut history = History::open(&dir).unwrap();
+        history.add("grep", "PATTERN", "foo");
+        history.add_recent("PATTERN", "string", "foo");
+        history.save().unwrap();
+
+        let history = History::open(&dir).unwrap();
+        assert_eq!(history.values("grep", "PATTERN"), &["foo"]);
+        assert_eq!(history.recent_values(|_| true), &["foo"]);
+
+        History::clear(&dir).unwrap();
+        let history = History::open(&dir).unwrap();
+        assert!(history.values("grep", "PATTERN").is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn commands_history() {
+        let dir = temp_dir("commands");
+        assert!(commands(&dir).unwrap().is_empty());
+        add_command(&dir, "grep -r TODO src", 100).unwrap();
+        add_command(&dir, "printf 'a\\nb'\necho done", 200).unwrap();
+        assert_eq!(
+            vec![
+                CommandEntry {
+                    time: 100,
+                    command: "grep -r TODO src".into()
+                },
+                CommandEntry {
+                    time: 200,
+                    command: "printf 'a\\nb'\necho done".into()
+                },
+            ],
+            commands(&dir).unwrap()
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn commands_history_capped() {
+        let dir = temp_dir("commands-capped");
+        for i in 0..5 {
+            add_command_capped(&dir, &format!("echo {}", i), i, 3).unwrap();
+        }
+        let entries: Vec<String> = commands(&dir)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.command)
+            .collect();
+        assert_eq!(vec!["echo 2", "echo 3", "echo 4"], entries);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
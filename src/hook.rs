@@ -0,0 +1,103 @@
+//! Runs the post-build hook with the built command
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+
+/// How long to wait for the hook before it is killed
+pub const HOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Run the hook with the command on stdin and the given environment variables.
+/// Hook output goes to stderr so that stdout carries only the command.
+pub fn post_build(
+    hook: &str,
+    command: &str,
+    env: &[(&str, &str)],
+    timeout: Duration,
+) -> Result<()> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(hook)
+        .envs(env.iter().copied())
+        .stdin(Stdio::piped())
+        .stdout(std::io::stderr())
+        .spawn()
+        .context(format!("Run '{}'", hook))?;
+    let mut stdin = child.stdin.take().expect("Stdin is piped");
+    // Hook is free not to read its input
+    let _ = writeln!(stdin, "{}", command);
+    drop(stdin);
+
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            if !status.success() {
+                return Err(anyhow!("'{}' failed with {}", hook, status));
+            }
+            return Ok(());
+        }
+        if started.elapsed() >= timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(anyhow!("'{}' timed out", hook));
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("snova-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn hook_receives_command() {
+        let dir = temp_dir("hook");
+        let script = dir.join("hook.sh");
+        std::fs::write(
+            &script,
+            "cat > \"$OUT/stdin\"\nprintf '%s|%s' \"$SNOVA_TEMPLATE\" \"$SNOVA_DESCRIPTION\" > \"$OUT/env\"\n",
+        )
+        .unwrap();
+        let hook = format!("OUT={} sh {}", dir.display(), script.display());
+        post_build(
+            &hook,
+            "grep -i 'it'\\''s' .",
+            &[
+                ("SNOVA_TEMPLATE", "grep [_OPTIONS_] _PATTERN_ _PATH_"),
+                ("SNOVA_DESCRIPTION", "Find lines"),
+            ],
+            HOOK_TIMEOUT,
+        )
+        .unwrap();
+        assert_eq!(
+            "grep -i 'it'\\''s' .\n",
+            std::fs::read_to_string(dir.join("stdin")).unwrap()
+        );
+        assert_eq!(
+            "grep [_OPTIONS_] _PATTERN_ _PATH_|Find lines",
+            std::fs::read_to_string(dir.join("env")).unwrap()
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn hook_failures() {
+        let err = post_build("exit 3", "ls", &[], HOOK_TIMEOUT).unwrap_err();
+        assert!(err.to_string().contains("exit status: 3"), "{}", err);
+
+        let started = Instant::now();
+        let err = post_build("sleep 5", "ls", &[], Duration::from_millis(100)).unwrap_err();
+        assert_eq!("'sleep 5' timed out", err.to_string());
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
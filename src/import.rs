@@ -0,0 +1,819 @@
+//! Imports shell aliases, functions and annotated scripts as commands
+
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::builder::{CommandBuilder, FlagBuilder};
+use crate::cmd::ValueType;
+use crate::parser;
+use crate::script;
+
+/// A shell alias or a one-line function
+#[derive(Debug, PartialEq)]
+pub struct Alias {
+    pub name: String,
+    pub body: String,
+}
+
+/// Aliases found in a shell file and lines that could not be imported
+#[derive(Debug, Default)]
+pub struct Imported {
+    pub aliases: Vec<Alias>,
+    /// Line number and reason
+    pub skipped: Vec<(usize, String)>,
+}
+
+/// Commands file with generated definitions
+#[derive(Serialize)]
+pub struct ImportFile {
+    pub commands: Vec<ImportDef>,
+}
+
+#[derive(Serialize)]
+pub struct ImportDef {
+    pub template: String,
+    pub description: String,
+    pub groups: BTreeMap<String, ImportGroup>,
+}
+
+#[derive(Serialize)]
+pub struct ImportGroup {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expect: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flags: Option<Vec<HelpFlag>>,
+}
+
+/// A flag parsed from --help output
+#[derive(Debug, PartialEq, Serialize)]
+pub struct HelpFlag {
+    pub template: String,
+    pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expect: Option<String>,
+}
+
+/// Flags found in --help output and option lines that could not be parsed
+#[derive(Debug, Default)]
+pub struct HelpImport {
+    pub flags: Vec<HelpFlag>,
+    pub skipped: Vec<String>,
+}
+
+/// Find aliases and one-line functions in a shell file.
+/// Anything that looks like a definition but cannot be parsed is reported as skipped.
+pub fn parse_shell(text: &str) -> Imported {
+    let mut imported = Imported::default();
+    let mut lines = text.lines().enumerate();
+
+    while let Some((index, line)) = lines.next() {
+        let line = line.trim();
+        let result = if let Some(rest) = line.strip_prefix("alias ") {
+            parse_alias(rest)
+        } else if let Some(name) = function_name(line) {
+            let rest = line[line.find('{').unwrap_or(line.len())..].trim();
+            if rest == "{" || rest.is_empty() {
+                // Function body on the following lines
+                let mut body = vec![];
+                for (_, line) in lines.by_ref() {
+                    let line = line.trim();
+                    if line == "}" {
+                        break;
+                    }
+                    if !line.is_empty() && line != "{" {
+                        body.push(line);
+                    }
+                }
+                match body.as_slice() {
+                    [body] => Ok(Alias {
+                        name,
+                        body: body.trim_end_matches(';').trim().to_string(),
+                    }),
+                    _ => Err(format!("Function '{}' has more than one line", name)),
+                }
+            } else {
+                parse_function_body(rest).map(|body| Alias { name, body })
+            }
+        } else {
+            continue;
+        };
+
+        match result {
+            Ok(alias) => imported.aliases.push(alias),
+            Err(reason) => imported.skipped.push((index + 1, reason)),
+        }
+    }
+
+    imported
+}
+
+/// Parse `name=value` part of the alias
+fn parse_alias(rest: &str) -> Result<Alias, String> {
+    // Skip options like zsh `alias -g`
+    let mut rest = rest.trim_start();
+    while rest.starts_with('-') {
+        let end = rest.find(' ').unwrap_or(rest.len());
+        rest = rest[end..].trim_start();
+    }
+
+    let eq = rest.find('=').ok_or("Alias without a value")?;
+    let name = &rest[..eq];
+    if name.is_empty() || !name.chars().all(is_name_char) {
+        return Err(format!("Unsupported alias name '{}'", name));
+    }
+
+    let (body, rest) = parse_word(&rest[eq + 1..])?;
+    let rest = rest.trim();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err(format!("Unexpected '{}' after alias '{}'", rest, name));
+    }
+    if body.trim().is_empty() {
+        return Err(format!("Alias '{}' is empty", name));
+    }
+
+    Ok(Alias {
+        name: name.to_string(),
+        body: body.trim().to_string(),
+    })
+}
+
+/// Read a shell word made of quoted and unquoted parts. Returns the word and the rest.
+pub fn parse_word(text: &str) -> Result<(String, &str), String> {
+    let mut word = String::new();
+    let mut chars = text.char_indices().peekable();
+
+    while let Some(&(i, c)) = chars.peek() {
+        match c {
+            '\'' => {
+                chars.next();
+                loop {
+                    match chars.next() {
+                        Some((_, '\'')) => break,
+                        Some((_, c)) => word.push(c),
+                        None => return Err("Unclosed single quote".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                chars.next();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, c)) if matches!(c, '"' | '\\' | '$' | '`') => word.push(c),
+                            Some((_, c)) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err("Unclosed double quote".to_string()),
+                        },
+                        Some((_, c)) => word.push(c),
+                        None => return Err("Unclosed double quote".to_string()),
+                    }
+                }
+            }
+            '\\' => {
+                chars.next();
+                match chars.next() {
+                    Some((_, c)) => word.push(c),
+                    None => return Err("Line continuation is not supported".to_string()),
+                }
+            }
+            c if c.is_whitespace() || c == ';' || c == '#' => return Ok((word, &text[i..])),
+            c => {
+                chars.next();
+                word.push(c);
+            }
+        }
+    }
+
+    Ok((word, ""))
+}
+
+/// Function name from `name() {` or `function name {` definitions
+fn function_name(line: &str) -> Option<String> {
+    let name = if let Some(rest) = line.strip_prefix("function ") {
+        rest.split(|c: char| c == '(' || c == '{' || c.is_whitespace())
+            .next()?
+    } else {
+        let paren = line.find("()")?;
+        line[..paren].trim()
+    };
+    if !name.is_empty() && name.chars().all(is_name_char) {
+        Some(name.to_string())
+    } else {
+        None
+    }
+}
+
+/// Body of a one-line function `{ body; }`
+fn parse_function_body(rest: &str) -> Result<String, String> {
+    let body = rest
+        .strip_prefix('{')
+        .and_then(|body| body.trim_end().strip_suffix('}'))
+        .ok_or("Unsupported function definition")?;
+    let body = body.trim().trim_end_matches(';').trim();
+    if body.is_empty() {
+        return Err("Function is empty".to_string());
+    }
+    if body.contains(';') || body.contains('{') {
+        return Err("Function has more than one command".to_string());
+    }
+    Ok(body.to_string())
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':')
+}
+
+/// Convert alias body into a command template. Positional parameters become groups.
+fn to_template(body: &str) -> Result<(String, Vec<String>), String> {
+    if body.contains(['*', '[', ']']) {
+        return Err("Contains '*', '[' or ']' that templates do not support".to_string());
+    }
+
+    // Values are quoted when the command is built
+    let mut body = body.to_string();
+    for param in ["1", "2", "3", "4", "5", "6", "7", "8", "9", "@", "*"].iter() {
+        body = body
+            .replace(&format!("\"${}\"", param), &format!("${}", param))
+            .replace(&format!("\"${{{}}}\"", param), &format!("${}", param));
+    }
+
+    let mut template = String::new();
+    let mut groups = vec![];
+    let mut chars = body.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '_' => template.push_str("\\_"),
+            '$' => {
+                let braced = chars.peek() == Some(&'{');
+                if braced {
+                    chars.next();
+                }
+                let group = match chars.peek() {
+                    Some(d @ '1'..='9') => Some(format!("ARG{}", d)),
+                    Some('@') | Some('*') => Some("ARGS".to_string()),
+                    _ => None,
+                };
+                match group {
+                    Some(group) => {
+                        chars.next();
+                        if braced && chars.next() != Some('}') {
+                            return Err("Unsupported parameter expansion".to_string());
+                        }
+                        if group == "ARGS" {
+                            template.push_str("[_ARGS_]");
+                        } else {
+                            template.push_str(&format!("_{}_", group));
+                        }
+                        if groups.contains(&group) {
+                            return Err(format!("{} is used more than once", group));
+                        }
+                        groups.push(group);
+                    }
+                    None => {
+                        template.push('$');
+                        if braced {
+                            template.push('{');
+                        }
+                    }
+                }
+            }
+            c => template.push(c),
+        }
+    }
+
+    Ok((template, groups))
+}
+
+/// Convert aliases into a commands file. Aliases that don't produce a valid command are skipped.
+pub fn to_commands_toml(aliases: &[Alias]) -> Result<(String, Vec<(String, String)>)> {
+    let mut commands = vec![];
+    let mut skipped = vec![];
+
+    for alias in aliases {
+        let (template, groups) = match to_template(&alias.body) {
+            Ok(template) => template,
+            Err(reason) => {
+                skipped.push((alias.name.clone(), reason));
+                continue;
+            }
+        };
+        let builder = groups.iter().fold(
+            CommandBuilder::new(&template).description(&alias.name),
+            |builder, name| builder.group(name, ValueType::String),
+        );
+        if let Err(err) = builder.build() {
+            skipped.push((alias.name.clone(), err.to_string()));
+            continue;
+        }
+
+        commands.push(ImportDef {
+            template,
+            description: alias.name.clone(),
+            groups: groups
+                .into_iter()
+                .map(|name| {
+                    let group = ImportGroup {
+                        expect: Some(ValueType::String.name().to_string()),
+                        flags: None,
+                    };
+                    (name, group)
+                })
+                .collect(),
+        });
+    }
+
+    let data =
+        toml::ser::to_string(&ImportFile { commands }).context("Serialize imported commands")?;
+    Ok((data, skipped))
+}
+
+/// Commands file with definitions extracted from a script
+#[derive(Serialize)]
+struct ScriptFile {
+    commands: Vec<toml::Value>,
+}
+
+/// Commands file converted from a script
+pub struct ScriptImport {
+    pub data: String,
+    /// Number of imported commands
+    pub imported: usize,
+    /// Line number and reason of skipped annotations
+    pub skipped: Vec<(usize, String)>,
+}
+
+/// Convert commands annotated in a script into a commands file. Annotations
+/// that don't produce a valid command are skipped.
+pub fn script_to_commands_toml(text: &str) -> Result<ScriptImport> {
+    let extracted = script::extract(text);
+    let mut skipped = extracted.errors;
+    let mut commands = vec![];
+
+    for def in extracted.defs {
+        // Verify that the command could be read back
+        let file = ScriptFile {
+            commands: vec![toml::Value::Table(def.def)],
+        };
+        let data = toml::ser::to_string(&file).context("Serialize imported command")?;
+        match parser::parse_str(&data) {
+            Ok(_) => commands.extend(file.commands),
+            Err(err) => skipped.push((def.line, err.to_string())),
+        }
+    }
+    skipped.sort_by_key(|(line, _)| *line);
+
+    let imported = commands.len();
+    let data =
+        toml::ser::to_string(&ScriptFile { commands }).context("Serialize imported commands")?;
+    Ok(ScriptImport {
+        data,
+        imported,
+        skipped,
+    })
+}
+
+/// Parse option listing of --help output (GNU, clap and Go flag layouts)
+pub fn parse_help(text: &str) -> HelpImport {
+    let mut imported = HelpImport::default();
+    let lines: Vec<&str> = text.lines().collect();
+
+    for (index, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_start();
+        if !trimmed.starts_with('-') {
+            continue;
+        }
+        let indent = line.len() - trimmed.len();
+
+        // Description is separated by at least two spaces or a tab
+        let (spec, description) = match split_description(trimmed) {
+            Some((spec, description)) => (spec, description.to_string()),
+            None => {
+                // Or placed on the following more indented lines
+                let description = lines[index + 1..]
+                    .iter()
+                    .take_while(|next| {
+                        let next_trimmed = next.trim_start();
+                        !next_trimmed.is_empty()
+                            && !next_trimmed.starts_with('-')
+                            && next.len() - next_trimmed.len() > indent
+                    })
+                    .map(|next| next.trim())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                (trimmed.trim_end(), description)
+            }
+        };
+
+        match parse_option_spec(spec) {
+            Ok(Some(mut flag)) => {
+                flag.description = clean_description(&description);
+                imported.flags.push(flag);
+            }
+            Ok(None) => {}
+            Err(reason) => imported
+                .skipped
+                .push(format!("{}: {}", trimmed.trim_end(), reason)),
+        }
+    }
+
+    imported
+}
+
+fn split_description(line: &str) -> Option<(&str, &str)> {
+    let gap = line.find("  ").into_iter().chain(line.find('\t')).min()?;
+    let description = line[gap..].trim();
+    if description.is_empty() {
+        None
+    } else {
+        Some((&line[..gap], description))
+    }
+}
+
+/// Parse option names and a metavar, e.g `-A, --after-context=NUM`.
+/// Returns None for flags that make no sense in a built command (help, version).
+fn parse_option_spec(spec: &str) -> Result<Option<HelpFlag>, String> {
+    let mut short = None;
+    let mut long = None;
+    let mut metavar = None;
+
+    for part in spec
+        .split(',')
+        .map(|part| part.trim())
+        .filter(|part| !part.is_empty())
+    {
+        let (name, meta) = match part.find(['=', ' ', '[', '<']) {
+            Some(i) => (&part[..i], Some(&part[i..])),
+            None => (part, None),
+        };
+        if !name.starts_with('-') {
+            return Err(format!("Unexpected '{}'", part));
+        }
+        let flag_name = name.trim_start_matches('-');
+        if flag_name.is_empty()
+            || !flag_name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!("Unsupported flag '{}'", name));
+        }
+        if name.starts_with("--") || flag_name.len() > 1 {
+            long = long.or(Some(name));
+        } else {
+            short = short.or(Some(name));
+        }
+        if let Some(meta) = meta {
+            metavar = Some(parse_metavar(meta)?);
+        }
+    }
+
+    let name = long.or(short).ok_or("No flag name")?;
+    if matches!(name, "-h" | "--help" | "-V" | "--version") {
+        return Ok(None);
+    }
+
+    let name = name.replace('_', "\\_");
+    let (template, expect) = match metavar {
+        Some((meta, joined)) => {
+            let group: String = meta
+                .to_uppercase()
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric())
+                .collect();
+            let group = if group.is_empty() {
+                "VALUE".to_string()
+            } else {
+                group
+            };
+            let separator = if joined { "=" } else { " " };
+            let template = format!("{}{}_{}_", name, separator, group);
+            (template, Some(expect_for(&group).to_string()))
+        }
+        None => (name, None),
+    };
+    Ok(Some(HelpFlag {
+        template,
+        description: String::new(),
+        expect,
+    }))
+}
+
+/// Metavar and whether it is joined with `=`
+fn parse_metavar(meta: &str) -> Result<(String, bool), String> {
+    let joined = meta.starts_with('=') || meta.starts_with("[=");
+    let value = meta
+        .trim_start_matches(['[', '=', ' '])
+        .trim_start_matches('<')
+        .trim_end_matches([']', '>', '.']);
+    if value.is_empty() || value.contains(char::is_whitespace) {
+        return Err(format!("Unsupported value '{}'", meta.trim()));
+    }
+    Ok((value.to_string(), joined))
+}
+
+/// Infer value type from the metavar
+fn expect_for(metavar: &str) -> &'static str {
+    match metavar {
+        "NUM" | "N" | "NUMBER" | "COUNT" | "INT" => "number",
+        "FILE" | "PATH" | "DIR" | "FILENAME" => "path",
+        _ => "string",
+    }
+}
+
+/// Remove characters that are used as markup in descriptions
+pub fn clean_description(description: &str) -> String {
+    description.replace(['*', '_'], "")
+}
+
+/// Convert parsed flags into a command skeleton with an OPTIONS group
+pub fn help_to_commands_toml(name: &str, flags: Vec<HelpFlag>) -> Result<String> {
+    let template = format!("{} [_OPTIONS_]", name.replace('_', "\\_"));
+    let mut builder_flags = vec![];
+    for flag in &flags {
+        let mut builder = FlagBuilder::new(&flag.template, &flag.description);
+        if let Some(expect) = &flag.expect {
+            builder = builder.expect(ValueType::parse(expect)?);
+        }
+        builder_flags.push(builder);
+    }
+    CommandBuilder::new(&template)
+        .description(&clean_description(name))
+        .flags_group("OPTIONS", builder_flags)
+        .build()
+        .context("Verify imported command")?;
+
+    let mut groups = BTreeMap::new();
+    groups.insert(
+        "OPTIONS".to_string(),
+        ImportGroup {
+            expect: None,
+            flags: Some(flags),
+        },
+    );
+    let file = ImportFile {
+        commands: vec![ImportDef {
+            template,
+            description: clean_description(name),
+            groups,
+        }],
+    };
+    toml::ser::to_string(&file).context("Serialize imported command")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASHRC: &str = r#"
+# Aliases
+alias ll='ls -alF'
+alias gs="git status"
+alias ..=cd\ ..
+alias -g G='| grep'
+alias gco='git checkout' # switch branches
+alias say='echo '\''hi'\'''
+alias bad='unclosed
+alias a=b c=d
+export PATH=$PATH:~/bin
+
+mkcd() { mkdir -p "$1" && cd "$1"; }
+function serve {
+  python3 -m http.server ${1}
+}
+deploy() {
+  make build
+  make deploy
+}
+"#;
+
+    #[test]
+    fn parse_common_styles() {
+        let imported = parse_shell(BASHRC);
+        let aliases: Vec<(&str, &str)> = imported
+            .aliases
+            .iter()
+            .map(|a| (a.name.as_ref(), a.body.as_ref()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("ll", "ls -alF"),
+                ("gs", "git status"),
+                ("..", "cd .."),
+                ("G", "| grep"),
+                ("gco", "git checkout"),
+                ("say", "echo 'hi'"),
+                ("mkcd", "mkdir -p \"$1\" && cd \"$1\""),
+                ("serve", "python3 -m http.server ${1}"),
+            ],
+            aliases
+        );
+        let lines: Vec<usize> = imported.skipped.iter().map(|(line, _)| *line).collect();
+        assert_eq!(vec![9, 10, 17], lines);
+    }
+
+    #[test]
+    fn template_with_params() {
+        assert_eq!(
+            to_template("cp \"$1\" \"${2}\""),
+            Ok((
+                "cp _ARG1_ _ARG2_".to_string(),
+                vec!["ARG1".to_string(), "ARG2".to_string()]
+            ))
+        );
+        assert!(to_template("mkdir -p \"$1\" && cd \"$1\"").is_err());
+        assert_eq!(
+            to_template("git log $@"),
+            Ok(("git log [_ARGS_]".to_string(), vec!["ARGS".to_string()]))
+        );
+        assert_eq!(
+            to_template("echo $HOME_DIR"),
+            Ok(("echo $HOME\\_DIR".to_string(), vec![]))
+        );
+        assert!(to_template("ls *.txt").is_err());
+    }
+
+    #[test]
+    fn script_toml_parses() {
+        let import =
+            script_to_commands_toml(include_str!("../tests/fixtures/runbook_partial.sh")).unwrap();
+        assert_eq!(1, import.imported);
+        let lines: Vec<usize> = import.skipped.iter().map(|(line, _)| *line).collect();
+        assert_eq!(vec![3, 8, 12, 15, 18, 22], lines);
+
+        let commands = parser::parse_str(&import.data).unwrap();
+        assert_eq!("curl -fsS https://_HOST_/health", commands[0].template);
+        assert_eq!("Check the health of a host", commands[0].description);
+    }
+
+    #[test]
+    fn commands_toml_parses() {
+        let aliases = vec![
+            Alias {
+                name: "ll".into(),
+                body: "ls -alF".into(),
+            },
+            Alias {
+                name: "serve".into(),
+                body: "python3 -m http.server ${1}".into(),
+            },
+            Alias {
+                name: "txt".into(),
+                body: "ls *.txt".into(),
+            },
+        ];
+        let (data, skipped) = to_commands_toml(&aliases).unwrap();
+        assert_eq!(1, skipped.len());
+        assert_eq!("txt", skipped[0].0);
+
+        let commands = parser::parse_str(&data).unwrap();
+        assert_eq!(2, commands.len());
+        assert_eq!("ll", commands[0].description);
+        assert_eq!("python3 -m http.server _ARG1_", commands[1].template);
+    }
+
+    const GNU_HELP: &str = "\
+Usage: grep [OPTION]... PATTERNS [FILE]...
+Search for PATTERNS in each FILE.
+
+Pattern selection and interpretation:
+  -E, --extended-regexp     PATTERNS are extended regular expressions
+  -i, --ignore-case         ignore case distinctions in patterns and data
+  -m, --max-count=NUM       stop after NUM selected lines
+      --color[=WHEN]        use markers to highlight the matching strings
+  -f, --file=FILE           take PATTERNS from FILE
+      --help                display this help text and exit
+  -@@, --weird              broken
+";
+
+    const CLAP_HELP: &str = "\
+ripgrep 13.0.0
+
+USAGE:
+    rg [OPTIONS] PATTERN [PATH ...]
+
+OPTIONS:
+    -A, --after-context <NUM>
+            Show NUM lines after each match.
+
+            This overrides the --context flag.
+
+    -g, --glob <GLOB>...
+            Include or exclude files and directories for searching that match
+            the given glob.
+
+    -S, --smart-case
+            Searches case insensitively if the pattern is all lowercase.
+
+    -V, --version
+            Prints version information
+";
+
+    const GO_HELP: &str = "\
+Usage of app:
+  -addr string
+    \tlisten address (default \":8080\")
+  -max_conns int
+    \tmaximum number of connections
+  -v\tverbose output
+";
+
+    #[test]
+    fn help_gnu() {
+        let imported = parse_help(GNU_HELP);
+        assert_eq!(
+            imported.flags,
+            vec![
+                HelpFlag {
+                    template: "--extended-regexp".into(),
+                    description: "PATTERNS are extended regular expressions".into(),
+                    expect: None,
+                },
+                HelpFlag {
+                    template: "--ignore-case".into(),
+                    description: "ignore case distinctions in patterns and data".into(),
+                    expect: None,
+                },
+                HelpFlag {
+                    template: "--max-count=_NUM_".into(),
+                    description: "stop after NUM selected lines".into(),
+                    expect: Some("number".into()),
+                },
+                HelpFlag {
+                    template: "--color=_WHEN_".into(),
+                    description: "use markers to highlight the matching strings".into(),
+                    expect: Some("string".into()),
+                },
+                HelpFlag {
+                    template: "--file=_FILE_".into(),
+                    description: "take PATTERNS from FILE".into(),
+                    expect: Some("path".into()),
+                },
+            ]
+        );
+        assert_eq!(imported.skipped.len(), 1);
+    }
+
+    #[test]
+    fn help_clap() {
+        let imported = parse_help(CLAP_HELP);
+        assert_eq!(
+            imported.flags,
+            vec![
+                HelpFlag {
+                    template: "--after-context _NUM_".into(),
+                    description: "Show NUM lines after each match.".into(),
+                    expect: Some("number".into()),
+                },
+                HelpFlag {
+                    template: "--glob _GLOB_".into(),
+                    description: "Include or exclude files and directories for searching that match the given glob.".into(),
+                    expect: Some("string".into()),
+                },
+                HelpFlag {
+                    template: "--smart-case".into(),
+                    description: "Searches case insensitively if the pattern is all lowercase.".into(),
+                    expect: None,
+                },
+            ]
+        );
+        assert!(imported.skipped.is_empty());
+    }
+
+    #[test]
+    fn help_go() {
+        let imported = parse_help(GO_HELP);
+        assert_eq!(
+            imported.flags,
+            vec![
+                HelpFlag {
+                    template: "-addr _STRING_".into(),
+                    description: "listen address (default \":8080\")".into(),
+                    expect: Some("string".into()),
+                },
+                HelpFlag {
+                    template: "-max\\_conns _INT_".into(),
+                    description: "maximum number of connections".into(),
+                    expect: Some("number".into()),
+                },
+                HelpFlag {
+                    template: "-v".into(),
+                    description: "verbose output".into(),
+                    expect: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn help_toml_parses() {
+        let imported = parse_help(CLAP_HELP);
+        let data = help_to_commands_toml("rg", imported.flags).unwrap();
+        let commands = parser::parse_str(&data).unwrap();
+        assert_eq!(commands[0].template, "rg [_OPTIONS_]");
+    }
+}
@@ -1,159 +1,4706 @@
-use std::io::Write;
-use std::{collections::HashMap, io::stdout};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{borrow::Cow, cmp::Ordering};
 
 use anyhow::{anyhow, Context, Result};
-use termion::raw::IntoRawMode;
+use termion::event::Event;
+use termion::{clear, cursor, style};
 
+mod builder;
+mod clipboard;
 mod cmd;
+mod dialect;
+mod error;
+mod export;
+mod history;
+mod hook;
+mod import;
+mod menu;
+mod messages;
+mod palette;
 mod parser;
+mod paths;
+mod presets;
+mod replay;
+mod script;
+mod settings_file;
+mod subprocess;
+mod suggest;
+mod suggest_defs;
+mod tty;
+mod usage;
 mod view;
 
 use cmd::*;
-use view::{fmt_text, Choice, FixedComplete};
+use error::SnovaError;
+use history::History;
+use menu::FinalAction;
+use messages::Messages;
+use palette::{Background, Palette};
+use paths::Paths;
+use presets::{PresetValues, Presets};
+use settings_file::SettingsFile;
+use usage::Usage;
+use view::{strip_markup, CaseMatching, Choice, ChoiceStyle, FixedComplete};
+
+/// Exit code when user cancelled or terminated the prompts
+const EXIT_CANCELLED: i32 = 130;
 
 fn main() {
-    match build_cmd() {
-        Ok(Some(cmd)) => {
-            println!("{}", cmd);
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => exit_with_error(err, &Messages::default()),
+    };
+    let mut paths = Paths::from_env(args.config.clone());
+    if args.verbose {
+        eprintln!("Config dir: {}", display_dir(&paths.config_dir));
+        eprintln!("Data dir: {}", display_dir(&paths.data_dir));
+    }
+    let SettingsFile {
+        messages,
+        post_build,
+        default_action,
+        script_dirs,
+        escalation,
+    } = match SettingsFile::load(paths.settings_file().as_deref()) {
+        Ok(file) => file,
+        Err(err) => exit_with_error(err, &Messages::default()),
+    };
+    paths.script_dirs = script_dirs;
+    let escalate = escalation_prefix(escalation.as_deref(), is_root);
+
+    if args.list {
+        let rest: Vec<&str> = args.command.iter().map(String::as_str).collect();
+        if let Err(err) = list_commands(&rest, &paths, &messages) {
+            exit_with_error(err, &messages);
+        }
+        return;
+    }
+
+    if args.last || args.history {
+        let printed = if args.last {
+            print_last(&paths, &messages)
+        } else {
+            pick_from_history(&paths, &messages)
+        };
+        if let Err(err) = printed {
+            exit_with_error(err, &messages);
+        }
+        return;
+    }
+
+    if !args.command.is_empty() {
+        if let Err(err) = run_subcommand(&args.command, &paths, &messages, escalate.as_deref()) {
+            exit_with_error(err, &messages);
+        }
+        return;
+    }
+
+    let replay = match &args.replay {
+        Some(file) => match read_replay(file, args.replay_speed) {
+            Ok(replay) => Some(replay),
+            Err(err) => exit_with_error(err, &messages),
+        },
+        None => None,
+    };
+    let menu = Some(default_action).filter(|_| !args.no_menu && !args.execute && !args.edit);
+    match build_cmd(
+        &paths,
+        &messages,
+        menu,
+        args.edit,
+        replay,
+        escalate.as_deref(),
+    ) {
+        Ok(Some((built, action))) => {
+            let action = if args.execute {
+                FinalAction::Run
+            } else {
+                action
+            };
+            if args.show_replay || args.verbose {
+                eprintln!("{}", replay_line(&built));
+            }
+            if let Some(name) = &args.save_preset {
+                if let Err(err) = save_preset(&paths, &built, name, &messages) {
+                    eprintln!("{} {:?}", messages.get("failed"), err);
+                }
+            }
+            let output = if args.json {
+                json_output(&built)
+            } else {
+                Ok(built.command.clone())
+            };
+            let cmd = built.command;
+            remember_command(&paths, &cmd);
+            let written = match &args.out {
+                Some(OutTarget::File(path)) => {
+                    write_atomic(path, &format!("{}\n", cmd), args.mkdirs)
+                }
+                _ => Ok(()),
+            };
+            let copy = action == FinalAction::Copy || args.copy;
+            let copied = if copy { clipboard::copy(&cmd) } else { Ok(()) };
+            match &copied {
+                Ok(()) if copy => eprintln!("{}", messages.get("copied")),
+                Ok(()) => {}
+                Err(err) => eprintln!(
+                    "{}",
+                    messages.format("copy_failed", &[("reason", &err.to_string())])
+                ),
+            }
+            let printed = action == FinalAction::Print
+                && (!args.quiet || !matches!(args.out, Some(OutTarget::File(_))));
+            // Command is never lost when it couldn't be written or copied
+            if printed || written.is_err() || copied.is_err() {
+                let output = output.unwrap_or_else(|err| {
+                    eprintln!("{} {:?}", messages.get("failed"), err);
+                    cmd.clone()
+                });
+                print!("{}{}", output, args.terminator());
+                let _ = std::io::stdout().flush();
+            }
+            // Command's own hook replaces the global one
+            if let Some(hook) = built.post_build.as_ref().or(post_build.as_ref()) {
+                let env = [
+                    ("SNOVA_TEMPLATE", built.template.as_str()),
+                    ("SNOVA_DESCRIPTION", built.description.as_str()),
+                ];
+                if let Err(err) = hook::post_build(hook, &cmd, &env, hook::HOOK_TIMEOUT) {
+                    eprintln!(
+                        "{}",
+                        messages.format("hook_failed", &[("reason", &err.to_string())])
+                    );
+                }
+            }
+            if let Err(err) = written {
+                exit_with_error(err, &messages);
+            }
+            if action == FinalAction::Run {
+                // Terminal is restored once keys are read, so the command
+                // could be interactive
+                match std::process::Command::new(user_shell())
+                    .arg("-c")
+                    .arg(&cmd)
+                    .status()
+                {
+                    // Killed by a signal when there is no code
+                    Ok(status) => std::process::exit(status.code().unwrap_or(1)),
+                    Err(err) => exit_with_error(err.into(), &messages),
+                }
+            }
+        }
+        Ok(None) => exit_with_error(SnovaError::Cancelled.into(), &messages),
+        Err(err) => exit_with_error(err, &messages),
+    }
+}
+
+/// Report the error and exit with 1, or with EXIT_CANCELLED when user
+/// cancelled. Cancelling is not reported as a failure.
+fn exit_with_error(err: anyhow::Error, messages: &Messages) -> ! {
+    match err.downcast_ref::<SnovaError>() {
+        Some(SnovaError::Cancelled) | Some(SnovaError::Interrupted) => {
+            eprintln!("{}", messages.get("nothing_selected"));
+            std::process::exit(EXIT_CANCELLED)
+        }
+        _ => {
+            eprintln!("{} {:?}", messages.get("failed"), err);
+            std::process::exit(1)
+        }
+    }
+}
+
+/// Command line arguments
+#[derive(Debug, Default, PartialEq)]
+struct Args {
+    /// Config directory (--config <dir>)
+    config: Option<PathBuf>,
+    /// Print resolved paths (--verbose)
+    verbose: bool,
+    /// Where to write the command besides stdout (--out <file|->)
+    out: Option<OutTarget>,
+    /// Don't print the command to stdout when it is written to a file (--quiet)
+    quiet: bool,
+    /// Create parent directories of the --out file (--mkdirs)
+    mkdirs: bool,
+    /// Print the non-interactive invocation to stderr (--show-replay)
+    show_replay: bool,
+    /// Save submitted values as a named preset (--save-preset <name>)
+    save_preset: Option<String>,
+    /// Print the command without showing the final menu (--no-menu)
+    no_menu: bool,
+    /// Run the command instead of printing it (-x, --execute)
+    execute: bool,
+    /// Copy the command to the clipboard besides printing it (--copy)
+    copy: bool,
+    /// Edit the command once it is built instead of showing the menu (--edit)
+    edit: bool,
+    /// End the printed command with a NUL byte instead of a newline (--print0)
+    print0: bool,
+    /// Print the command without a trailing newline (-n, --no-newline)
+    no_newline: bool,
+    /// Print the command with its words and values as JSON (--format json)
+    json: bool,
+    /// Print all commands like `snova list` (--list)
+    list: bool,
+    /// Print the last built command (--last)
+    last: bool,
+    /// Pick a built command to print again (--history)
+    history: bool,
+    /// Read keys from a file instead of the keyboard (--replay <file>)
+    replay: Option<PathBuf>,
+    /// Multiplier of the replay speed (--replay-speed <n>)
+    replay_speed: Option<f64>,
+    /// Subcommand with its arguments
+    command: Vec<String>,
+}
+
+impl Args {
+    /// Global flags come before the subcommand. Arguments from the first
+    /// subcommand word (or after `--`) are left to the subcommand.
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut parsed = Self::default();
+        while let Some(arg) = args.next() {
+            match arg.as_ref() {
+                "--" => {
+                    parsed.command.extend(args);
+                    break;
+                }
+                "--config" => {
+                    let dir = args
+                        .next()
+                        .ok_or_else(|| anyhow!("--config expects a dir"))?;
+                    parsed.config = Some(dir.into());
+                }
+                "--verbose" => parsed.verbose = true,
+                "--out" => {
+                    let out = args
+                        .next()
+                        .ok_or_else(|| anyhow!("--out expects a file or -"))?;
+                    parsed.out = Some(if out == "-" {
+                        OutTarget::Stdout
+                    } else {
+                        OutTarget::File(out.into())
+                    });
+                }
+                "--quiet" => parsed.quiet = true,
+                "--mkdirs" => parsed.mkdirs = true,
+                "--show-replay" => parsed.show_replay = true,
+                "--no-menu" => parsed.no_menu = true,
+                "-x" | "--execute" => parsed.execute = true,
+                "--copy" => parsed.copy = true,
+                "--edit" => parsed.edit = true,
+                "--print0" => parsed.print0 = true,
+                "-n" | "--no-newline" => parsed.no_newline = true,
+                "--format" => match args.next().as_deref() {
+                    Some("json") => parsed.json = true,
+                    _ => return Err(anyhow!("--format expects json")),
+                },
+                "--list" => parsed.list = true,
+                "--last" => parsed.last = true,
+                "--history" => parsed.history = true,
+                "--replay" => {
+                    let file = args
+                        .next()
+                        .ok_or_else(|| anyhow!("--replay expects a file"))?;
+                    parsed.replay = Some(file.into());
+                }
+                "--replay-speed" => {
+                    let speed = args
+                        .next()
+                        .and_then(|speed| speed.parse::<f64>().ok())
+                        .filter(|speed| speed.is_finite() && *speed > 0.0)
+                        .ok_or_else(|| anyhow!("--replay-speed expects a positive number"))?;
+                    parsed.replay_speed = Some(speed);
+                }
+                "--save-preset" => {
+                    let name = args
+                        .next()
+                        .ok_or_else(|| anyhow!("--save-preset expects a name"))?;
+                    parsed.save_preset = Some(name);
+                }
+                _ => {
+                    parsed.command.push(arg);
+                    parsed.command.extend(args);
+                    break;
+                }
+            }
+        }
+        if parsed.replay_speed.is_some() && parsed.replay.is_none() {
+            return Err(anyhow!("--replay-speed is used without --replay"));
+        }
+        if parsed.print0 && parsed.execute {
+            return Err(anyhow!("--print0 can't be used with --execute"));
+        }
+        if parsed.print0 && parsed.no_newline {
+            return Err(anyhow!("--print0 can't be used with --no-newline"));
+        }
+        Ok(parsed)
+    }
+
+    /// What ends the printed command
+    fn terminator(&self) -> &'static str {
+        if self.print0 {
+            "\0"
+        } else if self.no_newline {
+            ""
+        } else {
+            "\n"
+        }
+    }
+}
+
+/// Where the command is written
+#[derive(Debug, PartialEq)]
+enum OutTarget {
+    Stdout,
+    File(PathBuf),
+}
+
+/// Replace the file contents at once: data is written into a temporary file
+/// next to it which is then renamed.
+fn write_atomic(path: &Path, data: &str, mkdirs: bool) -> Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if mkdirs {
+        std::fs::create_dir_all(dir).context(format!("Create {}", dir.display()))?;
+    }
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("{} is not a file", path.display()))?;
+    let temp = dir.join(format!(
+        ".{}.{}.tmp",
+        name.to_string_lossy(),
+        std::process::id()
+    ));
+    std::fs::write(&temp, data).context(format!("Write {}", temp.display()))?;
+    if let Err(err) = std::fs::rename(&temp, path) {
+        let _ = std::fs::remove_file(&temp);
+        return Err(err).context(format!("Rename {} to {}", temp.display(), path.display()));
+    }
+    Ok(())
+}
+
+/// Shell that runs built commands: $SHELL or sh
+fn user_shell() -> String {
+    std::env::var("SHELL")
+        .ok()
+        .filter(|shell| !shell.is_empty())
+        .unwrap_or_else(|| "sh".to_string())
+}
+
+fn display_dir(dir: &Option<PathBuf>) -> String {
+    match dir {
+        Some(dir) => dir.display().to_string(),
+        None => "(not found)".to_string(),
+    }
+}
+
+/// Run a subcommand given in arguments
+fn run_subcommand(
+    args: &[String],
+    paths: &Paths,
+    messages: &Messages,
+    escalate: Option<&str>,
+) -> Result<()> {
+    let args: Vec<&str> = args.iter().map(|a| a.as_ref()).collect();
+    match args.as_slice() {
+        ["history", "clear-values"] => {
+            if let Some(dir) = &paths.data_dir {
+                History::clear(dir)?;
+            }
+            println!("{}", messages.get("history_cleared"));
+            Ok(())
+        }
+        ["import", "aliases", files @ ..] => import_aliases(files, paths),
+        ["import", "help", program] => import_help(program, paths),
+        ["import", "script", file] => import_script(file, paths),
+        ["check"] => check(paths, false, messages),
+        ["list", rest @ ..] => list_commands(rest, paths, messages),
+        ["export", rest @ ..] => export_commands(rest, paths, messages),
+        ["check", "--strict"] => check(paths, true, messages),
+        ["run", name, rest @ ..] => {
+            run_command(name, RunArgs::parse(rest)?, paths, messages, escalate)
+        }
+        ["fill", name, rest @ ..] => {
+            run_command(name, RunArgs::assignments(rest)?, paths, messages, escalate)
+        }
+        ["suggest-defs", rest @ ..] => suggest_defs(rest, paths, messages),
+        ["preset", "list"] => list_presets(paths),
+        ["preset", "rm", cmd, name] => remove_preset(cmd, name, paths, messages),
+        _ => Err(anyhow!("Unknown command '{}'", args.join(" "))),
+    }
+}
+
+/// Load all commands and print template warnings. Strict mode fails on any warning.
+fn check(paths: &Paths, strict: bool, messages: &Messages) -> Result<()> {
+    let settings = Settings::from_env(messages.clone())?;
+    let cwd = std::env::current_dir().ok();
+    let commands = parser::read_all(paths, cwd.as_deref(), settings.lenient)?;
+    let mut warnings = 0;
+    for cmd in &commands {
+        let lints = parser::lint_template(&cmd.template);
+        for warning in cmd.warnings.iter().chain(&lints) {
+            eprintln!("{}: {}", cmd.template, warning);
+            warnings += 1;
+        }
+    }
+    println!(
+        "{}",
+        messages.format(
+            "checked",
+            &[
+                ("commands", &commands.len().to_string()),
+                ("warnings", &warnings.to_string())
+            ]
+        )
+    );
+    if strict && warnings > 0 {
+        return Err(anyhow!("Found {} warnings", warnings));
+    }
+    Ok(())
+}
+
+/// Print all commands. Accepts `--examples` to include examples,
+/// `--markdown` to print a Markdown document (examples are always included)
+/// and `--source <kind>` to print only commands of the source.
+fn list_commands(args: &[&str], paths: &Paths, messages: &Messages) -> Result<()> {
+    let mut examples = false;
+    let mut markdown = false;
+    let mut source = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--examples" => examples = true,
+            "--markdown" => markdown = true,
+            "--source" => source = Some(source_kind(args.next())?),
+            _ => return Err(anyhow!("Unknown argument '{}'", arg)),
+        }
+    }
+    let commands = read_from_source(paths, messages, source)?;
+    if markdown {
+        print!("{}", markdown_list(&commands));
+    } else {
+        let palette = if termion::is_tty(&std::io::stdout()) {
+            Some(palette_from_env()?)
+        } else {
+            None
+        };
+        print!("{}", plain_list(&commands, examples, palette));
+    }
+    Ok(())
+}
+
+/// Print all commands with their groups and flags as JSON. Accepts
+/// `--format json` and `--source <kind>` to print only commands of the source.
+fn export_commands(args: &[&str], paths: &Paths, messages: &Messages) -> Result<()> {
+    let mut source = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match (*arg, args.clone().next()) {
+            ("--format", Some(&"json")) => {
+                args.next();
+            }
+            ("--source", _) => source = Some(source_kind(args.next())?),
+            _ => return Err(anyhow!("Unknown argument '{}'", arg)),
+        }
+    }
+    let commands = read_from_source(paths, messages, source)?;
+    println!("{}", export::to_json(&commands)?);
+    Ok(())
+}
+
+/// Value of `--source`: one of the source kinds
+fn source_kind(value: Option<&&str>) -> Result<&'static str> {
+    let kinds = CommandSource::KINDS;
+    let value = value.ok_or_else(|| anyhow!("--source expects one of {}", kinds.join(", ")))?;
+    kinds
+        .iter()
+        .find(|kind| *kind == value)
+        .copied()
+        .ok_or_else(|| {
+            anyhow!(
+                "Unknown source '{}', expected one of {}",
+                value,
+                kinds.join(", ")
+            )
+        })
+}
+
+/// Read all commands, or only the commands of the source kind
+fn read_from_source(
+    paths: &Paths,
+    messages: &Messages,
+    source: Option<&str>,
+) -> Result<Vec<Command>> {
+    let settings = Settings::from_env(messages.clone())?;
+    let cwd = std::env::current_dir().ok();
+    let mut commands = parser::read_all(paths, cwd.as_deref(), settings.lenient)?;
+    if let Some(source) = source {
+        commands.retain(|cmd| cmd.source.kind() == source);
+    }
+    Ok(commands)
+}
+
+/// Commands with their templates below the descriptions. Markup is rendered
+/// with the palette and removed without one.
+fn plain_list(commands: &[Command], examples: bool, palette: Option<Palette>) -> String {
+    let text = |text: &str| match palette {
+        Some(palette) => palette.fmt_text(text),
+        None => strip_markup(text),
+    };
+    let mut out = String::new();
+    for cmd in commands {
+        let source = match cmd.source.path() {
+            Some(path) => format!("{} {}", cmd.source.kind(), path.display()),
+            None => cmd.source.kind().to_string(),
+        };
+        out.push_str(&format!(
+            "{} [{}]\n  {}\n",
+            text(&cmd.description),
+            source,
+            text(&cmd.template)
+        ));
+        for example in cmd.examples.iter().filter(|_| examples) {
+            out.push_str(&format!(
+                "    {}: {}\n",
+                example.description, example.command
+            ));
+        }
+    }
+    out
+}
+
+/// Commands as Markdown sections
+fn markdown_list(commands: &[Command]) -> String {
+    let mut out = String::from("# Commands\n");
+    for cmd in commands {
+        out.push_str(&format!(
+            "\n## {}\n\n`{}`\n",
+            strip_markup(&cmd.description),
+            strip_markup(&cmd.template)
+        ));
+        if let Some(long_description) = &cmd.long_description {
+            out.push_str(&format!("\n{}\n", long_description));
         }
-        Ok(None) => {
-            println!("Nothing selected.");
+        if !cmd.examples.is_empty() {
+            out.push_str("\nExamples:\n\n");
         }
-        Err(err) => {
-            eprintln!("Failed: {:?}", err);
+        for example in &cmd.examples {
+            out.push_str(&format!(
+                "- {}: `{}`\n",
+                example.description, example.command
+            ));
         }
     }
+    out
 }
 
-/// Build command and return the result
-fn build_cmd() -> Result<Option<String>> {
-    let commands = parser::read_all()?;
-    let mut stdout = stdout().into_raw_mode()?;
+/// Print definitions for commands that are often run from the shell but are not defined.
+/// Accepts `--file <history>` and `--top <n>`.
+fn suggest_defs(args: &[&str], paths: &Paths, messages: &Messages) -> Result<()> {
+    let mut file = None;
+    let mut top = 10;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--file" => {
+                let path = args.next().ok_or_else(|| anyhow!("Missing --file value"))?;
+                file = Some(PathBuf::from(path));
+            }
+            "--top" => {
+                let n = args.next().ok_or_else(|| anyhow!("Missing --top value"))?;
+                top = n.parse().context(format!("Invalid --top value '{}'", n))?;
+            }
+            _ => return Err(anyhow!("Unknown argument '{}'", arg)),
+        }
+    }
+    let file = match file.or_else(suggest_defs::history_file) {
+        Some(file) => file,
+        None => return Err(anyhow!("Shell history not found, pass --file")),
+    };
+    let data = std::fs::read(&file).context(format!("Read {}", file.display()))?;
+    let history = suggest_defs::parse_history(&String::from_utf8_lossy(&data));
+
+    let settings = Settings::from_env(messages.clone())?;
+    let cwd = std::env::current_dir().ok();
+    let commands = parser::read_all(paths, cwd.as_deref(), settings.lenient)?;
+    let suggestions = suggest_defs::suggest(&history, &commands, top);
+    if suggestions.is_empty() {
+        eprintln!(
+            "No frequent commands without a definition in {}",
+            file.display()
+        );
+    }
+    for suggestion in &suggestions {
+        println!(
+            "# Run {} times\n{}",
+            suggestion.count,
+            suggestion.to_toml()?
+        );
+    }
+    Ok(())
+}
 
-    let cmd = view::Readline::new(&mut stdout)
-        .help("Pick a command:")
-        .choice(FixedComplete::new(&commands))
-        .context("Pick command")?;
+/// Build the command found by its template or a part of its description from
+/// values given with `--set NAME=VALUE` (or `NAME=VALUE` of `snova fill`) and
+/// print it. With `--preset <name>` the prompts are pre-filled with the preset
+/// values instead.
+fn run_command(
+    name: &str,
+    run: RunArgs,
+    paths: &Paths,
+    messages: &Messages,
+    escalate: Option<&str>,
+) -> Result<()> {
+    let mut settings = Settings::from_env(messages.clone())?;
+    settings.escalate = escalate.map(String::from);
+    let cwd = std::env::current_dir().ok();
+    let commands = parser::read_all(paths, cwd.as_deref(), settings.lenient)?;
+    let cmd = match_command(&commands, name)?;
 
-    let cmd = match cmd {
-        Some(c) => c,
+    let preset = match &run.preset {
+        Some(preset) => preset,
         None => {
-            return Ok(None);
+            let command = escalated(cmd, &settings, fill_from_values(cmd, &run.values)?);
+            remember_command(paths, &command);
+            println!("{}", command);
+            return Ok(());
         }
     };
+    let dir = paths
+        .data_dir
+        .as_ref()
+        .ok_or_else(|| anyhow!("Data directory not found"))?;
+    let mut prefill = Presets::open(dir)?
+        .get(&cmd.template, preset)
+        .cloned()
+        .ok_or_else(|| anyhow!("Preset '{}' not found for '{}'", preset, cmd.template))?;
+    prefill.extend(run.values);
 
-    writeln!(&mut stdout, "Command: {}\r", fmt_text(&cmd.template))?;
-    let mut user_input = HashMap::new();
+    let input = tty::Input::open(settings.tty)?;
+    let mut ui = input.ui()?;
+    let mut events = input.events()?;
+    let mut history = History::open(dir)?;
+    write_command(&mut ui, cmd, &settings)?;
+    let built = match fill_and_confirm(&mut ui, &mut events, cmd, &settings, &mut history, &prefill)
+    {
+        // There is no picker to go back to
+        Err(SnovaError::Restart) => None,
+        built => built?,
+    };
+    history.save()?;
+    match built {
+        Some(built) => {
+            remember_command(paths, &built.command);
+            println!("{}", built.command);
+        }
+        None => return Err(SnovaError::Cancelled.into()),
+    }
+    Ok(())
+}
 
-    for group in &cmd.groups {
-        match &group.expect {
-            GroupValue::Single(expect_type) => {
-                let prefix = format!("{}:", group.name);
-                let mut readline = view::Readline::new(&mut stdout)
-                    .prefix(&prefix)
-                    .expect(expect_type.clone());
-                let value = match &group.suggest {
-                    Some(suggest) => {
-                        // Return either a choice or user input
-                        let (choice, user_input) =
-                            readline.suggest(FixedComplete::new(&suggest))?;
-                        choice.map(|c| c.clone()).unwrap_or(user_input)
-                    }
-                    None => readline.line()?,
-                };
+/// Add the built command to the commands history. Failures are ignored, e.g
+/// a read-only data directory doesn't prevent printing the command.
+fn remember_command(paths: &Paths, command: &str) {
+    if let Some(dir) = &paths.data_dir {
+        let _ = history::add_command(dir, command, unix_now());
+    }
+}
+
+/// Seconds since the UNIX epoch
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default()
+}
+
+/// Built commands, most recent first and without repeats
+fn built_commands(paths: &Paths) -> Result<Vec<String>> {
+    let entries = match &paths.data_dir {
+        Some(dir) => history::commands(dir)?,
+        None => vec![],
+    };
+    let mut commands: Vec<String> = vec![];
+    for entry in entries.into_iter().rev() {
+        if !commands.contains(&entry.command) {
+            commands.push(entry.command);
+        }
+    }
+    Ok(commands)
+}
+
+/// Print the last built command
+fn print_last(paths: &Paths, messages: &Messages) -> Result<()> {
+    let commands = built_commands(paths)?;
+    let last = commands
+        .first()
+        .ok_or_else(|| anyhow!("{}", messages.get("history_empty")))?;
+    println!("{}", last);
+    Ok(())
+}
 
-                if value.is_empty() {
-                    return Err(anyhow!("No value for {} group", group.name));
+/// Let user pick one of the built commands and print it
+fn pick_from_history(paths: &Paths, messages: &Messages) -> Result<()> {
+    let commands = built_commands(paths)?;
+    if commands.is_empty() {
+        return Err(anyhow!("{}", messages.get("history_empty")));
+    }
+    let settings = Settings::from_env(messages.clone())?;
+    let input = tty::Input::open(settings.tty)?;
+    let mut ui = input.ui()?;
+    let mut events = input.events()?;
+    let picked = readline(&mut ui, &mut events, &settings)
+        .help(messages.get("pick_history"))
+        .choice(FixedComplete::new(&commands).case(settings.case))?;
+    match picked {
+        Some(command) => println!("{}", command),
+        None => return Err(SnovaError::Cancelled.into()),
+    }
+    Ok(())
+}
+
+/// Find the command by its template or description, or by the only
+/// description that contains the text
+fn match_command<'c>(commands: &'c [Command], text: &str) -> Result<&'c Command> {
+    find_command(commands, text).or_else(|err| {
+        let text = text.to_lowercase();
+        let found: Vec<&Command> = commands
+            .iter()
+            .filter(|cmd| {
+                strip_markup(&cmd.description)
+                    .to_lowercase()
+                    .contains(&text)
+            })
+            .collect();
+        match found.as_slice() {
+            [cmd] => Ok(*cmd),
+            [] => Err(err),
+            found => Err(anyhow!(
+                "Several commands match '{}': {}",
+                text,
+                found
+                    .iter()
+                    .map(|cmd| cmd.template.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    })
+}
+
+/// Arguments of `snova run`
+#[derive(Debug, Default, PartialEq)]
+struct RunArgs {
+    /// Group values (--set NAME=VALUE)
+    values: PresetValues,
+    /// Preset to pre-fill the prompts with (--preset <name>)
+    preset: Option<String>,
+}
+
+impl RunArgs {
+    fn parse(args: &[&str]) -> Result<Self> {
+        let mut parsed = Self::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match *arg {
+                "--set" => {
+                    let set = args
+                        .next()
+                        .ok_or_else(|| anyhow!("--set expects NAME=VALUE"))?;
+                    parsed.set(set)?;
+                }
+                "--preset" => {
+                    let name = args
+                        .next()
+                        .ok_or_else(|| anyhow!("--preset expects a name"))?;
+                    parsed.preset = Some(name.to_string());
                 }
-                user_input.insert(group.name.clone(), value);
+                _ => return Err(anyhow!("Unknown argument '{}'", arg)),
             }
-            GroupValue::Flags(flags) => {
-                let mut used_flags = vec![];
-                let mut combined = vec![];
-                user_input.insert(group.name.clone(), combined.join(" "));
+        }
+        Ok(parsed)
+    }
 
-                loop {
-                    let available_flags: Vec<_> = flags
-                        .iter()
-                        .filter(|flag| !used_flags.contains(flag))
-                        .collect();
-                    let flag = view::Readline::new(&mut stdout)
-                        .help((cmd.build)(&user_input))
-                        .choice(FixedComplete::new(&available_flags))
-                        .context("Pick a flag")?
-                        .cloned();
-
-                    match flag {
-                        Some(flag) => {
-                            // Remember that this flag was asked
-                            if !flag.multiple {
-                                used_flags.push(flag);
-                            }
+    /// Values of `NAME=VALUE` arguments
+    fn assignments(args: &[&str]) -> Result<Self> {
+        let mut parsed = Self::default();
+        for arg in args {
+            parsed.set(arg)?;
+        }
+        Ok(parsed)
+    }
 
-                            match &flag.expect {
-                                // Ask for input
-                                Some(expect) => match expect.value_type {
-                                    ValueType::String | ValueType::Path | ValueType::Number => {
-                                        let prefix = format!("{}:", flag.template);
-                                        let mut readline = view::Readline::new(&mut stdout)
-                                            .prefix(&prefix)
-                                            .help(&flag.description)
-                                            .expect(expect.value_type.clone());
-
-                                        let value = match &flag.suggest {
-                                            Some(suggest) => {
-                                                // Return either a choice or user input
-                                                let (choice, user_input) = readline
-                                                    .suggest(FixedComplete::new(&suggest))?;
-                                                choice.map(|c| c.clone()).unwrap_or(user_input)
-                                            }
-                                            None => readline.line()?,
-                                        };
-
-                                        if value.is_empty() {
-                                            return Err(anyhow!(
-                                                "No value for {} flag",
-                                                flag.template
-                                            ));
-                                        }
-                                        let result = (expect.build)(&value);
-                                        combined.push(result.clone());
-                                    }
-                                },
-                                // Save flag
-                                None => {
-                                    combined.push(flag.template.clone());
-                                }
-                            }
-                        }
-                        None => {
-                            // Nothing selected abort
-                            break;
-                        }
-                    }
+    fn set(&mut self, assignment: &str) -> Result<()> {
+        let (group, value) = assignment
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected NAME=VALUE, got '{}'", assignment))?;
+        self.values.insert(group.to_string(), value.to_string());
+        Ok(())
+    }
+}
+
+/// Save values of the built command as a named preset. Secret values are left out.
+fn save_preset(paths: &Paths, built: &BuildResult, name: &str, messages: &Messages) -> Result<()> {
+    let dir = paths
+        .data_dir
+        .as_ref()
+        .ok_or_else(|| anyhow!("Data directory not found"))?;
+    let mut presets = Presets::open(dir)?;
+    presets.set(
+        &built.template,
+        name,
+        built
+            .values
+            .iter()
+            .map(|(group, answer)| (group.clone(), answer.value()))
+            .collect(),
+    );
+    presets.save()?;
+    eprintln!("{}", messages.format("preset_saved", &[("name", name)]));
+    Ok(())
+}
+
+/// Print saved presets with their command templates
+fn list_presets(paths: &Paths) -> Result<()> {
+    let presets = match &paths.data_dir {
+        Some(dir) => Presets::open(dir)?,
+        None => Presets::default(),
+    };
+    for (template, name) in presets.list() {
+        println!("{}: {}", name, template);
+    }
+    Ok(())
+}
+
+/// Remove a preset of the command (found by description or template)
+fn remove_preset(cmd: &str, name: &str, paths: &Paths, messages: &Messages) -> Result<()> {
+    let dir = paths
+        .data_dir
+        .as_ref()
+        .ok_or_else(|| anyhow!("Data directory not found"))?;
+    let settings = Settings::from_env(messages.clone())?;
+    let cwd = std::env::current_dir().ok();
+    let commands = parser::read_all(paths, cwd.as_deref(), settings.lenient)?;
+    // Presets of removed commands are still found by their template
+    let template = find_command(&commands, cmd)
+        .map(|cmd| cmd.template.as_str())
+        .unwrap_or(cmd);
+    let mut presets = Presets::open(dir)?;
+    if !presets.remove(template, name) {
+        return Err(anyhow!("Preset '{}' not found for '{}'", name, template));
+    }
+    presets.save()?;
+    println!("{}", messages.format("preset_removed", &[("name", name)]));
+    Ok(())
+}
+
+/// Find a command by its description (without markup) or template
+fn find_command<'c>(commands: &'c [Command], name: &str) -> Result<&'c Command> {
+    let mut found = commands
+        .iter()
+        .filter(|cmd| strip_markup(&cmd.description) == name || cmd.template == name);
+    match (found.next(), found.next()) {
+        (Some(cmd), None) => Ok(cmd),
+        (Some(_), Some(_)) => Err(anyhow!(
+            "Several commands match '{}', use the template instead",
+            name
+        )),
+        (None, _) => Err(anyhow!("Command '{}' not found", name)),
+    }
+}
+
+/// Import aliases from shell files (~/.bashrc and ~/.zshrc by default) into commands.d
+fn import_aliases(files: &[&str], paths: &Paths) -> Result<()> {
+    let files: Vec<PathBuf> = if files.is_empty() {
+        let home = dirs::home_dir().ok_or_else(|| anyhow!("Home directory not found"))?;
+        vec![home.join(".bashrc"), home.join(".zshrc")]
+            .into_iter()
+            .filter(|file| file.is_file())
+            .collect()
+    } else {
+        files.iter().map(PathBuf::from).collect()
+    };
+
+    let mut aliases = vec![];
+    for file in &files {
+        let data = std::fs::read_to_string(file).context(format!("Read {}", file.display()))?;
+        let mut imported = import::parse_shell(&data);
+        for (line, reason) in imported.skipped {
+            eprintln!("Skipped {}:{}: {}", file.display(), line, reason);
+        }
+        aliases.append(&mut imported.aliases);
+    }
+
+    let (data, skipped) = import::to_commands_toml(&aliases)?;
+    for (name, reason) in &skipped {
+        eprintln!("Skipped alias '{}': {}", name, reason);
+    }
+
+    let dir = paths
+        .commands_d()
+        .ok_or_else(|| anyhow!("Config directory not found"))?;
+    let target = dir.join("aliases.toml");
+    if target.exists() {
+        return Err(anyhow!("{} already exists", target.display()));
+    }
+    std::fs::create_dir_all(&dir).context(format!("Create {}", dir.display()))?;
+    std::fs::write(&target, data).context(format!("Write {}", target.display()))?;
+    println!(
+        "Imported {} commands into {}. Please review them.",
+        aliases.len() - skipped.len(),
+        target.display()
+    );
+    Ok(())
+}
+
+/// Run `program --help` and write a command skeleton into commands.d
+fn import_help(program: &str, paths: &Paths) -> Result<()> {
+    let output = std::process::Command::new(program)
+        .arg("--help")
+        .output()
+        .context(format!("Run {} --help", program))?;
+    // Some programs print help to stderr
+    let help = if output.stdout.is_empty() {
+        String::from_utf8_lossy(&output.stderr)
+    } else {
+        String::from_utf8_lossy(&output.stdout)
+    };
+
+    let imported = import::parse_help(&help);
+    for line in &imported.skipped {
+        eprintln!("Skipped: {}", line);
+    }
+    if imported.flags.is_empty() {
+        return Err(anyhow!("No flags found in {} --help", program));
+    }
+
+    let name = std::path::Path::new(program)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(program);
+    let flags_count = imported.flags.len();
+    let data = import::help_to_commands_toml(name, imported.flags)?;
+
+    let dir = paths
+        .commands_d()
+        .ok_or_else(|| anyhow!("Config directory not found"))?;
+    let target = dir.join(format!("{}.toml", name));
+    if target.exists() {
+        return Err(anyhow!("{} already exists", target.display()));
+    }
+    std::fs::create_dir_all(&dir).context(format!("Create {}", dir.display()))?;
+    std::fs::write(&target, data).context(format!("Write {}", target.display()))?;
+    println!(
+        "Imported {} flags into {} ({} skipped). Please review it.",
+        flags_count,
+        target.display(),
+        imported.skipped.len()
+    );
+    Ok(())
+}
+
+/// Import commands annotated in a shell script into commands.d
+fn import_script(file: &str, paths: &Paths) -> Result<()> {
+    let file = Path::new(file);
+    let data = std::fs::read_to_string(file).context(format!("Read {}", file.display()))?;
+    let import::ScriptImport {
+        data,
+        imported,
+        skipped,
+    } = import::script_to_commands_toml(&data)?;
+    for (line, reason) in &skipped {
+        eprintln!("Skipped {}:{}: {}", file.display(), line, reason);
+    }
+    if imported == 0 {
+        return Err(anyhow!("No snova annotations found in {}", file.display()));
+    }
+
+    let name = file
+        .file_stem()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow!("Invalid script name {}", file.display()))?;
+    let dir = paths
+        .commands_d()
+        .ok_or_else(|| anyhow!("Config directory not found"))?;
+    let target = dir.join(format!("{}.toml", name));
+    if target.exists() {
+        return Err(anyhow!("{} already exists", target.display()));
+    }
+    std::fs::create_dir_all(&dir).context(format!("Create {}", dir.display()))?;
+    std::fs::write(&target, data).context(format!("Write {}", target.display()))?;
+    println!(
+        "Imported {} commands into {}. Please review them.",
+        imported,
+        target.display()
+    );
+    Ok(())
+}
+
+/// Source of terminal events
+type Events<'e> = dyn Iterator<Item = std::io::Result<Event>> + 'e;
+
+/// Read the keys file of --replay
+fn read_replay(file: &Path, speed: Option<f64>) -> Result<replay::Replay> {
+    let data = std::fs::read_to_string(file).context(format!("Read {}", file.display()))?;
+    let replay = replay::Replay::parse(&data).context(format!("Parse {}", file.display()))?;
+    Ok(replay.speed(speed.unwrap_or(1.0)))
+}
+
+/// Build command and return the result with the action picked in the final
+/// menu. The menu is shown only when given a default action and keys come from
+/// a terminal. The command could be edited before it is returned. Replayed
+/// keys replace the keyboard.
+fn build_cmd(
+    paths: &Paths,
+    messages: &Messages,
+    menu: Option<FinalAction>,
+    edit: bool,
+    replay: Option<replay::Replay>,
+    escalate: Option<&str>,
+) -> Result<Option<(BuildResult, FinalAction)>> {
+    let mut settings = Settings::from_env(messages.clone())?;
+    settings.escalate = escalate.map(String::from);
+    let cwd = if settings.project {
+        Some(std::env::current_dir().context("Read current directory")?)
+    } else {
+        None
+    };
+    let mut commands = parser::read_all(paths, cwd.as_deref(), settings.lenient)?;
+    let mut usage = match &paths.data_dir {
+        Some(dir) => Usage::open(dir)?,
+        None => Usage::default(),
+    };
+    usage.sort(&mut commands, unix_now());
+    let dumb = std::env::var("TERM").as_deref() == Ok("dumb");
+    let plain = settings.plain.unwrap_or_else(|| dumb && replay.is_none());
+    let input = if plain {
+        None
+    } else {
+        match tty::Input::open(settings.tty) {
+            // Terminal that can't be switched to raw mode gets plain prompts
+            Err(_) if settings.plain.is_none() && replay.is_none() => None,
+            input => Some(input?),
+        }
+    };
+    let mut input = match input {
+        Some(input) => input,
+        None => {
+            let stdin = std::io::stdin();
+            let built = match build_plain(
+                &mut std::io::stderr(),
+                &mut stdin.lock(),
+                &commands,
+                &settings,
+            ) {
+                Err(SnovaError::EmptyChoices) => return Err(no_commands(paths, messages)),
+                built => built?,
+            };
+            if let Some(built) = &built {
+                usage.add(&built.template, unix_now());
+                usage.save()?;
+            }
+            return Ok(built.map(|built| (built, FinalAction::Print)));
+        }
+    };
+    if let Some(replay) = replay {
+        input = input.replay(replay);
+    }
+    let mut ui = input.ui()?;
+    let mut events = input.events()?;
+    let mut history = match &paths.data_dir {
+        Some(dir) => History::open(dir)?,
+        None => History::default(),
+    };
+    let built = match pick_and_build(&mut ui, &mut events, &commands, &settings, &mut history) {
+        Err(SnovaError::EmptyChoices) => return Err(no_commands(paths, messages)),
+        built => built?,
+    };
+    history.save()?;
+    let mut built = match built {
+        Some(built) => built,
+        None => return Ok(None),
+    };
+    usage.add(&built.template, unix_now());
+    usage.save()?;
+    if edit {
+        edit_command(&mut ui, &mut events, &mut built, &settings)?;
+    }
+    let action = match menu {
+        Some(default) if input.is_terminal() => {
+            final_action(&mut ui, &mut events, &mut built, &settings, default)?
+        }
+        _ => FinalAction::Print,
+    };
+    Ok(Some((built, action)))
+}
+
+/// Error when there are no commands to pick from
+fn no_commands(paths: &Paths, messages: &Messages) -> anyhow::Error {
+    let file = match &paths.config_dir {
+        Some(dir) => dir.join("commands.toml").display().to_string(),
+        None => "commands.toml".to_string(),
+    };
+    anyhow!(messages.format("no_commands", &[("file", &file)]))
+}
 
-                    user_input.insert(group.name.clone(), combined.join(" "));
+/// Build the command with line prompts for terminals that can't draw the
+/// interactive ones: a command is picked by its number and values are typed
+/// in full. Flags are typed as they appear in the command.
+fn build_plain(
+    ui: &mut dyn Write,
+    input: &mut dyn BufRead,
+    commands: &[Command],
+    settings: &Settings,
+) -> error::Result<Option<BuildResult>> {
+    if commands.is_empty() {
+        return Err(SnovaError::EmptyChoices);
+    }
+    let messages = &settings.messages;
+    writeln!(ui, "{}", messages.get("pick_command"))?;
+    for (i, cmd) in commands.iter().enumerate() {
+        writeln!(ui, "{:>3}) {}", i + 1, strip_markup(&cmd.description))?;
+    }
+    let cmd = loop {
+        let line = match read_plain(ui, input, messages.get("pick_number"))? {
+            Some(line) => line,
+            None => return Ok(None),
+        };
+        let picked = line
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .and_then(|number| commands.get(number.checked_sub(1)?));
+        match picked {
+            Some(cmd) => break cmd,
+            None => writeln!(
+                ui,
+                "{}",
+                messages.format("invalid_number", &[("max", &commands.len().to_string())])
+            )?,
+        }
+    };
+    let template = strip_markup(&cmd.template);
+    writeln!(
+        ui,
+        "{}",
+        messages.format("command", &[("template", &template)])
+    )?;
 
-                    if flags.len() == used_flags.len() {
-                        break;
+    let mut values = PresetValues::new();
+    let mut answers = BTreeMap::new();
+    for group in cmd.groups.iter().filter(|group| group.computed.is_none()) {
+        if let GroupValue::Flags(flags) = &group.expect {
+            for flag in flags {
+                writeln!(
+                    ui,
+                    "  {}  {}",
+                    strip_markup(&flag.template),
+                    strip_markup(&flag.description)
+                )?;
+            }
+        }
+        let prompt = match &group.default {
+            Some(default) => format!("{} [{}]: ", group.name, default),
+            None => format!("{}: ", group.name),
+        };
+        let value = loop {
+            let value = match read_plain(ui, input, &prompt)? {
+                Some(value) => value,
+                None => return Ok(None),
+            };
+            let answer = match &group.expect {
+                GroupValue::Single(_) if value.is_empty() => None,
+                GroupValue::Single(expect) => match single_value(group, expect, &value) {
+                    Ok(_) => Some(GroupAnswer::Single(value.clone())),
+                    Err(err) => {
+                        writeln!(ui, "{}", err)?;
+                        continue;
                     }
-                }
+                },
+                GroupValue::Flags(_) => Some(GroupAnswer::Flags(vec![value.clone()])),
+            };
+            let required = matches!(group.expect, GroupValue::Single(_))
+                && !group.optional
+                && group.default.is_none();
+            if answer.is_none() && required {
+                writeln!(
+                    ui,
+                    "{}",
+                    messages.format("value_required", &[("prompt", &group.name)])
+                )?;
+                continue;
+            }
+            if let Some(answer) = answer {
+                record_answer(&mut answers, group, answer);
+            }
+            break value;
+        };
+        if !value.is_empty() {
+            values.insert(group.name.clone(), value);
+        }
+    }
+
+    let command = escalated(cmd, settings, fill_from_values(cmd, &values)?);
+    if let Some(message) = &cmd.confirm {
+        let escalation = match &settings.escalate {
+            Some(escalation) if cmd.needs_root => {
+                messages.format("confirm_escalation", &[("escalation", escalation)])
             }
+            _ => String::new(),
+        };
+        writeln!(ui, "{}{}: {}", message, escalation, command)?;
+        let word = messages.get("confirm_word");
+        let prompt = format!("{} ", messages.format("confirm", &[("word", word)]));
+        // Declined command is not built
+        if read_plain(ui, input, &prompt)?.as_deref().map(str::trim) != Some(word) {
+            return Ok(None);
         }
     }
 
-    let result = (cmd.build)(&user_input);
-    Ok(Some(result))
+    let description = strip_markup(&cmd.description);
+    Ok(Some(BuildResult {
+        command,
+        template: cmd.template.clone(),
+        name: description.clone(),
+        description,
+        values: answers,
+        post_build: cmd.post_build.clone(),
+    }))
 }
 
-impl Choice for Command {
-    fn text(&self) -> &str {
-        &self.description
+/// Print the prompt and read a line without its line ending. None once
+/// input is closed.
+fn read_plain(
+    ui: &mut dyn Write,
+    input: &mut dyn BufRead,
+    prompt: &str,
+) -> error::Result<Option<String>> {
+    write!(ui, "{}", prompt)?;
+    ui.flush()?;
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(None);
     }
+    Ok(Some(line.trim_end_matches(&['\r', '\n'][..]).to_string()))
 }
 
-impl Choice for Flag {
-    fn text(&self) -> &str {
-        &self.description
+/// Show the final menu until user picks what to do with the command.
+/// The edited command replaces the built one.
+fn final_action(
+    ui: &mut dyn Write,
+    events: &mut Events,
+    built: &mut BuildResult,
+    settings: &Settings,
+    default: FinalAction,
+) -> error::Result<FinalAction> {
+    loop {
+        match menu::pick(
+            ui,
+            events,
+            &built.command,
+            default,
+            &settings.messages,
+            settings.palette,
+        )? {
+            FinalAction::Edit => edit_command(ui, events, built, settings)?,
+            action => return Ok(action),
+        }
+    }
+}
+
+/// Let user edit the built command. Cancelled edit keeps the command.
+fn edit_command(
+    ui: &mut dyn Write,
+    events: &mut Events,
+    built: &mut BuildResult,
+    settings: &Settings,
+) -> error::Result<()> {
+    let edited = readline(ui, events, settings)
+        .help(settings.messages.get("edit_command"))
+        .initial(built.command.clone())
+        .line()?;
+    if let Some(command) = edited.filter(|command| !command.trim().is_empty()) {
+        built.command = command.trim().to_string();
+    }
+    Ok(())
+}
+
+/// Create a prompt that reads from the shared events source
+fn readline<'s>(
+    ui: &'s mut dyn Write,
+    events: &'s mut Events,
+    settings: &Settings,
+) -> view::Readline<'s> {
+    view::Readline::new(ui)
+        .footer(settings.footer)
+        .debug(settings.debug)
+        .messages(settings.messages.clone())
+        .palette(settings.palette)
+        .events(events)
+}
+
+/// A prompt for a single value
+struct ValuePrompt<'a> {
+    prefix: String,
+    help: String,
+    expect: &'a ValueType,
+    /// Range of number values
+    limits: &'a NumberLimits,
+    /// Range of string value lengths
+    length: &'a LengthLimits,
+    default: Option<&'a String>,
+    suggest: Option<&'a Vec<String>>,
+    /// Values submitted earlier in this session, most recent first. They are
+    /// suggested before the other suggestions.
+    previous: &'a [String],
+    /// Clipboard contents that are suggested first
+    clipboard: Option<String>,
+    /// Pre-filled input
+    initial: Option<String>,
+    /// Allow skipping the remaining groups
+    finish: bool,
+    /// Esc cancels the command
+    cancel_on_esc: bool,
+    /// Value could span multiple lines
+    multiline: bool,
+    /// Empty value skips the group
+    optional: bool,
+}
+
+/// Prompt until user submits a valid value. Returns None if user cancelled.
+fn read_value(
+    ui: &mut dyn Write,
+    events: &mut Events,
+    settings: &Settings,
+    prompt: &ValuePrompt,
+) -> error::Result<Option<String>> {
+    let mut error: Option<String> = None;
+    let mut attempts = 0;
+
+    loop {
+        let help = match &error {
+            Some(error) => format!(
+                "{}  {}{}{}",
+                prompt.help,
+                settings.palette.error(),
+                error,
+                style::Reset
+            ),
+            None => prompt.help.clone(),
+        };
+        let mut readline = readline(ui, events, settings)
+            .prefix(&prompt.prefix)
+            .help(help)
+            .expect(prompt.expect.clone())
+            .finish(prompt.finish)
+            .cancel_on_esc(prompt.cancel_on_esc)
+            .restart(true)
+            .allow_empty(true)
+            .multiline(prompt.multiline);
+        if let Some(default) = prompt.default {
+            readline = readline.default(default);
+        }
+        if let Some(max) = prompt.length.max {
+            readline = readline.counter(max);
+        }
+        if let Some(initial) = &prompt.initial {
+            readline = readline.initial(initial.as_str());
+        }
+
+        let mut suggestions: Vec<Suggestion> = vec![];
+        if let Some(clipboard) = &prompt.clipboard {
+            suggestions.push(Suggestion::clipboard(
+                clipboard,
+                settings.messages.get("clipboard_label"),
+            ));
+        }
+        suggestions.extend(
+            prompt
+                .previous
+                .iter()
+                .map(|value| Suggestion::previous(value, settings.messages.get("previous_label"))),
+        );
+        if let Some(suggest) = prompt.suggest {
+            suggestions.extend(
+                suggest
+                    .iter()
+                    .filter(|value| !prompt.previous.contains(value))
+                    .map(|value| Suggestion::plain(value)),
+            );
+        }
+
+        let value = if suggestions.is_empty() {
+            readline.line()?
+        } else {
+            // Return either a choice or user input
+            readline
+                .suggest(FixedComplete::new(&suggestions).case(settings.case))?
+                .map(|(choice, user_input)| choice.map(|c| c.value.clone()).unwrap_or(user_input))
+        };
+        let value = match value {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        if prompt.optional && value.is_empty() {
+            return Ok(Some(value));
+        }
+        if value.is_empty() {
+            // Asked again without counting as a failed attempt
+            let name = prompt.prefix.trim_end_matches(':');
+            error = Some(
+                settings
+                    .messages
+                    .format("value_required", &[("prompt", name)]),
+            );
+            continue;
+        }
+
+        let checked = prompt
+            .expect
+            .validate(&value)
+            .and_then(|_| match prompt.expect {
+                ValueType::Number => prompt.limits.apply(&value),
+                ValueType::Path => Ok(dialect::Dialect::current()
+                    .normalize_path(&value)
+                    .into_owned()),
+                _ => Ok(value),
+            })
+            .and_then(|value| prompt.length.check(&value).map(|_| value));
+        match checked {
+            Ok(value) => return Ok(Some(value)),
+            Err(err) => {
+                attempts += 1;
+                if matches!(settings.retries, Some(retries) if attempts > retries) {
+                    let prompt = prompt.prefix.trim_end_matches(':');
+                    return Err(err.context(
+                        settings
+                            .messages
+                            .format("no_valid_value", &[("prompt", prompt)]),
+                    ));
+                }
+                error = Some(err.to_string());
+            }
+        }
+    }
+}
+
+/// Clipboard contents are truncated in the list to this many chars
+const CLIPBOARD_DISPLAY_CHARS: usize = 40;
+
+/// A suggested value
+struct Suggestion {
+    value: String,
+    /// Displayed text
+    text: String,
+    /// Value could be accepted with a single Enter
+    preselect: bool,
+}
+
+impl Suggestion {
+    fn plain(value: &str) -> Self {
+        Self {
+            value: value.to_string(),
+            text: value.to_string(),
+            preselect: true,
+        }
+    }
+
+    /// Values submitted earlier are labeled after the value
+    fn previous(value: &str, label: &str) -> Self {
+        Self {
+            value: value.to_string(),
+            text: format!("{}{}", value, label),
+            preselect: true,
+        }
+    }
+
+    /// Clipboard contents are labeled and never accepted unless picked
+    fn clipboard(value: &str, label: &str) -> Self {
+        let mut text = label.to_string();
+        if value.chars().count() > CLIPBOARD_DISPLAY_CHARS {
+            text.extend(value.chars().take(CLIPBOARD_DISPLAY_CHARS - 1));
+            text.push('…');
+        } else {
+            text.push_str(value);
+        }
+        Self {
+            value: value.to_string(),
+            text,
+            preselect: false,
+        }
+    }
+}
+
+impl Choice for Suggestion {
+    fn text(&self) -> &str {
+        &self.text
+    }
+
+    fn search_text(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.value)
+    }
+
+    fn preselect(&self) -> bool {
+        self.preselect
+    }
+}
+
+/// Command built by user with the answers it was built from
+#[derive(Debug, PartialEq)]
+struct BuildResult {
+    command: String,
+    template: String,
+    /// Description without markup
+    description: String,
+    /// Name that finds the command with `snova run`
+    name: String,
+    /// Submitted values by group name. Secret and empty groups are left out.
+    values: BTreeMap<String, GroupAnswer>,
+    /// Hook of the command
+    post_build: Option<String>,
+}
+
+/// What user submitted for a group
+#[derive(Debug, Clone, PartialEq)]
+enum GroupAnswer {
+    Single(String),
+    /// Built flags in the order they were picked
+    Flags(Vec<String>),
+}
+
+impl GroupAnswer {
+    /// Value as it appears in the command (before quoting)
+    fn value(&self) -> String {
+        match self {
+            GroupAnswer::Single(value) => value.clone(),
+            GroupAnswer::Flags(flags) => flags.join(" "),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            GroupAnswer::Single(value) => value.is_empty(),
+            GroupAnswer::Flags(flags) => flags.is_empty(),
+        }
+    }
+}
+
+/// Answer of a group while the command is filled in
+#[derive(Debug, Clone, PartialEq)]
+enum GroupState {
+    Single(String),
+    Flags(Vec<PickedFlag>),
+}
+
+impl GroupState {
+    fn answer(&self) -> GroupAnswer {
+        match self {
+            GroupState::Single(value) => GroupAnswer::Single(value.clone()),
+            GroupState::Flags(picked) => {
+                GroupAnswer::Flags(picked.iter().map(|p| p.built.clone()).collect())
+            }
+        }
+    }
+}
+
+/// A flag picked in a flags group
+#[derive(Debug, Clone, PartialEq)]
+struct PickedFlag {
+    /// Index of the flag definition. Custom and pre-filled flags have none.
+    flag: Option<usize>,
+    /// Definition index that sorts flags in canonical order
+    position: usize,
+    /// Flag with its values as it appears in the command
+    built: String,
+}
+
+/// Choice of the flags picker
+enum FlagChoice<'a> {
+    /// Add the flag with the definition index
+    Add(usize, &'a Flag),
+    /// Remove the picked flag with the index, labeled with the text
+    Remove(usize, String),
+}
+
+impl Choice for FlagChoice<'_> {
+    fn text(&self) -> &str {
+        match self {
+            FlagChoice::Add(_, flag) => flag.text(),
+            FlagChoice::Remove(_, text) => text,
+        }
+    }
+
+    fn preview(&self) -> Option<String> {
+        match self {
+            FlagChoice::Add(_, flag) => flag.preview(),
+            FlagChoice::Remove(..) => None,
+        }
+    }
+
+    fn style(&self) -> ChoiceStyle {
+        match self {
+            FlagChoice::Add(_, flag) => flag.style(),
+            FlagChoice::Remove(..) => ChoiceStyle::Normal,
+        }
+    }
+
+    /// Flags are never removed with a single Enter
+    fn preselect(&self) -> bool {
+        matches!(self, FlagChoice::Add(..))
+    }
+}
+
+/// Parts of a single line to type the command values in place. Only commands
+/// whose prompted value groups have no suggestions, defaults or limits are
+/// typed in place; flags groups are asked after the line.
+fn inline_parts(cmd: &Command) -> Option<Vec<view::TemplatePart>> {
+    let mut parts = vec![];
+    let mut names: HashSet<String> = HashSet::new();
+    for slot in parser::template_slots(&cmd.template).ok()? {
+        let name = match slot {
+            parser::TemplateSlot::Text(text) => {
+                parts.push(view::TemplatePart::Text(text));
+                continue;
+            }
+            parser::TemplateSlot::Group(name) => name,
+        };
+        let group = cmd.groups.iter().find(|group| group.name == name)?;
+        let expect = match &group.expect {
+            GroupValue::Single(expect) if group.computed.is_none() => expect,
+            _ => {
+                parts.push(view::TemplatePart::Later(name));
+                continue;
+            }
+        };
+        let plain = group.suggest.is_none()
+            && group.suggest_source.is_none()
+            && group.default.is_none()
+            && !group.multiline
+            && !group.secret
+            && group.limits == NumberLimits::default()
+            && group.length == LengthLimits::default();
+        // Group used twice in the template is typed once
+        if !plain || !names.insert(name.clone()) {
+            return None;
+        }
+        parts.push(view::TemplatePart::Value {
+            name,
+            expect: expect.clone(),
+            optional: group.optional,
+        });
+    }
+    Some(parts).filter(|_| !names.is_empty())
+}
+
+/// Choice of the summary of the answers
+#[derive(Clone)]
+enum SummaryChoice {
+    /// Ask the group with the index again, labeled with its answer
+    Change(usize, String),
+    Done(String),
+}
+
+impl Choice for SummaryChoice {
+    fn text(&self) -> &str {
+        match self {
+            SummaryChoice::Change(_, text) | SummaryChoice::Done(text) => text,
+        }
+    }
+}
+
+/// Prompted groups with their answers followed by Done. Secret answers are
+/// hidden.
+fn summary_choices(
+    cmd: &Command,
+    states: &HashMap<&str, GroupState>,
+    messages: &Messages,
+) -> Vec<SummaryChoice> {
+    let mut choices: Vec<SummaryChoice> = cmd
+        .groups
+        .iter()
+        .enumerate()
+        .filter(|(_, group)| group.is_prompted())
+        .map(|(index, group)| {
+            let answer = states
+                .get(group.name.as_str())
+                .map(|state| state.answer())
+                .filter(|answer| !answer.is_empty());
+            let value = match answer {
+                None => messages.get("summary_empty").to_string(),
+                Some(_) if group.secret => "******".to_string(),
+                Some(GroupAnswer::Single(value)) => value,
+                Some(GroupAnswer::Flags(flags)) => flags.join(" "),
+            };
+            SummaryChoice::Change(index, format!("{}: {}", group.name, value))
+        })
+        .collect();
+    choices.push(SummaryChoice::Done(
+        messages.get("summary_done").to_string(),
+    ));
+    choices
+}
+
+/// Keep the answer unless it is empty or secret
+fn record_answer(
+    answers: &mut BTreeMap<String, GroupAnswer>,
+    group: &CmdGroup,
+    answer: GroupAnswer,
+) {
+    if !group.secret && !answer.is_empty() {
+        answers.insert(group.name.clone(), answer);
+    }
+}
+
+/// Equivalent non-interactive invocation, e.g `snova run 'Find lines' --set PATTERN=foo`
+fn replay_line(built: &BuildResult) -> String {
+    let mut line = format!("snova run {}", quote(&built.name, QuotePolicy::Auto));
+    for (group, answer) in &built.values {
+        line.push_str(&format!(
+            " --set {}={}",
+            group,
+            quote(&answer.value(), QuotePolicy::Auto)
+        ));
+    }
+    line
+}
+
+/// Built command as JSON for `--format json`
+#[derive(Debug, serde::Serialize)]
+struct JsonOutput<'a> {
+    command: &'a str,
+    /// Words of the command as the shell would pass them
+    argv: Vec<String>,
+    template: &'a str,
+    values: BTreeMap<&'a str, String>,
+}
+
+fn json_output(built: &BuildResult) -> Result<String> {
+    let argv = split_words(&built.command).context("Split the command into words")?;
+    let output = JsonOutput {
+        command: &built.command,
+        argv,
+        template: &built.template,
+        values: built
+            .values
+            .iter()
+            .map(|(group, answer)| (group.as_str(), answer.value()))
+            .collect(),
+    };
+    Ok(serde_json::to_string(&output)?)
+}
+
+/// Let user pick a command and fill in its groups
+fn pick_and_build(
+    ui: &mut dyn Write,
+    events: &mut Events,
+    commands: &[Command],
+    settings: &Settings,
+    history: &mut History,
+) -> error::Result<Option<BuildResult>> {
+    let prefill = PresetValues::new();
+    // Command that was abandoned with Ctrl-G is preselected
+    let mut previous: Option<&Command> = None;
+    loop {
+        let cmd = {
+            let mut picker =
+                readline(ui, events, settings).help(settings.messages.get("pick_command"));
+            if let Some(previous) = previous.take() {
+                picker = picker.default(previous.description.as_str());
+            }
+            picker.choice(FixedComplete::new(commands).case(settings.case))?
+        };
+
+        let cmd = match cmd {
+            Some(c) => c,
+            None => {
+                return Ok(None);
+            }
+        };
+
+        write_command(ui, cmd, settings)?;
+        let result = match fill_and_confirm(ui, events, cmd, settings, history, &prefill) {
+            Err(SnovaError::Restart) => {
+                previous = Some(cmd);
+                None
+            }
+            result => result?,
+        };
+
+        match result {
+            Some(mut built) => {
+                // Description is ambiguous when several commands share it
+                if find_command(commands, &built.name).is_err() {
+                    built.name = cmd.template.clone();
+                }
+                return Ok(Some(built));
+            }
+            None => {
+                // User cancelled this command: erase it and pick again
+                write!(ui, "{}\r{}", cursor::Up(1), clear::AfterCursor)?;
+            }
+        }
+    }
+}
+
+/// Fill in command groups and confirm the result if the command asks to.
+/// Returns None if user cancelled or declined.
+fn fill_and_confirm(
+    ui: &mut dyn Write,
+    events: &mut Events,
+    cmd: &Command,
+    settings: &Settings,
+    history: &mut History,
+    prefill: &PresetValues,
+) -> error::Result<Option<BuildResult>> {
+    let result = match fill_groups(ui, events, cmd, settings, history, prefill) {
+        Ok(result) => result,
+        Err(SnovaError::Cancelled) => None,
+        Err(err) => return Err(err),
+    };
+
+    match (result, &cmd.confirm) {
+        (Some(result), Some(message)) => {
+            let escalation = match &settings.escalate {
+                Some(escalation) if cmd.needs_root => settings
+                    .messages
+                    .format("confirm_escalation", &[("escalation", escalation)]),
+                _ => String::new(),
+            };
+            let help = format!(
+                "{}{}{}: {}{}",
+                settings.palette.error(),
+                message,
+                escalation,
+                result.command,
+                style::Reset
+            );
+            let word = settings.messages.get("confirm_word");
+            let confirmed = readline(ui, events, settings)
+                .prefix(settings.messages.format("confirm", &[("word", word)]))
+                .help(help)
+                .confirm(word)?;
+            // Declined command goes back to the picker
+            Ok(Some(result).filter(|_| confirmed))
+        }
+        (result, _) => Ok(result),
+    }
+}
+
+/// Show the picked command above its prompts
+fn write_command(ui: &mut dyn Write, cmd: &Command, settings: &Settings) -> error::Result<()> {
+    let template = settings.palette.fmt_text(&cmd.template);
+    writeln!(
+        ui,
+        "{}\r",
+        settings
+            .messages
+            .format("command", &[("template", &template)])
+    )?;
+    Ok(())
+}
+
+/// Answers collected while the command is filled in
+#[derive(Default)]
+struct Answers<'c> {
+    /// Values the command is built with
+    user_input: HashMap<String, String>,
+    /// Answers by group name. They are kept when going back to restore the groups.
+    states: HashMap<&'c str, GroupState>,
+    /// Values of each flag entered so far, most recent first
+    previous: HashMap<(&'c str, &'c str), Vec<String>>,
+    /// Output of `suggest_cmd` by the command. A command runs once per session,
+    /// failures included, so a slow one delays only the first prompt.
+    suggested: HashMap<String, Vec<String>>,
+}
+
+impl<'c> Answers<'c> {
+    fn set_flags(&mut self, group: &'c CmdGroup, picked: Vec<PickedFlag>) {
+        self.user_input
+            .insert(group.name.clone(), join_flags(&picked));
+        self.states.insert(&group.name, GroupState::Flags(picked));
+    }
+}
+
+/// Ask user to fill in command groups pre-filled with the given values.
+/// Esc goes back to the previous group with its answer restored. With the
+/// summary enabled the answers are listed once all groups are filled so that
+/// a single group could be asked again.
+/// Returns None if user cancelled.
+fn fill_groups(
+    ui: &mut dyn Write,
+    events: &mut Events,
+    cmd: &Command,
+    settings: &Settings,
+    history: &mut History,
+    prefill: &PresetValues,
+) -> error::Result<Option<BuildResult>> {
+    let mut answers = Answers::default();
+    let group_names: Vec<&str> = cmd.groups.iter().map(|g| g.name.as_ref()).collect();
+    // Prompted groups that Esc goes back to
+    let mut visited: Vec<usize> = vec![];
+    let mut group_index = 0;
+    // Group is asked again from the summary which is shown once it is answered
+    let mut changing = false;
+    // Value groups that were typed in place of their placeholders
+    let mut inline: HashSet<&str> = HashSet::new();
+    if let Some(parts) = inline_parts(cmd).filter(|_| settings.inline) {
+        let mut line = view::TemplateLine::new(ui, parts)
+            .footer(settings.footer)
+            .cancel_on_esc(true)
+            .restart(true)
+            .messages(settings.messages.clone())
+            .palette(settings.palette)
+            .events(&mut *events);
+        for group in &cmd.groups {
+            if let Some(initial) = prefill.get(&group.name) {
+                line = line.initial(&group.name, initial.as_str());
+            }
+        }
+        let values = match line.run()? {
+            Some(values) => values,
+            None => return Ok(None),
+        };
+        for group in &cmd.groups {
+            let value = match (values.get(&group.name), &group.expect) {
+                (Some(value), GroupValue::Single(expect)) => match expect {
+                    ValueType::Path => dialect::Dialect::current()
+                        .normalize_path(value)
+                        .into_owned(),
+                    _ => value.clone(),
+                },
+                _ => continue,
+            };
+            inline.insert(&group.name);
+            if !value.is_empty() {
+                answers
+                    .user_input
+                    .insert(group.name.clone(), group.transform_value(&value));
+                answers
+                    .states
+                    .insert(&group.name, GroupState::Single(value));
+            }
+        }
+    }
+
+    'summary: loop {
+        'groups: while let Some(group) = cmd.groups.get(group_index) {
+            if !group.is_prompted() || inline.contains(group.name.as_str()) {
+                group_index += 1;
+                continue;
+            }
+            let progress = progress(&group_names, group_index, settings.palette);
+            // Remaining groups could be skipped once all required groups are filled
+            let can_finish = cmd.groups[group_index..]
+                .iter()
+                .all(|g| g.optional || g.computed.is_some());
+
+            let answered = match &group.expect {
+                GroupValue::Single(expect_type) => {
+                    // Previous values follow the defined suggestions
+                    let mut suggest = match &group.suggest {
+                        Some(suggest) => {
+                            resolve_suggestions(suggest, &answers.user_input, settings)?
+                        }
+                        None => vec![],
+                    };
+                    for value in history.values(&cmd.template, &group.name) {
+                        if !suggest.contains(value) {
+                            suggest.push(value.clone());
+                        }
+                    }
+                    // Looked up values could be many and go last
+                    if let (Some(source), Ok(cwd)) = (group.suggest_source, std::env::current_dir())
+                    {
+                        let known: HashSet<String> = suggest.iter().cloned().collect();
+                        suggest.extend(
+                            suggest::list(
+                                source,
+                                &group.name,
+                                expect_type,
+                                &cwd,
+                                history,
+                                &settings.runner,
+                            )
+                            .into_iter()
+                            .filter(|value| !known.contains(value)),
+                        );
+                    }
+                    let computed = match &group.computed {
+                        Some(computed) => Some(format_computed(computed, &answers.user_input)?),
+                        None => None,
+                    };
+                    let initial = match answers.states.get(group.name.as_str()) {
+                        Some(GroupState::Single(value)) => Some(value.clone()),
+                        _ => prefill.get(&group.name).cloned().or(computed),
+                    };
+                    let prompt = ValuePrompt {
+                        prefix: format!("{}:", group.name),
+                        help: progress,
+                        expect: expect_type,
+                        limits: &group.limits,
+                        length: &group.length,
+                        default: group.default.as_ref(),
+                        suggest: Some(&suggest).filter(|s| !s.is_empty()),
+                        previous: &[],
+                        clipboard: settings
+                            .clipboard
+                            .and_then(|read| clipboard::suggestion(read(), expect_type)),
+                        initial,
+                        finish: can_finish,
+                        cancel_on_esc: true,
+                        multiline: group.multiline,
+                        optional: group.optional,
+                    };
+                    match read_value(ui, events, settings, &prompt) {
+                        Ok(Some(value)) => {
+                            if value.is_empty() {
+                                // Skipped group is left out of the command
+                                answers.user_input.remove(&group.name);
+                                answers.states.remove(group.name.as_str());
+                            } else {
+                                answers
+                                    .user_input
+                                    .insert(group.name.clone(), group.transform_value(&value));
+                                answers
+                                    .states
+                                    .insert(&group.name, GroupState::Single(value));
+                            }
+                            Ok(Some(()))
+                        }
+                        // Optional group is skipped with Ctrl-D too
+                        Ok(None) if group.optional => {
+                            answers.user_input.remove(&group.name);
+                            answers.states.remove(group.name.as_str());
+                            Ok(Some(()))
+                        }
+                        result => result.map(|_| None),
+                    }
+                }
+                GroupValue::Flags(flags) => {
+                    let picked = match answers.states.get(group.name.as_str()) {
+                        Some(GroupState::Flags(picked)) => picked.clone(),
+                        // Pre-filled flags go first
+                        _ => prefill
+                            .get(&group.name)
+                            .map(|value| PickedFlag {
+                                flag: None,
+                                position: 0,
+                                built: value.clone(),
+                            })
+                            .into_iter()
+                            .collect(),
+                    };
+                    let prompt = FlagsPrompt {
+                        group,
+                        flags,
+                        help: progress,
+                        finish: can_finish,
+                    };
+                    pick_flags(ui, events, settings, cmd, &prompt, &mut answers, picked)
+                }
+            };
+            match answered {
+                Ok(Some(())) => {}
+                // User cancelled
+                Ok(None) => return Ok(None),
+                Err(SnovaError::Finished) => break 'summary,
+                Err(SnovaError::Cancelled) if changing => break 'groups,
+                Err(SnovaError::Cancelled) => {
+                    group_index = visited.pop().ok_or(SnovaError::Cancelled)?;
+                    continue 'groups;
+                }
+                Err(err) => return Err(err),
+            }
+            visited.push(group_index);
+            group_index += 1;
+            if changing {
+                break 'groups;
+            }
+        }
+
+        if !settings.summary {
+            break;
+        }
+        changing = false;
+        match ask_summary(ui, events, settings, cmd, &answers) {
+            Ok(Some(SummaryChoice::Change(index, _))) => {
+                group_index = index;
+                changing = true;
+                // Typed in place before and asked with its own prompt now
+                inline.remove(cmd.groups[index].name.as_str());
+            }
+            Ok(Some(SummaryChoice::Done(_))) => break,
+            // User cancelled
+            Ok(None) => return Ok(None),
+            // Back to the last group
+            Err(SnovaError::Cancelled) => {
+                group_index = visited.pop().ok_or(SnovaError::Cancelled)?;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    finish_build(ui, cmd, settings, history, answers).map(Some)
+}
+
+/// The flags picker of a flags group
+struct FlagsPrompt<'c> {
+    group: &'c CmdGroup,
+    flags: &'c [Flag],
+    help: String,
+    /// Allow skipping the remaining groups
+    finish: bool,
+}
+
+/// Pick flags of the group until nothing is selected or every flag is used.
+/// The answers are updated on every change so that the picked flags are kept
+/// when going back or finishing early. Returns None if user cancelled.
+fn pick_flags<'c>(
+    ui: &mut dyn Write,
+    events: &mut Events,
+    settings: &Settings,
+    cmd: &Command,
+    prompt: &FlagsPrompt<'c>,
+    answers: &mut Answers<'c>,
+    mut picked: Vec<PickedFlag>,
+) -> error::Result<Option<()>> {
+    let FlagsPrompt { group, flags, .. } = *prompt;
+    let mut error: Option<String> = None;
+
+    loop {
+        answers.set_flags(group, picked.clone());
+
+        let mut choices: Vec<FlagChoice> = flags
+            .iter()
+            .enumerate()
+            .filter(|(index, flag)| flag.multiple || !picked.iter().any(|p| p.flag == Some(*index)))
+            .map(|(index, flag)| FlagChoice::Add(index, flag))
+            .collect();
+        // Picked flags could be removed. Defined ones are told
+        // apart by their descriptions.
+        let remove_label = settings.messages.get("remove_flag_label");
+        choices.extend(picked.iter().enumerate().map(|(index, p)| {
+            let text = match p.flag {
+                Some(flag) => format!("{}{}  {}", remove_label, p.built, flags[flag].description),
+                None => format!("{}{}", remove_label, p.built),
+            };
+            FlagChoice::Remove(index, text)
+        }));
+        let command = escalated(cmd, settings, (cmd.build)(&answers.user_input));
+        let mut help = prompt.help.clone();
+        if !picked.is_empty() {
+            let count = picked.len().to_string();
+            help = format!(
+                "{} {}",
+                help,
+                settings.palette.counter(
+                    settings
+                        .messages
+                        .format("flags_selected", &[("count", &count)])
+                )
+            );
+        }
+        help = format!("{}  {}", help, command);
+        if let Some(error) = error.take() {
+            help = format!(
+                "{}  {}{}{}",
+                help,
+                settings.palette.error(),
+                error,
+                style::Reset
+            );
+        }
+        let choice = {
+            let mut picker = readline(ui, events, settings)
+                .help(help)
+                .finish(prompt.finish)
+                .cancel_on_esc(true)
+                .restart(true);
+            let complete = FixedComplete::new(&choices).case(settings.case);
+            if group.allow_custom {
+                picker.choice_or_custom(complete)?
+            } else {
+                picker
+                    .choice(complete)?
+                    .map(|choice| (Some(choice), String::new()))
+            }
+        };
+        let (choice, custom) = match choice {
+            Some((choice, custom)) => (choice, custom),
+            // Nothing selected
+            None => break,
+        };
+
+        match choice {
+            Some(FlagChoice::Remove(index, _)) => {
+                picked.remove(*index);
+                continue;
+            }
+            Some(FlagChoice::Add(position, flag)) => {
+                let built = match read_flag(ui, events, settings, group, flag, answers) {
+                    Ok(Some(built)) => built,
+                    // User cancelled
+                    Ok(None) => return Ok(None),
+                    // Back to the flags without adding this one
+                    Err(SnovaError::Cancelled) => continue,
+                    Err(err) => return Err(err),
+                };
+                let flag = PickedFlag {
+                    flag: Some(*position),
+                    position: *position,
+                    built,
+                };
+                add_flag(&mut picked, flag, group.canonical_order);
+            }
+            // Typed text is passed through as long as it is quoted properly
+            None => match check_quotes(custom.trim()) {
+                Ok(()) if !custom.trim().is_empty() => {
+                    // Custom flags follow the defined ones
+                    let flag = PickedFlag {
+                        flag: None,
+                        position: flags.len(),
+                        built: custom.trim().to_string(),
+                    };
+                    add_flag(&mut picked, flag, group.canonical_order);
+                }
+                Ok(()) => continue,
+                Err(err) => {
+                    error = Some(err.to_string());
+                    continue;
+                }
+            },
+        }
+
+        let all_used = flags
+            .iter()
+            .enumerate()
+            .all(|(index, flag)| !flag.multiple && picked.iter().any(|p| p.flag == Some(index)));
+        if all_used {
+            break;
+        }
+    }
+    answers.set_flags(group, picked);
+    Ok(Some(()))
+}
+
+/// Ask for the values of the picked flag and build it. Returns None if user
+/// cancelled; Esc fails with `Cancelled` to go back to the flags picker.
+fn read_flag<'c>(
+    ui: &mut dyn Write,
+    events: &mut Events,
+    settings: &Settings,
+    group: &'c CmdGroup,
+    flag: &'c Flag,
+    answers: &mut Answers<'c>,
+) -> error::Result<Option<String>> {
+    let expect = match &flag.expect {
+        Some(expect) => expect,
+        None => return Ok(Some(flag.template.clone())),
+    };
+    let suggest = flag_suggestions(flag, &answers.user_input, &mut answers.suggested, settings)?;
+    let key = (group.name.as_str(), flag.template.as_str());
+    let mut values = vec![];
+    for (index, input) in expect.inputs.iter().enumerate() {
+        let prefix = if expect.inputs.len() == 1 {
+            format!("{}:", flag.template)
+        } else {
+            format!("{} {}:", flag.template, input.name)
+        };
+        // Default and suggestions are for the first value
+        let first = index == 0;
+        let previous = match answers.previous.get(&key) {
+            Some(previous) if first => previous.as_slice(),
+            _ => &[],
+        };
+        let prompt = ValuePrompt {
+            prefix,
+            help: flag.description.clone(),
+            expect: &input.value_type,
+            limits: &NumberLimits::default(),
+            length: &LengthLimits::default(),
+            default: flag.default.as_ref().filter(|_| first),
+            suggest: Some(&suggest).filter(|s| first && !s.is_empty()),
+            previous,
+            clipboard: None,
+            initial: None,
+            finish: false,
+            cancel_on_esc: true,
+            multiline: false,
+            optional: false,
+        };
+        match read_value(ui, events, settings, &prompt)? {
+            Some(value) => values.push(value),
+            None => return Ok(None),
+        }
+    }
+    if let Some(value) = values.first() {
+        let previous = answers.previous.entry(key).or_default();
+        previous.retain(|v| v != value);
+        previous.insert(0, value.clone());
+    }
+    Ok(Some((expect.build)(&values)))
+}
+
+/// List the answers to pick a group to ask again or Done.
+/// Returns None if user cancelled.
+fn ask_summary(
+    ui: &mut dyn Write,
+    events: &mut Events,
+    settings: &Settings,
+    cmd: &Command,
+    answers: &Answers,
+) -> error::Result<Option<SummaryChoice>> {
+    let choices = summary_choices(cmd, &answers.states, &settings.messages);
+    let done = settings.messages.get("summary_done");
+    let command = escalated(cmd, settings, (cmd.build)(&answers.user_input));
+    let choice = readline(ui, events, settings)
+        .help(command)
+        .cancel_on_esc(true)
+        .restart(true)
+        .default(done)
+        .choice(FixedComplete::new(&choices).case(settings.case))?;
+    Ok(choice.cloned())
+}
+
+/// Build the command of the answers, remember its values and warn about
+/// destructive flags
+fn finish_build(
+    ui: &mut dyn Write,
+    cmd: &Command,
+    settings: &Settings,
+    history: &mut History,
+    mut answers: Answers,
+) -> error::Result<BuildResult> {
+    derive_computed(cmd, &mut answers.user_input)?;
+
+    let mut values = BTreeMap::new();
+    // Chosen flags that are marked as dangerous
+    let mut destructive = vec![];
+    for group in &cmd.groups {
+        let state = match answers.states.get(group.name.as_str()) {
+            Some(state) => state,
+            None => continue,
+        };
+        record_answer(&mut values, group, state.answer());
+        match (state, &group.expect) {
+            // Values to remember once the command is built
+            (GroupState::Single(value), GroupValue::Single(expect)) if !group.secret => {
+                history.add(&cmd.template, &group.name, value);
+                history.add_recent(&group.name, expect.name(), value);
+            }
+            (GroupState::Flags(picked), GroupValue::Flags(flags)) => {
+                destructive.extend(
+                    picked
+                        .iter()
+                        .filter_map(|p| p.flag.map(|index| &flags[index]))
+                        .filter(|flag| flag.danger)
+                        .map(|flag| strip_markup(&flag.template)),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    if !destructive.is_empty() {
+        writeln!(
+            ui,
+            "{}{}{}\r",
+            settings.palette.warning(),
+            settings
+                .messages
+                .format("destructive_options", &[("flags", &destructive.join(" "))]),
+            style::Reset
+        )?;
+    }
+
+    let description = strip_markup(&cmd.description);
+    Ok(BuildResult {
+        command: escalated(cmd, settings, (cmd.build)(&answers.user_input)),
+        template: cmd.template.clone(),
+        name: description.clone(),
+        description,
+        values,
+        post_build: cmd.post_build.clone(),
+    })
+}
+
+/// Add a picked flag. In canonical order it goes after the flags that are defined
+/// before it (or are the same flag) instead of the end.
+fn add_flag(picked: &mut Vec<PickedFlag>, flag: PickedFlag, canonical: bool) {
+    let index = if canonical {
+        picked.partition_point(|p| p.position <= flag.position)
+    } else {
+        picked.len()
+    };
+    picked.insert(index, flag);
+}
+
+fn join_flags(picked: &[PickedFlag]) -> String {
+    let built: Vec<&str> = picked.iter().map(|p| p.built.as_ref()).collect();
+    built.join(" ")
+}
+
+/// Fill in references to answered groups in the suggested values. Values that
+/// refer to groups without an answer yet are left out.
+fn resolve_suggestions(
+    suggest: &[String],
+    user_input: &HashMap<String, String>,
+    settings: &Settings,
+) -> error::Result<Vec<String>> {
+    let mut resolved = vec![];
+    for value in suggest {
+        let missing: Vec<String> = computed_refs(value)?
+            .into_iter()
+            .filter(|name| !user_input.contains_key(name))
+            .collect();
+        if missing.is_empty() {
+            resolved.push(format_computed(value, user_input)?);
+        } else if settings.debug {
+            eprint!(
+                "{}\r\n",
+                settings.messages.format(
+                    "suggestion_skipped",
+                    &[("value", value), ("groups", &missing.join(", "))]
+                )
+            );
+        }
+    }
+    Ok(resolved)
+}
+
+/// Suggestions of the flag value: the defined list followed by `suggest_cmd`
+/// output. The output is kept by the command to reuse it.
+fn flag_suggestions(
+    flag: &Flag,
+    user_input: &HashMap<String, String>,
+    suggested: &mut HashMap<String, Vec<String>>,
+    settings: &Settings,
+) -> error::Result<Vec<String>> {
+    let mut suggest = match &flag.suggest {
+        Some(suggest) => resolve_suggestions(suggest, user_input, settings)?,
+        None => vec![],
+    };
+    let commands = match &flag.suggest_cmd {
+        Some(command) => resolve_suggestions(std::slice::from_ref(command), user_input, settings)?,
+        None => vec![],
+    };
+    if let (Some(command), Ok(cwd)) = (commands.first(), std::env::current_dir()) {
+        let lines = suggested
+            .entry(command.clone())
+            .or_insert_with(|| suggest::command_lines(command, &cwd, &settings.runner));
+        for value in lines.iter() {
+            if !suggest.contains(value) {
+                suggest.push(value.clone());
+            }
+        }
+    }
+    Ok(suggest)
+}
+
+/// Derive values that were not asked (groups are ordered by their references)
+fn derive_computed(cmd: &Command, user_input: &mut HashMap<String, String>) -> error::Result<()> {
+    for group in &cmd.groups {
+        if let Some(computed) = &group.computed {
+            if !user_input.contains_key(&group.name) {
+                let value = format_computed(computed, user_input)?;
+                user_input.insert(group.name.clone(), group.transform_value(&value));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Build the command from given values without asking anything.
+/// Flags groups take the combined flags as they appear in the command.
+fn fill_from_values(cmd: &Command, values: &PresetValues) -> error::Result<String> {
+    if let Some(unknown) = values
+        .keys()
+        .find(|name| !cmd.groups.iter().any(|g| &g.name == *name))
+    {
+        return Err(SnovaError::validation(format!(
+            "Unknown group '{}'",
+            unknown
+        )));
+    }
+
+    let mut user_input = HashMap::new();
+    for group in &cmd.groups {
+        let value = match (values.get(&group.name), &group.expect) {
+            (Some(value), GroupValue::Single(expect)) => single_value(group, expect, value)?,
+            (Some(value), GroupValue::Flags(_)) => value.clone(),
+            (None, GroupValue::Single(_)) if group.computed.is_none() => match &group.default {
+                Some(default) => group.transform_value(default),
+                None if group.optional => continue,
+                None => {
+                    return Err(SnovaError::validation(format!(
+                        "Missing value for {}",
+                        group.name
+                    )))
+                }
+            },
+            (None, _) => continue,
+        };
+        user_input.insert(group.name.clone(), value);
+    }
+    derive_computed(cmd, &mut user_input)?;
+    Ok((cmd.build)(&user_input))
+}
+
+/// Validate the value of a single value group and transform it
+fn single_value(group: &CmdGroup, expect: &ValueType, value: &str) -> error::Result<String> {
+    expect
+        .validate(value)
+        .map_err(|err| err.context(format!("Value of {}", group.name)))?;
+    let value = match expect {
+        ValueType::Number => group.limits.apply(value)?,
+        _ => value.to_string(),
+    };
+    group
+        .length
+        .check(&value)
+        .map_err(|err| err.context(format!("Value of {}", group.name)))?;
+    Ok(group.transform_value(&value))
+}
+
+/// Format group names as breadcrumbs, e.g "[2/3] PATTERN ▸ *PATH* ▸ OPTIONS".
+/// Groups before the current one are done and displayed as counters.
+fn progress(names: &[&str], current: usize, palette: Palette) -> String {
+    let crumbs: Vec<String> = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| match i.cmp(&current) {
+            Ordering::Less => palette.counter(name),
+            Ordering::Equal => format!("*{}*", name),
+            Ordering::Greater => name.to_string(),
+        })
+        .collect();
+    format!("[{}/{}] {}", current + 1, names.len(), crumbs.join(" ▸ "))
+}
+
+/// Prefix the command with the escalation when it needs root
+fn escalated(cmd: &Command, settings: &Settings, command: String) -> String {
+    match &settings.escalate {
+        Some(escalation) if cmd.needs_root => format!("{} {}", escalation, command),
+        _ => command,
+    }
+}
+
+/// Prefix of commands that need root: sudo unless another escalation is
+/// given. None when the user is root already.
+fn escalation_prefix(escalation: Option<&str>, is_root: impl Fn() -> bool) -> Option<String> {
+    if is_root() {
+        return None;
+    }
+    Some(escalation.unwrap_or("sudo").to_string())
+}
+
+/// Current user is root
+fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+/// User settings read from the environment
+struct Settings {
+    /// Case matching mode (SNOVA_CASE=smart|sensitive|insensitive)
+    case: CaseMatching,
+    /// Show key bindings below user input (SNOVA_FOOTER=on|off)
+    footer: bool,
+    /// How many times to ask again for a valid value (SNOVA_RETRIES=3).
+    /// Ask until user cancels if not set.
+    retries: Option<usize>,
+    /// Where to read keys from (SNOVA_TTY=auto|always|never)
+    tty: tty::TtyMode,
+    /// Read commands from the project directory (SNOVA_PROJECT=on|off)
+    project: bool,
+    /// Allow unused group definitions in user files (SNOVA_LENIENT=on|off)
+    lenient: bool,
+    /// Log dropped input to stderr (SNOVA_DEBUG=on|off)
+    debug: bool,
+    /// Show the answers to change before the command is built
+    /// (SNOVA_SUMMARY=on|off)
+    summary: bool,
+    /// Type the values of simple commands in place of their placeholders
+    /// (SNOVA_INLINE=on|off)
+    inline: bool,
+    /// Ask with plain numbered prompts (SNOVA_PLAIN=auto|on|off). Auto uses
+    /// them on dumb terminals and when raw mode can't be enabled.
+    plain: Option<bool>,
+    /// Reads clipboard contents to suggest them (SNOVA_CLIPBOARD=on|off)
+    clipboard: Option<fn() -> Option<String>>,
+    /// Runs commands that list suggestions (SNOVA_SUGGEST_TIMEOUT=2000 in ms)
+    runner: subprocess::Runner,
+    /// Prefix of commands that need root (`escalation` in the settings file).
+    /// Not set when the user is root.
+    escalate: Option<String>,
+    /// UI strings (`[messages]` in the settings file)
+    messages: Messages,
+    /// Styles for the terminal background (SNOVA_BACKGROUND=auto|dark|light).
+    /// Auto reads the background from COLORFGBG.
+    palette: Palette,
+}
+
+impl Settings {
+    fn from_env(messages: Messages) -> Result<Self> {
+        let case = match std::env::var("SNOVA_CASE") {
+            Ok(value) => CaseMatching::parse(&value).context("Read SNOVA_CASE")?,
+            Err(_) => CaseMatching::default(),
+        };
+        let footer = match std::env::var("SNOVA_FOOTER").as_deref() {
+            Ok("on") | Err(_) => true,
+            Ok("off") => false,
+            Ok(value) => return Err(anyhow!("Unknown SNOVA_FOOTER value '{}'", value)),
+        };
+        let retries = match std::env::var("SNOVA_RETRIES") {
+            Ok(value) => Some(value.parse().context("Read SNOVA_RETRIES")?),
+            Err(_) => None,
+        };
+        let project = match std::env::var("SNOVA_PROJECT").as_deref() {
+            Ok("on") | Err(_) => true,
+            Ok("off") => false,
+            Ok(value) => return Err(anyhow!("Unknown SNOVA_PROJECT value '{}'", value)),
+        };
+        let lenient = match std::env::var("SNOVA_LENIENT").as_deref() {
+            Ok("off") | Err(_) => false,
+            Ok("on") => true,
+            Ok(value) => return Err(anyhow!("Unknown SNOVA_LENIENT value '{}'", value)),
+        };
+        let debug = match std::env::var("SNOVA_DEBUG").as_deref() {
+            Ok("off") | Err(_) => false,
+            Ok("on") => true,
+            Ok(value) => return Err(anyhow!("Unknown SNOVA_DEBUG value '{}'", value)),
+        };
+        let summary = match std::env::var("SNOVA_SUMMARY").as_deref() {
+            Ok("off") | Err(_) => false,
+            Ok("on") => true,
+            Ok(value) => return Err(anyhow!("Unknown SNOVA_SUMMARY value '{}'", value)),
+        };
+        let inline = match std::env::var("SNOVA_INLINE").as_deref() {
+            Ok("off") | Err(_) => false,
+            Ok("on") => true,
+            Ok(value) => return Err(anyhow!("Unknown SNOVA_INLINE value '{}'", value)),
+        };
+        let plain = match std::env::var("SNOVA_PLAIN").as_deref() {
+            Ok("auto") | Err(_) => None,
+            Ok("on") => Some(true),
+            Ok("off") => Some(false),
+            Ok(value) => return Err(anyhow!("Unknown SNOVA_PLAIN value '{}'", value)),
+        };
+        let clipboard: Option<fn() -> Option<String>> =
+            match std::env::var("SNOVA_CLIPBOARD").as_deref() {
+                Ok("on") | Err(_) => Some(clipboard::read),
+                Ok("off") => None,
+                Ok(value) => return Err(anyhow!("Unknown SNOVA_CLIPBOARD value '{}'", value)),
+            };
+        let timeout = match std::env::var("SNOVA_SUGGEST_TIMEOUT") {
+            Ok(value) => {
+                Duration::from_millis(value.parse().context("Read SNOVA_SUGGEST_TIMEOUT")?)
+            }
+            Err(_) => subprocess::DEFAULT_TIMEOUT,
+        };
+        let tty = match std::env::var("SNOVA_TTY") {
+            Ok(value) => tty::TtyMode::parse(&value).context("Read SNOVA_TTY")?,
+            Err(_) => tty::TtyMode::default(),
+        };
+        Ok(Self {
+            case,
+            footer,
+            retries,
+            tty,
+            project,
+            lenient,
+            debug,
+            summary,
+            inline,
+            plain,
+            clipboard,
+            runner: subprocess::Runner::new(timeout).debug(debug),
+            escalate: None,
+            messages,
+            palette: palette_from_env()?,
+        })
+    }
+}
+
+/// Palette for the background that is set or detected
+fn palette_from_env() -> Result<Palette> {
+    let background = match std::env::var("SNOVA_BACKGROUND").as_deref() {
+        Ok("auto") | Err(_) => std::env::var("COLORFGBG")
+            .ok()
+            .and_then(|value| Background::from_colorfgbg(&value))
+            .unwrap_or_default(),
+        Ok(value) => Background::parse(value).context("Read SNOVA_BACKGROUND")?,
+    };
+    Ok(Palette::new(background))
+}
+
+impl Choice for Command {
+    fn text(&self) -> &str {
+        &self.description
+    }
+
+    fn search_text(&self) -> Cow<'_, str> {
+        let mut text = format!("{} {}", self.description, self.template);
+        for keyword in &self.keywords {
+            text.push(' ');
+            text.push_str(keyword);
+        }
+        Cow::Owned(text)
+    }
+
+    fn hint(&self) -> Option<&str> {
+        Some(&self.template)
+    }
+
+    fn badge(&self) -> Option<&str> {
+        Some(self.source.kind())
+    }
+
+    fn preview(&self) -> Option<String> {
+        let mut lines = vec![self.template.clone()];
+        if let Some(long_description) = &self.long_description {
+            lines.push(long_description.clone());
+        }
+        for group in &self.groups {
+            if let GroupValue::Flags(flags) = &group.expect {
+                let templates: Vec<&str> = flags.iter().map(|f| f.template.as_ref()).collect();
+                lines.push(format!("{}: {}", group.name, templates.join(", ")));
+            }
+        }
+        if let Some(example) = &self.example {
+            lines.push(format!("e.g {}", example));
+        }
+        for example in &self.examples {
+            lines.push(format!("{}: {}", example.description, example.command));
+        }
+        Some(lines.join("\n"))
+    }
+}
+
+impl Choice for Flag {
+    fn text(&self) -> &str {
+        &self.description
+    }
+
+    fn style(&self) -> ChoiceStyle {
+        if self.danger {
+            ChoiceStyle::Warning
+        } else {
+            ChoiceStyle::Normal
+        }
+    }
+
+    fn preview(&self) -> Option<String> {
+        let mut lines = vec![self.template.clone()];
+        if self.danger {
+            lines.push("*Destructive option*".to_string());
+        }
+        if let Some(long_description) = &self.long_description {
+            lines.push(long_description.clone());
+        }
+        if let Some(example) = &self.example {
+            lines.push(format!("e.g {}", example));
+        }
+        Some(lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use view::AutoComplete;
+
+    use termion::event::Key;
+
+    /// Run the whole flow with scripted key presses
+    fn run_keys(commands: &[Command], keys: Vec<Key>) -> Result<Option<String>> {
+        run_keys_with_history(commands, keys, &mut History::default())
+    }
+
+    fn run_keys_with_history(
+        commands: &[Command],
+        keys: Vec<Key>,
+        history: &mut History,
+    ) -> Result<Option<String>> {
+        run_keys_with_settings(commands, keys, history, &test_settings())
+    }
+
+    fn run_keys_with_settings(
+        commands: &[Command],
+        keys: Vec<Key>,
+        history: &mut History,
+        settings: &Settings,
+    ) -> Result<Option<String>> {
+        let mut out = vec![];
+        let mut events = keys.into_iter().map(|key| Ok(Event::Key(key)));
+        let built = pick_and_build(&mut out, &mut events, commands, settings, history)?;
+        Ok(built.map(|built| built.command))
+    }
+
+    fn test_settings() -> Settings {
+        Settings {
+            case: CaseMatching::default(),
+            footer: false,
+            retries: Some(3),
+            tty: tty::TtyMode::Never,
+            project: false,
+            lenient: false,
+            debug: false,
+            summary: false,
+            inline: false,
+            plain: Some(false),
+            clipboard: None,
+            runner: subprocess::Runner::default(),
+            escalate: None,
+            messages: Messages::default(),
+            palette: Palette::default(),
+        }
+    }
+
+    /// Convert text into key presses
+    fn type_keys(text: &str) -> Vec<Key> {
+        text.chars().map(Key::Char).collect()
+    }
+
+    fn grep_commands() -> Vec<Command> {
+        let defs = toml::de::from_str(
+            r#"
+[[commands]]
+template = "grep [_OPTIONS_] _PATTERN_ [_PATH_]"
+description = "Find lines in a file (*grep*)"
+groups.PATTERN.expect = "string"
+groups.PATH.expect = "path"
+groups.OPTIONS.flags = [
+  { template = "-i", description = "Case insensitive matching" },
+  { template = "-v", description = "Invert match" },
+]
+"#,
+        )
+        .unwrap();
+        parser::parse_defs(defs).unwrap()
+    }
+
+    #[test]
+    fn change_group_from_summary() {
+        let commands = head_commands();
+        let settings = Settings {
+            summary: true,
+            ..test_settings()
+        };
+        // Done is preselected
+        let keys = type_keys("head\n10\nlog.txt\n\n");
+        let result = run_keys_with_settings(&commands, keys, &mut History::default(), &settings);
+        assert_eq!(Some("head -n 10 log.txt".to_string()), result.unwrap());
+
+        // Only the picked group is asked again
+        let mut keys = type_keys("head\n10\nlog.txt\nNUM\n");
+        keys.push(Key::Ctrl('u'));
+        keys.extend(type_keys("20\n\n"));
+        let result = run_keys_with_settings(&commands, keys, &mut History::default(), &settings);
+        assert_eq!(Some("head -n 20 log.txt".to_string()), result.unwrap());
+
+        // Esc goes back to the last group
+        let mut keys = type_keys("head\n10\nlog.txt\n");
+        keys.push(Key::Esc);
+        keys.push(Key::Ctrl('u'));
+        keys.extend(type_keys("all.txt\n\n"));
+        let result = run_keys_with_settings(&commands, keys, &mut History::default(), &settings);
+        assert_eq!(Some("head -n 10 all.txt".to_string()), result.unwrap());
+    }
+
+    #[test]
+    fn inline_values() {
+        let commands = grep_commands();
+        let settings = Settings {
+            inline: true,
+            ..test_settings()
+        };
+        // Values are typed in place and flags are picked after them
+        let mut keys = type_keys("grep\nfoo\tsrc\n\n");
+        keys.push(Key::Ctrl('d'));
+        let result = run_keys_with_settings(&commands, keys, &mut History::default(), &settings);
+        assert_eq!(Some("grep -i foo src".to_string()), result.unwrap());
+
+        // Skipped optional value is left out
+        let mut keys = type_keys("grep\nfoo\n");
+        keys.push(Key::Ctrl('d'));
+        let result = run_keys_with_settings(&commands, keys, &mut History::default(), &settings);
+        assert_eq!(Some("grep foo".to_string()), result.unwrap());
+    }
+
+    #[test]
+    fn inline_parts_plain_groups() {
+        let parts = |data: &str| {
+            let commands = parser::parse_str(data).unwrap();
+            inline_parts(&commands[0]).map(|parts| {
+                parts
+                    .iter()
+                    .map(|part| match part {
+                        view::TemplatePart::Text(text) => text.clone(),
+                        view::TemplatePart::Value { name, .. } => format!("<{}>", name),
+                        view::TemplatePart::Later(name) => format!("({})", name),
+                    })
+                    .collect::<String>()
+            })
+        };
+        assert_eq!(
+            Some("grep (OPTIONS) <PATTERN> <PATH>".to_string()),
+            parts(
+                r#"
+[[commands]]
+template = "grep [_OPTIONS_] _PATTERN_ [_PATH_]"
+description = "Find lines"
+groups.PATTERN.expect = "string"
+groups.PATH.expect = "path"
+groups.OPTIONS.flags = [{ template = "-i", description = "Ignore case" }]
+"#
+            )
+        );
+        // Suggestions need their own prompt
+        assert_eq!(
+            None,
+            parts(
+                r#"
+[[commands]]
+template = "kill -s _SIGNAL_ _PID_"
+description = "Send a signal"
+groups.SIGNAL.expect = "string"
+groups.SIGNAL.suggest = ["TERM", "KILL"]
+groups.PID.expect = "number"
+"#
+            )
+        );
+    }
+
+    #[test]
+    fn summary_answers() {
+        let commands = parser::parse_str(
+            r#"
+[[commands]]
+template = "login [_OPTIONS_] _USER_ _TOKEN_ [_NOTE_]"
+description = "Log in"
+groups.USER.expect = "string"
+groups.TOKEN.expect = "string"
+groups.TOKEN.secret = true
+groups.NOTE.expect = "string"
+groups.OPTIONS.flags = [{ template = "-v", description = "Verbose" }]
+"#,
+        )
+        .unwrap();
+        let cmd = &commands[0];
+        let mut states = HashMap::new();
+        states.insert("USER", GroupState::Single("me".into()));
+        states.insert("TOKEN", GroupState::Single("t0ken".into()));
+        states.insert(
+            "OPTIONS",
+            GroupState::Flags(vec![PickedFlag {
+                flag: Some(0),
+                position: 0,
+                built: "-v".into(),
+            }]),
+        );
+        let texts: Vec<String> = summary_choices(cmd, &states, &Messages::default())
+            .iter()
+            .map(|choice| choice.text().to_string())
+            .collect();
+        let mut expected: Vec<String> = cmd
+            .groups
+            .iter()
+            .map(|group| match group.name.as_str() {
+                "USER" => "USER: me",
+                "TOKEN" => "TOKEN: ******",
+                "OPTIONS" => "OPTIONS: -v",
+                _ => "NOTE: (empty)",
+            })
+            .map(String::from)
+            .collect();
+        expected.push("Done".into());
+        assert_eq!(expected, texts);
+    }
+
+    #[test]
+    fn selected_flags_count() {
+        let commands = grep_commands();
+        let mut ui = vec![];
+        let mut keys = type_keys("grep\nfoo\n");
+        keys.extend(vec![Key::Char('\n'), Key::Char('\n'), Key::Ctrl('s')]);
+        let mut events = keys.into_iter().map(|key| Ok(Event::Key(key)));
+        let built = pick_and_build(
+            &mut ui,
+            &mut events,
+            &commands,
+            &test_settings(),
+            &mut History::default(),
+        )
+        .unwrap();
+        assert_eq!("grep -i -v foo", built.unwrap().command);
+        let ui = String::from_utf8_lossy(&ui);
+        assert!(ui.contains("(1 selected)"), "{}", ui);
+        assert!(!ui.contains("(0 selected)"), "{}", ui);
+    }
+
+    #[test]
+    fn finish_early() {
+        let commands = grep_commands();
+        let mut keys = type_keys("grep\nfoo\n");
+        // Pick -i and finish
+        keys.extend(vec![Key::Char('\n'), Key::Ctrl('s')]);
+        let result = run_keys(&commands, keys).unwrap();
+        assert_eq!(Some("grep -i foo".to_string()), result);
+    }
+
+    /// Build grep with -i and pick an action in the final menu
+    fn final_action_keys(
+        keys: Vec<Key>,
+        default: FinalAction,
+    ) -> error::Result<(String, FinalAction)> {
+        let commands = grep_commands();
+        let mut all_keys = type_keys("grep\nfoo\n");
+        all_keys.extend(vec![Key::Char('\n'), Key::Ctrl('s')]);
+        all_keys.extend(keys);
+        let settings = test_settings();
+        let mut out = vec![];
+        let mut events = all_keys.into_iter().map(|key| Ok(Event::Key(key)));
+        let mut built = pick_and_build(
+            &mut out,
+            &mut events,
+            &commands,
+            &settings,
+            &mut History::default(),
+        )?
+        .unwrap();
+        let action = final_action(&mut out, &mut events, &mut built, &settings, default)?;
+        Ok((built.command, action))
+    }
+
+    #[test]
+    fn final_menu_actions() {
+        assert_eq!(
+            ("grep -i foo".to_string(), FinalAction::Print),
+            final_action_keys(vec![Key::Char('\n')], FinalAction::Print).unwrap()
+        );
+        assert_eq!(
+            ("grep -i foo".to_string(), FinalAction::Run),
+            final_action_keys(vec![Key::Char('\n')], FinalAction::Run).unwrap()
+        );
+        assert_eq!(
+            FinalAction::Copy,
+            final_action_keys(vec![Key::Char('c')], FinalAction::Print)
+                .unwrap()
+                .1
+        );
+        assert!(matches!(
+            final_action_keys(vec![Key::Ctrl('d')], FinalAction::Print),
+            Err(SnovaError::Cancelled)
+        ));
+        assert!(matches!(
+            final_action_keys(vec![Key::Ctrl('c')], FinalAction::Print),
+            Err(SnovaError::Interrupted)
+        ));
+    }
+
+    #[test]
+    fn final_menu_edit() {
+        // Edited command goes back to the menu
+        let mut keys = vec![Key::Char('e'), Key::Ctrl('u')];
+        keys.extend(type_keys("grep -r foo .\n"));
+        keys.push(Key::Char('r'));
+        assert_eq!(
+            ("grep -r foo .".to_string(), FinalAction::Run),
+            final_action_keys(keys, FinalAction::Print).unwrap()
+        );
+        // Cancelled edit keeps the command
+        let keys = vec![
+            Key::Char('e'),
+            Key::Char('x'),
+            Key::Ctrl('d'),
+            Key::Char('\n'),
+        ];
+        assert_eq!(
+            ("grep -i foo".to_string(), FinalAction::Print),
+            final_action_keys(keys, FinalAction::Print).unwrap()
+        );
+    }
+
+    #[test]
+    fn edit_built_command() {
+        let edit_keys = |keys: Vec<Key>| -> error::Result<String> {
+            let commands = grep_commands();
+            let mut all_keys = type_keys("grep\nfoo\n");
+            all_keys.extend(vec![Key::Char('\n'), Key::Ctrl('s')]);
+            all_keys.extend(keys);
+            let settings = test_settings();
+            let mut out = vec![];
+            let mut events = all_keys.into_iter().map(|key| Ok(Event::Key(key)));
+            let mut built = pick_and_build(
+                &mut out,
+                &mut events,
+                &commands,
+                &settings,
+                &mut History::default(),
+            )?
+            .unwrap();
+            edit_command(&mut out, &mut events, &mut built, &settings)?;
+            Ok(built.command)
+        };
+        let mut keys = vec![Key::Backspace];
+        keys.extend(type_keys("x\n"));
+        assert_eq!("grep -i fox", edit_keys(keys).unwrap());
+        // Ctrl-D keeps the built command
+        assert_eq!(
+            "grep -i foo",
+            edit_keys(vec![Key::Char('x'), Key::Ctrl('d')]).unwrap()
+        );
+        assert!(matches!(
+            edit_keys(vec![Key::Ctrl('c')]),
+            Err(SnovaError::Interrupted)
+        ));
+    }
+
+    /// Split the line the way the shell does
+    fn shell_split(line: &str) -> Vec<String> {
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(format!("printf '%s\\0' {}", line))
+            .output()
+            .unwrap();
+        let mut args: Vec<String> = String::from_utf8(output.stdout)
+            .unwrap()
+            .split('\0')
+            .map(String::from)
+            .collect();
+        args.pop();
+        args
+    }
+
+    #[test]
+    fn plain_prompts() {
+        let commands = grep_commands();
+        let mut out = vec![];
+        let mut input = std::io::Cursor::new("x\n5\n1\n\nfoo bar\n-i\n\n");
+        let built = build_plain(&mut out, &mut input, &commands, &test_settings())
+            .unwrap()
+            .unwrap();
+        assert_eq!("grep -i 'foo bar'", built.command);
+        assert_eq!(
+            Some(&GroupAnswer::Single("foo bar".into())),
+            built.values.get("PATTERN")
+        );
+        assert!(!built.values.contains_key("PATH"));
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(
+            out.contains("  1) Find lines in a file (grep)\n"),
+            "{}",
+            out
+        );
+        assert_eq!(
+            2,
+            out.matches("Type a number from 1 to 1").count(),
+            "{}",
+            out
+        );
+        assert!(out.contains("  -i  Case insensitive matching\n"), "{}", out);
+        assert!(out.contains("PATTERN is required"), "{}", out);
+        assert!(!out.contains('\x1b'), "{}", out);
+    }
+
+    #[test]
+    fn plain_prompts_closed_input() {
+        let commands = grep_commands();
+        let mut input = std::io::Cursor::new("1\n");
+        let built = build_plain(&mut vec![], &mut input, &commands, &test_settings()).unwrap();
+        assert!(built.is_none());
+        let mut input = std::io::Cursor::new("1\n\n");
+        let result = build_plain(&mut vec![], &mut input, &[], &test_settings());
+        assert!(matches!(result, Err(SnovaError::EmptyChoices)));
+    }
+
+    #[test]
+    fn replay_round_trip() {
+        let commands = grep_commands();
+        let mut keys = type_keys("grep\nit's $HOME\n");
+        keys.extend(vec![Key::Char('\n'), Key::Ctrl('s')]);
+        let mut out = vec![];
+        let mut events = keys.into_iter().map(|key| Ok(Event::Key(key)));
+        let built = pick_and_build(
+            &mut out,
+            &mut events,
+            &commands,
+            &test_settings(),
+            &mut History::default(),
+        )
+        .unwrap()
+        .unwrap();
+
+        let line = replay_line(&built);
+        assert_eq!(
+            "snova run 'Find lines in a file (grep)' --set OPTIONS=-i --set PATTERN='it'\\''s $HOME'",
+            line
+        );
+        let args = shell_split(&line);
+        assert_eq!(
+            vec!["snova", "run", "Find lines in a file (grep)"],
+            args[..3]
+        );
+        let args: Vec<&str> = args.iter().map(|a| a.as_ref()).collect();
+        let run = RunArgs::parse(&args[3..]).unwrap();
+        let cmd = find_command(&commands, args[2]).unwrap();
+        assert_eq!(built.command, fill_from_values(cmd, &run.values).unwrap());
+    }
+
+    #[test]
+    fn json_output_argv() {
+        let commands = grep_commands();
+        let mut keys = type_keys("grep\nit's \"$HOME\"\n");
+        keys.extend(vec![Key::Char('\n'), Key::Ctrl('s')]);
+        let mut out = vec![];
+        let mut events = keys.into_iter().map(|key| Ok(Event::Key(key)));
+        let built = pick_and_build(
+            &mut out,
+            &mut events,
+            &commands,
+            &test_settings(),
+            &mut History::default(),
+        )
+        .unwrap()
+        .unwrap();
+
+        let json: serde_json::Value = serde_json::from_str(&json_output(&built).unwrap()).unwrap();
+        assert_eq!(
+            serde_json::json!({
+                "command": built.command,
+                "argv": shell_split(&built.command),
+                "template": "grep [_OPTIONS_] _PATTERN_ [_PATH_]",
+                "values": { "OPTIONS": "-i", "PATTERN": "it's \"$HOME\"" },
+            }),
+            json
+        );
+        assert_eq!(
+            serde_json::json!(["grep", "-i", "it's \"$HOME\""]),
+            json["argv"]
+        );
+    }
+
+    #[test]
+    fn esc_on_flag_value() {
+        let commands = parser::parse_str(
+            r#"
+[[commands]]
+template = "tar [_OPTIONS_] _FILE_"
+description = "Create an archive (*tar*)"
+groups.FILE.expect = "path"
+groups.OPTIONS.flags = [
+  { template = "-c", description = "Create archive" },
+  { template = "--exclude _PATTERN_", description = "Exclude files", expect = "string" },
+]
+"#,
+        )
+        .unwrap();
+        // Exclude is not added when its value prompt is left with Esc
+        let mut keys = type_keys("tar\nout.tar\nExclude\n*.l");
+        keys.push(Key::Esc);
+        keys.extend(type_keys("Create\n"));
+        keys.push(Key::Ctrl('s'));
+        let result = run_keys(&commands, keys).unwrap();
+        assert_eq!(Some("tar -c out.tar".to_string()), result);
+    }
+
+    #[test]
+    fn build_result_answers() {
+        let commands = parser::parse_str(
+            r#"
+[[commands]]
+template = "tar [_OPTIONS_] _FILE_"
+description = "Create an archive (*tar*)"
+groups.FILE.expect = "path"
+groups.OPTIONS.flags = [
+  { template = "-c", description = "Create archive" },
+  { template = "-z", description = "Compress with gzip" },
+  { template = "--exclude _PATTERN_", description = "Exclude files", expect = "string", multiple = true },
+]
+"#,
+        )
+        .unwrap();
+        let mut keys = type_keys("tar\nout.tar\nCreate\nExclude\n*.log\nExclude\ntmp\n");
+        keys.push(Key::Ctrl('s'));
+        let mut out = vec![];
+        let mut events = keys.into_iter().map(|key| Ok(Event::Key(key)));
+        let built = pick_and_build(
+            &mut out,
+            &mut events,
+            &commands,
+            &test_settings(),
+            &mut History::default(),
+        )
+        .unwrap()
+        .unwrap();
+
+        let mut values = BTreeMap::new();
+        values.insert(
+            "FILE".to_string(),
+            GroupAnswer::Single("out.tar".to_string()),
+        );
+        values.insert(
+            "OPTIONS".to_string(),
+            GroupAnswer::Flags(vec![
+                "-c".to_string(),
+                "--exclude '*.log'".to_string(),
+                "--exclude tmp".to_string(),
+            ]),
+        );
+        assert_eq!(
+            BuildResult {
+                command: "tar -c --exclude '*.log' --exclude tmp out.tar".to_string(),
+                template: "tar [_OPTIONS_] _FILE_".to_string(),
+                description: "Create an archive (tar)".to_string(),
+                name: "Create an archive (tar)".to_string(),
+                values,
+                post_build: None,
+            },
+            built
+        );
+    }
+
+    #[test]
+    fn fill_from_values_errors() {
+        let commands = grep_commands();
+        let cmd = find_command(&commands, "grep [_OPTIONS_] _PATTERN_ [_PATH_]").unwrap();
+        let mut values = PresetValues::new();
+        assert!(fill_from_values(cmd, &values).is_err());
+        values.insert("PATTERN".to_string(), "foo".to_string());
+        assert_eq!("grep foo", fill_from_values(cmd, &values).unwrap());
+        values.insert("PORT".to_string(), "80".to_string());
+        assert!(fill_from_values(cmd, &values).is_err());
+
+        assert!(find_command(&commands, "Find lines").is_err());
+        assert!(RunArgs::parse(&["--set", "PATTERN"]).is_err());
+        assert!(RunArgs::parse(&["PATTERN=foo"]).is_err());
+        assert!(RunArgs::parse(&["--preset"]).is_err());
+    }
+
+    #[test]
+    fn fill_by_description_part() {
+        let mut commands = grep_commands();
+        commands.extend(head_commands());
+        let cmd = match_command(&commands, "first lines").unwrap();
+        assert_eq!("head -n _NUM_ _PATH_", cmd.template);
+        assert!(match_command(&commands, "tail").is_err());
+        // Both descriptions contain the letter
+        let err = match_command(&commands, "e").err().unwrap().to_string();
+        assert!(err.starts_with("Several commands match"), "{}", err);
+
+        let values = RunArgs::assignments(&["NUM=abc", "PATH=a=b.txt"])
+            .unwrap()
+            .values;
+        assert_eq!(Some("a=b.txt"), values.get("PATH").map(String::as_str));
+        let err = fill_from_values(cmd, &values).unwrap_err().to_string();
+        assert!(err.contains("Value of NUM"), "{}", err);
+        let values = RunArgs::assignments(&["PATH=a.txt"]).unwrap().values;
+        let err = fill_from_values(cmd, &values).unwrap_err().to_string();
+        assert!(err.contains("Missing value for NUM"), "{}", err);
+        assert!(RunArgs::assignments(&["NUM"]).is_err());
+        assert!(RunArgs::assignments(&["--set", "NUM=1"]).is_err());
+    }
+
+    #[test]
+    fn preset_round_trip() {
+        let dir = temp_dir("preset-round-trip");
+        let paths = Paths {
+            config_dir: None,
+            data_dir: Some(dir.clone()),
+            script_dirs: vec![],
+        };
+        let commands = grep_commands();
+        let mut keys = type_keys("grep\nfoo bar\n");
+        keys.extend(vec![Key::Char('\n'), Key::Ctrl('s')]);
+        let mut out = vec![];
+        let mut events = keys.into_iter().map(|key| Ok(Event::Key(key)));
+        let built = pick_and_build(
+            &mut out,
+            &mut events,
+            &commands,
+            &test_settings(),
+            &mut History::default(),
+        )
+        .unwrap()
+        .unwrap();
+        save_preset(&paths, &built, "work", &Messages::default()).unwrap();
+
+        let presets = Presets::open(&dir).unwrap();
+        assert_eq!(
+            vec![(commands[0].template.as_str(), "work")],
+            presets.list()
+        );
+        let prefill = presets.get(&commands[0].template, "work").unwrap();
+
+        // Pre-filled values are accepted as is
+        let keys = vec![Key::Char('\n'), Key::Ctrl('s')];
+        let mut events = keys.into_iter().map(|key| Ok(Event::Key(key)));
+        let result = fill_and_confirm(
+            &mut out,
+            &mut events,
+            &commands[0],
+            &test_settings(),
+            &mut History::default(),
+            prefill,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!("grep -i 'foo bar'", result.command);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn secret_values_not_stored() {
+        let commands = parser::parse_str(
+            r#"
+[[commands]]
+template = "login _USER_ _TOKEN_"
+description = "Log in"
+groups.USER.expect = "string"
+groups.TOKEN.expect = "string"
+groups.TOKEN.secret = true
+"#,
+        )
+        .unwrap();
+        let mut out = vec![];
+        let keys = type_keys("login\nme\nhunter2\n");
+        let mut events = keys.into_iter().map(|key| Ok(Event::Key(key)));
+        let mut history = History::default();
+        let built = pick_and_build(
+            &mut out,
+            &mut events,
+            &commands,
+            &test_settings(),
+            &mut history,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!("login me hunter2", built.command);
+        assert_eq!(
+            vec![("USER", GroupAnswer::Single("me".to_string()))],
+            built
+                .values
+                .iter()
+                .map(|(group, answer)| (group.as_str(), answer.clone()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(&["me"], history.values("login _USER_ _TOKEN_", "USER"));
+        assert!(history.values("login _USER_ _TOKEN_", "TOKEN").is_empty());
+    }
+
+    #[test]
+    fn finish_unavailable_for_required_groups() {
+        let commands = grep_commands();
+        let mut keys = type_keys("grep\n");
+        // Ctrl-S is ignored while PATTERN is not filled
+        keys.push(Key::Ctrl('s'));
+        keys.extend(type_keys("foo\n"));
+        keys.push(Key::Ctrl('s'));
+        let result = run_keys(&commands, keys).unwrap();
+        assert_eq!(Some("grep foo".to_string()), result);
+    }
+
+    fn parse_args(args: &[&str]) -> Result<Args> {
+        Args::parse(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn args_parse() {
+        assert_eq!(parse_args(&[]).unwrap(), Args::default());
+        let args =
+            parse_args(&["--config", "/cfg", "--verbose", "history", "clear-values"]).unwrap();
+        assert_eq!(args.config, Some("/cfg".into()));
+        assert!(args.verbose);
+        assert_eq!(args.command, vec!["history", "clear-values"]);
+        assert!(parse_args(&["--config"]).is_err());
+        assert!(parse_args(&["--no-menu"]).unwrap().no_menu);
+        assert!(parse_args(&["-x"]).unwrap().execute);
+        assert!(parse_args(&["--execute"]).unwrap().execute);
+        assert!(parse_args(&["--copy"]).unwrap().copy);
+        assert!(parse_args(&["--edit"]).unwrap().edit);
+        assert!(parse_args(&["--format", "json"]).unwrap().json);
+        assert!(parse_args(&["--format"]).is_err());
+        assert!(parse_args(&["--format", "yaml"]).is_err());
+    }
+
+    #[test]
+    fn args_parse_subcommand() {
+        // Subcommand arguments are not taken for global flags
+        let args = parse_args(&["-x", "export", "--format", "json", "-n"]).unwrap();
+        assert!(args.execute);
+        assert!(!args.json);
+        assert!(!args.no_newline);
+        assert_eq!(args.command, vec!["export", "--format", "json", "-n"]);
+        let args = parse_args(&["--verbose", "--", "--config", "x"]).unwrap();
+        assert!(args.verbose);
+        assert_eq!(None, args.config);
+        assert_eq!(args.command, vec!["--config", "x"]);
+    }
+
+    #[test]
+    fn args_parse_terminator() {
+        assert_eq!("\n", parse_args(&[]).unwrap().terminator());
+        assert_eq!("\0", parse_args(&["--print0"]).unwrap().terminator());
+        assert_eq!("", parse_args(&["-n"]).unwrap().terminator());
+        assert_eq!("", parse_args(&["--no-newline"]).unwrap().terminator());
+        assert!(parse_args(&["--print0", "-x"]).is_err());
+        assert!(parse_args(&["--execute", "--print0"]).is_err());
+        assert!(parse_args(&["--print0", "-n"]).is_err());
+        assert!(parse_args(&["-n", "-x"]).is_ok());
+    }
+
+    #[test]
+    fn args_parse_replay() {
+        let args = parse_args(&["--replay", "keys.txt", "--replay-speed", "2.5"]).unwrap();
+        assert_eq!(args.replay, Some("keys.txt".into()));
+        assert_eq!(args.replay_speed, Some(2.5));
+        assert!(parse_args(&["--replay"]).is_err());
+        assert!(parse_args(&["--replay", "keys.txt", "--replay-speed", "0"]).is_err());
+        assert!(parse_args(&["--replay", "keys.txt", "--replay-speed", "fast"]).is_err());
+        assert!(parse_args(&["--replay-speed", "2"]).is_err());
+    }
+
+    #[test]
+    fn args_parse_out() {
+        let args = parse_args(&["--out", "/tmp/cmd", "--quiet", "--mkdirs"]).unwrap();
+        assert_eq!(args.out, Some(OutTarget::File("/tmp/cmd".into())));
+        assert!(args.quiet);
+        assert!(args.mkdirs);
+        assert!(args.command.is_empty());
+        assert!(parse_args(&["--show-replay"]).unwrap().show_replay);
+        let args = parse_args(&["--out", "-"]).unwrap();
+        assert_eq!(args.out, Some(OutTarget::Stdout));
+        assert!(parse_args(&["--out"]).is_err());
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("snova-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn write_out_atomic() {
+        let dir = temp_dir("out");
+        let target = dir.join("nested/cmd.sh");
+        // Parent directories are created on request only
+        assert!(write_atomic(&target, "ls\n", false).is_err());
+        write_atomic(&target, "ls\n", true).unwrap();
+        write_atomic(&target, "ls -la\n", false).unwrap();
+        assert_eq!("ls -la\n", std::fs::read_to_string(&target).unwrap());
+        // No temporary files are left behind
+        let entries: Vec<_> = std::fs::read_dir(dir.join("nested")).unwrap().collect();
+        assert_eq!(1, entries.len());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn head_commands() -> Vec<Command> {
+        let defs = toml::de::from_str(
+            r#"
+[[commands]]
+template = "head -n _NUM_ _PATH_"
+description = "Print first lines (*head*)"
+groups.NUM.expect = "number"
+groups.NUM.suggest = ["auto", "10"]
+groups.PATH.expect = "path"
+"#,
+        )
+        .unwrap();
+        parser::parse_defs(defs).unwrap()
+    }
+
+    #[test]
+    fn reprompt_invalid_value() {
+        let commands = head_commands();
+        // Pick "auto" that is not a number, then type a valid value
+        let keys = type_keys("head\n\n5\nfile.txt\n");
+        let result = run_keys(&commands, keys).unwrap();
+        assert_eq!(Some("head -n 5 file.txt".to_string()), result);
+    }
+
+    #[test]
+    fn reprompt_value_length() {
+        let commands: Vec<Command> = parser::parse_defs(
+            toml::de::from_str(
+                r#"
+[[commands]]
+template = "git commit -m _SUBJECT_"
+description = "Commit"
+groups.SUBJECT.expect = "string"
+groups.SUBJECT.min_len = 3
+groups.SUBJECT.max_len = 10
+"#,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        // Too short, then too long, then exactly the max
+        let mut keys = type_keys("Commit\nab\n");
+        keys.push(Key::Ctrl('u'));
+        keys.extend(type_keys("abcdefghijk\n"));
+        keys.push(Key::Ctrl('u'));
+        keys.extend(type_keys("abcdefghij\n"));
+        let result = run_keys(&commands, keys).unwrap();
+        assert_eq!(Some("git commit -m abcdefghij".to_string()), result);
+
+        // Multi-byte chars count as one
+        let result = run_keys(&commands, type_keys("Commit\nüäöüäöüäöü\n")).unwrap();
+        assert_eq!(Some("git commit -m üäöüäöüäöü".to_string()), result);
+        let result = run_keys(&commands, type_keys("Commit\näö\n"));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn number_leading_zeros_stripped() {
+        let commands = head_commands();
+        let keys = type_keys("head\n007\nfile.txt\n");
+        let result = run_keys(&commands, keys).unwrap();
+        assert_eq!(Some("head -n 7 file.txt".to_string()), result);
+    }
+
+    #[test]
+    fn reprompt_number_overflow() {
+        let commands = head_commands();
+        let mut keys = type_keys("head\n");
+        keys.extend(type_keys(&"9".repeat(40)));
+        keys.push(Key::Char('\n'));
+        keys.push(Key::Ctrl('u'));
+        keys.extend(type_keys("5\nfile.txt\n"));
+        let result = run_keys(&commands, keys).unwrap();
+        assert_eq!(Some("head -n 5 file.txt".to_string()), result);
+    }
+
+    #[test]
+    fn clipboard_suggestion() {
+        let commands = head_commands();
+        let settings = Settings {
+            clipboard: Some(|| Some("42".to_string())),
+            runner: subprocess::Runner::default(),
+            escalate: None,
+            ..test_settings()
+        };
+        let mut history = History::default();
+
+        // Clipboard is suggested first but not preselected
+        let keys = type_keys("head\n\n10\nfile.txt\n");
+        let result = run_keys_with_settings(&commands, keys.clone(), &mut history, &settings);
+        assert_eq!(Some("head -n 10 file.txt".to_string()), result.unwrap());
+
+        let mut keys = type_keys("head\n");
+        keys.push(Key::Up);
+        keys.extend(type_keys("\nfile.txt\n"));
+        let result = run_keys_with_settings(&commands, keys, &mut history, &settings);
+        assert_eq!(Some("head -n 42 file.txt".to_string()), result.unwrap());
+    }
+
+    #[test]
+    fn clipboard_suggestion_truncated() {
+        let long = "x".repeat(100);
+        let suggestion = Suggestion::clipboard(&long, "(clipboard) ");
+        assert_eq!(long, suggestion.value);
+        assert_eq!(
+            format!("(clipboard) {}…", "x".repeat(CLIPBOARD_DISPLAY_CHARS - 1)),
+            suggestion.text
+        );
+        assert_eq!(
+            "(clipboard) ok",
+            Suggestion::clipboard("ok", "(clipboard) ").text
+        );
+    }
+
+    #[test]
+    fn reprompt_retries_exceeded() {
+        let commands = head_commands();
+        let keys = type_keys("head\n\n\n\n\n");
+        let result = run_keys(&commands, keys);
+        assert!(result.is_err());
+        assert_eq!(
+            "No valid value for NUM: 'auto' is not a valid Number value",
+            format!("{}", result.unwrap_err())
+        );
+    }
+
+    #[test]
+    fn history_suggestions() {
+        let commands = head_commands();
+        let template = &commands[0].template;
+        let mut history = History::default();
+        history.add(template, "NUM", "5");
+        history.add(template, "PATH", "a.txt");
+
+        // Previous values come after defined suggestions
+        let mut keys = type_keys("head\n");
+        keys.extend(vec![Key::Down, Key::Down]);
+        keys.extend(type_keys("\n\n"));
+        let result = run_keys_with_history(&commands, keys, &mut history).unwrap();
+        assert_eq!(Some("head -n 5 a.txt".to_string()), result);
+
+        let keys = type_keys("head\n20\nb.txt\n");
+        run_keys_with_history(&commands, keys, &mut history).unwrap();
+        assert_eq!(history.values(template, "NUM"), &["20", "5"]);
+        assert_eq!(history.values(template, "PATH"), &["b.txt", "a.txt"]);
+    }
+
+    #[test]
+    fn history_values_across_commands() {
+        let commands = parser::parse_str(
+            r#"
+[[commands]]
+template = "curl _URL_"
+description = "Fetch a URL (curl)"
+groups.URL = { expect = "string", suggest = "history_values" }
+
+[[commands]]
+template = "wget _URL_"
+description = "Download a URL (wget)"
+groups.URL = { expect = "string", suggest = "history_values" }
+"#,
+        )
+        .unwrap();
+        let mut history = History::default();
+        let keys = type_keys("curl\nhttps://example.com\n");
+        run_keys_with_history(&commands, keys, &mut history).unwrap();
+
+        // Enter picks the value submitted to curl
+        let keys = type_keys("wget\n\n");
+        let result = run_keys_with_history(&commands, keys, &mut history).unwrap();
+        assert_eq!(Some("wget https://example.com".to_string()), result);
+    }
+
+    #[test]
+    fn cancel_value() {
+        let commands = head_commands();
+        let mut keys = type_keys("head\n");
+        // Cancel the value and then the picker
+        keys.extend(vec![Key::Ctrl('d'), Key::Ctrl('d')]);
+        let result = run_keys(&commands, keys).unwrap();
+        assert_eq!(None, result);
+    }
+
+    #[test]
+    fn cancel_then_pick_another() {
+        let mut commands = head_commands();
+        commands.append(&mut grep_commands());
+
+        // Cancel head command and pick grep
+        let mut keys = type_keys("head\n");
+        keys.push(Key::Ctrl('d'));
+        keys.extend(type_keys("grep\nfoo\n"));
+        keys.push(Key::Ctrl('s'));
+        let result = run_keys(&commands, keys).unwrap();
+        assert_eq!(Some("grep foo".to_string()), result);
+    }
+
+    #[test]
+    fn esc_on_first_group() {
+        let mut commands = head_commands();
+        commands.append(&mut grep_commands());
+
+        let mut keys = type_keys("head\n");
+        keys.push(Key::Esc);
+        keys.extend(type_keys("grep\nfoo\n"));
+        // Esc on the second group goes back to the first with its value
+        keys.push(Key::Esc);
+        keys.extend(type_keys("d\n"));
+        keys.push(Key::Ctrl('s'));
+        let result = run_keys(&commands, keys).unwrap();
+        assert_eq!(Some("grep food".to_string()), result);
+    }
+
+    #[test]
+    fn back_to_flags_group() {
+        let commands = grep_commands();
+        // Pick -i and -v, then go back from PATH and remove -i
+        let mut keys = type_keys("grep\nfoo\n\n\n");
+        keys.push(Key::Esc);
+        keys.extend(type_keys("remove: -i\n"));
+        keys.push(Key::Ctrl('s'));
+        let result = run_keys(&commands, keys).unwrap();
+        assert_eq!(Some("grep -v foo".to_string()), result);
+    }
+
+    #[test]
+    fn remove_flag_with_value() {
+        let commands = parser::parse_str(
+            r#"
+[[commands]]
+template = "ls [_OPTIONS_]"
+description = "List files"
+groups.OPTIONS.flags = [
+  { template = "-l", description = "Long listing" },
+  { template = "*-I* _PATTERN_", description = "Ignore entries matching _PATTERN_", expect = "string" },
+]
+"#,
+        )
+        .unwrap();
+        let mut keys = type_keys("ls\nignore\ntmp\nremove\nlong\n");
+        keys.push(Key::Ctrl('s'));
+        let mut out = vec![];
+        let mut events = keys.into_iter().map(|key| Ok(Event::Key(key)));
+        let built = pick_and_build(
+            &mut out,
+            &mut events,
+            &commands,
+            &test_settings(),
+            &mut History::default(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!("ls -l", built.command);
+        let out = String::from_utf8(out).unwrap();
+        let entry = format!(
+            "remove: -I tmp  Ignore entries matching {}PATTERN{}",
+            style::Underline,
+            style::NoUnderline
+        );
+        assert!(out.contains(&entry), "{}", out);
+    }
+
+    #[test]
+    fn skip_optional_group() {
+        let commands = parser::parse_str(
+            r#"
+[[commands]]
+template = "tar [_COMPRESSION_] -cf _ARCHIVE_ _PATH_"
+description = "Create an archive"
+groups.COMPRESSION.expect = "string"
+groups.ARCHIVE.expect = "path"
+groups.PATH.expect = "path"
+"#,
+        )
+        .unwrap();
+        // Optional COMPRESSION is asked last
+        let result = run_keys(&commands, type_keys("tar\na.tar\nsrc\n\n")).unwrap();
+        assert_eq!(Some("tar -cf a.tar src".to_string()), result);
+
+        let mut keys = type_keys("tar\na.tar\nsrc\n");
+        keys.push(Key::Ctrl('d'));
+        let result = run_keys(&commands, keys).unwrap();
+        assert_eq!(Some("tar -cf a.tar src".to_string()), result);
+
+        let result = run_keys(&commands, type_keys("tar\na.tar\nsrc\n-z\n")).unwrap();
+        assert_eq!(Some("tar -z -cf a.tar src".to_string()), result);
+    }
+
+    #[test]
+    fn empty_required_value() {
+        let commands = parser::parse_str(
+            r#"
+[[commands]]
+template = "echo _TEXT_"
+description = "Print text"
+groups.TEXT.expect = "string"
+"#,
+        )
+        .unwrap();
+        // Empty value doesn't count as a failed attempt
+        let mut settings = test_settings();
+        settings.retries = Some(0);
+        let mut ui = vec![];
+        let mut events = type_keys("echo\n\nhi\n")
+            .into_iter()
+            .map(|key| Ok(Event::Key(key)));
+        let built = pick_and_build(
+            &mut ui,
+            &mut events,
+            &commands,
+            &settings,
+            &mut History::default(),
+        )
+        .unwrap();
+        assert_eq!("echo hi", built.unwrap().command);
+        assert!(String::from_utf8_lossy(&ui).contains("TEXT is required"));
+    }
+
+    #[test]
+    fn restart_from_group() {
+        let mut commands = grep_commands();
+        commands.extend(head_commands());
+        // Abandoned command is preselected in the picker
+        let mut keys = type_keys("head\n5");
+        keys.push(Key::Ctrl('g'));
+        keys.extend(type_keys("\n10\nlog.txt\n"));
+        let result = run_keys(&commands, keys).unwrap();
+        assert_eq!(Some("head -n 10 log.txt".to_string()), result);
+
+        // Also from the flags picker
+        let mut keys = type_keys("grep\nfoo\n");
+        keys.push(Key::Ctrl('g'));
+        keys.extend(type_keys("head\n10\nlog.txt\n"));
+        let result = run_keys(&commands, keys).unwrap();
+        assert_eq!(Some("head -n 10 log.txt".to_string()), result);
+    }
+
+    #[test]
+    fn no_commands_to_pick() {
+        let err = run_keys(&[], type_keys("\n")).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SnovaError>(),
+            Some(SnovaError::EmptyChoices)
+        ));
+    }
+
+    #[test]
+    fn escalation_for_non_root() {
+        assert_eq!(Some("sudo".to_string()), escalation_prefix(None, || false));
+        assert_eq!(
+            Some("doas".to_string()),
+            escalation_prefix(Some("doas"), || false)
+        );
+        assert_eq!(None, escalation_prefix(Some("doas"), || true));
+    }
+
+    #[test]
+    fn escalate_command_that_needs_root() {
+        let defs = toml::de::from_str(
+            r#"
+[[commands]]
+template = "systemctl restart _SERVICE_"
+description = "Restart a service"
+needs_root = true
+groups.SERVICE.expect = "string"
+
+[[commands]]
+template = "systemctl status _SERVICE_"
+description = "Show service status"
+groups.SERVICE.expect = "string"
+"#,
+        )
+        .unwrap();
+        let commands = parser::parse_defs(defs).unwrap();
+        let run = |keys: &str, is_root: bool| {
+            let mut settings = test_settings();
+            settings.escalate = escalation_prefix(Some("doas"), || is_root);
+            run_keys_with_settings(
+                &commands,
+                type_keys(keys),
+                &mut History::default(),
+                &settings,
+            )
+            .unwrap()
+        };
+        assert_eq!(
+            Some("doas systemctl restart api".to_string()),
+            run("restart\napi\n", false)
+        );
+        assert_eq!(
+            Some("systemctl status api".to_string()),
+            run("status\napi\n", false)
+        );
+        // Root runs the command as is
+        assert_eq!(
+            Some("systemctl restart api".to_string()),
+            run("restart\napi\n", true)
+        );
+    }
+
+    #[test]
+    fn cancel_picker() {
+        let commands = head_commands();
+        let result = run_keys(&commands, vec![Key::Ctrl('d')]).unwrap();
+        assert_eq!(None, result);
+    }
+
+    fn commands() -> Vec<Command> {
+        let defs = toml::de::from_str(
+            r#"
+[[commands]]
+template = "tar -xf _ARCHIVE_"
+description = "Unpack an archive (*tar*)"
+keywords = ["extract", "untar"]
+examples = [{ description = "Unpack into a directory", command = "tar -xf site.tar -C /srv" }]
+groups.ARCHIVE.expect = "path"
+
+[[commands]]
+template = "unzip [_OPTIONS_] _ARCHIVE_"
+long_description = "Extract files from a zip archive"
+example = "unzip -o files.zip"
+groups.OPTIONS.flags = [
+  { template = "-o", description = "Overwrite files" },
+  { template = "-q", description = "Quiet mode" },
+]
+description = "Decompress a zip file"
+groups.ARCHIVE.expect = "path"
+"#,
+        )
+        .unwrap();
+        parser::parse_defs(defs).unwrap()
+    }
+
+    #[test]
+    fn progress_breadcrumbs() {
+        let names = vec!["PATTERN", "PATH", "OPTIONS"];
+        let palette = Palette::default();
+        assert_eq!(
+            "[1/3] *PATTERN* ▸ PATH ▸ OPTIONS",
+            progress(&names, 0, palette)
+        );
+        assert_eq!(
+            format!(
+                "[3/3] {}PATTERN{} ▸ {}PATH{} ▸ *OPTIONS*",
+                style::Italic,
+                style::NoItalic,
+                style::Italic,
+                style::NoItalic
+            ),
+            progress(&names, 2, palette)
+        );
+        assert_eq!("[1/1] *URL*", progress(&["URL"], 0, palette));
+        let light = Palette::new(Background::Light);
+        assert_eq!(
+            format!("[2/3] {} ▸ *PATH* ▸ OPTIONS", light.counter("PATTERN")),
+            progress(&names, 1, light)
+        );
+    }
+
+    #[test]
+    fn pick_command_by_keyword() {
+        let commands = commands();
+        let mut complete = FixedComplete::new(&commands);
+
+        let found = complete.list("extract");
+        assert_eq!(1, found.len());
+        assert_eq!("Unpack an archive (*tar*)", found[0].text());
+
+        // Keywords are not displayed
+        assert!(!found[0].text().contains("extract"));
+    }
+
+    #[test]
+    fn pick_command_by_template() {
+        let commands = commands();
+        let mut complete = FixedComplete::new(&commands);
+
+        // Only the template matches
+        let found = complete.list("-xf");
+        assert_eq!(1, found.len());
+        assert_eq!("tar -xf _ARCHIVE_", found[0].template);
+        assert_eq!(
+            Some("tar -xf _ARCHIVE_"),
+            view::matched_hint(found[0], "-xf", CaseMatching::Smart)
+        );
+
+        // Template markup is ignored
+        let found = complete.list("-xf archive");
+        assert_eq!(1, found.len());
+    }
+
+    #[test]
+    fn command_preview() {
+        let commands = commands();
+        assert_eq!(
+            Some(
+                "tar -xf _ARCHIVE_\n\
+                 Unpack into a directory: tar -xf site.tar -C /srv"
+                    .to_string()
+            ),
+            commands[0].preview()
+        );
+        assert_eq!(
+            Some(
+                "unzip [_OPTIONS_] _ARCHIVE_\n\
+                 Extract files from a zip archive\n\
+                 OPTIONS: -o, -q\n\
+                 e.g unzip -o files.zip"
+                    .to_string()
+            ),
+            commands[1].preview()
+        );
+    }
+
+    #[test]
+    fn list_commands_plain() {
+        let commands = commands();
+        assert_eq!(
+            "Unpack an archive (tar) [builtin]\n  tar -xf ARCHIVE\n\
+             Decompress a zip file [builtin]\n  unzip [OPTIONS] ARCHIVE\n",
+            plain_list(&commands, false, None)
+        );
+        assert_eq!(
+            "Unpack an archive (tar) [builtin]\n  tar -xf ARCHIVE\n\
+             \x20   Unpack into a directory: tar -xf site.tar -C /srv\n\
+             Decompress a zip file [builtin]\n  unzip [OPTIONS] ARCHIVE\n",
+            plain_list(&commands, true, None)
+        );
+    }
+
+    #[test]
+    fn list_commands_source_and_style() {
+        let mut commands = commands();
+        commands.truncate(1);
+        commands[0].source = CommandSource::User("/home/me/.config/snova/commands.toml".into());
+        assert_eq!(
+            "Unpack an archive (tar) [user /home/me/.config/snova/commands.toml]\n  tar -xf ARCHIVE\n",
+            plain_list(&commands, false, None)
+        );
+        assert_eq!(
+            format!(
+                "{} [user /home/me/.config/snova/commands.toml]\n  {}\n",
+                Palette::default().fmt_text(&commands[0].description),
+                Palette::default().fmt_text(&commands[0].template)
+            ),
+            plain_list(&commands, false, Some(Palette::default()))
+        );
+    }
+
+    #[test]
+    fn source_kinds() {
+        assert_eq!("project", source_kind(Some(&"project")).unwrap());
+        assert_eq!(
+            "Unknown source 'repo', expected one of builtin, user, project, imported",
+            source_kind(Some(&"repo")).unwrap_err().to_string()
+        );
+        assert!(source_kind(None).is_err());
+    }
+
+    #[test]
+    fn list_commands_markdown() {
+        let commands = commands();
+        assert_eq!(
+            "# Commands\n\
+             \n## Unpack an archive (tar)\n\n`tar -xf ARCHIVE`\n\
+             \nExamples:\n\n- Unpack into a directory: `tar -xf site.tar -C /srv`\n\
+             \n## Decompress a zip file\n\n`unzip [OPTIONS] ARCHIVE`\n\
+             \nExtract files from a zip archive\n",
+            markdown_list(&commands)
+        );
+    }
+
+    #[test]
+    fn pick_command_by_description_and_template() {
+        let commands = commands();
+        let mut complete = FixedComplete::new(&commands);
+
+        // Both description and template match: no need to show the template
+        let found = complete.list("tar");
+        assert_eq!(1, found.len());
+        assert_eq!(
+            None,
+            view::matched_hint(found[0], "tar", CaseMatching::Smart)
+        );
+
+        let found = complete.list("zip");
+        assert_eq!(1, found.len());
+        assert_eq!("unzip [_OPTIONS_] _ARCHIVE_", found[0].template);
+        assert_eq!(
+            None,
+            view::matched_hint(found[0], "zip", CaseMatching::Smart)
+        );
+    }
+
+    #[test]
+    fn flag_with_two_values() {
+        let commands = parser::parse_str(
+            r#"
+[[commands]]
+template = "rsync [_OPTIONS_] _SRC_"
+description = "Sync files (*rsync*)"
+groups.SRC.expect = "path"
+groups.OPTIONS.flags = [
+  { template = "--map _FROM_:_TO_", description = "Map", expect = { FROM = "string", TO = "number" } },
+]
+"#,
+        )
+        .unwrap();
+        let mut keys = type_keys("rsync\nsrc\nmap\nmy dir\n");
+        // Number is expected for the second value
+        keys.extend(type_keys("x\n8\n"));
+        let result = run_keys(&commands, keys).unwrap();
+        assert_eq!(Some("rsync --map 'my dir':8 src".to_string()), result);
+    }
+
+    fn fetch_commands(allow_custom: bool) -> Vec<Command> {
+        parser::parse_str(&format!(
+            r#"
+[[commands]]
+template = "curl [_OPTIONS_] _URL_"
+description = "Fetch a page"
+groups.URL.expect = "string"
+groups.OPTIONS.allow_custom = {}
+groups.OPTIONS.flags = [
+  {{ template = "-L", description = "Follow redirects" }},
+  {{ template = "-v", description = "Verbose logging" }},
+]
+"#,
+            allow_custom
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn custom_flags() {
+        let commands = fetch_commands(true);
+        let mut keys = type_keys("Fetch\nexample.com\nFollow\n--compressed\n");
+        // Unclosed quote is asked again
+        keys.extend(type_keys("-H 'X-Id: 1\n"));
+        keys.push(Key::Ctrl('u'));
+        keys.extend(type_keys("-H 'X-Id: 1'\nVerbose\n"));
+        let mut out = vec![];
+        let mut events = keys.into_iter().map(|key| Ok(Event::Key(key)));
+        let built = pick_and_build(
+            &mut out,
+            &mut events,
+            &commands,
+            &test_settings(),
+            &mut History::default(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            "curl -L --compressed -H 'X-Id: 1' -v example.com",
+            built.command
+        );
+        assert_eq!(
+            Some(&GroupAnswer::Flags(vec![
+                "-L".to_string(),
+                "--compressed".to_string(),
+                "-H 'X-Id: 1'".to_string(),
+                "-v".to_string(),
+            ])),
+            built.values.get("OPTIONS")
+        );
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("use as-is: --compressed"));
+        assert!(out.contains("Unclosed single quote"));
+        // Preview includes the custom flags
+        assert!(out.contains("curl -L --compressed -H 'X-Id: 1' example.com"));
+    }
+
+    #[test]
+    fn custom_flags_disabled() {
+        let commands = fetch_commands(false);
+        let mut keys = type_keys("Fetch\nexample.com\n--compressed\n");
+        keys.push(Key::Ctrl('u'));
+        keys.extend(type_keys("Follow\n"));
+        keys.push(Key::Ctrl('d'));
+        let result = run_keys(&commands, keys).unwrap();
+        assert_eq!(Some("curl -L example.com".to_string()), result);
+    }
+
+    #[test]
+    fn suggestions_refer_to_answers() {
+        let commands = parser::parse_str(
+            r#"
+[[commands]]
+template = "scp _HOST_:_PATH_ _DEST_"
+description = "Copy from a host (*scp*)"
+groups.HOST.expect = "string"
+groups.PATH.expect = "path"
+groups.PATH.suggest = ["/srv/{HOST}/logs", "{DEST}/backup"]
+groups.DEST.expect = "path"
+"#,
+        )
+        .unwrap();
+        let keys = type_keys("scp\nweb\n\nout/\n");
+        let mut out = vec![];
+        let mut events = keys.into_iter().map(|key| Ok(Event::Key(key)));
+        let built = pick_and_build(
+            &mut out,
+            &mut events,
+            &commands,
+            &test_settings(),
+            &mut History::default(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!("scp web:/srv/web/logs out/", built.command);
+        // DEST is asked after PATH
+        let out = String::from_utf8(out).unwrap();
+        assert!(!out.contains("/backup"));
+    }
+
+    #[test]
+    fn flag_suggest_cmd() {
+        let commands = parser::parse_str(
+            r#"
+[[commands]]
+template = "docker run [_OPTIONS_] _IMAGE_"
+description = "Run a container (*docker*)"
+groups.IMAGE.expect = "string"
+groups.OPTIONS.flags = [
+  { template = "*--network* _NET_", description = "Network", expect = "string", suggest = ["host"], suggest_cmd = "printf 'bridge\\nhost\\n{IMAGE}-net\\n'" },
+]
+"#,
+        )
+        .unwrap();
+        // Pick the network listed by the command
+        let mut keys = type_keys("docker\nnginx\nNetwork\n");
+        keys.extend(vec![Key::Down, Key::Down, Key::Char('\n'), Key::Ctrl('d')]);
+        let result = run_keys(&commands, keys).unwrap();
+        assert_eq!(
+            Some("docker run --network nginx-net nginx".to_string()),
+            result
+        );
+
+        let cmd = &commands[0];
+        let flag = match &cmd.groups[1].expect {
+            GroupValue::Flags(flags) => &flags[0],
+            GroupValue::Single(_) => panic!("Expected flags"),
+        };
+        let mut user_input = HashMap::new();
+        user_input.insert("IMAGE".to_string(), "redis".to_string());
+        let mut suggested = HashMap::new();
+        assert_eq!(
+            vec!["host", "bridge", "redis-net"],
+            flag_suggestions(flag, &user_input, &mut suggested, &test_settings()).unwrap()
+        );
+        // Output is reused for the same command
+        let command = "printf 'bridge\\nhost\\nredis-net\\n'";
+        suggested.insert(command.to_string(), vec!["cached".to_string()]);
+        assert_eq!(
+            vec!["host", "cached"],
+            flag_suggestions(flag, &user_input, &mut suggested, &test_settings()).unwrap()
+        );
+        // Command is not run until the referenced group is answered
+        assert_eq!(
+            vec!["host"],
+            flag_suggestions(flag, &HashMap::new(), &mut suggested, &test_settings()).unwrap()
+        );
+    }
+
+    #[test]
+    fn resolve_suggestion_refs() {
+        let suggest = vec![
+            "{NAMESPACE}".to_string(),
+            "pod/{POD}".to_string(),
+            "{{literal}}".to_string(),
+        ];
+        let mut user_input = HashMap::new();
+        user_input.insert("NAMESPACE".to_string(), "prod".to_string());
+        let resolved = resolve_suggestions(&suggest, &user_input, &test_settings()).unwrap();
+        assert_eq!(vec!["prod", "{literal}"], resolved);
+
+        user_input.insert("POD".to_string(), "api-1".to_string());
+        let resolved = resolve_suggestions(&suggest, &user_input, &test_settings()).unwrap();
+        assert_eq!(vec!["prod", "pod/api-1", "{literal}"], resolved);
+    }
+
+    #[test]
+    fn canonical_flag_order() {
+        let commands = parser::parse_str(
+            r#"
+[[commands]]
+template = "tar [_OPTIONS_] _FILE_"
+description = "Create an archive (*tar*)"
+groups.FILE.expect = "path"
+groups.OPTIONS.canonical_order = true
+groups.OPTIONS.flags = [
+  { template = "-c", description = "Create archive" },
+  { template = "-z", description = "Compress with gzip" },
+  { template = "--exclude _PATTERN_", description = "Exclude files", expect = "string", multiple = true },
+]
+"#,
+        )
+        .unwrap();
+        let mut keys = type_keys("tar\nout.tar\nExclude\n.cache\nCompress\n");
+        keys.extend(type_keys("--verbose\nExclude\ntmp\nCreate\n"));
+        keys.push(Key::Ctrl('d'));
+        let mut out = vec![];
+        let mut events = keys.into_iter().map(|key| Ok(Event::Key(key)));
+        let built = pick_and_build(
+            &mut out,
+            &mut events,
+            &commands,
+            &test_settings(),
+            &mut History::default(),
+        )
+        .unwrap()
+        .unwrap();
+        // Repeated flags stay in picking order and custom flags go last
+        assert_eq!(
+            "tar -c -z --exclude .cache --exclude tmp --verbose out.tar",
+            built.command
+        );
+        // Preview is ordered while picking
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("tar -z --exclude .cache --verbose out.tar"));
+    }
+
+    #[test]
+    fn previous_flag_values() {
+        let commands = parser::parse_str(
+            r#"
+[[commands]]
+template = "curl [_OPTIONS_] _URL_"
+description = "Fetch a URL (*curl*)"
+groups.URL.expect = "string"
+groups.OPTIONS.flags = [
+  { template = "-H _HEADER_", description = "Add a header", expect = "string", multiple = true, suggest = ["Accept: application/json"] },
+]
+"#,
+        )
+        .unwrap();
+        let mut keys = type_keys("curl\nexample.com\nheader\nX-Token: abc\n");
+        // Second time the entered header is suggested first
+        keys.extend(type_keys("header\n\n"));
+        keys.push(Key::Ctrl('d'));
+        let mut out = vec![];
+        let mut events = keys.into_iter().map(|key| Ok(Event::Key(key)));
+        let built = pick_and_build(
+            &mut out,
+            &mut events,
+            &commands,
+            &test_settings(),
+            &mut History::default(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            "curl -H 'X-Token: abc' -H 'X-Token: abc' example.com",
+            built.command
+        );
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(
+            1,
+            out.matches("X-Token: abc  (previous)").count(),
+            "{:?}",
+            out
+        );
+        assert!(out.contains("Accept: application/json"));
+    }
+
+    #[test]
+    fn transform_value() {
+        let commands = parser::parse_str(
+            r#"
+[[commands]]
+template = "curl https://example.com/search?q=_QUERY_"
+description = "Search (*curl*)"
+groups.QUERY.expect = "string"
+groups.QUERY.transform = ["trim", "urlencode"]
+"#,
+        )
+        .unwrap();
+        let keys = type_keys("search\n rust lang\n");
+        let result = run_keys(&commands, keys).unwrap();
+        assert_eq!(
+            Some("curl https://example.com/search?q=rust%20lang".to_string()),
+            result
+        );
+    }
+
+    fn tar_commands() -> Vec<Command> {
+        parser::parse_str(
+            r#"
+[[commands]]
+template = "tar -czf _ARCHIVE_ _DIR_"
+description = "Compress a directory (*tar*)"
+groups.DIR.expect = "path"
+groups.ARCHIVE.expect = "path"
+groups.ARCHIVE.computed = "{DIR}.tar.gz"
+
+[[commands]]
+template = "zip _ARCHIVE_ _DIR_"
+description = "Zip a directory (*zip*)"
+groups.DIR.expect = "path"
+groups.ARCHIVE.expect = "path"
+groups.ARCHIVE.computed = "{DIR}.zip"
+groups.ARCHIVE.editable = true
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn computed_group() {
+        let keys = type_keys("tar\nlogs\n");
+        let result = run_keys(&tar_commands(), keys).unwrap();
+        assert_eq!(Some("tar -czf logs.tar.gz logs".to_string()), result);
+    }
+
+    #[test]
+    fn computed_group_editable() {
+        // Computed value is pre-filled
+        let keys = type_keys("zip\nlogs\n\n");
+        let result = run_keys(&tar_commands(), keys).unwrap();
+        assert_eq!(Some("zip logs.zip logs".to_string()), result);
+
+        let mut keys = type_keys("zip\nlogs\n");
+        keys.push(Key::Ctrl('u'));
+        keys.extend(type_keys("out.zip\n"));
+        let result = run_keys(&tar_commands(), keys).unwrap();
+        assert_eq!(Some("zip out.zip logs".to_string()), result);
+    }
+
+    #[test]
+    fn multiline_value_quoted() {
+        let commands = parser::parse_str(
+            r#"
+[[commands]]
+template = "git commit -m _MESSAGE_"
+description = "Commit changes (*git*)"
+groups.MESSAGE.expect = "string"
+groups.MESSAGE.multiline = true
+"#,
+        )
+        .unwrap();
+        let mut keys = type_keys("commit\nFix it");
+        keys.push(Key::Alt('\r'));
+        keys.push(Key::Alt('\r'));
+        keys.extend(type_keys("Details\n"));
+        let result = run_keys(&commands, keys).unwrap();
+        assert_eq!(
+            Some("git commit -m 'Fix it\n\nDetails'".to_string()),
+            result
+        );
+    }
+
+    #[test]
+    fn suggested_paths_quoted_on_build() {
+        let commands = parser::parse_str(
+            r#"
+[[commands]]
+template = "cat _PATH_"
+description = "Print a file (*cat*)"
+groups.PATH.expect = "path"
+groups.PATH.suggest = ["My Documents/notes.txt", "it's.txt", "$HOME/x"]
+"#,
+        )
+        .unwrap();
+        // Suggestions are inserted as is and quoted when the command is built
+        let cases = [
+            (0, "cat 'My Documents/notes.txt'"),
+            (1, "cat 'it'\\''s.txt'"),
+            (2, "cat '$HOME/x'"),
+        ];
+        for (down, expected) in cases.iter() {
+            let mut keys = type_keys("cat\n");
+            keys.extend(vec![Key::Down; *down]);
+            keys.push(Key::Char('\n'));
+            let result = run_keys(&commands, keys).unwrap();
+            assert_eq!(Some(expected.to_string()), result);
+        }
+
+        // Typing after a space continues the raw value
+        let keys = type_keys("cat\nMy Documents/other.txt\n");
+        let result = run_keys(&commands, keys).unwrap();
+        assert_eq!(Some("cat 'My Documents/other.txt'".to_string()), result);
+    }
+
+    #[test]
+    fn suggested_flag_paths_quoted_on_build() {
+        let commands = parser::parse_str(
+            r#"
+[[commands]]
+template = "tar [_OPTIONS_] -xf _ARCHIVE_"
+description = "Unpack an archive (*tar*)"
+groups.ARCHIVE.expect = "path"
+groups.OPTIONS.flags = [
+  { template = "-C _DIR_", description = "Change to directory", expect = "path", suggest = ["My Documents"] },
+]
+"#,
+        )
+        .unwrap();
+        let keys = type_keys("tar\na.tar\nchange\n\n");
+        let result = run_keys(&commands, keys).unwrap();
+        assert_eq!(Some("tar -C 'My Documents' -xf a.tar".to_string()), result);
+    }
+
+    fn rm_commands() -> Vec<Command> {
+        parser::parse_str(
+            r#"
+[[commands]]
+template = "rm -rf _PATH_"
+description = "Remove a directory (*rm*)"
+confirm = true
+groups.PATH.expect = "path"
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn confirm_accept() {
+        let keys = type_keys("rm\nbuild\nyes\n");
+        let result = run_keys(&rm_commands(), keys).unwrap();
+        assert_eq!(Some("rm -rf build".to_string()), result);
+    }
+
+    #[test]
+    fn confirm_decline() {
+        // Declining goes back to the picker
+        let mut keys = type_keys("rm\nbuild\n\n");
+        keys.extend(type_keys("rm\nbuild\nno\n"));
+        keys.push(Key::Ctrl('d'));
+        let result = run_keys(&rm_commands(), keys).unwrap();
+        assert_eq!(None, result);
+    }
+
+    #[test]
+    fn confirm_plain() {
+        let plain = |input: &str| {
+            let mut input = std::io::Cursor::new(input.to_string());
+            build_plain(&mut vec![], &mut input, &rm_commands(), &test_settings())
+                .unwrap()
+                .map(|built| built.command)
+        };
+        assert_eq!(Some("rm -rf build".to_string()), plain("1\nbuild\nyes\n"));
+        assert_eq!(None, plain("1\nbuild\nno\n"));
+        assert_eq!(None, plain("1\nbuild\n"));
+    }
+
+    #[test]
+    fn confirm_terminate() {
+        let mut keys = type_keys("rm\nbuild\n");
+        keys.push(Key::Ctrl('c'));
+        let result = run_keys(&rm_commands(), keys);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn destructive_options_note() {
+        let commands = parser::parse_str(
+            r#"
+[[commands]]
+template = "rsync [_OPTIONS_] _SRC_"
+description = "Sync files (*rsync*)"
+groups.SRC.expect = "path"
+groups.OPTIONS.flags = [
+  { template = "-v", description = "Verbose" },
+  { template = "--delete", description = "Delete extra files", danger = true },
+]
+"#,
+        )
+        .unwrap();
+        let settings = Settings {
+            case: CaseMatching::default(),
+            footer: false,
+            retries: None,
+            tty: tty::TtyMode::Never,
+            project: false,
+            lenient: false,
+            debug: false,
+            summary: false,
+            inline: false,
+            plain: Some(false),
+            clipboard: None,
+            runner: subprocess::Runner::default(),
+            escalate: None,
+            messages: Messages::default(),
+            palette: Palette::default(),
+        };
+        let mut out = vec![];
+        let mut keys = type_keys("rsync\nsrc\ndelete\n");
+        keys.push(Key::Ctrl('d'));
+        let mut events = keys.into_iter().map(|key| Ok(Event::Key(key)));
+        let result = pick_and_build(
+            &mut out,
+            &mut events,
+            &commands,
+            &settings,
+            &mut History::default(),
+        )
+        .unwrap()
+        .map(|built| built.command);
+        assert_eq!(Some("rsync --delete src".to_string()), result);
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Destructive options: --delete"));
     }
 }
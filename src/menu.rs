@@ -0,0 +1,195 @@
+//! Menu that is shown once the command is built to choose what to do with it
+
+use std::io::Write;
+
+use termion::event::{Event, Key};
+use termion::{clear, cursor, style};
+
+use crate::error::{Result, SnovaError};
+use crate::messages::Messages;
+use crate::palette::Palette;
+use crate::view::input_closed;
+
+/// What to do with the built command
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FinalAction {
+    /// Print the command to stdout
+    #[default]
+    Print,
+    /// Run the command in a shell
+    Run,
+    /// Copy the command to the clipboard
+    Copy,
+    /// Edit the command and show the menu again
+    Edit,
+}
+
+impl FinalAction {
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        match value {
+            "print" => Ok(Self::Print),
+            "run" => Ok(Self::Run),
+            "copy" => Ok(Self::Copy),
+            "edit" => Ok(Self::Edit),
+            _ => Err(anyhow::anyhow!("Unknown action '{}'", value)),
+        }
+    }
+
+    /// Action of the key pressed in the menu
+    fn from_key(key: char) -> Option<Self> {
+        match key {
+            'p' => Some(Self::Print),
+            'r' => Some(Self::Run),
+            'c' => Some(Self::Copy),
+            'e' => Some(Self::Edit),
+            _ => None,
+        }
+    }
+
+    /// Message key of the action name
+    fn message(self) -> &'static str {
+        match self {
+            Self::Print => "action_print",
+            Self::Run => "action_run",
+            Self::Copy => "action_copy",
+            Self::Edit => "action_edit",
+        }
+    }
+}
+
+/// Show the command with the available actions below the cursor and wait for
+/// a key. Enter picks the default action. The menu is erased before returning.
+pub fn pick(
+    ui: &mut dyn Write,
+    events: &mut dyn Iterator<Item = std::io::Result<Event>>,
+    command: &str,
+    default: FinalAction,
+    messages: &Messages,
+    palette: Palette,
+) -> Result<FinalAction> {
+    let hint = messages.format(
+        "footer_menu",
+        &[("action", messages.get(default.message()))],
+    );
+    write!(
+        ui,
+        "{}{}{}\r\n{}{}{}",
+        palette.strong(),
+        command.replace('\n', "\r\n"),
+        style::Reset,
+        palette.faint(),
+        hint,
+        style::Reset
+    )?;
+    ui.flush()?;
+
+    let picked = loop {
+        let key = match events.next() {
+            Some(Ok(Event::Key(key))) => key,
+            Some(Ok(_)) => continue,
+            Some(Err(err)) => break Err(err.into()),
+            None => break Err(input_closed()),
+        };
+        match key {
+            Key::Char('\n') => break Ok(default),
+            Key::Char(c) => {
+                if let Some(action) = FinalAction::from_key(c) {
+                    break Ok(action);
+                }
+            }
+            Key::Ctrl('c') => break Err(SnovaError::Interrupted),
+            Key::Ctrl('d') | Key::Esc => break Err(SnovaError::Cancelled),
+            _ => {}
+        }
+    };
+
+    // Command could span several lines
+    let rows = command.matches('\n').count() as u16 + 1;
+    write!(ui, "{}\r{}", cursor::Up(rows), clear::AfterCursor)?;
+    ui.flush()?;
+    picked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::Background;
+
+    fn pick_with_keys(keys: Vec<Key>, default: FinalAction) -> (Result<FinalAction>, String) {
+        pick_with_palette(keys, default, Palette::default())
+    }
+
+    fn pick_with_palette(
+        keys: Vec<Key>,
+        default: FinalAction,
+        palette: Palette,
+    ) -> (Result<FinalAction>, String) {
+        let mut out = vec![];
+        let mut events = keys.into_iter().map(|key| Ok(Event::Key(key)));
+        let picked = pick(
+            &mut out,
+            &mut events,
+            "grep -i foo src",
+            default,
+            &Messages::default(),
+            palette,
+        );
+        (picked, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn enter_picks_default() {
+        let (picked, _) = pick_with_keys(vec![Key::Char('\n')], FinalAction::Print);
+        assert_eq!(FinalAction::Print, picked.unwrap());
+        let (picked, out) = pick_with_keys(vec![Key::Char('\n')], FinalAction::Copy);
+        assert_eq!(FinalAction::Copy, picked.unwrap());
+        assert!(out.contains("enter copy"), "{:?}", out);
+    }
+
+    #[test]
+    fn keys_pick_actions() {
+        for (key, action) in [
+            ('p', FinalAction::Print),
+            ('r', FinalAction::Run),
+            ('c', FinalAction::Copy),
+            ('e', FinalAction::Edit),
+        ] {
+            // Other keys are ignored
+            let (picked, _) =
+                pick_with_keys(vec![Key::Char('x'), Key::Char(key)], FinalAction::Run);
+            assert_eq!(action, picked.unwrap());
+        }
+    }
+
+    #[test]
+    fn cancel_and_interrupt() {
+        let (picked, _) = pick_with_keys(vec![Key::Ctrl('d')], FinalAction::Print);
+        assert!(matches!(picked, Err(SnovaError::Cancelled)));
+        let (picked, _) = pick_with_keys(vec![Key::Esc], FinalAction::Print);
+        assert!(matches!(picked, Err(SnovaError::Cancelled)));
+        let (picked, _) = pick_with_keys(vec![Key::Ctrl('c')], FinalAction::Print);
+        assert!(matches!(picked, Err(SnovaError::Interrupted)));
+        let (picked, _) = pick_with_keys(vec![], FinalAction::Print);
+        assert!(matches!(picked, Err(SnovaError::Io { .. })));
+    }
+
+    #[test]
+    fn menu_is_erased() {
+        let (_, out) = pick_with_keys(vec![Key::Char('r')], FinalAction::Print);
+        assert!(out.starts_with(&format!("{}grep -i foo src", style::Bold)));
+        let light = Palette::new(Background::Light);
+        let (_, out) = pick_with_palette(vec![Key::Char('r')], FinalAction::Print, light);
+        assert!(out.starts_with(&format!("{}grep -i foo src", light.strong())));
+        assert!(
+            out.ends_with(&format!("{}\r{}", cursor::Up(1), clear::AfterCursor)),
+            "{:?}",
+            out
+        );
+    }
+
+    #[test]
+    fn parse_action() {
+        assert_eq!(FinalAction::Run, FinalAction::parse("run").unwrap());
+        assert!(FinalAction::parse("exec").is_err());
+    }
+}
@@ -0,0 +1,194 @@
+//! User-facing UI strings with English defaults that could be overridden
+//! from the `[messages]` table of the settings file
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::cmd::{format_computed, parse_computed};
+
+/// Message keys with their English text. Placeholders look like `{name}`.
+const DEFAULTS: &[(&str, &str)] = &[
+    ("pick_command", "Pick a command:"),
+    ("pick_number", "Command number: "),
+    ("invalid_number", "Type a number from 1 to {max}"),
+    ("nothing_selected", "Nothing selected."),
+    (
+        "no_commands",
+        "No commands defined: create {file} or add some with `snova import`",
+    ),
+    ("command", "Command: {template}"),
+    ("confirm", "Type '{word}' to confirm:"),
+    ("confirm_word", "yes"),
+    ("confirm_escalation", " (runs as root with {escalation})"),
+    ("destructive_options", "Destructive options: {flags}"),
+    ("no_valid_value", "No valid value for {prompt}"),
+    ("value_required", "{prompt} is required"),
+    ("clipboard_label", "(clipboard) "),
+    ("previous_label", "  (previous)"),
+    ("remove_flag_label", "remove: "),
+    ("flags_selected", "({count} selected)"),
+    ("summary_done", "Done"),
+    ("summary_empty", "(empty)"),
+    ("use_typed_label", "(use typed) "),
+    ("custom_label", "use as-is: "),
+    (
+        "footer_choice",
+        "↑↓ navigate · enter select · ? preview · ^u clear · ^d cancel",
+    ),
+    (
+        "footer_suggest",
+        "↑↓ navigate · enter select or use typed · ^u clear · ^d cancel",
+    ),
+    (
+        "footer_input",
+        "enter submit · ^u clear · ^z undo · ^d cancel",
+    ),
+    (
+        "footer_custom",
+        "↑↓ navigate · enter select or use as-is · ? preview · ^u clear · ^d cancel",
+    ),
+    (
+        "footer_menu",
+        "enter {action} · p print · r run · c copy · e edit · ^d cancel",
+    ),
+    ("footer_new_line", " · alt-enter new line"),
+    (
+        "footer_template",
+        "tab next · shift-tab previous · enter submit · ^u clear · ^d cancel",
+    ),
+    ("footer_finish", " · ^s finish"),
+    ("footer_back", " · esc back"),
+    ("footer_restart", " · ^g commands"),
+    ("footer_full_help", " · ^o full help"),
+    ("full_help_return", "Press any key to return"),
+    (
+        "stripped_input",
+        "snova: stripped {count} control chars from input",
+    ),
+    (
+        "suggestion_skipped",
+        "snova: skipped suggestion '{value}' until {groups} is answered",
+    ),
+    ("action_print", "print"),
+    ("action_run", "run"),
+    ("action_copy", "copy"),
+    ("action_edit", "edit"),
+    ("edit_command", "Edit the command:"),
+    ("copied", "Copied to the clipboard."),
+    (
+        "copy_failed",
+        "snova: couldn't copy to the clipboard: {reason}",
+    ),
+    ("failed", "Failed:"),
+    ("hook_failed", "snova: post_build hook failed: {reason}"),
+    ("history_cleared", "Value history cleared."),
+    ("pick_history", "Pick a built command:"),
+    ("history_empty", "No commands built yet."),
+    (
+        "checked",
+        "Checked {commands} commands, {warnings} warnings.",
+    ),
+    ("preset_saved", "Saved preset '{name}'."),
+    ("preset_removed", "Removed preset '{name}'."),
+];
+
+/// Message catalog
+#[derive(Debug, Clone, Default)]
+pub struct Messages {
+    overrides: HashMap<String, String>,
+}
+
+impl Messages {
+    /// Catalog with the given texts instead of the defaults
+    pub fn new(overrides: HashMap<String, String>) -> Result<Self> {
+        if let Some(key) = overrides
+            .keys()
+            .find(|key| !DEFAULTS.iter().any(|(k, _)| k == key))
+        {
+            return Err(anyhow!("Unknown message '{}'", key));
+        }
+        for (key, text) in &overrides {
+            parse_computed(text).with_context(|| format!("In message '{}'", key))?;
+        }
+        Ok(Self { overrides })
+    }
+
+    /// Message text without substitutions
+    pub fn get(&self, key: &str) -> &str {
+        match self.overrides.get(key) {
+            Some(text) => text,
+            None => {
+                DEFAULTS
+                    .iter()
+                    .find(|(k, _)| *k == key)
+                    .unwrap_or_else(|| panic!("Message '{}' is not defined", key))
+                    .1
+            }
+        }
+    }
+
+    /// Message text with placeholders replaced by the values
+    pub fn format(&self, key: &str, values: &[(&str, &str)]) -> String {
+        let values: HashMap<String, String> = values
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        format_computed(self.get(key), &values).expect("Messages are validated when loaded")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings_file::SettingsFile;
+
+    impl Messages {
+        fn parse(data: &str) -> Result<Self> {
+            SettingsFile::parse(data).map(|file| file.messages)
+        }
+    }
+
+    #[test]
+    fn defaults() {
+        let messages = Messages::default();
+        assert_eq!("Pick a command:", messages.get("pick_command"));
+        assert_eq!(
+            "Checked 3 commands, 0 warnings.",
+            messages.format("checked", &[("commands", "3"), ("warnings", "0")])
+        );
+    }
+
+    #[test]
+    fn overrides() {
+        let messages = Messages::parse(
+            r#"
+[messages]
+pick_command = "Wähle einen Befehl:"
+confirm = "'{word}' eingeben zum Bestätigen ({{{word}}}):"
+"#,
+        )
+        .unwrap();
+        assert_eq!("Wähle einen Befehl:", messages.get("pick_command"));
+        assert_eq!(
+            "'ja' eingeben zum Bestätigen ({ja}):",
+            messages.format("confirm", &[("word", "ja")])
+        );
+        assert_eq!("Nothing selected.", messages.get("nothing_selected"));
+    }
+
+    #[test]
+    fn defaults_valid() {
+        for (key, text) in DEFAULTS {
+            assert!(parse_computed(text).is_ok(), "{}", key);
+        }
+        assert!(Messages::parse("[messages]\nconfirm = \"Type {word\"").is_err());
+    }
+
+    #[test]
+    fn unknown_overrides() {
+        assert!(Messages::parse("[messages]\npick = \"Pick:\"").is_err());
+        assert!(Messages::parse("[colors]\nerror = \"red\"").is_err());
+        assert!(Messages::parse("").is_ok());
+    }
+}
@@ -0,0 +1,208 @@
+//! Text styles that stay readable on dark and light terminal backgrounds
+
+use termion::{color, style};
+
+use crate::error::{Result, SnovaError};
+
+/// Terminal background
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Background {
+    #[default]
+    Dark,
+    Light,
+}
+
+impl Background {
+    pub fn parse(v: &str) -> Result<Background> {
+        match v {
+            "dark" => Ok(Background::Dark),
+            "light" => Ok(Background::Light),
+            _ => Err(SnovaError::parse(format!("Unknown background '{}'", v))),
+        }
+    }
+
+    /// Background from the `COLORFGBG` variable that some terminals set,
+    /// e.g `15;0` or `0;default;15`. The last field is the background color.
+    pub fn from_colorfgbg(value: &str) -> Option<Background> {
+        match value.rsplit(';').next()?.parse::<u8>().ok()? {
+            7 | 9..=15 => Some(Background::Light),
+            0..=6 | 8 => Some(Background::Dark),
+            _ => None,
+        }
+    }
+}
+
+/// Styles of the prompt. Bold alone is nearly invisible on light backgrounds
+/// of terminals that render it as a brighter color, so color is added there.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Palette {
+    background: Background,
+}
+
+impl Palette {
+    pub fn new(background: Background) -> Self {
+        Self { background }
+    }
+
+    /// Start of `*bold*` text and of the selected choice. Ends with `style::Reset`.
+    pub fn strong(&self) -> String {
+        match self.background {
+            Background::Dark => style::Bold.to_string(),
+            Background::Light => format!("{}{}", style::Bold, color::Fg(color::Blue)),
+        }
+    }
+
+    /// Start of dimmed text, e.g hints. Ends with `style::Reset`.
+    pub fn faint(&self) -> String {
+        style::Faint.to_string()
+    }
+
+    /// Marker in front of the selected choice
+    pub fn marker(&self) -> String {
+        match self.background {
+            Background::Dark => "> ".to_string(),
+            Background::Light => format!("{}> ", color::Fg(color::Blue)),
+        }
+    }
+
+    /// Counters, e.g the position of the selected choice
+    pub fn counter(&self, text: impl std::fmt::Display) -> String {
+        match self.background {
+            Background::Dark => format!("{}{}{}", style::Italic, text, style::NoItalic),
+            Background::Light => format!(
+                "{}{}{}{}{}",
+                style::Italic,
+                color::Fg(color::Blue),
+                text,
+                color::Fg(color::Reset),
+                style::NoItalic
+            ),
+        }
+    }
+
+    /// Color of choices that need attention. Yellow is unreadable on light backgrounds.
+    pub fn warning(&self) -> String {
+        match self.background {
+            Background::Dark => color::Fg(color::Yellow).to_string(),
+            Background::Light => color::Fg(color::Magenta).to_string(),
+        }
+    }
+
+    /// Color of errors
+    pub fn error(&self) -> String {
+        color::Fg(color::Red).to_string()
+    }
+
+    /// Render `*bold*` and `_underline_` markup
+    pub fn fmt_text(&self, text: impl AsRef<str>) -> String {
+        let text = text.as_ref();
+        let mut result = String::new();
+        let mut state = FmtState::default();
+
+        for c in text.chars() {
+            match c {
+                '*' => {
+                    if state.bold {
+                        // End bold
+                        // Somehow NoBold doesn't work properly hence using Reset for now
+                        // result.push_str(style::NoBold.as_ref());
+                        result.push_str(style::Reset.as_ref());
+                    } else {
+                        // Start bold
+                        result.push_str(&self.strong());
+                    }
+
+                    state.bold = !state.bold;
+                }
+                '_' => {
+                    if state.underline {
+                        // End underline
+                        result.push_str(style::NoUnderline.as_ref());
+                    } else {
+                        // Start underline
+                        result.push_str(style::Underline.as_ref());
+                    }
+
+                    state.underline = !state.underline;
+                }
+                _ => {
+                    result.push(c);
+                }
+            }
+        }
+
+        // Clean styles if not closed properly
+        if state.bold {
+            // Somehow NoBold doesn't work properly hence using Reset for now
+            // result.push_str(style::NoBold.as_ref());
+            result.push_str(style::Reset.as_ref());
+        }
+
+        if state.underline {
+            result.push_str(style::NoUnderline.as_ref());
+        }
+
+        result
+    }
+}
+
+#[derive(Default)]
+struct FmtState {
+    /// Bold text has started
+    bold: bool,
+    /// Underline text has started
+    underline: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fmt_text_ok() {
+        let palette = Palette::default();
+        assert_eq!(
+            format!(
+                "Hello {}UNDERLINE{} and {}bold{}",
+                style::Underline,
+                style::NoUnderline,
+                style::Bold,
+                style::Reset
+            ),
+            palette.fmt_text("Hello _UNDERLINE_ and *bold*")
+        );
+
+        assert_eq!(
+            format!("inline={}underline{}", style::Underline, style::NoUnderline),
+            palette.fmt_text("inline=_underline_")
+        );
+    }
+
+    #[test]
+    fn fmt_text_light() {
+        assert_eq!(
+            format!(
+                "Find {}{}lines{}",
+                style::Bold,
+                color::Fg(color::Blue),
+                style::Reset
+            ),
+            Palette::new(Background::Light).fmt_text("Find *lines*")
+        );
+    }
+
+    #[test]
+    fn background_from_colorfgbg() {
+        assert_eq!(Some(Background::Dark), Background::from_colorfgbg("15;0"));
+        assert_eq!(Some(Background::Light), Background::from_colorfgbg("0;15"));
+        assert_eq!(
+            Some(Background::Light),
+            Background::from_colorfgbg("0;default;7")
+        );
+        assert_eq!(Some(Background::Dark), Background::from_colorfgbg("7;8"));
+        assert_eq!(None, Background::from_colorfgbg("0;default"));
+        assert_eq!(None, Background::from_colorfgbg(""));
+        assert!(Background::parse("light").is_ok());
+        assert!(Background::parse("auto").is_err());
+    }
+}
@@ -2,16 +2,19 @@
 
 use std::{
     cmp::Ordering,
-    collections::{HashMap, VecDeque},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    path::{Path, PathBuf},
 };
 
-use anyhow::{anyhow, Context, Result};
 use serde::{Deserialize, Serialize};
 
 use crate::cmd::*;
+use crate::error::{Context, Result, SnovaError};
+use crate::paths::Paths;
+use crate::script;
 
 /// Builtin commands
-const BUILTIN_DEF: &'static str = include_str!("../defs/builtin.toml");
+const BUILTIN_DEF: &str = include_str!("../defs/builtin.toml");
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CommandsDef {
@@ -20,249 +23,1135 @@ pub struct CommandsDef {
 
 /// A single command definition in the config file
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 struct CommandDef {
     template: String,
     description: String,
-    groups: HashMap<String, GroupDef>,
+    /// Extra words to find the command by (not displayed)
+    #[serde(default)]
+    keywords: Vec<String>,
+    long_description: Option<String>,
+    example: Option<String>,
+    /// Complete commands with what they do (not validated against the template)
+    #[serde(default)]
+    examples: Vec<ExampleDef>,
+    /// Require typing "yes" before the command is returned
+    #[serde(default)]
+    confirm: bool,
+    confirm_message: Option<String>,
+    /// Shell command that receives the built command instead of the global hook
+    post_build: Option<String>,
+    /// Prefix the command with sudo (or another escalation) unless run as root
+    #[serde(default)]
+    needs_root: bool,
+    /// How a template written on several lines is rendered (oneline, multiline)
+    render: Option<String>,
+    groups: GroupDefs,
+}
+
+/// Group definitions in the order they are written
+#[derive(Debug, Default)]
+struct GroupDefs(Vec<(String, GroupDef)>);
+
+impl GroupDefs {
+    fn insert(&mut self, name: String, group: GroupDef) {
+        self.0.retain(|(other, _)| *other != name);
+        self.0.push((name, group));
+    }
+
+    fn contains_key(&self, name: &str) -> bool {
+        self.0.iter().any(|(other, _)| other == name)
+    }
+
+    fn keys(&self) -> impl Iterator<Item = &String> {
+        self.0.iter().map(|(name, _)| name)
+    }
+
+    fn remove(&mut self, name: &str) -> Option<GroupDef> {
+        let index = self.0.iter().position(|(other, _)| other == name)?;
+        Some(self.0.remove(index).1)
+    }
+}
+
+impl Serialize for GroupDefs {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(name, group)| (name, group)))
+    }
+}
+
+impl<'de> Deserialize<'de> for GroupDefs {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        struct GroupDefsVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for GroupDefsVisitor {
+            type Value = GroupDefs;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a table of groups")
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> std::result::Result<GroupDefs, A::Error> {
+                let mut groups = GroupDefs::default();
+                while let Some((name, group)) = map.next_entry()? {
+                    groups.insert(name, group);
+                }
+                Ok(groups)
+            }
+        }
+
+        deserializer.deserialize_map(GroupDefsVisitor)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExampleDef {
+    description: String,
+    command: String,
 }
 
 /// A single group definition in the config file
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 struct GroupDef {
     expect: Option<ValueTypeDef>,
-    suggest: Option<Vec<String>>,
+    suggest: Option<SuggestDef>,
+    default: Option<String>,
     flags: Option<VecDeque<FlagDef>>,
+    /// How to quote user input: auto, always or never
+    quote: Option<String>,
+    /// Transformations applied to the submitted value in order
+    #[serde(default)]
+    transform: Vec<String>,
+    prefix: Option<String>,
+    suffix: Option<String>,
+    /// Value derived from other groups, e.g "{DIR}.tar.gz"
+    computed: Option<String>,
+    /// Pre-fill the prompt with the computed value instead of skipping it
+    #[serde(default)]
+    editable: bool,
+    /// Allow new lines in the value (e.g request bodies)
+    #[serde(default)]
+    multiline: bool,
+    /// Range of number values
+    min: Option<u64>,
+    max: Option<u64>,
+    /// Keep leading zeros of number values instead of stripping them
+    #[serde(default)]
+    keep_leading_zeros: bool,
+    /// Range of string value lengths in chars
+    min_len: Option<usize>,
+    max_len: Option<usize>,
+    /// Never store the value (e.g passwords and tokens)
+    #[serde(default)]
+    secret: bool,
+    /// Offer typed text that matches no flag as a custom flag (on by default)
+    allow_custom: Option<bool>,
+    /// Order picked flags as they are defined instead of the picking order
+    #[serde(default)]
+    canonical_order: bool,
 }
 
 type ValueTypeDef = String;
 
+/// Suggestions: a fixed list or a source name (e.g "git_files")
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum SuggestDef {
+    List(Vec<String>),
+    Source(String),
+}
+
+impl SuggestDef {
+    fn into_list(self) -> Option<Vec<String>> {
+        match self {
+            SuggestDef::List(list) => Some(list),
+            SuggestDef::Source(_) => None,
+        }
+    }
+}
+
+/// Flag value types: a single type or a table keyed by group name
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum FlagExpectDef {
+    Single(ValueTypeDef),
+    Groups(BTreeMap<String, ValueTypeDef>),
+}
+
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 struct FlagDef {
     template: String,
     description: String,
-    expect: Option<ValueTypeDef>,
+    long_description: Option<String>,
+    example: Option<String>,
+    expect: Option<FlagExpectDef>,
     #[serde(default)]
     multiple: bool,
+    /// Flag is destructive (e.g --force)
+    #[serde(default)]
+    danger: bool,
     suggest: Option<Vec<String>>,
+    /// Shell command that lists suggestions for the flag value, e.g "kubectl get namespaces -o name"
+    suggest_cmd: Option<String>,
+    default: Option<String>,
 }
 
-/// Read all commands
-pub fn read_all() -> Result<Vec<Command>> {
+/// Read all commands in a fixed order: builtin, the user file, `commands.d`
+/// files and scripts by name, then project commands. Project commands are
+/// looked up from the cwd if given. Lenient mode applies to user and project
+/// files.
+pub fn read_all(paths: &Paths, cwd: Option<&Path>, lenient: bool) -> Result<Vec<Command>> {
     let mut all = builtin()?;
-    let mut user = user_commands()?;
-    all.append(&mut user);
+    if let Some(commands_file) = paths.commands_file() {
+        let mut user = user_commands(&commands_file, lenient)?;
+        all.append(&mut user);
+    }
+    if let Some(commands_d) = paths.commands_d() {
+        let mut extra = commands_d_commands(&commands_d, lenient)?;
+        all.append(&mut extra);
+    }
+    for dir in &paths.script_dirs {
+        let mut scripts = scripts_commands(dir, lenient)?;
+        all.append(&mut scripts);
+    }
+    if let Some(cwd) = cwd {
+        let mut project = project_commands(cwd, lenient)?;
+        all.append(&mut project);
+    }
     Ok(all)
 }
 
-/// Read user commands
-fn user_commands() -> Result<Vec<Command>> {
-    if let Some(mut config_dir) = dirs::config_dir() {
-        config_dir.push("snova");
-        if config_dir.is_dir() {
-            let commands_file = config_dir.join("commands.toml");
-            if commands_file.is_file() {
-                // Try reading user commands file
-                let data = std::fs::read_to_string(&commands_file)
-                    .context(format!("Read {}", commands_file.display()))?;
-                let defs: CommandsDef =
-                    toml::de::from_str(&data).context("Parse user commands toml")?;
-                return parse_defs(defs);
+/// Parse commands from toml
+pub fn parse_str(data: &str) -> Result<Vec<Command>> {
+    let defs = parse_toml(data)?;
+    parse_defs(defs)
+}
+
+/// Known fields of CommandDef, GroupDef and FlagDef
+const COMMAND_FIELDS: &[&str] = &[
+    "template",
+    "description",
+    "keywords",
+    "long_description",
+    "example",
+    "examples",
+    "confirm",
+    "confirm_message",
+    "post_build",
+    "needs_root",
+    "render",
+    "groups",
+];
+const GROUP_FIELDS: &[&str] = &[
+    "expect",
+    "suggest",
+    "default",
+    "flags",
+    "quote",
+    "transform",
+    "prefix",
+    "suffix",
+    "computed",
+    "editable",
+    "multiline",
+    "min",
+    "max",
+    "keep_leading_zeros",
+    "min_len",
+    "max_len",
+    "secret",
+    "allow_custom",
+    "canonical_order",
+];
+const FLAG_FIELDS: &[&str] = &[
+    "template",
+    "description",
+    "long_description",
+    "example",
+    "expect",
+    "multiple",
+    "danger",
+    "suggest",
+    "suggest_cmd",
+    "default",
+];
+
+/// Deserialize definitions. Unknown fields are reported with their location
+/// and a suggestion instead of the plain serde error.
+fn parse_toml(data: &str) -> Result<CommandsDef> {
+    match toml::de::from_str(data) {
+        Ok(defs) => Ok(defs),
+        Err(err) => {
+            let value: toml::Value = toml::de::from_str(data)?;
+            check_fields(&value)?;
+            Err(err.into())
+        }
+    }
+}
+
+/// Find the first unknown field in commands, groups and flags
+fn check_fields(value: &toml::Value) -> Result<()> {
+    let commands = value.get("commands").and_then(|c| c.as_array());
+    for cmd in commands.into_iter().flatten() {
+        let template = cmd.get("template").and_then(|t| t.as_str()).unwrap_or("");
+        let in_command = |err: SnovaError| err.in_command(template);
+        unknown_field(cmd, COMMAND_FIELDS, None).map_err(in_command)?;
+
+        let groups = cmd.get("groups").and_then(|g| g.as_table());
+        for (name, group) in groups.into_iter().flatten() {
+            let location = format!("group {}", name);
+            unknown_field(group, GROUP_FIELDS, Some(&location)).map_err(in_command)?;
+
+            let flags = group.get("flags").and_then(|f| f.as_array());
+            for flag in flags.into_iter().flatten() {
+                let flag_template = flag.get("template").and_then(|t| t.as_str()).unwrap_or("");
+                let location = format!("flag '{}' in {}", flag_template, location);
+                unknown_field(flag, FLAG_FIELDS, Some(&location)).map_err(in_command)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn unknown_field(value: &toml::Value, known: &[&str], location: Option<&str>) -> Result<()> {
+    let table = match value.as_table() {
+        Some(table) => table,
+        None => return Ok(()),
+    };
+    match table.keys().find(|key| !known.contains(&key.as_str())) {
+        Some(key) => {
+            let mut msg = format!("Unknown field '{}'", key);
+            if let Some(location) = location {
+                msg.push_str(&format!(" in {}", location));
             }
+            if let Some(similar) = closest(key, known) {
+                msg.push_str(&format!(". Did you mean '{}'?", similar));
+            }
+            Err(SnovaError::parse(msg))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Find the most similar known name within a small edit distance
+fn closest<'a>(name: &str, known: &[&'a str]) -> Option<&'a str> {
+    known
+        .iter()
+        .map(|k| (edit_distance(name, k), *k))
+        .filter(|(distance, k)| *distance <= 2.max(k.len() / 3))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, k)| k)
+}
+
+/// Levenshtein distance
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = prev[j] + if ca == *cb { 0 } else { 1 };
+            current.push(substitute.min(prev[j + 1] + 1).min(current[j] + 1));
+        }
+        prev = current;
+    }
+    prev[b.len()]
+}
+
+/// Read user commands
+fn user_commands(commands_file: &Path, lenient: bool) -> Result<Vec<Command>> {
+    if commands_file.is_file() {
+        // Try reading user commands file
+        let data = std::fs::read_to_string(commands_file)
+            .map_err(|err| SnovaError::io(commands_file, err))?;
+        let mut commands = parse_toml(&data)
+            .and_then(|defs| build_commands(defs, lenient))
+            .map_err(|err| err.in_file(commands_file))?;
+        for cmd in &mut commands {
+            cmd.source = CommandSource::User(commands_file.to_path_buf());
         }
+        return Ok(commands);
     }
 
     Ok(vec![])
 }
 
+/// Read all toml files from the directory in name order
+fn commands_d_commands(dir: &Path, lenient: bool) -> Result<Vec<Command>> {
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+
+    let mut files = vec![];
+    let entries = std::fs::read_dir(dir).map_err(|err| SnovaError::io(dir, err))?;
+    for entry in entries {
+        let path = entry.map_err(|err| SnovaError::io(dir, err))?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "toml") {
+            files.push(path);
+        }
+    }
+    files.sort();
+
+    let mut commands = vec![];
+    for file in files {
+        let data = std::fs::read_to_string(&file).map_err(|err| SnovaError::io(&file, err))?;
+        let mut file_commands = parse_toml(&data)
+            .and_then(|defs| build_commands(defs, lenient))
+            .map_err(|err| err.in_file(&file))?;
+        for cmd in &mut file_commands {
+            cmd.source = CommandSource::User(file.clone());
+        }
+        commands.append(&mut file_commands);
+    }
+    Ok(commands)
+}
+
+/// Read annotated commands from `*.sh` files of the directory in name order
+fn scripts_commands(dir: &Path, lenient: bool) -> Result<Vec<Command>> {
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+
+    let mut files = vec![];
+    let entries = std::fs::read_dir(dir).map_err(|err| SnovaError::io(dir, err))?;
+    for entry in entries {
+        let path = entry.map_err(|err| SnovaError::io(dir, err))?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "sh") {
+            files.push(path);
+        }
+    }
+    files.sort();
+
+    let mut commands = vec![];
+    for file in files {
+        let data = std::fs::read_to_string(&file).map_err(|err| SnovaError::io(&file, err))?;
+        let mut file_commands =
+            script_commands(&data, lenient).map_err(|err| err.in_file(&file))?;
+        for cmd in &mut file_commands {
+            check_auto_run(cmd).map_err(|err| err.in_file(&file))?;
+            cmd.source = CommandSource::Imported(file.clone());
+        }
+        commands.append(&mut file_commands);
+    }
+    Ok(commands)
+}
+
+/// Build commands annotated in a shell script. Errors are prefixed with the
+/// line of the annotation.
+pub fn script_commands(data: &str, lenient: bool) -> Result<Vec<Command>> {
+    let extracted = script::extract(data);
+    if let Some((line, reason)) = extracted.errors.into_iter().next() {
+        return Err(SnovaError::parse(reason).context(format!("line {}", line)));
+    }
+
+    let mut commands = vec![];
+    for script::ScriptDef { line, def } in extracted.defs {
+        let cmd =
+            table_command(def, lenient).map_err(|err| err.context(format!("line {}", line)))?;
+        commands.push(cmd);
+    }
+    Ok(commands)
+}
+
+/// Build a command of a definition table, e.g one extracted from a script
+pub fn table_command(def: toml::value::Table, lenient: bool) -> Result<Command> {
+    let template = def
+        .get("template")
+        .and_then(|template| template.as_str())
+        .unwrap_or_default()
+        .to_string();
+    toml::Value::Table(def)
+        .try_into::<CommandDef>()
+        .map_err(SnovaError::from)
+        .and_then(|def| build_command(def, lenient))
+        .map_err(|err| err.in_command(&template))
+}
+
+/// Read commands from the nearest project file
+fn project_commands(cwd: &Path, lenient: bool) -> Result<Vec<Command>> {
+    let commands_file = match find_project_file(cwd) {
+        Some(file) => file,
+        None => return Ok(vec![]),
+    };
+    let data = std::fs::read_to_string(&commands_file)
+        .map_err(|err| SnovaError::io(&commands_file, err))?;
+    let mut commands = parse_toml(&data)
+        .and_then(|defs| build_commands(defs, lenient))
+        .map_err(|err| err.in_file(&commands_file))?;
+    for cmd in &mut commands {
+        check_auto_run(cmd).map_err(|err| err.in_file(&commands_file))?;
+        cmd.source = CommandSource::Project(commands_file.clone());
+    }
+    Ok(commands)
+}
+
+/// Project files and scripts come with other people's code, so commands
+/// defined there may not run anything on their own.
+fn check_auto_run(cmd: &Command) -> Result<()> {
+    if cmd.post_build.is_some() {
+        return Err(
+            SnovaError::parse("post_build is only allowed in user commands")
+                .in_command(&cmd.template),
+        );
+    }
+    for group in &cmd.groups {
+        if let GroupValue::Flags(flags) = &group.expect {
+            if let Some(flag) = flags.iter().find(|flag| flag.suggest_cmd.is_some()) {
+                return Err(SnovaError::parse(format!(
+                    "Flag '{}' defines suggest_cmd, which is only allowed in user commands",
+                    flag.template
+                ))
+                .in_command(&cmd.template));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Walk up from the directory and find `.snova.toml` or `.snova/commands.toml`
+fn find_project_file(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .flat_map(|dir| {
+            vec![
+                dir.join(".snova.toml"),
+                dir.join(".snova").join("commands.toml"),
+            ]
+        })
+        .find(|file| file.is_file())
+}
+
 /// Read builtin commands
-fn builtin() -> Result<Vec<Command>> {
-    let defs: CommandsDef =
-        toml::de::from_str(BUILTIN_DEF).context("Parse builtin commands toml")?;
+pub fn builtin() -> Result<Vec<Command>> {
+    let defs = parse_toml(BUILTIN_DEF).with_context(|| "Parse builtin commands toml")?;
     parse_defs(defs)
 }
 
 /// Parse and validate command definitions
-pub fn parse_defs(mut defs: CommandsDef) -> Result<Vec<Command>> {
+pub fn parse_defs(defs: CommandsDef) -> Result<Vec<Command>> {
+    build_commands(defs, false)
+}
+
+/// Parse and validate command definitions. In lenient mode unused group
+/// definitions are reported as command warnings instead of errors.
+fn build_commands(mut defs: CommandsDef, lenient: bool) -> Result<Vec<Command>> {
     let mut commands = vec![];
 
     // Verify and build commands
-    while let Some(mut def) = defs.commands.pop_front() {
-        // Get group names from the template
-        let group_names = parse_template_groups(&def.template)
-            .context(format!("In template: {}", def.template))?;
+    while let Some(def) = defs.commands.pop_front() {
+        let template = def.template.clone();
+        let cmd = build_command(def, lenient).map_err(|err| err.in_command(&template))?;
+        commands.push(cmd);
+    }
 
-        if group_names.len() == 0 {
-            return Err(anyhow!("Empty template"));
-        }
+    Ok(commands)
+}
 
-        let user_input_groups: Vec<&GroupName> = group_names
-            .iter()
-            .filter(|g| matches!(g.group_type, GroupNameType::UserInput { .. }))
-            .collect();
+/// Verify the definition and build the command
+fn build_command(mut def: CommandDef, lenient: bool) -> Result<Command> {
+    let render = match &def.render {
+        Some(render) => Render::parse(render)?,
+        None => Render::default(),
+    };
+    // Get group names from the template
+    let group_names = parse_template_groups(&render.join_lines(&def.template))?;
+    // Template is displayed on a single line
+    def.template = Render::OneLine.join_lines(&def.template);
+
+    if group_names.is_empty() {
+        return Err(SnovaError::parse("Empty template"));
+    }
 
-        // Verify all groups are defined
-        for group_name in &user_input_groups {
-            // Missing group definition
-            if !def.groups.contains_key(&group_name.name) {
-                return Err(anyhow!(
-                    "Command '{}' is missing '{}' group definition.",
-                    def.template,
-                    group_name.name
+    let user_input_groups: Vec<&GroupName> = group_names
+        .iter()
+        .filter(|g| matches!(g.group_type, GroupNameType::UserInput { .. }))
+        .collect();
+
+    // Verify template groups and group definitions match
+    let mut seen = HashSet::new();
+    for group_name in &user_input_groups {
+        if !seen.insert(&group_name.name) {
+            return Err(SnovaError::parse(format!(
+                "Group '{}' is used more than once",
+                group_name.name
+            )));
+        }
+    }
+    let missing: Vec<&str> = user_input_groups
+        .iter()
+        .map(|g| g.name.as_str())
+        .filter(|name| !def.groups.contains_key(name))
+        .collect();
+    let unused: Vec<&String> = def
+        .groups
+        .keys()
+        .filter(|name| !seen.contains(name))
+        .collect();
+    let mut warnings = vec![];
+    if !missing.is_empty() || (!unused.is_empty() && !lenient) {
+        let mut msg = String::from("Groups do not match:");
+        for name in &missing {
+            msg.push_str(&format!("\n  missing '{}' group definition", name));
+        }
+        for name in &unused {
+            msg.push_str(&format!("\n  '{}' group is not in the template", name));
+        }
+        return Err(SnovaError::parse(msg));
+    }
+    for name in unused {
+        warnings.push(format!("Group '{}' is not in the template", name));
+    }
+    // Examples are plain text, they are only checked to run the same program
+    let program = group_names
+        .first()
+        .filter(|g| matches!(g.group_type, GroupNameType::Fixed))
+        .and_then(|g| g.name.split_whitespace().next());
+    if let Some(program) = program {
+        for example in &def.examples {
+            if example.command.split_whitespace().next() != Some(program) {
+                warnings.push(format!(
+                    "Example '{}' doesn't start with '{}'",
+                    example.command, program
                 ));
             }
         }
+    }
 
-        // Group counts do not match
-        if def.groups.keys().count() != user_input_groups.len() {
-            let template_group_names: Vec<&str> =
-                user_input_groups.iter().map(|g| g.name.as_ref()).collect();
-            let groups: Vec<&String> = def.groups.keys().collect();
-            return Err(anyhow!(
-                "Group counts do not match template={:?} and groups={:?}",
-                template_group_names,
-                groups,
-            ));
-        }
-
-        let mut cmd_groups = vec![];
-
-        // Verify each group is correctly defined and build cmd groups
-        for group_name in &user_input_groups {
-            let name = &group_name.name;
-            let group = def.groups.remove(name).expect("Group defined");
-            let optional =
-                matches!(group_name.group_type, GroupNameType::UserInput { optional } if optional);
+    let mut cmd_groups = vec![];
 
-            match (group.expect, group.flags) {
-                (Some(_expect), Some(_flags)) => {
-                    return Err(anyhow!(
-                        "Group '{}' defines both expect and flags in '{}'",
-                        name,
-                        def.template
-                    ));
+    // Verify each group is correctly defined and build cmd groups
+    for group_name in &user_input_groups {
+        let name = &group_name.name;
+        let group = def.groups.remove(name).expect("Group defined");
+        let optional =
+            matches!(group_name.group_type, GroupNameType::UserInput { optional } if optional);
+        // Groups that suggestions could refer to
+        let other_groups: Vec<&str> = user_input_groups
+            .iter()
+            .map(|g| g.name.as_str())
+            .filter(|other| other != name)
+            .collect();
+        let quote = match &group.quote {
+            Some(quote) => {
+                QuotePolicy::parse(quote).with_context(|| format!("In group '{}'", name))?
+            }
+            None => QuotePolicy::default(),
+        };
+        let transform = group
+            .transform
+            .iter()
+            .map(|t| Transform::parse(t))
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| format!("In group '{}'", name))?;
+
+        match (group.expect, group.flags) {
+            (Some(_expect), Some(_flags)) => {
+                return Err(SnovaError::parse(format!(
+                    "Group '{}' defines both expect and flags",
+                    name
+                )));
+            }
+            (None, None) => {
+                return Err(SnovaError::parse(format!(
+                    "Group '{}' should define expect or flags",
+                    name
+                )));
+            }
+            (Some(_expect), None) if group.allow_custom.is_some() => {
+                return Err(SnovaError::parse(format!(
+                    "Group '{}' defines allow_custom without flags",
+                    name
+                )));
+            }
+            (Some(_expect), None) if group.canonical_order => {
+                return Err(SnovaError::parse(format!(
+                    "Group '{}' defines canonical_order without flags",
+                    name
+                )));
+            }
+            (Some(expect), None) => {
+                let value_type = ValueType::parse(&expect)?;
+                let (suggest, suggest_source) = match group.suggest {
+                    Some(SuggestDef::Source(source)) => {
+                        let source = SuggestSource::parse(&source)
+                            .with_context(|| format!("In group '{}'", name))?;
+                        if source == SuggestSource::GitFiles
+                            && !matches!(value_type, ValueType::Path)
+                        {
+                            return Err(SnovaError::parse(format!(
+                                "Group '{}' suggests {:?} for a {:?} value",
+                                name, source, value_type
+                            )));
+                        }
+                        (None, Some(source))
+                    }
+                    Some(SuggestDef::List(list)) => {
+                        check_suggest_refs(&list, &other_groups)
+                            .with_context(|| format!("In group '{}'", name))?;
+                        (Some(list), None)
+                    }
+                    None => (None, None),
+                };
+                let limits = NumberLimits {
+                    min: group.min,
+                    max: group.max,
+                    keep_leading_zeros: group.keep_leading_zeros,
+                };
+                if limits != NumberLimits::default() && !matches!(value_type, ValueType::Number) {
+                    return Err(SnovaError::parse(format!(
+                        "Group '{}' defines number limits for a {:?} value",
+                        name, value_type
+                    )));
                 }
-                (None, None) => {
-                    return Err(anyhow!(
-                        "Group '{}' should define expect or flags in '{}'",
-                        name,
-                        def.template
-                    ));
+                let length = LengthLimits {
+                    min: group.min_len,
+                    max: group.max_len,
+                };
+                if length != LengthLimits::default() && !matches!(value_type, ValueType::String) {
+                    return Err(SnovaError::parse(format!(
+                        "Group '{}' defines length limits for a {:?} value",
+                        name, value_type
+                    )));
                 }
-                (Some(expect), None) => {
-                    cmd_groups.push(CmdGroup {
-                        name: name.clone(),
-                        suggest: group.suggest,
-                        expect: GroupValue::Single(ValueType::parse(&expect)?),
-                        optional,
-                    });
+                if let (Some(min), Some(max)) = (length.min, length.max) {
+                    if min > max {
+                        return Err(SnovaError::parse(format!(
+                            "Group '{}' min_len {} is greater than max_len {}",
+                            name, min, max
+                        )));
+                    }
                 }
-                (None, Some(flags)) => {
-                    cmd_groups.push(CmdGroup {
-                        name: name.clone(),
-                        suggest: group.suggest,
-                        expect: GroupValue::Flags(prepare_flags(flags)?),
-                        optional,
-                    });
+                if let Some(default) = &group.default {
+                    validate_default(default, &value_type)
+                        .with_context(|| format!("In group '{}'", name))?;
+                    if matches!(value_type, ValueType::Number) {
+                        limits
+                            .apply(default)
+                            .with_context(|| format!("In group '{}' default", name))?;
+                    }
+                    length
+                        .check(default)
+                        .with_context(|| format!("In group '{}' default", name))?;
+                }
+                if let Some(computed) = &group.computed {
+                    if group.default.is_some() {
+                        return Err(SnovaError::parse(format!(
+                            "Group '{}' defines both default and computed",
+                            name
+                        )));
+                    }
+                    for reference in
+                        computed_refs(computed).with_context(|| format!("In group '{}'", name))?
+                    {
+                        if !user_input_groups.iter().any(|g| g.name == reference) {
+                            return Err(SnovaError::parse(format!(
+                                "Group '{}' refers to unknown group '{}'",
+                                name, reference
+                            )));
+                        }
+                    }
                 }
+                cmd_groups.push(CmdGroup {
+                    name: name.clone(),
+                    suggest,
+                    suggest_source,
+                    expect: GroupValue::Single(value_type),
+                    optional,
+                    default: group.default,
+                    quote,
+                    transform,
+                    prefix: group.prefix,
+                    suffix: group.suffix,
+                    computed: group.computed,
+                    editable: group.editable,
+                    multiline: group.multiline,
+                    limits,
+                    length,
+                    secret: group.secret,
+                    allow_custom: false,
+                    canonical_order: false,
+                });
+            }
+            (None, Some(_flags)) if group.computed.is_some() => {
+                return Err(SnovaError::parse(format!(
+                    "Group '{}' defines both computed and flags",
+                    name
+                )));
+            }
+            (None, Some(_flags)) if group.default.is_some() => {
+                return Err(SnovaError::parse(format!(
+                    "Group '{}' defines both default and flags",
+                    name
+                )));
+            }
+            (None, Some(_flags))
+                if group.min.is_some() || group.max.is_some() || group.keep_leading_zeros =>
+            {
+                return Err(SnovaError::parse(format!(
+                    "Group '{}' defines both number limits and flags",
+                    name
+                )));
+            }
+            (None, Some(_flags)) if group.min_len.is_some() || group.max_len.is_some() => {
+                return Err(SnovaError::parse(format!(
+                    "Group '{}' defines both length limits and flags",
+                    name
+                )));
+            }
+            (None, Some(_flags)) if group.multiline => {
+                return Err(SnovaError::parse(format!(
+                    "Group '{}' defines both multiline and flags",
+                    name
+                )));
+            }
+            (None, Some(_flags))
+                if !transform.is_empty() || group.prefix.is_some() || group.suffix.is_some() =>
+            {
+                return Err(SnovaError::parse(format!(
+                    "Group '{}' defines both transform and flags",
+                    name
+                )));
+            }
+            (None, Some(flags)) => {
+                cmd_groups.push(CmdGroup {
+                    name: name.clone(),
+                    suggest: group.suggest.and_then(SuggestDef::into_list),
+                    suggest_source: None,
+                    expect: GroupValue::Flags(prepare_flags(flags, quote, &other_groups)?),
+                    optional,
+                    default: None,
+                    quote,
+                    transform: vec![],
+                    prefix: None,
+                    suffix: None,
+                    computed: None,
+                    editable: false,
+                    multiline: false,
+                    limits: NumberLimits::default(),
+                    length: LengthLimits::default(),
+                    secret: group.secret,
+                    allow_custom: group.allow_custom.unwrap_or(true),
+                    canonical_order: group.canonical_order,
+                });
             }
         }
+    }
 
-        cmd_groups.sort_by(|a, b| {
-            // Required group goes before optional
-            // Optional groups are equal
-            // Required groups are equal
-            if !a.optional && b.optional {
-                Ordering::Less
-            } else if a.optional && !b.optional {
-                Ordering::Greater
-            } else {
-                Ordering::Equal
+    cmd_groups.sort_by(|a, b| {
+        // Required group goes before optional
+        // Optional groups are equal
+        // Required groups are equal
+        if !a.optional && b.optional {
+            Ordering::Less
+        } else if a.optional && !b.optional {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
+        }
+    });
+
+    let cmd_groups = order_computed(cmd_groups)?;
+
+    // Flag values are quoted when flags are built
+    let quotes: HashMap<String, QuotePolicy> = cmd_groups
+        .iter()
+        .filter(|g| matches!(g.expect, GroupValue::Single(_)))
+        .map(|g| (g.name.clone(), g.quote))
+        .collect();
+
+    let parts = split_context_vars(group_names)?;
+    // Group of the same name wins over a context variable
+    let mut shadowed = HashSet::new();
+    for part in &parts {
+        if let TemplatePart::Context(var) = part {
+            let name = var.name();
+            if cmd_groups.iter().any(|g| g.name == name) && shadowed.insert(name) {
+                warnings.push(format!(
+                    "Group '{}' hides the context variable {{{}}}",
+                    name, name
+                ));
             }
-        });
-
-        let build = move |user_input: &HashMap<String, String>| -> String {
-            let mut parts = vec![];
+        }
+    }
 
-            for g in &group_names {
-                match g.group_type {
-                    // No user input expected
-                    GroupNameType::Fixed => {
-                        parts.push(g.name.clone());
-                    }
-                    GroupNameType::UserInput { optional } => match user_input.get(&g.name) {
-                        // Replace group with user input
-                        Some(value) if !value.is_empty() => {
-                            parts.push(value.clone());
-                        }
-                        // Requires user input -> keep showing the group
-                        None if !optional => {
-                            parts.push(format!("_{}_", g.name));
+    let capacity = def.template.len();
+    let build = move |user_input: &HashMap<String, String>| -> String {
+        let mut result = String::with_capacity(capacity);
+        // Spaces around an omitted optional group are dropped with it
+        let mut omitted = false;
+
+        for part in &parts {
+            let g = match part {
+                TemplatePart::Context(var) if shadowed.contains(var.name()) => {
+                    omitted = false;
+                    if let Some(value) = user_input.get(var.name()) {
+                        match quotes.get(var.name()) {
+                            Some(policy) => result.push_str(&quote(value, *policy)),
+                            None => result.push_str(value),
                         }
-                        // Doesn't require user input or empty value -> ignore
-                        _ => {}
-                    },
+                    }
+                    continue;
+                }
+                TemplatePart::Context(var) => {
+                    omitted = false;
+                    result.push_str(&quote(&var.value(), QuotePolicy::Auto));
+                    continue;
+                }
+                TemplatePart::Group(g) => g,
+            };
+            match g.group_type {
+                // No user input expected
+                GroupNameType::Fixed => {
+                    let mut text = g.name.as_str();
+                    if omitted && (result.is_empty() || result.ends_with(' ')) {
+                        text = text.trim_start_matches(' ');
+                    }
+                    omitted &= text.is_empty();
+                    result.push_str(text);
                 }
+                GroupNameType::UserInput { optional } => match user_input.get(&g.name) {
+                    // Replace group with user input
+                    Some(value) if !value.is_empty() => {
+                        omitted = false;
+                        match quotes.get(&g.name) {
+                            Some(policy) => result.push_str(&quote(value, *policy)),
+                            None => result.push_str(value),
+                        }
+                    }
+                    // Requires user input -> keep showing the group
+                    None if !optional => {
+                        omitted = false;
+                        result.push('_');
+                        result.push_str(&g.name);
+                        result.push('_');
+                    }
+                    // Doesn't require user input or empty value -> ignore
+                    _ => omitted = true,
+                },
             }
+        }
+        if omitted {
+            result.truncate(result.trim_end_matches(' ').len());
+        }
 
-            parts.join("")
-        };
-
-        commands.push(Command {
-            template: def.template,
-            description: def.description,
-            groups: cmd_groups,
-            build: Box::new(build),
-        });
-    }
-
-    Ok(commands)
+        result
+    };
+
+    Ok(Command {
+        template: def.template,
+        description: def.description,
+        keywords: def.keywords,
+        long_description: def.long_description,
+        example: def.example,
+        examples: def
+            .examples
+            .into_iter()
+            .map(|example| Example {
+                description: example.description,
+                command: example.command,
+            })
+            .collect(),
+        groups: cmd_groups,
+        confirm: if def.confirm {
+            Some(
+                def.confirm_message
+                    .unwrap_or_else(|| "This command could be dangerous".to_string()),
+            )
+        } else {
+            None
+        },
+        post_build: def.post_build,
+        needs_root: def.needs_root,
+        warnings,
+        source: CommandSource::Builtin,
+        build: Box::new(build),
+    })
 }
 
-fn prepare_flags(mut defs: VecDeque<FlagDef>) -> Result<Vec<Flag>> {
+fn prepare_flags(
+    mut defs: VecDeque<FlagDef>,
+    policy: QuotePolicy,
+    cmd_groups: &[&str],
+) -> Result<Vec<Flag>> {
     let mut flags = vec![];
 
     while let Some(flag_def) = defs.pop_front() {
+        let template = &flag_def.template;
         let group_names = parse_template_groups(&flag_def.template)
-            .context(format!("In flag {}", flag_def.template))?;
+            .with_context(|| format!("In flag {}", template))?;
         let user_input_groups: Vec<_> = group_names
             .iter()
             .filter(|g| matches!(g.group_type, GroupNameType::UserInput { .. }))
             .collect();
 
         let expect = match flag_def.expect {
-            Some(_) if user_input_groups.len() != 1 => {
-                return Err(anyhow!(
-                    "Expected one input group for {}",
-                    flag_def.template
-                ));
+            Some(expect) => {
+                let inputs = flag_inputs(expect, &user_input_groups)
+                    .with_context(|| format!("In flag {}", template))?;
+                Some(FlagExpectation {
+                    inputs,
+                    build: Box::new(move |values| {
+                        // Substitute values positionally
+                        let mut values = values.iter();
+                        group_names
+                            .iter()
+                            .map(|g| match g.group_type {
+                                GroupNameType::Fixed => g.name.clone(),
+                                GroupNameType::UserInput { .. } => values
+                                    .next()
+                                    .map(|value| quote(value, policy).into_owned())
+                                    .unwrap_or_default(),
+                            })
+                            .collect::<String>()
+                    }),
+                })
             }
-            Some(expect) => Some(FlagExpectation {
-                value_type: ValueType::parse(&expect)?,
-                build: Box::new(move |user_input| {
-                    group_names
-                        .iter()
-                        .map(|g| match g.group_type {
-                            GroupNameType::Fixed => &g.name,
-                            GroupNameType::UserInput { .. } => user_input,
-                        })
-                        .collect::<String>()
-                }),
-            }),
             None => None,
         };
 
+        if let Some(suggest) = &flag_def.suggest {
+            check_suggest_refs(suggest, cmd_groups)
+                .with_context(|| format!("In flag {}", template))?;
+        }
+        if let Some(suggest_cmd) = &flag_def.suggest_cmd {
+            if expect.is_none() {
+                return Err(SnovaError::parse(format!(
+                    "Flag '{}' defines suggest_cmd without expect",
+                    template
+                )));
+            }
+            check_suggest_refs(std::slice::from_ref(suggest_cmd), cmd_groups)
+                .with_context(|| format!("In flag {}", template))?;
+        }
+
+        // Default is used for the first value
+        if let (Some(default), Some(expect)) = (&flag_def.default, &expect) {
+            validate_default(default, &expect.inputs[0].value_type)
+                .with_context(|| format!("In flag {}", template))?;
+        }
+
         flags.push(Flag {
             template: flag_def.template,
             description: flag_def.description,
+            long_description: flag_def.long_description,
+            example: flag_def.example,
             expect,
             multiple: flag_def.multiple,
+            danger: flag_def.danger,
             suggest: flag_def.suggest,
+            suggest_cmd: flag_def.suggest_cmd,
+            default: flag_def.default,
         });
     }
 
     Ok(flags)
 }
 
+/// Suggested values could refer to other groups of the command, e.g "{HOST}:/var/log"
+fn check_suggest_refs(suggest: &[String], groups: &[&str]) -> Result<()> {
+    for value in suggest {
+        for reference in computed_refs(value)? {
+            if !groups.contains(&reference.as_str()) {
+                return Err(SnovaError::parse(format!(
+                    "Suggestion '{}' refers to unknown group '{}'",
+                    value, reference
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Place computed groups after the groups they refer to. Fails if groups refer to each other.
+fn order_computed(groups: Vec<CmdGroup>) -> Result<Vec<CmdGroup>> {
+    // References are read once per group
+    let mut groups: Vec<(CmdGroup, Vec<String>)> = groups
+        .into_iter()
+        .map(|group| {
+            let refs = match &group.computed {
+                Some(computed) => computed_refs(computed).unwrap_or_default(),
+                None => vec![],
+            };
+            (group, refs)
+        })
+        .collect();
+
+    let mut ordered: Vec<CmdGroup> = Vec::with_capacity(groups.len());
+    while !groups.is_empty() {
+        let ready = groups.iter().position(|(_, refs)| {
+            refs.iter()
+                .all(|r| ordered.iter().any(|done| &done.name == r))
+        });
+        match ready {
+            Some(index) => ordered.push(groups.remove(index).0),
+            None => {
+                let names: Vec<&str> = groups.iter().map(|(g, _)| g.name.as_ref()).collect();
+                return Err(SnovaError::parse(format!(
+                    "Computed groups refer to each other: {}",
+                    names.join(", ")
+                )));
+            }
+        }
+    }
+    Ok(ordered)
+}
+
+/// Verify that every input group of a flag has a value type
+fn flag_inputs(expect: FlagExpectDef, groups: &[&GroupName]) -> Result<Vec<FlagInput>> {
+    match expect {
+        FlagExpectDef::Single(_) if groups.len() != 1 => Err(SnovaError::parse(format!(
+            "Expected one input group, found {}",
+            groups.len()
+        ))),
+        FlagExpectDef::Single(value_type) => Ok(vec![FlagInput {
+            name: groups[0].name.clone(),
+            value_type: ValueType::parse(&value_type)?,
+        }]),
+        FlagExpectDef::Groups(mut types) => {
+            if groups.is_empty() {
+                return Err(SnovaError::parse("Expected input groups in the template"));
+            }
+            let mut inputs = vec![];
+            for group in groups {
+                let value_type = types.remove(&group.name).ok_or_else(|| {
+                    SnovaError::parse(format!("Group '{}' should define expect", group.name))
+                })?;
+                inputs.push(FlagInput {
+                    name: group.name.clone(),
+                    value_type: ValueType::parse(&value_type)?,
+                });
+            }
+            if let Some(name) = types.keys().next() {
+                return Err(SnovaError::parse(format!(
+                    "Group '{}' is not in the template",
+                    name
+                )));
+            }
+            Ok(inputs)
+        }
+    }
+}
+
+/// Verify that a default value could have been typed by the user
+fn validate_default(default: &str, value_type: &ValueType) -> Result<()> {
+    if default.is_empty() || !default.chars().all(|c| value_type.is_valid_char(c)) {
+        return Err(SnovaError::parse(format!(
+            "Default '{}' is not a valid {:?} value",
+            default, value_type
+        )));
+    }
+    Ok(())
+}
+
 #[derive(Debug, Clone, PartialEq)]
 struct GroupName {
     name: String,
@@ -279,6 +1168,57 @@ enum GroupNameType {
     Fixed,
 }
 
+/// Part of a command template
+enum TemplatePart {
+    Group(GroupName),
+    /// Value that is looked up when the command is built
+    Context(ContextVar),
+}
+
+/// Find context variables like `{cwd}` in the fixed text of a template.
+/// Other text in braces (e.g `find -exec rm {} \;`) is kept as is.
+fn split_context_vars(groups: Vec<GroupName>) -> Result<Vec<TemplatePart>> {
+    let mut parts = vec![];
+    for group in groups {
+        if !matches!(group.group_type, GroupNameType::Fixed) {
+            parts.push(TemplatePart::Group(group));
+            continue;
+        }
+        let mut fixed = String::new();
+        let mut text = group.name.as_str();
+        while let Some(start) = text.find('{') {
+            let end = match text[start..].find('}') {
+                Some(end) => start + end,
+                None => break,
+            };
+            let var = ContextVar::parse(&text[start + 1..end])
+                .with_context(|| format!("In '{}'", &text[start..=end]))?;
+            match var {
+                Some(var) => {
+                    fixed.push_str(&text[..start]);
+                    if !fixed.is_empty() {
+                        parts.push(TemplatePart::Group(GroupName {
+                            name: std::mem::take(&mut fixed),
+                            group_type: GroupNameType::Fixed,
+                        }));
+                    }
+                    parts.push(TemplatePart::Context(var));
+                }
+                None => fixed.push_str(&text[..=end]),
+            }
+            text = &text[end + 1..];
+        }
+        fixed.push_str(text);
+        if !fixed.is_empty() {
+            parts.push(TemplatePart::Group(GroupName {
+                name: fixed,
+                group_type: GroupNameType::Fixed,
+            }));
+        }
+    }
+    Ok(parts)
+}
+
 /// Read command template and return a list of group names.
 fn parse_template_groups(template: &str) -> Result<Vec<GroupName>> {
     let mut groups = vec![];
@@ -292,21 +1232,31 @@ fn parse_template_groups(template: &str) -> Result<Vec<GroupName>> {
             '*' => {
                 // Strip bold
             }
+            // Escaped chars are kept without the backslash
+            '_' | '[' | ']' if prev_char == '\\' => {
+                current_group.pop();
+                current_group.push(c);
+            }
             '[' => optional_started = true,
             ']' => {
                 if !optional_started {
-                    return Err(anyhow!("Unexpected ']' in group '{}'", current_group));
+                    return Err(SnovaError::parse(format!(
+                        "Unexpected ']' in group '{}'",
+                        current_group
+                    )));
                 }
                 optional_started = false;
             }
-            '_' if prev_char != '\\' => match state {
+            '_' => match state {
                 GroupNameType::UserInput { .. } => {
+                    if current_group.is_empty() {
+                        return Err(SnovaError::parse("Group name is empty"));
+                    }
                     // Close the group
                     groups.push(GroupName {
-                        name: current_group.replace("\\_", "_"),
+                        name: std::mem::take(&mut current_group),
                         group_type: state,
                     });
-                    current_group = String::new();
                     state = GroupNameType::Fixed;
                 }
                 GroupNameType::Fixed => {
@@ -314,10 +1264,9 @@ fn parse_template_groups(template: &str) -> Result<Vec<GroupName>> {
                     if !current_group.is_empty() {
                         // If there is some input already then store it in a separate group
                         groups.push(GroupName {
-                            name: current_group.replace("\\_", "_"),
+                            name: std::mem::take(&mut current_group),
                             group_type: state.clone(),
                         });
-                        current_group = String::new();
                     }
 
                     state = GroupNameType::UserInput {
@@ -331,12 +1280,18 @@ fn parse_template_groups(template: &str) -> Result<Vec<GroupName>> {
     }
 
     if !matches!(state, GroupNameType::Fixed) {
-        return Err(anyhow!("Group '{}' is not closed", current_group));
+        return Err(SnovaError::parse(format!(
+            "Group '{}' is not closed",
+            current_group
+        )));
+    }
+    if optional_started {
+        return Err(SnovaError::parse("Optional group is missing a closing ']'"));
     }
 
     if !current_group.is_empty() {
         groups.push(GroupName {
-            name: current_group.replace("\\_", "_"),
+            name: current_group,
             group_type: state,
         });
     }
@@ -344,17 +1299,101 @@ fn parse_template_groups(template: &str) -> Result<Vec<GroupName>> {
     Ok(groups)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Fixed text or a group placeholder of a template
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplateSlot {
+    /// Text without markup and brackets
+    Text(String),
+    Group(String),
+}
 
-    #[test]
-    fn parse_template_groups_grep() {
-        let template = "grep [_OPTIONS_] _PATTERN_ _PATH_";
-        let names = parse_template_groups(template);
-        assert!(names.is_ok(), "No errors");
-        assert_eq!(
-            vec![
+/// Split the template into fixed text and group placeholders in order
+pub fn template_slots(template: &str) -> Result<Vec<TemplateSlot>> {
+    Ok(parse_template_groups(template)?
+        .into_iter()
+        .map(|group| match group.group_type {
+            GroupNameType::Fixed => TemplateSlot::Text(group.name),
+            GroupNameType::UserInput { .. } => TemplateSlot::Group(group.name),
+        })
+        .collect())
+}
+
+/// Find template constructs that parse but produce confusing prompts or commands
+pub fn lint_template(template: &str) -> Vec<String> {
+    let mut warnings = vec![];
+
+    let mut open = 0;
+    let mut prev_char = ' ';
+    for c in template.chars() {
+        match c {
+            '[' if prev_char != '\\' => open += 1,
+            ']' if prev_char != '\\' && open > 0 => open -= 1,
+            _ => {}
+        }
+        prev_char = c;
+    }
+    if open > 0 {
+        warnings.push("Optional group is missing a closing ']'".to_string());
+    }
+
+    let groups = match parse_template_groups(template) {
+        Ok(groups) => groups,
+        // Errors are reported when parsing definitions
+        Err(_) => return warnings,
+    };
+    let is_input = |g: &GroupName| matches!(g.group_type, GroupNameType::UserInput { .. });
+
+    for (i, group) in groups.iter().enumerate() {
+        let prev = i.checked_sub(1).and_then(|i| groups.get(i));
+        let next = groups.get(i + 1);
+        match group.group_type {
+            GroupNameType::UserInput { optional } => {
+                if let Some(next) = next.filter(|next| is_input(next)) {
+                    warnings.push(format!(
+                        "Groups {} and {} are not separated",
+                        group.name, next.name
+                    ));
+                }
+                let spaced_before =
+                    prev.is_none_or(|p| !is_input(p) && p.name.ends_with(char::is_whitespace));
+                let spaced_after =
+                    next.is_none_or(|n| !is_input(n) && n.name.starts_with(char::is_whitespace));
+                if optional && !(spaced_before && spaced_after) {
+                    warnings.push(format!(
+                        "Optional group {} is not surrounded by whitespace",
+                        group.name
+                    ));
+                }
+            }
+            GroupNameType::Fixed => {
+                let between_inputs = prev.is_some_and(is_input) && next.is_some_and(is_input);
+                if between_inputs && group.name.trim().is_empty() && group.name != " " {
+                    warnings.push(format!(
+                        "Groups {} and {} are separated by {:?}, use a single space",
+                        prev.unwrap().name,
+                        next.unwrap().name,
+                        group.name
+                    ));
+                }
+            }
+        }
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn parse_template_groups_grep() {
+        let template = "grep [_OPTIONS_] _PATTERN_ _PATH_";
+        let names = parse_template_groups(template);
+        assert!(names.is_ok(), "No errors");
+        assert_eq!(
+            vec![
                 GroupName {
                     name: "grep ".into(),
                     group_type: GroupNameType::Fixed,
@@ -457,36 +1496,175 @@ mod tests {
         assert_eq!("Group 'PATH' is not closed", err_str);
     }
 
+    #[test]
+    fn parse_template_groups_edge_cases() {
+        let err = |template| format!("{}", parse_template_groups(template).unwrap_err());
+        assert_eq!("Optional group is missing a closing ']'", err("ls [_PATH_"));
+        assert_eq!("Optional group is missing a closing ']'", err("["));
+        assert_eq!("Unexpected ']' in group ''", err("]"));
+        assert_eq!("Group name is empty", err("__"));
+        assert_eq!("Group name is empty", err("echo __ _TEXT_"));
+
+        let fixed = |name: &str| GroupName {
+            name: name.to_string(),
+            group_type: GroupNameType::Fixed,
+        };
+        assert_eq!(
+            vec![fixed("echo \\")],
+            parse_template_groups("echo \\").unwrap()
+        );
+        assert_eq!(
+            vec![
+                fixed("echo [ "),
+                GroupName {
+                    name: "TEXT".into(),
+                    group_type: GroupNameType::UserInput { optional: false },
+                },
+                fixed(" ]"),
+            ],
+            parse_template_groups("echo \\[ _TEXT_ \\]").unwrap()
+        );
+    }
+
+    /// Template with bold, escapes and brackets removed. Placeholders keep their underscores.
+    fn plain_template(template: &str) -> String {
+        let mut plain = String::new();
+        let mut prev_char = ' ';
+        for c in template.chars() {
+            match c {
+                '*' => {}
+                '_' | '[' | ']' if prev_char == '\\' => {
+                    plain.pop();
+                    plain.push(c);
+                }
+                '[' | ']' => {}
+                c => plain.push(c),
+            }
+            prev_char = c;
+        }
+        plain
+    }
+
+    /// Templates made of the chars that the parser treats specially
+    fn template_strategy() -> impl Strategy<Value = String> {
+        let token = prop_oneof![
+            Just("_".to_string()),
+            Just("[".to_string()),
+            Just("]".to_string()),
+            Just("\\".to_string()),
+            Just("*".to_string()),
+            "[a-zA-Z -]{1,4}",
+        ];
+        prop::collection::vec(token, 0..16).prop_map(|tokens| tokens.concat())
+    }
+
+    proptest! {
+        #[test]
+        fn parse_template_groups_any_input(template in "\\PC{0,32}") {
+            let _ = parse_template_groups(&template);
+        }
+
+        #[test]
+        fn parse_template_groups_reconstructs(template in template_strategy()) {
+            let first = parse_template_groups(&template).map_err(|err| err.to_string());
+            let second = parse_template_groups(&template).map_err(|err| err.to_string());
+            prop_assert_eq!(&first, &second);
+
+            if let Ok(groups) = first {
+                let mut rebuilt = String::new();
+                for (i, group) in groups.iter().enumerate() {
+                    prop_assert!(!group.name.is_empty());
+                    match group.group_type {
+                        GroupNameType::UserInput { .. } => {
+                            rebuilt.push('_');
+                            rebuilt.push_str(&group.name);
+                            rebuilt.push('_');
+                        }
+                        GroupNameType::Fixed => {
+                            // Fixed text between groups is never split
+                            let prev = i.checked_sub(1).map(|i| &groups[i].group_type);
+                            prop_assert_ne!(Some(&GroupNameType::Fixed), prev);
+                            rebuilt.push_str(&group.name);
+                        }
+                    }
+                }
+                prop_assert_eq!(plain_template(&template), rebuilt);
+            }
+        }
+    }
+
     #[test]
     fn parse_defs_ok() {
-        let mut groups = HashMap::new();
+        let mut groups = GroupDefs::default();
         groups.insert(
             "PATH".to_string(),
             GroupDef {
+                quote: None,
+                transform: vec![],
+                prefix: None,
+                suffix: None,
+                computed: None,
+                editable: false,
+                multiline: false,
+                min: None,
+                max: None,
+                keep_leading_zeros: false,
+                min_len: None,
+                max_len: None,
+                secret: false,
+                allow_custom: None,
+                canonical_order: false,
                 expect: Some("path".into()),
                 suggest: None,
                 flags: None,
+                default: None,
             },
         );
         groups.insert(
             "OPTIONS".to_string(),
             GroupDef {
+                quote: None,
+                transform: vec![],
+                prefix: None,
+                suffix: None,
+                computed: None,
+                editable: false,
+                multiline: false,
+                min: None,
+                max: None,
+                keep_leading_zeros: false,
+                min_len: None,
+                max_len: None,
+                secret: false,
+                allow_custom: None,
+                canonical_order: false,
                 expect: None,
                 suggest: None,
+                default: None,
                 flags: Some(VecDeque::from(vec![
                     FlagDef {
                         template: "-i".into(),
                         description: "Case insensitive matching".into(),
+                        long_description: None,
+                        example: None,
                         expect: None,
                         multiple: false,
+                        danger: false,
                         suggest: None,
+                        suggest_cmd: None,
+                        default: None,
                     },
                     FlagDef {
                         template: "*-A*_NUM_".into(),
                         description: "Print _NUM_ lines after the matched line".into(),
-                        expect: Some("number".into()),
+                        long_description: None,
+                        example: None,
+                        expect: Some(FlagExpectDef::Single("number".into())),
                         multiple: false,
+                        danger: false,
                         suggest: None,
+                        suggest_cmd: None,
+                        default: None,
                     },
                 ])),
             },
@@ -496,6 +1674,15 @@ mod tests {
             commands: vec![CommandDef {
                 template: "grep [_OPTIONS_] _PATH_".into(),
                 description: "Find lines in a file (*grep*)".into(),
+                keywords: vec![],
+                long_description: None,
+                example: None,
+                examples: vec![],
+                confirm: false,
+                confirm_message: None,
+                post_build: None,
+                needs_root: false,
+                render: None,
                 groups,
             }]
             .into(),
@@ -524,18 +1711,82 @@ mod tests {
         user_input.insert("PATH".to_string(), "./one".to_string());
 
         let result = (cmd.build)(&user_input);
-        assert_eq!("grep  ./one", result);
+        assert_eq!("grep ./one", result);
+    }
+
+    #[test]
+    fn omitted_optional_groups() {
+        let commands = parse_str(
+            r#"
+[[commands]]
+template = "tar [_COMPRESSION_] -cf _ARCHIVE_ _PATH_"
+description = "Create an archive"
+groups.COMPRESSION.expect = "string"
+groups.ARCHIVE.expect = "path"
+groups.PATH.expect = "path"
+
+[[commands]]
+template = "[_SUDO_] ls [_A_] [_B_] _DIR_ [_C_]"
+description = "List"
+groups.SUDO.expect = "string"
+groups.A.expect = "string"
+groups.B.expect = "string"
+groups.C.expect = "string"
+groups.DIR.expect = "path"
+"#,
+        )
+        .unwrap();
+        let build = |cmd: &Command, values: &[(&str, &str)]| {
+            let user_input: HashMap<String, String> = values
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+            (cmd.build)(&user_input)
+        };
+
+        let tar = &commands[0];
+        let values = [("ARCHIVE", "a.tar"), ("PATH", "src")];
+        assert_eq!("tar -cf a.tar src", build(tar, &values));
+        let values = [("COMPRESSION", "-z"), ("ARCHIVE", "a.tar"), ("PATH", "src")];
+        assert_eq!("tar -z -cf a.tar src", build(tar, &values));
+
+        let ls = &commands[1];
+        assert_eq!("ls /tmp", build(ls, &[("DIR", "/tmp")]));
+        assert_eq!("ls -b /tmp", build(ls, &[("DIR", "/tmp"), ("B", "-b")]));
+        assert_eq!(
+            "sudo ls -a /tmp -c",
+            build(
+                ls,
+                &[("SUDO", "sudo"), ("A", "-a"), ("DIR", "/tmp"), ("C", "-c")]
+            )
+        );
     }
 
     #[test]
     fn parse_defs_inline_group() {
-        let mut groups = HashMap::new();
+        let mut groups = GroupDefs::default();
         groups.insert(
             "VALUE".to_string(),
             GroupDef {
+                quote: None,
+                transform: vec![],
+                prefix: None,
+                suffix: None,
+                computed: None,
+                editable: false,
+                multiline: false,
+                min: None,
+                max: None,
+                keep_leading_zeros: false,
+                min_len: None,
+                max_len: None,
+                secret: false,
+                allow_custom: None,
+                canonical_order: false,
                 expect: Some("string".into()),
                 suggest: None,
                 flags: None,
+                default: None,
             },
         );
 
@@ -543,6 +1794,15 @@ mod tests {
             commands: vec![CommandDef {
                 template: "curl http://localhost?one=_VALUE_".into(),
                 description: "Get something".into(),
+                keywords: vec![],
+                long_description: None,
+                example: None,
+                examples: vec![],
+                confirm: false,
+                confirm_message: None,
+                post_build: None,
+                needs_root: false,
+                render: None,
                 groups,
             }]
             .into(),
@@ -567,15 +1827,689 @@ mod tests {
         assert_eq!("curl http://localhost?one=value", result);
     }
 
+    #[test]
+    fn parse_defs_keywords() {
+        let defs: CommandsDef = toml::de::from_str(
+            r#"
+[[commands]]
+template = "tar -xf _ARCHIVE_"
+description = "Unpack an archive (*tar*)"
+keywords = ["extract", "untar"]
+groups.ARCHIVE.expect = "path"
+"#,
+        )
+        .unwrap();
+
+        let commands = parse_defs(defs);
+        assert!(
+            commands.is_ok(),
+            "Parse defs is ok (err={:?})",
+            commands.err()
+        );
+        let commands = commands.ok().unwrap();
+        assert_eq!(vec!["extract", "untar"], commands[0].keywords);
+    }
+
+    #[test]
+    fn parse_defs_default() {
+        let defs: CommandsDef = toml::de::from_str(
+            r#"
+[[commands]]
+template = "grep [_OPTIONS_] _PATH_"
+description = "Find lines in a file (*grep*)"
+groups.PATH.expect = "path"
+groups.PATH.default = "."
+groups.OPTIONS.flags = [
+  { template = "*-A* _NUM_", description = "Print _NUM_ lines after", expect = "number", default = "2" },
+]
+"#,
+        )
+        .unwrap();
+
+        let commands = parse_defs(defs);
+        assert!(
+            commands.is_ok(),
+            "Parse defs is ok (err={:?})",
+            commands.err()
+        );
+        let commands = commands.ok().unwrap();
+        assert_eq!(Some(".".to_string()), commands[0].groups[0].default);
+        match &commands[0].groups[1].expect {
+            GroupValue::Flags(flags) => assert_eq!(Some("2".to_string()), flags[0].default),
+            _ => panic!("Expected flags"),
+        }
+    }
+
+    #[test]
+    fn parse_defs_number_limits() {
+        let commands = parse_str(
+            r#"
+[[commands]]
+template = "ssh -p _PORT_ _HOST_"
+description = "Connect (*ssh*)"
+groups.PORT.expect = "number"
+groups.PORT.min = 1
+groups.PORT.max = 65535
+groups.PORT.default = "22"
+groups.HOST.expect = "string"
+"#,
+        )
+        .unwrap();
+        let port = &commands[0].groups[0];
+        assert_eq!(Some(1), port.limits.min);
+        assert_eq!(Some(65535), port.limits.max);
+
+        let err = parse_str(
+            r#"
+[[commands]]
+template = "ssh -p _PORT_"
+description = "Connect (*ssh*)"
+groups.PORT.expect = "number"
+groups.PORT.max = 65535
+groups.PORT.default = "70000"
+"#,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            "In 'ssh -p _PORT_': In group 'PORT' default: 70000 is greater than 65535",
+            err.to_string()
+        );
+        // Invalid default is a definition error of the command
+        match err {
+            SnovaError::Parse { command, .. } => {
+                assert_eq!(Some("ssh -p _PORT_".to_string()), command)
+            }
+            err => panic!("Expected a parse error, got {:?}", err),
+        }
+
+        let err = parse_str(
+            r#"
+[[commands]]
+template = "ssh _HOST_"
+description = "Connect (*ssh*)"
+groups.HOST.expect = "string"
+groups.HOST.keep_leading_zeros = true
+"#,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            "In 'ssh _HOST_': Group 'HOST' defines number limits for a String value",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn parse_defs_length_limits() {
+        let commands = parse_str(
+            r#"
+[[commands]]
+template = "docker run --name _NAME_ _IMAGE_"
+description = "Run a container"
+groups.NAME.expect = "string"
+groups.NAME.min_len = 1
+groups.NAME.max_len = 63
+groups.IMAGE.expect = "string"
+"#,
+        )
+        .unwrap();
+        let name = &commands[0].groups[0];
+        assert_eq!(
+            LengthLimits {
+                min: Some(1),
+                max: Some(63)
+            },
+            name.length
+        );
+
+        let errors = [
+            (
+                "groups.NAME.expect = \"string\"\ngroups.NAME.min_len = 5\ngroups.NAME.max_len = 4",
+                "Group 'NAME' min_len 5 is greater than max_len 4",
+            ),
+            (
+                "groups.NAME.expect = \"path\"\ngroups.NAME.max_len = 4",
+                "Group 'NAME' defines length limits for a Path value",
+            ),
+            (
+                "groups.NAME.flags = [{ template = \"-a\", description = \"All\" }]\ngroups.NAME.max_len = 4",
+                "Group 'NAME' defines both length limits and flags",
+            ),
+            (
+                "groups.NAME.expect = \"string\"\ngroups.NAME.default = \"abcde\"\ngroups.NAME.max_len = 4",
+                "In group 'NAME' default: Value is 5 chars long, at most 4 allowed",
+            ),
+        ];
+        for (group, expected) in errors.iter() {
+            let data = format!(
+                "[[commands]]\ntemplate = \"docker run _NAME_\"\ndescription = \"Run\"\n{}\n",
+                group
+            );
+            let err = parse_str(&data).err().unwrap().to_string();
+            assert!(err.contains(expected), "{}", err);
+        }
+    }
+
+    #[test]
+    fn parse_defs_allow_custom() {
+        let commands = parse_str(
+            r#"
+[[commands]]
+template = "curl [_OPTIONS_] [_HEADERS_] _URL_"
+description = "Fetch a page (*curl*)"
+groups.URL.expect = "string"
+groups.OPTIONS.flags = [{ template = "-L", description = "Follow redirects" }]
+groups.HEADERS.allow_custom = false
+groups.HEADERS.flags = [{ template = "-H _VALUE_", description = "Add a header", expect = "string" }]
+"#,
+        )
+        .unwrap();
+        let allowed: Vec<(&str, bool)> = commands[0]
+            .groups
+            .iter()
+            .map(|g| (g.name.as_str(), g.allow_custom))
+            .collect();
+        assert_eq!(
+            vec![("URL", false), ("OPTIONS", true), ("HEADERS", false)],
+            allowed
+        );
+
+        let err = parse_str(
+            r#"
+[[commands]]
+template = "curl _URL_"
+description = "Fetch a page (*curl*)"
+groups.URL.expect = "string"
+groups.URL.allow_custom = true
+"#,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            "In 'curl _URL_': Group 'URL' defines allow_custom without flags",
+            err.to_string()
+        );
+
+        let err = parse_str(
+            r#"
+[[commands]]
+template = "curl _URL_"
+description = "Fetch a page (*curl*)"
+groups.URL.expect = "string"
+groups.URL.canonical_order = true
+"#,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            "In 'curl _URL_': Group 'URL' defines canonical_order without flags",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn parse_defs_suggest_refs() {
+        let commands = parse_str(
+            r#"
+[[commands]]
+template = "kubectl logs -n _NAMESPACE_ [_OPTIONS_] _POD_"
+description = "Print pod logs (*kubectl*)"
+groups.NAMESPACE.expect = "string"
+groups.POD.expect = "string"
+groups.POD.suggest = ["{NAMESPACE}-api"]
+groups.OPTIONS.flags = [
+  { template = "*-c* _CONTAINER_", description = "Container", expect = "string", suggest = ["{POD}"] },
+]
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            Some(vec!["{NAMESPACE}-api".to_string()]),
+            commands[0].groups[1].suggest
+        );
+
+        let err = parse_str(
+            r#"
+[[commands]]
+template = "kubectl logs _POD_"
+description = "Print pod logs (*kubectl*)"
+groups.POD.expect = "string"
+groups.POD.suggest = ["{POD}", "{NAMESPACE}-api"]
+"#,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            "In 'kubectl logs _POD_': In group 'POD': Suggestion '{POD}' refers to unknown group 'POD'",
+            err.to_string()
+        );
+
+        let err = parse_str(
+            r#"
+[[commands]]
+template = "kubectl logs [_OPTIONS_]"
+description = "Print pod logs (*kubectl*)"
+groups.OPTIONS.flags = [
+  { template = "*-c* _CONTAINER_", description = "Container", expect = "string", suggest = ["{CONTAINER"] },
+]
+"#,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            "In 'kubectl logs [_OPTIONS_]': In flag *-c* _CONTAINER_: Reference '{CONTAINER' at column 1 is not closed",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn parse_defs_flag_suggest_cmd() {
+        let commands = parse_str(
+            r#"
+[[commands]]
+template = "docker run [_OPTIONS_] _IMAGE_"
+description = "Run a container (*docker*)"
+groups.IMAGE.expect = "string"
+groups.OPTIONS.flags = [
+  { template = "*--network* _NET_", description = "Network", expect = "string", suggest = ["host"], suggest_cmd = "docker network ls --format '{{{{.Name}}}}'" },
+]
+"#,
+        )
+        .unwrap();
+        match &commands[0].groups[1].expect {
+            GroupValue::Flags(flags) => assert_eq!(
+                Some("docker network ls --format '{{{{.Name}}}}'"),
+                flags[0].suggest_cmd.as_deref()
+            ),
+            GroupValue::Single(_) => panic!("Expected flags"),
+        }
+
+        let err = parse_str(
+            r#"
+[[commands]]
+template = "docker run [_OPTIONS_] _IMAGE_"
+description = "Run a container (*docker*)"
+groups.IMAGE.expect = "string"
+groups.OPTIONS.flags = [
+  { template = "--rm", description = "Remove when stopped", suggest_cmd = "echo --rm" },
+]
+"#,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            "In 'docker run [_OPTIONS_] _IMAGE_': Flag '--rm' defines suggest_cmd without expect",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn parse_defs_suggest_source() {
+        let commands = parse_str(
+            r#"
+[[commands]]
+template = "git add _PATH_"
+description = "Stage a file (*git*)"
+groups.PATH.expect = "path"
+groups.PATH.suggest = "git_files"
+"#,
+        )
+        .unwrap();
+        let path = &commands[0].groups[0];
+        assert_eq!(Some(SuggestSource::GitFiles), path.suggest_source);
+        assert!(path.suggest.is_none());
+
+        let err = parse_str(
+            r#"
+[[commands]]
+template = "git add _PATH_"
+description = "Stage a file (*git*)"
+groups.PATH.expect = "path"
+groups.PATH.suggest = "svn_files"
+"#,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            "In 'git add _PATH_': In group 'PATH': Unknown suggestion source 'svn_files' (valid: git_files, history_values, history_type_values)",
+            err.to_string()
+        );
+
+        let err = parse_str(
+            r#"
+[[commands]]
+template = "git checkout _BRANCH_"
+description = "Switch branches (*git*)"
+groups.BRANCH.expect = "string"
+groups.BRANCH.suggest = "git_files"
+"#,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            "In 'git checkout _BRANCH_': Group 'BRANCH' suggests GitFiles for a String value",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn parse_defs_invalid_default() {
+        let defs: CommandsDef = toml::de::from_str(
+            r#"
+[[commands]]
+template = "head -n _NUM_"
+description = "Print first lines"
+groups.NUM.expect = "number"
+groups.NUM.default = "ten"
+"#,
+        )
+        .unwrap();
+
+        let commands = parse_defs(defs);
+        assert!(commands.is_err(), "Parse defs is err");
+        let err_str = format!("{}", commands.err().unwrap());
+        assert_eq!(
+            "In 'head -n _NUM_': In group 'NUM': Default 'ten' is not a valid Number value",
+            err_str
+        );
+    }
+
+    #[test]
+    fn builtin_ok() {
+        let commands = builtin();
+        assert!(commands.is_ok(), "Builtin is ok (err={:?})", commands.err());
+    }
+
+    fn write_project(dir: &Path, file: &str, description: &str) {
+        let path = dir.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let data = format!(
+            "[[commands]]\ntemplate = \"make\"\ndescription = \"{}\"\ngroups = {{}}\n",
+            description
+        );
+        std::fs::write(path, data).unwrap();
+    }
+
+    #[test]
+    fn project_nearest_wins() {
+        let root = std::env::temp_dir().join(format!("snova-project-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let nested = root.join("a").join("b");
+        std::fs::create_dir_all(&nested).unwrap();
+        write_project(&root, ".snova.toml", "Root");
+        write_project(&root.join("a"), ".snova/commands.toml", "Nested");
+
+        let commands = project_commands(&nested, false).unwrap();
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].description, "Nested");
+
+        let commands = project_commands(&root, false).unwrap();
+        assert_eq!(commands[0].description, "Root");
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn read_all_order() {
+        let root = std::env::temp_dir().join(format!("snova-read-all-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let config = root.join("config");
+        let project = root.join("project");
+        let scripts = root.join("scripts");
+        std::fs::create_dir_all(config.join("commands.d")).unwrap();
+        std::fs::create_dir_all(&scripts).unwrap();
+        std::fs::create_dir_all(&project).unwrap();
+        let def = |name: &str| {
+            format!(
+                "[[commands]]\ntemplate = \"{} [_B_] _A_ _C_\"\ndescription = \"{}\"\n\
+                 groups.C.expect = \"string\"\ngroups.A.expect = \"string\"\n\
+                 groups.B.flags = [{{ template = \"-x\", description = \"X\" }}]\n",
+                name, name
+            )
+        };
+        std::fs::write(config.join("commands.toml"), def("user")).unwrap();
+        // Files are read by name whatever order they were created in
+        std::fs::write(config.join("commands.d").join("b.toml"), def("b")).unwrap();
+        std::fs::write(config.join("commands.d").join("a.toml"), def("a")).unwrap();
+        std::fs::write(project.join(".snova.toml"), def("project")).unwrap();
+        std::fs::write(
+            scripts.join("ops.sh"),
+            "# snova: description = \"script\"\nls _DIR_ # snova-groups: DIR\n",
+        )
+        .unwrap();
+
+        let paths = Paths {
+            config_dir: Some(config.clone()),
+            data_dir: None,
+            script_dirs: vec![scripts.clone()],
+        };
+        let load = || {
+            let commands = read_all(&paths, Some(&project), false).unwrap();
+            commands
+                .iter()
+                .map(|cmd| {
+                    let groups: Vec<String> = cmd.groups.iter().map(|g| g.name.clone()).collect();
+                    (cmd.description.clone(), groups, cmd.source.clone())
+                })
+                .collect::<Vec<_>>()
+        };
+        let loaded = load();
+        assert_eq!(loaded, load());
+
+        let builtin = builtin().unwrap().len();
+        assert!(loaded[..builtin]
+            .iter()
+            .all(|(_, _, source)| *source == CommandSource::Builtin));
+        let sources: Vec<(&str, CommandSource)> = loaded[builtin..]
+            .iter()
+            .map(|(description, _, source)| (description.as_str(), source.clone()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("user", CommandSource::User(paths.commands_file().unwrap())),
+                (
+                    "a",
+                    CommandSource::User(config.join("commands.d").join("a.toml"))
+                ),
+                (
+                    "b",
+                    CommandSource::User(config.join("commands.d").join("b.toml"))
+                ),
+                ("script", CommandSource::Imported(scripts.join("ops.sh"))),
+                (
+                    "project",
+                    CommandSource::Project(project.join(".snova.toml"))
+                ),
+            ],
+            sources
+        );
+        // Required groups are prompted first, each in template order
+        assert_eq!(vec!["A", "C", "B"], loaded[builtin].1);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn project_error_location() {
+        let root = std::env::temp_dir().join(format!("snova-project-err-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        let file = root.join(".snova.toml");
+        std::fs::write(
+            &file,
+            "[[commands]]\ntemplate = \"make _TARGET\"\ndescription = \"Make\"\ngroups = {}\n",
+        )
+        .unwrap();
+
+        let err = project_commands(&root, false).err().unwrap();
+        assert_eq!(
+            format!(
+                "{}: In 'make _TARGET': Group 'TARGET' is not closed",
+                file.display()
+            ),
+            err.to_string()
+        );
+        assert!(matches!(err, SnovaError::Parse { file: Some(_), .. }));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn project_post_build() {
+        let root = std::env::temp_dir().join(format!("snova-project-hook-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let nested = root.join("a").join("b");
+        std::fs::create_dir_all(&nested).unwrap();
+        let file = root.join(".snova.toml");
+        std::fs::write(
+            &file,
+            "[[commands]]\ntemplate = \"make\"\ndescription = \"Make\"\n\
+             post_build = \"touch hook\"\ngroups = {}\n",
+        )
+        .unwrap();
+
+        // Lenient loading doesn't skip the check
+        let err = project_commands(&nested, true).err().unwrap();
+        assert_eq!(
+            format!(
+                "{}: In 'make': post_build is only allowed in user commands",
+                file.display()
+            ),
+            err.to_string()
+        );
+
+        let script = root.join("scripts");
+        std::fs::create_dir_all(&script).unwrap();
+        std::fs::write(
+            script.join("ops.sh"),
+            "# snova: description = \"List\"\n# snova: post_build = \"touch hook\"\nls\n",
+        )
+        .unwrap();
+        let err = scripts_commands(&script, false).err().unwrap();
+        assert_eq!(
+            format!(
+                "{}: In 'ls': post_build is only allowed in user commands",
+                script.join("ops.sh").display()
+            ),
+            err.to_string()
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn project_suggest_cmd() {
+        let root =
+            std::env::temp_dir().join(format!("snova-project-suggest-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        let file = root.join(".snova.toml");
+        std::fs::write(
+            &file,
+            r#"[[commands]]
+template = "docker run [_OPTIONS_] _IMAGE_"
+description = "Run a container"
+groups.IMAGE.expect = "string"
+groups.OPTIONS.flags = [
+  { template = "--network _NET_", description = "Network", expect = "string", suggest_cmd = "docker network ls" },
+]
+"#,
+        )
+        .unwrap();
+
+        let err = project_commands(&root, false).err().unwrap();
+        assert_eq!(
+            format!(
+                "{}: In 'docker run [_OPTIONS_] _IMAGE_': Flag '--network _NET_' defines suggest_cmd, which is only allowed in user commands",
+                file.display()
+            ),
+            err.to_string()
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn scripts_dir() {
+        let root = std::env::temp_dir().join(format!("snova-scripts-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        for name in ["runbook_valid.sh", "runbook_functions.sh"] {
+            std::fs::copy(Path::new("tests/fixtures").join(name), root.join(name)).unwrap();
+        }
+        // Only shell scripts are read
+        std::fs::write(root.join("notes.txt"), "# snova: oops").unwrap();
+
+        let commands = scripts_commands(&root, false).unwrap();
+        let descriptions: Vec<&str> = commands.iter().map(|c| c.description.as_str()).collect();
+        assert_eq!(
+            vec![
+                "Restart a deployment",
+                "Follow logs of a deployment",
+                "Restart the API",
+                "Back up a directory",
+                "Check that a directory exists",
+            ],
+            descriptions
+        );
+        assert_eq!(
+            CommandSource::Imported(root.join("runbook_functions.sh")),
+            commands[0].source
+        );
+        assert!(commands[3].confirm.is_some());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn script_error_line() {
+        let err = script_commands(include_str!("../tests/fixtures/runbook_partial.sh"), false)
+            .err()
+            .unwrap();
+        assert!(
+            err.to_string().starts_with("line 3: Invalid annotation"),
+            "{}",
+            err
+        );
+
+        let err = script_commands(
+            "\n# snova: description = \"List\"\nls _DIR_ # snova-groups: DIR:color\n",
+            false,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            "In 'ls _DIR_': line 2: Unknown value type 'color'",
+            err.to_string()
+        );
+    }
+
     #[test]
     fn parse_defs_missing_group() {
-        let mut groups = HashMap::new();
+        let mut groups = GroupDefs::default();
         groups.insert(
             "PATH".to_string(),
             GroupDef {
+                quote: None,
+                transform: vec![],
+                prefix: None,
+                suffix: None,
+                computed: None,
+                editable: false,
+                multiline: false,
+                min: None,
+                max: None,
+                keep_leading_zeros: false,
+                min_len: None,
+                max_len: None,
+                secret: false,
+                allow_custom: None,
+                canonical_order: false,
                 expect: Some("path".into()),
                 suggest: None,
                 flags: None,
+                default: None,
             },
         );
 
@@ -583,6 +2517,15 @@ mod tests {
             commands: vec![CommandDef {
                 template: "grep [_OPTIONS_] _PATH_".into(),
                 description: "Find lines in a file (*grep*)".into(),
+                keywords: vec![],
+                long_description: None,
+                example: None,
+                examples: vec![],
+                confirm: false,
+                confirm_message: None,
+                post_build: None,
+                needs_root: false,
+                render: None,
                 groups,
             }]
             .into(),
@@ -592,20 +2535,36 @@ mod tests {
         assert!(commands.is_err(), "Parse defs is err");
         let err_str = format!("{}", commands.err().unwrap());
         assert_eq!(
-            "Command 'grep [_OPTIONS_] _PATH_' is missing 'OPTIONS' group definition.",
+            "In 'grep [_OPTIONS_] _PATH_': Groups do not match:\n  missing 'OPTIONS' group definition",
             err_str
         );
     }
 
     #[test]
     fn parse_defs_missing_expect_and_flags() {
-        let mut groups = HashMap::new();
+        let mut groups = GroupDefs::default();
         groups.insert(
             "OPTIONS".to_string(),
             GroupDef {
+                quote: None,
+                transform: vec![],
+                prefix: None,
+                suffix: None,
+                computed: None,
+                editable: false,
+                multiline: false,
+                min: None,
+                max: None,
+                keep_leading_zeros: false,
+                min_len: None,
+                max_len: None,
+                secret: false,
+                allow_custom: None,
+                canonical_order: false,
                 expect: None,
                 suggest: None,
                 flags: None,
+                default: None,
             },
         );
 
@@ -613,6 +2572,15 @@ mod tests {
             commands: vec![CommandDef {
                 template: "grep [_OPTIONS_]".into(),
                 description: "Find lines in a file (*grep*)".into(),
+                keywords: vec![],
+                long_description: None,
+                example: None,
+                examples: vec![],
+                confirm: false,
+                confirm_message: None,
+                post_build: None,
+                needs_root: false,
+                render: None,
                 groups,
             }]
             .into(),
@@ -622,8 +2590,655 @@ mod tests {
         assert!(commands.is_err(), "Parse defs is err");
         let err_str = format!("{}", commands.err().unwrap());
         assert_eq!(
-            "Group 'OPTIONS' should define expect or flags in 'grep [_OPTIONS_]'",
+            "In 'grep [_OPTIONS_]': Group 'OPTIONS' should define expect or flags",
             err_str
         );
     }
+
+    #[test]
+    fn parse_defs_quote_values() {
+        let commands = parse_str(
+            r#"
+[[commands]]
+template = "curl [_OPTIONS_] _URL_"
+description = "Send a request"
+groups.URL.expect = "string"
+groups.OPTIONS.flags = [
+  { template = "-d _DATA_", description = "Body", expect = "string" },
+  { template = "-H _HEADER_", description = "Header", expect = "string" },
+]
+
+[[commands]]
+template = "find . _EXPRESSION_"
+description = "Find files"
+groups.EXPRESSION.expect = "string"
+groups.EXPRESSION.quote = "never"
+"#,
+        )
+        .unwrap();
+        let curl = &commands[0];
+        let flags = match &curl.groups[1].expect {
+            GroupValue::Flags(flags) => flags,
+            _ => panic!("Expected flags"),
+        };
+        let data = flags[0].expect.as_ref().unwrap();
+        let header = flags[1].expect.as_ref().unwrap();
+
+        let cases = [
+            ("one", "-d one"),
+            ("two words", "-d 'two words'"),
+            ("it's", "-d 'it'\\''s'"),
+            ("{\"a\": \"b\"}", "-d '{\"a\": \"b\"}'"),
+            ("$(whoami)", "-d '$(whoami)'"),
+        ];
+        for (value, expected) in cases.iter() {
+            assert_eq!(*expected, (data.build)(&[value.to_string()]));
+        }
+        assert_eq!(
+            "-H 'Content-Type: application/json'",
+            (header.build)(&["Content-Type: application/json".to_string()])
+        );
+
+        let mut user_input = HashMap::new();
+        user_input.insert("URL".to_string(), "http://localhost/?a=1&b=2".to_string());
+        assert_eq!(
+            "curl 'http://localhost/?a=1&b=2'",
+            (curl.build)(&user_input)
+        );
+
+        let mut user_input = HashMap::new();
+        user_input.insert("EXPRESSION".to_string(), "-name '*.rs'".to_string());
+        assert_eq!("find . -name '*.rs'", (commands[1].build)(&user_input));
+    }
+
+    #[test]
+    fn parse_defs_invalid_quote() {
+        let result = parse_str(
+            r#"
+[[commands]]
+template = "echo _TEXT_"
+description = "Print text"
+groups.TEXT.expect = "string"
+groups.TEXT.quote = "sometimes"
+"#,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn parse_defs_flag_inputs() {
+        let commands = parse_str(
+            r#"
+[[commands]]
+template = "ffmpeg -i _INPUT_ [_FILTERS_] _OUTPUT_"
+description = "Convert video"
+groups.INPUT.expect = "path"
+groups.OUTPUT.expect = "path"
+groups.FILTERS.flags = [
+  { template = "-vf scale=_W_:_H_", description = "Scale", expect = { W = "number", H = "number" } },
+]
+"#,
+        )
+        .unwrap();
+        let flags = match &commands[0].groups[2].expect {
+            GroupValue::Flags(flags) => flags,
+            _ => panic!("Expected flags"),
+        };
+        let expect = flags[0].expect.as_ref().unwrap();
+        let names: Vec<&str> = expect.inputs.iter().map(|i| i.name.as_ref()).collect();
+        assert_eq!(vec!["W", "H"], names);
+        let values = vec!["1280".to_string(), "720".to_string()];
+        assert_eq!("-vf scale=1280:720", (expect.build)(&values));
+    }
+
+    #[test]
+    fn parse_defs_flag_inputs_missing_type() {
+        let flags = |expect: &str| {
+            parse_str(&format!(
+                r#"
+[[commands]]
+template = "rsync [_OPTIONS_]"
+description = "Sync"
+groups.OPTIONS.flags = [
+  {{ template = "--map _FROM_:_TO_", description = "Map", expect = {} }},
+]
+"#,
+                expect
+            ))
+        };
+        assert!(flags(r#"{ FROM = "string", TO = "string" }"#).is_ok());
+        assert!(flags(r#"{ FROM = "string" }"#).is_err());
+        assert!(flags(r#"{ FROM = "string", TO = "string", OTHER = "string" }"#).is_err());
+        assert!(flags(r#""string""#).is_err());
+    }
+
+    #[test]
+    fn parse_defs_transform() {
+        let commands = parse_str(
+            r#"
+[[commands]]
+template = "git push origin _BRANCH_"
+description = "Push a branch"
+groups.BRANCH.expect = "string"
+groups.BRANCH.transform = ["trim", "lower"]
+groups.BRANCH.prefix = "refs/heads/"
+"#,
+        )
+        .unwrap();
+        let group = &commands[0].groups[0];
+        assert_eq!(vec![Transform::Trim, Transform::Lower], group.transform);
+        assert_eq!("refs/heads/main", group.transform_value(" Main"));
+    }
+
+    #[test]
+    fn parse_defs_unknown_transform() {
+        let result = parse_str(
+            r#"
+[[commands]]
+template = "echo _TEXT_"
+description = "Print text"
+groups.TEXT.expect = "string"
+groups.TEXT.transform = ["reverse"]
+"#,
+        );
+        let err = format!("{}", result.err().unwrap());
+        assert!(
+            err.contains("valid: trim, lower, upper, urlencode"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn parse_defs_computed_order() {
+        let commands = parse_str(
+            r#"
+[[commands]]
+template = "cp _SRC_ _BACKUP_ _DEST_"
+description = "Copy"
+groups.BACKUP.expect = "path"
+groups.BACKUP.computed = "{DEST}.bak"
+groups.SRC.expect = "path"
+groups.DEST.expect = "path"
+"#,
+        )
+        .unwrap();
+        let names: Vec<&str> = commands[0].groups.iter().map(|g| g.name.as_ref()).collect();
+        assert_eq!(vec!["SRC", "DEST", "BACKUP"], names);
+    }
+
+    #[test]
+    fn parse_defs_computed_cycle() {
+        let result = parse_str(
+            r#"
+[[commands]]
+template = "cp _A_ _B_"
+description = "Copy"
+groups.A.expect = "path"
+groups.A.computed = "{B}.1"
+groups.B.expect = "path"
+groups.B.computed = "{A}.2"
+"#,
+        );
+        let err = format!("{}", result.err().unwrap());
+        assert!(
+            err.contains("Computed groups refer to each other: A, B"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn parse_defs_computed_unknown() {
+        let result = parse_str(
+            r#"
+[[commands]]
+template = "cp _A_"
+description = "Copy"
+groups.A.expect = "path"
+groups.A.computed = "{B}.1"
+"#,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn parse_defs_context_vars() {
+        let commands = parse_str(
+            r#"
+[[commands]]
+template = "tar -czf backup-{date:%Y}.tar.gz _DIR_ # {user}@{hostname} in {cwd}"
+description = "Backup"
+groups.DIR.expect = "path"
+
+[[commands]]
+template = "find _DIR_ -exec rm {} \\; {unknown"
+description = "Remove"
+groups.DIR.expect = "path"
+"#,
+        )
+        .unwrap();
+        let mut user_input = HashMap::new();
+        user_input.insert("DIR".to_string(), "src".to_string());
+        let expected = format!(
+            "tar -czf backup-{}.tar.gz src # {}@{} in {}",
+            ContextVar::Date("%Y".into()).value(),
+            quote(&ContextVar::User.value(), QuotePolicy::Auto),
+            quote(&ContextVar::Hostname.value(), QuotePolicy::Auto),
+            quote(&ContextVar::Cwd.value(), QuotePolicy::Auto),
+        );
+        assert_eq!(expected, (commands[0].build)(&user_input));
+        assert!(commands[0].warnings.is_empty());
+        // Other braces are kept
+        assert_eq!(
+            "find src -exec rm {} \\; {unknown",
+            (commands[1].build)(&user_input)
+        );
+    }
+
+    #[test]
+    fn parse_defs_context_var_errors() {
+        let err = parse_str(
+            r#"
+[[commands]]
+template = "ls {cwd:short} _DIR_"
+description = "List"
+groups.DIR.expect = "path"
+"#,
+        )
+        .err()
+        .unwrap()
+        .to_string();
+        assert!(
+            err.contains("In '{cwd:short}': Context variable 'cwd' takes no format"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn parse_defs_context_var_collision() {
+        let commands = parse_str(
+            r#"
+[[commands]]
+template = "echo {date} _date_"
+description = "Echo"
+groups.date.expect = "string"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            vec!["Group 'date' hides the context variable {date}".to_string()],
+            commands[0].warnings
+        );
+        // Group value is used for the variable as well
+        let mut user_input = HashMap::new();
+        user_input.insert("date".to_string(), "next week".to_string());
+        assert_eq!(
+            "echo 'next week' 'next week'",
+            (commands[0].build)(&user_input)
+        );
+        // Unanswered group leaves the variable empty
+        assert_eq!("echo  _date_", (commands[0].build)(&HashMap::new()));
+    }
+
+    const DOCKER_RUN: &str = r#"
+[[commands]]
+template = """
+docker run --rm \\
+  -v _SRC_:/src \\
+  [_OPTIONS_]
+
+  _IMAGE_"""
+description = "Run a container"
+groups.SRC.expect = "path"
+groups.IMAGE.expect = "string"
+groups.OPTIONS.flags = [{ template = "-it", description = "Interactive" }]
+"#;
+
+    #[test]
+    fn parse_defs_render_oneline() {
+        let commands = parse_str(DOCKER_RUN).unwrap();
+        let cmd = &commands[0];
+        assert_eq!(
+            "docker run --rm -v _SRC_:/src [_OPTIONS_] _IMAGE_",
+            cmd.template
+        );
+        let mut user_input = HashMap::new();
+        user_input.insert("SRC".to_string(), "my dir".to_string());
+        user_input.insert("OPTIONS".to_string(), "-it".to_string());
+        user_input.insert("IMAGE".to_string(), "alpine".to_string());
+        assert_eq!(
+            "docker run --rm -v 'my dir':/src -it alpine",
+            (cmd.build)(&user_input)
+        );
+    }
+
+    #[test]
+    fn parse_defs_render_multiline() {
+        let data = DOCKER_RUN.replace(
+            "description = \"Run a container\"",
+            "description = \"Run a container\"\nrender = \"multiline\"",
+        );
+        let commands = parse_str(&data).unwrap();
+        let cmd = &commands[0];
+        // Template is still displayed on one line
+        assert_eq!(
+            "docker run --rm -v _SRC_:/src [_OPTIONS_] _IMAGE_",
+            cmd.template
+        );
+        let mut user_input = HashMap::new();
+        user_input.insert("SRC".to_string(), "src".to_string());
+        user_input.insert("OPTIONS".to_string(), "-it".to_string());
+        user_input.insert("IMAGE".to_string(), "alpine".to_string());
+        assert_eq!(
+            "docker run --rm \\\n  -v src:/src \\\n  -it \\\n  alpine",
+            (cmd.build)(&user_input)
+        );
+
+        let err = parse_str(&data.replace("multiline", "wrapped"))
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.contains("Unknown render mode 'wrapped'"), "{}", err);
+    }
+
+    #[test]
+    fn parse_defs_examples() {
+        let commands = parse_str(
+            r#"
+[[commands]]
+template = "tar -xf _ARCHIVE_"
+description = "Unpack"
+groups.ARCHIVE.expect = "path"
+examples = [
+  { description = "Into a directory", command = "tar -xf site.tar -C /srv" },
+  { description = "Other program", command = "unzip site.zip" },
+]
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            Example {
+                description: "Into a directory".into(),
+                command: "tar -xf site.tar -C /srv".into(),
+            },
+            commands[0].examples[0]
+        );
+        assert_eq!(
+            vec!["Example 'unzip site.zip' doesn't start with 'tar'".to_string()],
+            commands[0].warnings
+        );
+        // Example must have a command
+        let err = parse_str(
+            r#"
+[[commands]]
+template = "tar -xf _ARCHIVE_"
+description = "Unpack"
+groups.ARCHIVE.expect = "path"
+examples = [{ description = "Into a directory" }]
+"#,
+        );
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn parse_defs_confirm() {
+        let commands = parse_str(
+            r#"
+[[commands]]
+template = "rm -rf _PATH_"
+description = "Remove a directory"
+confirm = true
+groups.PATH.expect = "path"
+
+[[commands]]
+template = "dd if=_IN_ of=_OUT_"
+description = "Copy a disk"
+confirm = true
+confirm_message = "Output device will be overwritten"
+groups.IN.expect = "path"
+groups.OUT.expect = "path"
+
+[[commands]]
+template = "ls _PATH_"
+description = "List files"
+groups.PATH.expect = "path"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            Some("This command could be dangerous"),
+            commands[0].confirm.as_deref()
+        );
+        assert_eq!(
+            Some("Output device will be overwritten"),
+            commands[1].confirm.as_deref()
+        );
+        assert_eq!(None, commands[2].confirm);
+    }
+
+    #[test]
+    fn parse_defs_danger() {
+        let commands = parse_str(
+            r#"
+[[commands]]
+template = "rsync [_OPTIONS_] _SRC_ _DEST_"
+description = "Sync files"
+groups.SRC.expect = "path"
+groups.DEST.expect = "path"
+groups.OPTIONS.flags = [
+  { template = "-v", description = "Verbose" },
+  { template = "--delete", description = "Delete extra files", danger = true },
+]
+"#,
+        )
+        .unwrap();
+        let flags = match &commands[0].groups[2].expect {
+            GroupValue::Flags(flags) => flags,
+            _ => panic!("Expected flags"),
+        };
+        assert!(!flags[0].danger);
+        assert!(flags[1].danger);
+    }
+
+    #[test]
+    fn lint_adjacent_groups() {
+        assert_eq!(
+            vec!["Groups SRC and DST are not separated".to_string()],
+            lint_template("scp _SRC__DST_")
+        );
+        assert!(lint_template("scp _SRC_ _DST_").is_empty());
+        assert!(lint_template("scp _SRC_:_DST_").is_empty());
+    }
+
+    #[test]
+    fn lint_optional_whitespace() {
+        assert_eq!(
+            vec!["Optional group OPTIONS is not surrounded by whitespace".to_string()],
+            lint_template("ls-[_OPTIONS_] _PATH_")
+        );
+        assert_eq!(
+            vec!["Optional group OPTIONS is not surrounded by whitespace".to_string()],
+            lint_template("ls [_OPTIONS_]/tmp")
+        );
+        assert!(lint_template("grep [_OPTIONS_] _PATTERN_ _PATH_").is_empty());
+        assert!(lint_template("ls [_OPTIONS_]").is_empty());
+    }
+
+    #[test]
+    fn lint_unclosed_bracket() {
+        assert_eq!(
+            vec!["Optional group is missing a closing ']'".to_string()],
+            lint_template("ls [_OPTIONS_ _PATH_")
+        );
+        assert!(lint_template("echo \\[ _TEXT_").is_empty());
+    }
+
+    #[test]
+    fn lint_whitespace_separator() {
+        assert_eq!(
+            vec!["Groups SRC and DST are separated by \"  \", use a single space".to_string()],
+            lint_template("cp _SRC_  _DST_")
+        );
+        assert!(lint_template("cp _SRC_ _DST_").is_empty());
+        assert!(lint_template("cp  _SRC_").is_empty());
+    }
+
+    #[test]
+    fn lint_builtin() {
+        let defs: CommandsDef = toml::de::from_str(BUILTIN_DEF).unwrap();
+        for def in defs.commands {
+            assert!(lint_template(&def.template).is_empty(), "{}", def.template);
+        }
+    }
+
+    #[test]
+    fn unknown_command_field() {
+        let result = parse_str(
+            r#"
+[[commands]]
+template = "echo _TEXT_"
+description = "Print text"
+keyword = ["print"]
+groups.TEXT.expect = "string"
+"#,
+        );
+        assert_eq!(
+            "In 'echo _TEXT_': Unknown field 'keyword'. Did you mean 'keywords'?",
+            format!("{}", result.err().unwrap())
+        );
+    }
+
+    #[test]
+    fn unknown_group_field() {
+        let result = parse_str(
+            r#"
+[[commands]]
+template = "echo _TEXT_"
+description = "Print text"
+groups.TEXT.expects = "string"
+"#,
+        );
+        assert_eq!(
+            "In 'echo _TEXT_': Unknown field 'expects' in group TEXT. Did you mean 'expect'?",
+            format!("{}", result.err().unwrap())
+        );
+    }
+
+    #[test]
+    fn unknown_flag_field() {
+        let result = parse_str(
+            r#"
+[[commands]]
+template = "curl [_OPTIONS_] _URL_"
+description = "Send a request"
+groups.URL.expect = "string"
+groups.OPTIONS.flags = [
+  { template = "*-X* _METHOD_", description = "Method", expect = "string", sugest = ["GET"] },
+]
+"#,
+        );
+        assert_eq!(
+            "In 'curl [_OPTIONS_] _URL_': Unknown field 'sugest' in flag '*-X* _METHOD_' in group OPTIONS. Did you mean 'suggest'?",
+            format!("{}", result.err().unwrap())
+        );
+    }
+
+    #[test]
+    fn unknown_field_without_suggestion() {
+        let result = parse_str(
+            r#"
+[[commands]]
+template = "echo _TEXT_"
+description = "Print text"
+groups.TEXT.expect = "string"
+groups.TEXT.placeholder = "hello"
+"#,
+        );
+        assert_eq!(
+            "In 'echo _TEXT_': Unknown field 'placeholder' in group TEXT",
+            format!("{}", result.err().unwrap())
+        );
+    }
+
+    #[test]
+    fn edit_distance_values() {
+        assert_eq!(0, edit_distance("expect", "expect"));
+        assert_eq!(1, edit_distance("sugest", "suggest"));
+        assert_eq!(2, edit_distance("defualt", "default"));
+        assert_eq!(6, edit_distance("", "prefix"));
+    }
+
+    const UNUSED_GROUPS: &str = r#"
+[[commands]]
+template = "cp _SRC_ _DEST_"
+description = "Copy"
+groups.SRC.expect = "path"
+groups.DEST.expect = "path"
+groups.TARGET.expect = "path"
+groups.BACKUP.expect = "path"
+"#;
+
+    #[test]
+    fn parse_defs_unused_groups() {
+        // Unused groups are listed in definition order
+        let result = parse_str(UNUSED_GROUPS);
+        assert_eq!(
+            "In 'cp _SRC_ _DEST_': Groups do not match:\n  'TARGET' group is not in the template\n  'BACKUP' group is not in the template",
+            format!("{}", result.err().unwrap())
+        );
+    }
+
+    #[test]
+    fn group_defs_order() {
+        let defs = parse_toml(UNUSED_GROUPS).unwrap();
+        let names: Vec<&String> = defs.commands[0].groups.keys().collect();
+        assert_eq!(vec!["SRC", "DEST", "TARGET", "BACKUP"], names);
+    }
+
+    #[test]
+    fn parse_defs_unused_groups_lenient() {
+        let commands = build_commands(parse_toml(UNUSED_GROUPS).unwrap(), true).unwrap();
+        assert_eq!(
+            vec![
+                "Group 'TARGET' is not in the template".to_string(),
+                "Group 'BACKUP' is not in the template".to_string(),
+            ],
+            commands[0].warnings
+        );
+    }
+
+    #[test]
+    fn parse_defs_missing_and_unused_groups() {
+        let toml = r#"
+[[commands]]
+template = "cp _SRC_ _DEST_"
+description = "Copy"
+groups.SRC.expect = "path"
+groups.DST.expect = "path"
+"#;
+        let expected = "In 'cp _SRC_ _DEST_': Groups do not match:\n  missing 'DEST' group definition\n  'DST' group is not in the template";
+        let result = parse_str(toml);
+        assert_eq!(expected, format!("{}", result.err().unwrap()));
+        // Missing definitions are errors even in lenient mode
+        let result = build_commands(parse_toml(toml).unwrap(), true);
+        assert_eq!(expected, format!("{}", result.err().unwrap()));
+    }
+
+    #[test]
+    fn parse_defs_repeated_group() {
+        let result = parse_str(
+            r#"
+[[commands]]
+template = "cp _SRC_ _SRC_"
+description = "Copy"
+groups.SRC.expect = "path"
+"#,
+        );
+        assert_eq!(
+            "In 'cp _SRC_ _SRC_': Group 'SRC' is used more than once",
+            format!("{}", result.err().unwrap())
+        );
+    }
 }
@@ -0,0 +1,127 @@
+//! Resolves where configuration and data are stored
+
+use std::path::PathBuf;
+
+/// Config and data directories
+#[derive(Debug, PartialEq)]
+pub struct Paths {
+    pub config_dir: Option<PathBuf>,
+    pub data_dir: Option<PathBuf>,
+    /// Directories with annotated shell scripts (`script_dirs` in settings.toml)
+    pub script_dirs: Vec<PathBuf>,
+}
+
+impl Paths {
+    /// Resolve paths in order: `--config` flag, environment variables
+    /// (SNOVA_CONFIG_DIR and SNOVA_DATA_DIR), platform defaults.
+    pub fn from_env(config_flag: Option<PathBuf>) -> Self {
+        Self::resolve(
+            config_flag,
+            |name| std::env::var_os(name).map(PathBuf::from),
+            dirs::config_dir(),
+            dirs::data_dir(),
+        )
+    }
+
+    fn resolve(
+        config_flag: Option<PathBuf>,
+        env: impl Fn(&str) -> Option<PathBuf>,
+        default_config: Option<PathBuf>,
+        default_data: Option<PathBuf>,
+    ) -> Self {
+        let env = |name| env(name).filter(|dir: &PathBuf| !dir.as_os_str().is_empty());
+        let config_dir = config_flag
+            .or_else(|| env("SNOVA_CONFIG_DIR"))
+            .or_else(|| default_config.map(|dir| dir.join("snova")));
+        let data_dir = env("SNOVA_DATA_DIR").or_else(|| default_data.map(|dir| dir.join("snova")));
+        Self {
+            config_dir,
+            data_dir,
+            script_dirs: vec![],
+        }
+    }
+
+    /// User commands file
+    pub fn commands_file(&self) -> Option<PathBuf> {
+        self.config_dir
+            .as_ref()
+            .map(|dir| dir.join("commands.toml"))
+    }
+
+    /// Directory with extra commands files
+    pub fn commands_d(&self) -> Option<PathBuf> {
+        self.config_dir.as_ref().map(|dir| dir.join("commands.d"))
+    }
+
+    /// User settings file
+    pub fn settings_file(&self) -> Option<PathBuf> {
+        self.config_dir
+            .as_ref()
+            .map(|dir| dir.join("settings.toml"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(flag: Option<&str>, vars: &[(&str, &str)]) -> Paths {
+        let vars: Vec<(String, String)> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        Paths::resolve(
+            flag.map(PathBuf::from),
+            |name| {
+                vars.iter()
+                    .find(|(k, _)| k == name)
+                    .map(|(_, v)| PathBuf::from(v))
+            },
+            Some("/home/me/.config".into()),
+            Some("/home/me/.local/share".into()),
+        )
+    }
+
+    #[test]
+    fn defaults() {
+        let paths = resolve(None, &[]);
+        assert_eq!(paths.config_dir, Some("/home/me/.config/snova".into()));
+        assert_eq!(paths.data_dir, Some("/home/me/.local/share/snova".into()));
+        assert_eq!(
+            paths.commands_file(),
+            Some("/home/me/.config/snova/commands.toml".into())
+        );
+    }
+
+    #[test]
+    fn env_over_defaults() {
+        let paths = resolve(
+            None,
+            &[("SNOVA_CONFIG_DIR", "/cfg"), ("SNOVA_DATA_DIR", "/data")],
+        );
+        assert_eq!(paths.config_dir, Some("/cfg".into()));
+        assert_eq!(paths.data_dir, Some("/data".into()));
+    }
+
+    #[test]
+    fn flag_over_env() {
+        let paths = resolve(Some("/flag"), &[("SNOVA_CONFIG_DIR", "/cfg")]);
+        assert_eq!(paths.config_dir, Some("/flag".into()));
+        assert_eq!(paths.data_dir, Some("/home/me/.local/share/snova".into()));
+    }
+
+    #[test]
+    fn empty_env_ignored() {
+        let paths = resolve(None, &[("SNOVA_CONFIG_DIR", ""), ("SNOVA_DATA_DIR", "")]);
+        assert_eq!(paths.config_dir, Some("/home/me/.config/snova".into()));
+        assert_eq!(paths.data_dir, Some("/home/me/.local/share/snova".into()));
+    }
+
+    #[test]
+    fn no_defaults() {
+        let paths = Paths::resolve(None, |_| None, None, None);
+        assert_eq!(paths.config_dir, None);
+        assert_eq!(paths.data_dir, None);
+        assert_eq!(paths.commands_file(), None);
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+const PRESETS_FILE: &str = "presets.toml";
+
+/// Group values by group name
+pub type PresetValues = BTreeMap<String, String>;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PresetsFile {
+    /// Presets by command template and preset name
+    #[serde(default)]
+    presets: BTreeMap<String, BTreeMap<String, PresetValues>>,
+}
+
+/// Named sets of group values saved for commands
+#[derive(Debug, Default)]
+pub struct Presets {
+    /// Where presets are stored. Nothing is persisted if not set.
+    path: Option<PathBuf>,
+    presets: BTreeMap<String, BTreeMap<String, PresetValues>>,
+}
+
+impl Presets {
+    /// Read presets from the directory
+    pub fn open(dir: &Path) -> Result<Self> {
+        let path = dir.join(PRESETS_FILE);
+        let file = if path.is_file() {
+            let data =
+                std::fs::read_to_string(&path).context(format!("Read {}", path.display()))?;
+            toml::de::from_str(&data).context("Parse presets")?
+        } else {
+            PresetsFile::default()
+        };
+        Ok(Self {
+            path: Some(path),
+            presets: file.presets,
+        })
+    }
+
+    /// Values of the named preset for the command
+    pub fn get(&self, template: &str, name: &str) -> Option<&PresetValues> {
+        self.presets.get(template)?.get(name)
+    }
+
+    /// Save values under the name replacing the previous preset
+    pub fn set(&mut self, template: &str, name: &str, values: PresetValues) {
+        self.presets
+            .entry(template.to_string())
+            .or_default()
+            .insert(name.to_string(), values);
+    }
+
+    /// Remove the named preset. Returns false if it didn't exist.
+    pub fn remove(&mut self, template: &str, name: &str) -> bool {
+        let removed = match self.presets.get_mut(template) {
+            Some(presets) => presets.remove(name).is_some(),
+            None => false,
+        };
+        self.presets.retain(|_, presets| !presets.is_empty());
+        removed
+    }
+
+    /// Command templates with their preset names in order
+    pub fn list(&self) -> Vec<(&str, &str)> {
+        self.presets
+            .iter()
+            .flat_map(|(template, presets)| {
+                presets
+                    .keys()
+                    .map(move |name| (template.as_ref(), name.as_ref()))
+            })
+            .collect()
+    }
+
+    /// Write presets to disk
+    pub fn save(&self) -> Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).context(format!("Create {}", dir.display()))?;
+        }
+        let file = PresetsFile {
+            presets: self.presets.clone(),
+        };
+        let data = toml::ser::to_string(&file).context("Serialize presets")?;
+        std::fs::write(path, data).context(format!("Write {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("snova-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn values(pairs: &[(&str, &str)]) -> PresetValues {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn save_list_remove() {
+        let dir = temp_dir("presets");
+        let mut presets = Presets::open(&dir).unwrap();
+        presets.set(
+            "curl _URL_",
+            "staging",
+            values(&[("URL", "https://staging")]),
+        );
+        presets.set("curl _URL_", "prod", values(&[("URL", "https://prod")]));
+        presets.set("grep _PATTERN_", "todo", values(&[("PATTERN", "TODO")]));
+        presets.save().unwrap();
+
+        let mut presets = Presets::open(&dir).unwrap();
+        assert_eq!(
+            vec![
+                ("curl _URL_", "prod"),
+                ("curl _URL_", "staging"),
+                ("grep _PATTERN_", "todo")
+            ],
+            presets.list()
+        );
+        assert_eq!(
+            Some(&values(&[("URL", "https://staging")])),
+            presets.get("curl _URL_", "staging")
+        );
+        assert_eq!(None, presets.get("curl _URL_", "dev"));
+
+        assert!(presets.remove("grep _PATTERN_", "todo"));
+        assert!(!presets.remove("grep _PATTERN_", "todo"));
+        presets.save().unwrap();
+        let presets = Presets::open(&dir).unwrap();
+        assert_eq!(
+            vec![("curl _URL_", "prod"), ("curl _URL_", "staging")],
+            presets.list()
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
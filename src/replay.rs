@@ -0,0 +1,224 @@
+//! Keys replayed from a file (`--replay`) to record demos and reproduce
+//! rendering bugs.
+//!
+//! Keys are separated by whitespace, e.g `down down enter "foo" enter ^d`.
+//! Quoted text types its chars, `^x` is Ctrl-x and `alt-x` is Alt-x. A duration
+//! like `500ms` or `2s` pauses before the next key. `#` starts a comment.
+
+use std::io;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use termion::event::{Event, Key};
+
+/// Pause before every key
+const KEY_DELAY: Duration = Duration::from_millis(150);
+/// Pause before every char of quoted text
+const CHAR_DELAY: Duration = Duration::from_millis(60);
+
+/// Keys with the pauses before them
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    steps: Vec<(Duration, Key)>,
+    speed: f64,
+}
+
+impl Replay {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut steps = vec![];
+        let mut pause = Duration::ZERO;
+        for (i, line) in text.lines().enumerate() {
+            let tokens = tokenize(line).map_err(|err| anyhow!("Line {}: {}", i + 1, err))?;
+            for token in tokens {
+                match token {
+                    Token::Text(text) => {
+                        for c in text.chars() {
+                            steps.push((pause + CHAR_DELAY, Key::Char(c)));
+                            pause = Duration::ZERO;
+                        }
+                    }
+                    Token::Word(word) => match parse_duration(word) {
+                        Some(duration) => pause += duration,
+                        None => {
+                            let key = parse_key(word)
+                                .map_err(|err| anyhow!("Line {}: {}", i + 1, err))?;
+                            steps.push((pause + KEY_DELAY, key));
+                            pause = Duration::ZERO;
+                        }
+                    },
+                }
+            }
+        }
+        Ok(Self { steps, speed: 1.0 })
+    }
+
+    /// Multiply the replay speed, e.g 2 halves every pause
+    pub fn speed(mut self, speed: f64) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Key events that arrive after their pauses. Input is closed once the
+    /// keys run out.
+    pub fn events(&self) -> impl Iterator<Item = io::Result<Event>> {
+        let speed = self.speed;
+        self.steps.clone().into_iter().map(move |(pause, key)| {
+            std::thread::sleep(pause.div_f64(speed));
+            Ok(Event::Key(key))
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    /// Quoted text
+    Text(String),
+    Word(&'a str),
+}
+
+/// Split the line into words and quoted text. Quoted text could contain `\"`
+/// and `\\`.
+fn tokenize(line: &str) -> Result<Vec<Token<'_>>, String> {
+    let mut tokens = vec![];
+    let mut rest = line.trim_start();
+    while !rest.is_empty() && !rest.starts_with('#') {
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let mut text = String::new();
+            let mut chars = quoted.char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((i, '"')) => break i,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, c @ ('"' | '\\'))) => text.push(c),
+                        Some((_, c)) => return Err(format!("Unknown escape '\\{}'", c)),
+                        None => return Err("Quote is not closed".to_string()),
+                    },
+                    Some((_, c)) => text.push(c),
+                    None => return Err("Quote is not closed".to_string()),
+                }
+            };
+            tokens.push(Token::Text(text));
+            rest = &quoted[end + 1..];
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            tokens.push(Token::Word(&rest[..end]));
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Parse `500ms` or `2s`
+fn parse_duration(word: &str) -> Option<Duration> {
+    if let Some(ms) = word.strip_suffix("ms") {
+        return ms.parse().ok().map(Duration::from_millis);
+    }
+    word.strip_suffix('s')?
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+fn parse_key(word: &str) -> Result<Key, String> {
+    let key = match word {
+        "enter" => Key::Char('\n'),
+        "tab" => Key::Char('\t'),
+        "space" => Key::Char(' '),
+        "esc" => Key::Esc,
+        "backspace" => Key::Backspace,
+        "delete" => Key::Delete,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "alt-enter" => Key::Alt('\r'),
+        _ => {
+            let single = |rest: &str| {
+                let mut chars = rest.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Some(c),
+                    _ => None,
+                }
+            };
+            if let Some(c) = word.strip_prefix('^').and_then(single) {
+                Key::Ctrl(c.to_ascii_lowercase())
+            } else if let Some(c) = word.strip_prefix("alt-").and_then(single) {
+                Key::Alt(c)
+            } else if let Some(c) = single(word) {
+                Key::Char(c)
+            } else {
+                return Err(format!("Unknown key '{}'", word));
+            }
+        }
+    };
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(text: &str) -> Vec<Key> {
+        Replay::parse(text)
+            .unwrap()
+            .steps
+            .into_iter()
+            .map(|(_, key)| key)
+            .collect()
+    }
+
+    #[test]
+    fn parse_keys() {
+        assert_eq!(
+            vec![
+                Key::Down,
+                Key::Down,
+                Key::Char('\n'),
+                Key::Char('f'),
+                Key::Char(' '),
+                Key::Char('"'),
+                Key::Char('\n'),
+                Key::Ctrl('d'),
+                Key::Alt('\r'),
+                Key::Alt('b'),
+                Key::Char('?'),
+            ],
+            keys("down down enter \"f \\\"\" enter ^D\nalt-enter alt-b ? # comment")
+        );
+        assert_eq!(Vec::<Key>::new(), keys("# only a comment\n\n"));
+    }
+
+    #[test]
+    fn parse_pauses() {
+        let replay = Replay::parse("enter 1s 500ms \"ab\" 2s").unwrap();
+        assert_eq!(
+            vec![
+                (KEY_DELAY, Key::Char('\n')),
+                (Duration::from_millis(1500) + CHAR_DELAY, Key::Char('a')),
+                (CHAR_DELAY, Key::Char('b')),
+            ],
+            replay.steps
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = |text| Replay::parse(text).unwrap_err().to_string();
+        assert_eq!("Line 2: Unknown key 'entr'", err("enter\nentr"));
+        assert_eq!("Line 1: Quote is not closed", err("\"abc"));
+        assert_eq!("Line 1: Unknown escape '\\n'", err("\"a\\nb\""));
+        assert_eq!("Line 1: Unknown key '^ab'", err("^ab"));
+    }
+
+    #[test]
+    fn replay_events() {
+        let replay = Replay::parse("1s x").unwrap().speed(1000.0);
+        let events: Vec<Event> = replay.events().map(|event| event.unwrap()).collect();
+        assert_eq!(vec![Event::Key(Key::Char('x'))], events);
+    }
+}
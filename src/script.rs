@@ -0,0 +1,294 @@
+//! Extracts command definitions from annotated shell scripts:
+//!
+//! ```sh
+//! # snova: description = "Restart the API"
+//! # snova: keywords = ["deploy"]
+//! systemctl restart _SERVICE_ # snova-groups: SERVICE
+//! ```
+//!
+//! Every `# snova:` line is a TOML key of the command definition. The command
+//! is the next line (and its `\` continuations). Only groups listed in the
+//! trailing `# snova-groups:` annotation are prompted for, other underscores
+//! and brackets are kept literally.
+
+use toml::value::{Table, Value};
+
+const FIELD_MARK: &str = "# snova:";
+const GROUPS_MARK: &str = "# snova-groups:";
+
+/// Definition found in a script
+#[derive(Debug, PartialEq)]
+pub struct ScriptDef {
+    /// Line of the first annotation
+    pub line: usize,
+    /// Command definition with the template and groups
+    pub def: Table,
+}
+
+/// Definitions found in a script and malformed annotations
+#[derive(Debug, Default)]
+pub struct Extracted {
+    pub defs: Vec<ScriptDef>,
+    /// Line number and reason
+    pub errors: Vec<(usize, String)>,
+}
+
+/// Find annotated commands in the script. Indentation is ignored, so commands
+/// could be annotated inside functions.
+pub fn extract(text: &str) -> Extracted {
+    let mut extracted = Extracted::default();
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .peekable();
+
+    while let Some((start, line)) = lines.next() {
+        let mut fields = match line.strip_prefix(FIELD_MARK) {
+            Some(field) => vec![(start, field)],
+            None => {
+                if line.contains(GROUPS_MARK) {
+                    extracted
+                        .errors
+                        .push((start, "Groups annotation without a snova block".into()));
+                }
+                continue;
+            }
+        };
+        while let Some(&(line, text)) = lines.peek() {
+            match text.strip_prefix(FIELD_MARK) {
+                Some(field) => fields.push((line, field)),
+                None => break,
+            }
+            lines.next();
+        }
+
+        let mut command = vec![];
+        while let Some(&(line, text)) = lines.peek() {
+            if text.is_empty() || text.starts_with('#') {
+                break;
+            }
+            lines.next();
+            command.push((line, text));
+            if !split_groups(text).0.ends_with('\\') {
+                break;
+            }
+        }
+
+        match build_def(&fields, &command) {
+            Ok(def) => extracted.defs.push(ScriptDef { line: start, def }),
+            Err((line, reason)) => extracted.errors.push((line, reason)),
+        }
+    }
+    extracted
+}
+
+/// Split the command line into the code and the groups annotation
+fn split_groups(line: &str) -> (&str, Option<&str>) {
+    match line.find(GROUPS_MARK) {
+        Some(i) => (line[..i].trim_end(), Some(&line[i + GROUPS_MARK.len()..])),
+        None => (line, None),
+    }
+}
+
+/// Build a command definition of the annotation fields and command lines.
+/// Errors come with the line they were found on.
+fn build_def(
+    fields: &[(usize, &str)],
+    command: &[(usize, &str)],
+) -> Result<Table, (usize, String)> {
+    let mut def = Table::new();
+    for (line, field) in fields {
+        let table: Table = toml::de::from_str(field)
+            .map_err(|err| (*line, format!("Invalid annotation: {}", err)))?;
+        for (key, value) in table {
+            if key == "template" || key == "groups" {
+                return Err((*line, format!("'{}' is taken from the command line", key)));
+            }
+            if def.insert(key.clone(), value).is_some() {
+                return Err((*line, format!("'{}' is set twice", key)));
+            }
+        }
+    }
+
+    let last_field = fields.last().map(|(line, _)| *line).unwrap_or_default();
+    let (last_line, _) = command.last().ok_or((
+        last_field,
+        "Annotation is not followed by a command".to_string(),
+    ))?;
+
+    let mut code = vec![];
+    let mut groups = vec![];
+    for (line, text) in command {
+        let (text, annotation) = split_groups(text);
+        if let Some(annotation) = annotation {
+            if line != last_line {
+                return Err((*line, "Groups annotation must end the command".into()));
+            }
+            groups = parse_groups(annotation).map_err(|reason| (*line, reason))?;
+        }
+        code.push(text);
+    }
+
+    let template = to_template(&code.join("\n"), &groups).map_err(|reason| (*last_line, reason))?;
+    let groups: Table = groups
+        .into_iter()
+        .map(|(name, expect)| {
+            let mut group = Table::new();
+            group.insert("expect".into(), Value::String(expect.to_string()));
+            (name.to_string(), Value::Table(group))
+        })
+        .collect();
+    def.insert("template".into(), Value::String(template));
+    def.insert("groups".into(), Value::Table(groups));
+    Ok(def)
+}
+
+/// Parse `NAME, COUNT:number` into group names with their value types
+fn parse_groups(annotation: &str) -> Result<Vec<(&str, &str)>, String> {
+    let mut groups: Vec<(&str, &str)> = vec![];
+    for group in annotation.split(',').map(str::trim) {
+        let (name, expect) = match group.split_once(':') {
+            Some((name, expect)) => (name.trim(), expect.trim()),
+            None => (group, "string"),
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '-') {
+            return Err(format!("Invalid group name '{}'", name));
+        }
+        if groups.iter().any(|(other, _)| *other == name) {
+            return Err(format!("Group '{}' is listed twice", name));
+        }
+        groups.push((name, expect));
+    }
+    Ok(groups)
+}
+
+/// Escape the command so that only the listed groups are template groups
+fn to_template(code: &str, groups: &[(&str, &str)]) -> Result<String, String> {
+    if code.contains('*') {
+        return Err("Contains '*' that templates do not support".into());
+    }
+
+    let mut template = String::new();
+    let mut used = vec![false; groups.len()];
+    let mut rest = code;
+    while let Some(c) = rest.chars().next() {
+        if c == '_' {
+            let group = groups.iter().position(|(name, _)| {
+                rest[1..].starts_with(name) && rest[1 + name.len()..].starts_with('_')
+            });
+            if let Some(i) = group {
+                let len = groups[i].0.len() + 2;
+                template.push_str(&rest[..len]);
+                rest = &rest[len..];
+                used[i] = true;
+                continue;
+            }
+        }
+        if matches!(c, '_' | '[' | ']') {
+            template.push('\\');
+        }
+        template.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    match groups.iter().zip(used).find(|(_, used)| !used) {
+        Some(((name, _), _)) => Err(format!("Group '{}' is not used in the command", name)),
+        None => Ok(template),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(def: &ScriptDef) -> &str {
+        def.def["template"].as_str().unwrap()
+    }
+
+    #[test]
+    fn extract_valid() {
+        let extracted = extract(include_str!("../tests/fixtures/runbook_valid.sh"));
+        assert_eq!(Vec::<(usize, String)>::new(), extracted.errors);
+        assert_eq!(3, extracted.defs.len());
+
+        let restart = &extracted.defs[0];
+        assert_eq!(5, restart.line);
+        assert_eq!(
+            "Restart the API",
+            restart.def["description"].as_str().unwrap()
+        );
+        assert_eq!(
+            r"systemctl restart api@_ENV_.service && journalctl -u api\_svc -n _LINES_",
+            template(restart)
+        );
+        assert_eq!(
+            "number",
+            restart.def["groups"]["LINES"]["expect"].as_str().unwrap()
+        );
+
+        let backup = &extracted.defs[1];
+        assert_eq!("tar -czf backup-{date}.tar.gz \\\n_DIR_", template(backup));
+        assert!(backup.def["confirm"].as_bool().unwrap());
+
+        assert_eq!(
+            r"\[ -d _DIR_ \] && echo exists",
+            template(&extracted.defs[2])
+        );
+    }
+
+    #[test]
+    fn extract_partially_valid() {
+        let extracted = extract(include_str!("../tests/fixtures/runbook_partial.sh"));
+        assert_eq!(
+            vec!["Check the health of a host"],
+            extracted
+                .defs
+                .iter()
+                .map(|def| def.def["description"].as_str().unwrap())
+                .collect::<Vec<_>>()
+        );
+        let errors: Vec<(usize, &str)> = extracted
+            .errors
+            .iter()
+            .map(|(line, reason)| (*line, reason.split(':').next().unwrap()))
+            .collect();
+        assert_eq!(
+            vec![
+                (3, "Invalid annotation"),
+                (8, "Annotation is not followed by a command"),
+                (12, "Group 'PORT' is not used in the command"),
+                (15, "Groups annotation without a snova block"),
+                (18, "'description' is set twice"),
+                (22, "Contains '*' that templates do not support"),
+            ],
+            errors
+        );
+    }
+
+    #[test]
+    fn extract_nested_in_function() {
+        let extracted = extract(include_str!("../tests/fixtures/runbook_functions.sh"));
+        assert_eq!(Vec::<(usize, String)>::new(), extracted.errors);
+        let templates: Vec<&str> = extracted.defs.iter().map(template).collect();
+        assert_eq!(
+            vec![
+                "kubectl rollout restart deploy/_NAME_ -n _NAMESPACE_",
+                "kubectl logs -f deploy/_NAME_",
+            ],
+            templates
+        );
+        assert_eq!(4, extracted.defs[0].line);
+    }
+
+    #[test]
+    fn group_annotations() {
+        assert_eq!(
+            Ok(vec![("A", "string"), ("B", "path")]),
+            parse_groups(" A, B:path ")
+        );
+        assert!(parse_groups("A,,B").is_err());
+        assert!(parse_groups("A B").is_err());
+        assert!(parse_groups("A, A").is_err());
+    }
+}
@@ -0,0 +1,109 @@
+//! User settings file (`settings.toml` in the config directory)
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::menu::FinalAction;
+use crate::messages::Messages;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SettingsDef {
+    #[serde(default)]
+    messages: HashMap<String, String>,
+    post_build: Option<String>,
+    default_action: Option<String>,
+    #[serde(default)]
+    script_dirs: Vec<String>,
+    escalation: Option<String>,
+}
+
+/// Settings that are read from the file
+#[derive(Debug, Default)]
+pub struct SettingsFile {
+    /// UI strings (`[messages]`)
+    pub messages: Messages,
+    /// Shell command that receives every built command on stdin
+    pub post_build: Option<String>,
+    /// Action of Enter in the final menu
+    pub default_action: FinalAction,
+    /// Directories with annotated shell scripts. A leading `~` is the home directory.
+    pub script_dirs: Vec<PathBuf>,
+    /// Prefix of commands that need root, e.g `doas` (sudo if not set)
+    pub escalation: Option<String>,
+}
+
+impl SettingsFile {
+    /// Read the settings file if it exists
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        match path {
+            Some(path) if path.is_file() => {
+                let data =
+                    std::fs::read_to_string(path).context(format!("Read {}", path.display()))?;
+                Self::parse(&data).context(format!("Parse {}", path.display()))
+            }
+            _ => Ok(Self::default()),
+        }
+    }
+
+    pub fn parse(data: &str) -> Result<Self> {
+        let def: SettingsDef = toml::de::from_str(data)?;
+        Ok(Self {
+            messages: Messages::new(def.messages)?,
+            post_build: def.post_build,
+            default_action: match def.default_action {
+                Some(action) => FinalAction::parse(&action)?,
+                None => FinalAction::default(),
+            },
+            script_dirs: def.script_dirs.iter().map(|dir| expand_home(dir)).collect(),
+            escalation: def.escalation,
+        })
+    }
+}
+
+/// Replace a leading `~` with the home directory
+fn expand_home(dir: &str) -> PathBuf {
+    match (dir.strip_prefix('~'), dirs::home_dir()) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            home.join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(dir),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_settings() {
+        let file = SettingsFile::parse(
+            r#"
+post_build = "tee -a ~/snova.log"
+default_action = "copy"
+script_dirs = ["/srv/runbooks", "~/runbooks"]
+escalation = "sudo -E"
+
+[messages]
+failed = "Fehler:"
+"#,
+        )
+        .unwrap();
+        assert_eq!(Some("tee -a ~/snova.log"), file.post_build.as_deref());
+        assert_eq!("Fehler:", file.messages.get("failed"));
+        assert_eq!(FinalAction::Copy, file.default_action);
+        assert_eq!(PathBuf::from("/srv/runbooks"), file.script_dirs[0]);
+        assert!(file.script_dirs[1].ends_with("runbooks"));
+        assert!(!file.script_dirs[1].starts_with("~"));
+        assert_eq!(Some("sudo -E"), file.escalation.as_deref());
+
+        let file = SettingsFile::parse("").unwrap();
+        assert_eq!(None, file.post_build);
+        assert_eq!(FinalAction::Print, file.default_action);
+        assert!(SettingsFile::parse("default_action = \"exec\"").is_err());
+        assert!(SettingsFile::parse("post_bulid = \"true\"").is_err());
+    }
+}
@@ -0,0 +1,212 @@
+//! Runs helper commands that list values at prompt time (e.g `suggest_cmd`,
+//! `git ls-files` or clipboard tools) without letting them hang the prompt
+
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+
+/// How long to wait for a command by default
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+/// Max number of output lines read by default
+const MAX_LINES: usize = 5000;
+/// Max size of the output read by default
+const MAX_BYTES: usize = 1024 * 1024;
+/// Max size of stderr kept for the debug log
+const MAX_STDERR: u64 = 4096;
+/// How long to wait for stderr once the command has exited
+const STDERR_WAIT: Duration = Duration::from_millis(100);
+
+/// Runs commands with their input closed and a limit on time and output size
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Runner {
+    timeout: Duration,
+    max_lines: usize,
+    max_bytes: usize,
+    /// Print stderr of commands to our stderr
+    debug: bool,
+}
+
+impl Default for Runner {
+    fn default() -> Self {
+        Self::new(DEFAULT_TIMEOUT)
+    }
+}
+
+/// Output of a command
+#[derive(Debug, Default, PartialEq)]
+pub struct Output {
+    pub lines: Vec<String>,
+    /// Beginning of stderr
+    pub stderr: String,
+    /// Output was cut at a limit and the command was killed
+    pub truncated: bool,
+}
+
+impl Runner {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            max_lines: MAX_LINES,
+            max_bytes: MAX_BYTES,
+            debug: false,
+        }
+    }
+
+    pub fn max_lines(mut self, max: usize) -> Self {
+        self.max_lines = max;
+        self
+    }
+
+    #[cfg(test)]
+    pub fn max_bytes(mut self, max: usize) -> Self {
+        self.max_bytes = max;
+        self
+    }
+
+    pub fn debug(mut self, enabled: bool) -> Self {
+        self.debug = enabled;
+        self
+    }
+
+    /// Run the command and read its output up to the limits. The command is
+    /// killed once it reaches a limit or doesn't finish in time.
+    pub fn run(&self, cmd: &mut Command) -> Result<Output> {
+        let mut child = cmd
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context(format!("Run {:?}", cmd))?;
+        let stdout = child.stdout.take().expect("Stdout is piped");
+        let stderr = child.stderr.take().expect("Stderr is piped");
+
+        // Readers are never joined: a process started by the command could
+        // keep the pipes open after the command is killed
+        let (sender, receiver) = mpsc::channel();
+        let (max_lines, max_bytes) = (self.max_lines, self.max_bytes);
+        std::thread::spawn(move || {
+            let mut reader = BufReader::new(stdout.take(max_bytes as u64));
+            let mut lines = vec![];
+            let mut read = 0;
+            let mut line = String::new();
+            while lines.len() < max_lines {
+                line.clear();
+                match reader.read_line(&mut line) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => read += n,
+                }
+                lines.push(line.trim_end_matches(&['\r', '\n'][..]).to_string());
+            }
+            let truncated = lines.len() >= max_lines || read >= max_bytes;
+            let _ = sender.send((lines, truncated));
+        });
+        let (stderr_sender, stderr_receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut text = String::new();
+            let _ = stderr.take(MAX_STDERR).read_to_string(&mut text);
+            let _ = stderr_sender.send(text);
+        });
+
+        let (lines, truncated) = match receiver.recv_timeout(self.timeout) {
+            Ok(read) => read,
+            Err(_) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                self.log(cmd, &stderr_receiver.try_recv().unwrap_or_default());
+                return Err(anyhow!("{:?} timed out", cmd));
+            }
+        };
+        if truncated {
+            // The rest of the output is not needed
+            let _ = child.kill();
+        }
+        let status = child.wait()?;
+        let stderr = stderr_receiver
+            .recv_timeout(STDERR_WAIT)
+            .unwrap_or_default();
+        self.log(cmd, &stderr);
+        if !truncated && !status.success() {
+            return Err(anyhow!("{:?} failed with {}", cmd, status));
+        }
+        Ok(Output {
+            lines,
+            stderr,
+            truncated,
+        })
+    }
+
+    fn log(&self, cmd: &Command, stderr: &str) {
+        if !self.debug {
+            return;
+        }
+        for line in stderr.lines() {
+            eprint!("snova: {:?}: {}\r\n", cmd.get_program(), line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn sh(script: &str) -> Command {
+        let mut sh = Command::new("sh");
+        sh.arg("-c").arg(script);
+        sh
+    }
+
+    #[test]
+    fn output_and_stderr() {
+        let output = Runner::default()
+            .run(&mut sh("echo a; echo oops >&2; printf 'b\\r\\n'"))
+            .unwrap();
+        assert_eq!(
+            Output {
+                lines: vec!["a".into(), "b".into()],
+                stderr: "oops\n".into(),
+                truncated: false,
+            },
+            output
+        );
+        assert!(Runner::default().run(&mut sh("exit 1")).is_err());
+    }
+
+    #[test]
+    fn stdin_is_closed() {
+        // Reading stdin ends at once instead of waiting for input
+        let output = Runner::new(Duration::from_secs(5))
+            .run(&mut sh("cat; echo done"))
+            .unwrap();
+        assert_eq!(vec!["done"], output.lines);
+    }
+
+    #[test]
+    fn timeout_kills_command() {
+        let started = Instant::now();
+        let err = Runner::new(Duration::from_millis(100))
+            .run(&mut sh("echo partial; sleep 5"))
+            .unwrap_err();
+        assert!(err.to_string().ends_with("timed out"), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn output_limits() {
+        let runner = Runner::new(Duration::from_secs(5));
+        let output = runner.max_lines(3).run(&mut Command::new("yes")).unwrap();
+        assert_eq!(vec!["y", "y", "y"], output.lines);
+        assert!(output.truncated);
+
+        // Output is cut after 10 bytes even though the command keeps writing
+        let output = runner
+            .max_bytes(10)
+            .run(&mut sh("echo 1234; echo 5678; echo 90ab; sleep 5"))
+            .unwrap();
+        assert_eq!(vec!["1234", "5678"], output.lines);
+        assert!(output.truncated);
+    }
+}
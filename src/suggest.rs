@@ -0,0 +1,229 @@
+//! Looks up suggestions from outside of the definitions
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::process::Command;
+
+use crate::cmd::{SuggestSource, ValueType};
+use crate::dialect::Dialect;
+use crate::history::History;
+use crate::subprocess::Runner;
+
+/// Max number of suggestions read from a source
+const SOURCE_LIMIT: usize = 5000;
+
+/// Suggestions of the source for the group. Files are looked up in the
+/// directory and commands are run with the runner. Failures result in fewer
+/// suggestions.
+pub fn list(
+    source: SuggestSource,
+    group: &str,
+    expect: &ValueType,
+    dir: &Path,
+    history: &History,
+    runner: &Runner,
+) -> Vec<String> {
+    match source {
+        SuggestSource::GitFiles => {
+            // Paths use the separator of the current dialect
+            let dialect = Dialect::current();
+            git_files(dir, SOURCE_LIMIT, runner)
+                .into_iter()
+                .map(|value| dialect.normalize_path(&value).into_owned())
+                .collect()
+        }
+        // Group names are matched exactly, values of another type could be invalid
+        SuggestSource::HistoryValues => {
+            history.recent_values(|recent| recent.group == group && recent.expect == expect.name())
+        }
+        SuggestSource::HistoryTypeValues => {
+            history.recent_values(|recent| recent.expect == expect.name())
+        }
+    }
+}
+
+/// Non-empty output lines of a shell command (`suggest_cmd`). A failed command
+/// has no lines.
+pub fn command_lines(command: &str, dir: &Path, runner: &Runner) -> Vec<String> {
+    let mut sh = Command::new("sh");
+    sh.arg("-c").arg(command).current_dir(dir);
+    runner
+        .max_lines(SOURCE_LIMIT)
+        .run(&mut sh)
+        .map(|output| output.lines)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|line| !line.trim().is_empty())
+        .collect()
+}
+
+/// Directory entries followed by files tracked by git under the directory.
+/// Only directory entries are listed outside of a git work tree.
+fn git_files(dir: &Path, limit: usize, runner: &Runner) -> Vec<String> {
+    let mut values = dir_entries(dir);
+    let mut seen: HashSet<String> = values.iter().cloned().collect();
+
+    let mut git = Command::new("git");
+    git.arg("ls-files").current_dir(dir);
+    if let Ok(output) = runner.max_lines(limit).run(&mut git) {
+        for file in output.lines {
+            if seen.insert(file.clone()) {
+                values.push(file);
+            }
+        }
+    }
+
+    values.truncate(limit);
+    values
+}
+
+/// Visible entries of the directory sorted by name. Directories end with a slash.
+fn dir_entries(dir: &Path) -> Vec<String> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if name.starts_with('.') {
+                return None;
+            }
+            let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+            Some(if is_dir { format!("{}/", name) } else { name })
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::process::Stdio;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("snova-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?}", args);
+    }
+
+    #[test]
+    fn git_files_in_repo() {
+        let dir = temp_dir("git-files");
+        git(&dir, &["init", "-q"]);
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join("src/main.rs"), "").unwrap();
+        std::fs::write(dir.join("README.md"), "").unwrap();
+        std::fs::write(dir.join(".gitignore"), "target/\n").unwrap();
+        git(&dir, &["add", "src/main.rs", "README.md", ".gitignore"]);
+        // Untracked and ignored entries
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+        std::fs::create_dir_all(dir.join("target")).unwrap();
+        std::fs::write(dir.join("target/out.bin"), "").unwrap();
+
+        let files = git_files(&dir, 100, &Runner::default());
+        assert_eq!(
+            vec![
+                "README.md",
+                "notes.txt",
+                "src/",
+                "target/",
+                ".gitignore",
+                "src/main.rs"
+            ],
+            files
+        );
+
+        // Listed from a subdirectory
+        let files = git_files(&dir.join("src"), 100, &Runner::default());
+        assert_eq!(vec!["main.rs"], files);
+
+        assert_eq!(2, git_files(&dir, 2, &Runner::default()).len());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn history_values() {
+        let dir = temp_dir("history-values");
+        std::fs::copy(
+            "tests/fixtures/history_values.toml",
+            dir.join("values.toml"),
+        )
+        .unwrap();
+        let history = History::open(&dir).unwrap();
+        let runner = Runner::default();
+        let list = |source, group, expect| list(source, group, &expect, &dir, &history, &runner);
+
+        // curl and wget share the URL group
+        assert_eq!(
+            vec![
+                "https://api.example.com/v2/users",
+                "https://example.com/status"
+            ],
+            list(SuggestSource::HistoryValues, "URL", ValueType::String)
+        );
+        // Same name of another type is left out
+        assert_eq!(
+            vec!["urls.txt"],
+            list(SuggestSource::HistoryValues, "URL", ValueType::Path)
+        );
+        assert!(list(SuggestSource::HistoryValues, "url", ValueType::String).is_empty());
+        assert_eq!(
+            vec![
+                "https://api.example.com/v2/users",
+                "example.com",
+                "https://example.com/status",
+            ],
+            list(
+                SuggestSource::HistoryTypeValues,
+                "ENDPOINT",
+                ValueType::String
+            )
+        );
+        assert_eq!(
+            vec!["8080"],
+            list(SuggestSource::HistoryTypeValues, "COUNT", ValueType::Number)
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn git_files_outside_repo() {
+        let dir = temp_dir("no-git-files");
+        std::fs::write(dir.join("a.txt"), "").unwrap();
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        assert_eq!(
+            vec!["a.txt", "sub/"],
+            git_files(&dir, 100, &Runner::default())
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn command_lines_output() {
+        let dir = temp_dir("command-lines");
+        let runner = Runner::default();
+        assert_eq!(
+            vec!["bridge", "host"],
+            command_lines("echo bridge; echo; echo host", &dir, &runner)
+        );
+
+        assert!(command_lines("exit 1", &dir, &runner).is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,499 @@
+//! Recommends definitions for commands that are often run from the shell
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+
+use crate::cmd::{quote, Command, QuotePolicy};
+use crate::import::{self, ImportDef, ImportFile, ImportGroup};
+use crate::parser;
+
+/// Commands that were run fewer times are not suggested
+const MIN_COUNT: usize = 2;
+
+/// A command from the history split into words
+pub type Argv = Vec<String>;
+
+/// Command that is often run but has no definition
+#[derive(Debug, PartialEq)]
+pub struct Suggestion {
+    /// How many times the command was run
+    pub count: usize,
+    pub template: String,
+    pub description: String,
+    /// Group names with their value types in template order
+    pub groups: Vec<(String, String)>,
+}
+
+impl Suggestion {
+    /// Commands file with this command
+    pub fn to_toml(&self) -> Result<String> {
+        let file = ImportFile {
+            commands: vec![ImportDef {
+                template: self.template.clone(),
+                description: self.description.clone(),
+                groups: self
+                    .groups
+                    .iter()
+                    .map(|(name, expect)| {
+                        let group = ImportGroup {
+                            expect: Some(expect.clone()),
+                            flags: None,
+                        };
+                        (name.clone(), group)
+                    })
+                    .collect(),
+            }],
+        };
+        toml::ser::to_string(&file).context("Serialize suggested command")
+    }
+}
+
+/// Shell history file from HISTFILE or the zsh and bash defaults
+pub fn history_file() -> Option<PathBuf> {
+    if let Some(file) = std::env::var_os("HISTFILE").filter(|file| !file.is_empty()) {
+        return Some(file.into());
+    }
+    let home = dirs::home_dir()?;
+    vec![home.join(".zsh_history"), home.join(".bash_history")]
+        .into_iter()
+        .find(|file| file.is_file())
+}
+
+/// Read commands from bash or zsh (plain or extended) history. Only the
+/// first command of a pipeline or a list is kept.
+pub fn parse_history(text: &str) -> Vec<Argv> {
+    text.lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            // Extended zsh history `: <start>:<duration>;command`
+            let line = match line.strip_prefix(": ") {
+                Some(rest) => rest.split_once(';')?.1,
+                None => line,
+            };
+            // Bash writes timestamps as comments
+            if line.starts_with('#') {
+                return None;
+            }
+            split_command(line)
+        })
+        .collect()
+}
+
+/// Words of the first simple command without leading `sudo` and variable assignments
+fn split_command(line: &str) -> Option<Argv> {
+    let mut words = vec![];
+    let mut rest = line;
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() || rest.starts_with(';') || rest.starts_with('#') {
+            break;
+        }
+        let (word, next) = import::parse_word(rest).ok()?;
+        if matches!(word.as_str(), "|" | "||" | "&&" | "&") {
+            break;
+        }
+        rest = next;
+        if words.is_empty() && (word == "sudo" || is_assignment(&word)) {
+            continue;
+        }
+        words.push(word);
+    }
+    Some(words).filter(|words| !words.is_empty())
+}
+
+fn is_assignment(word: &str) -> bool {
+    match word.find('=') {
+        Some(eq) => eq > 0 && word[..eq].chars().all(|c| c.is_alphanumeric() || c == '_'),
+        None => false,
+    }
+}
+
+/// Find commands that were run at least twice and are not covered by the
+/// defined commands. The most frequent ones go first.
+pub fn suggest(history: &[Argv], commands: &[Command], top: usize) -> Vec<Suggestion> {
+    let defined: Vec<Vec<String>> = commands
+        .iter()
+        .map(|cmd| fixed_words(&cmd.template))
+        .filter(|words| !words.is_empty())
+        .collect();
+
+    // Invocations of the same program, subcommand and flags are aligned by position
+    let mut shapes: HashMap<Vec<Option<&str>>, Vec<&Argv>> = HashMap::new();
+    for argv in history {
+        shapes.entry(shape(argv)).or_default().push(argv);
+    }
+
+    let mut suggestions: BTreeMap<String, Suggestion> = BTreeMap::new();
+    for invocations in shapes.values() {
+        if invocations.len() < MIN_COUNT {
+            continue;
+        }
+        let parts = generalize(invocations);
+        let words: Vec<&str> = parts
+            .iter()
+            .map_while(|part| match part {
+                Part::Word(word) => Some(word.as_str()),
+                Part::Group { .. } => None,
+            })
+            .collect();
+        if defined
+            .iter()
+            .any(|fixed| words.starts_with(&fixed.iter().map(|w| w.as_str()).collect::<Vec<_>>()))
+        {
+            continue;
+        }
+        let suggestion = match to_suggestion(&parts, invocations.len()) {
+            Some(suggestion) => suggestion,
+            None => continue,
+        };
+        match suggestions.get_mut(&suggestion.template) {
+            Some(existing) => existing.count += suggestion.count,
+            None => {
+                suggestions.insert(suggestion.template.clone(), suggestion);
+            }
+        }
+    }
+
+    let mut suggestions: Vec<Suggestion> = suggestions.into_values().collect();
+    // Sort is stable so equally frequent commands stay ordered by template
+    suggestions.sort_by_key(|s| std::cmp::Reverse(s.count));
+    suggestions.truncate(top);
+    suggestions
+}
+
+/// Words at the start of the template before the first group
+fn fixed_words(template: &str) -> Vec<String> {
+    let end = template.find(['_', '[']).unwrap_or(template.len());
+    template[..end]
+        .replace('*', "")
+        .split_whitespace()
+        .map(String::from)
+        .collect()
+}
+
+/// Key of invocations that could be aligned. The program, a subcommand-like
+/// second word and flags must match while other arguments could vary.
+fn shape(argv: &[String]) -> Vec<Option<&str>> {
+    argv.iter()
+        .enumerate()
+        .map(|(i, word)| {
+            if i == 0 || (i == 1 && is_subcommand(word)) {
+                Some(word.as_str())
+            } else if is_flag(word) {
+                Some(flag_key(word))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn is_subcommand(word: &str) -> bool {
+    word.len() > 1 && word.chars().all(|c| c.is_ascii_lowercase() || c == '-')
+}
+
+/// Negative numbers like `-5` are values
+fn is_flag(word: &str) -> bool {
+    let mut chars = word.chars();
+    chars.next() == Some('-') && chars.next().is_some_and(|c| !c.is_ascii_digit())
+}
+
+/// Flag without its value, e.g `--color=` for `--color=auto`
+fn flag_key(word: &str) -> &str {
+    match word.find('=') {
+        Some(eq) => &word[..=eq],
+        None => word,
+    }
+}
+
+/// Part of a generalized command
+#[derive(Debug, PartialEq)]
+enum Part {
+    Word(String),
+    /// Varying argument, optionally after a fixed prefix like `--color=`
+    Group {
+        prefix: String,
+        name: String,
+        expect: &'static str,
+    },
+}
+
+/// Keep words that are the same in every invocation and replace the rest with groups
+fn generalize(invocations: &[&Argv]) -> Vec<Part> {
+    let mut parts: Vec<Part> = vec![];
+    let mut names: Vec<String> = vec![];
+    for i in 0..invocations[0].len() {
+        let first = &invocations[0][i];
+        if invocations.iter().all(|argv| &argv[i] == first) {
+            parts.push(Part::Word(first.clone()));
+            continue;
+        }
+        let prefix = if is_flag(first) {
+            flag_key(first)
+        } else if invocations.iter().all(|argv| argv[i].starts_with('-')) {
+            // Numeric options like `-5`
+            "-"
+        } else {
+            ""
+        };
+        let values: Vec<&str> = invocations
+            .iter()
+            .map(|argv| &argv[i][prefix.len()..])
+            .collect();
+        // Value of a flag is named after it
+        let flag = if prefix.len() < 2 {
+            match parts.last() {
+                Some(Part::Word(word)) if is_flag(word) => word.as_str(),
+                _ => "",
+            }
+        } else {
+            prefix
+        };
+        let mut name: String = flag
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_uppercase();
+        if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+            name = format!("ARG{}", names.len() + 1);
+        }
+        if names.contains(&name) {
+            name = format!("{}{}", name, names.len() + 1);
+        }
+        names.push(name.clone());
+        parts.push(Part::Group {
+            prefix: prefix.to_string(),
+            name,
+            expect: value_type(&values),
+        });
+    }
+    parts
+}
+
+/// Value type that fits all values
+fn value_type(values: &[&str]) -> &'static str {
+    if values
+        .iter()
+        .all(|v| !v.is_empty() && v.chars().all(|c| c.is_ascii_digit()))
+    {
+        "number"
+    } else if values
+        .iter()
+        .all(|v| v.contains('/') || v.starts_with('.') || v.starts_with('~'))
+    {
+        "path"
+    } else {
+        "string"
+    }
+}
+
+/// Build the template. Commands that can't be expressed as a valid template are skipped.
+fn to_suggestion(parts: &[Part], count: usize) -> Option<Suggestion> {
+    let mut words = vec![];
+    let mut description = vec![];
+    let mut groups = vec![];
+    for part in parts {
+        match part {
+            Part::Word(word) => {
+                if word.contains('*') {
+                    return None;
+                }
+                if groups.is_empty() && !is_flag(word) {
+                    description.push(word.as_str());
+                }
+                words.push(escape(&quote(word, QuotePolicy::Auto)));
+            }
+            Part::Group {
+                prefix,
+                name,
+                expect,
+            } => {
+                if prefix.contains('*') {
+                    return None;
+                }
+                words.push(format!("{}_{}_", escape(prefix), name));
+                groups.push((name.clone(), expect.to_string()));
+            }
+        }
+    }
+    let suggestion = Suggestion {
+        count,
+        template: words.join(" "),
+        description: import::clean_description(&description.join(" ")),
+        groups,
+    };
+    // Verify that the command could be read back
+    let data = suggestion.to_toml().ok()?;
+    parser::parse_str(&data).ok()?;
+    Some(suggestion)
+}
+
+/// Escape chars that have a meaning in templates
+fn escape(word: &str) -> String {
+    word.replace('_', "\\_")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASH_HISTORY: &str = r#"
+#1700000000
+git commit -m 'Fix tests'
+git commit -m "Add README"
+git commit -m wip
+git status
+git status
+git log --oneline -5
+git log --oneline -20
+kubectl logs -f api-7d4 --tail 100
+kubectl logs -f web-1c2 --tail 20
+kubectl logs -f web-1c2 --tail 50 | grep ERROR
+DEBUG=1 sudo docker run --rm -it ubuntu:22.04
+docker run --rm -it alpine
+grep -r TODO src
+grep -r FIXME src/parser.rs
+ls
+cd ..
+"#;
+
+    const ZSH_HISTORY: &str = r#": 1700000000:0;rsync -av ./build/ web:/srv/app
+: 1700000100:2;rsync -av ./dist/ web:/srv/site
+: 1700000200:0;wget --timeout=5 https://example.com
+: 1700000300:0;wget --timeout=10 https://example.com
+"#;
+
+    fn words(line: &str) -> Argv {
+        line.split(' ').map(String::from).collect()
+    }
+
+    fn builtin() -> Vec<Command> {
+        parser::read_all(
+            &crate::paths::Paths {
+                config_dir: None,
+                data_dir: None,
+                script_dirs: vec![],
+            },
+            None,
+            false,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn parse_bash_and_zsh() {
+        let history = parse_history(BASH_HISTORY);
+        assert_eq!(16, history.len());
+        assert_eq!(
+            vec!["git", "commit", "-m", "Fix tests"],
+            history[0].iter().map(|w| w.as_str()).collect::<Vec<_>>()
+        );
+        // Pipelines are cut and the environment and sudo are dropped
+        assert_eq!(words("kubectl logs -f web-1c2 --tail 50"), history[9]);
+        assert_eq!(words("docker run --rm -it ubuntu:22.04"), history[10]);
+
+        let history = parse_history(ZSH_HISTORY);
+        assert_eq!(
+            vec![
+                words("rsync -av ./build/ web:/srv/app"),
+                words("rsync -av ./dist/ web:/srv/site"),
+                words("wget --timeout=5 https://example.com"),
+                words("wget --timeout=10 https://example.com"),
+            ],
+            history
+        );
+    }
+
+    #[test]
+    fn generalize_varying_arguments() {
+        let logs = [
+            words("kubectl logs -f api-7d4 --tail 100"),
+            words("kubectl logs -f web-1c2 --tail 20"),
+        ];
+        let invocations: Vec<&Argv> = logs.iter().collect();
+        let suggestion = to_suggestion(&generalize(&invocations), 2).unwrap();
+        assert_eq!("kubectl logs -f _F_ --tail _TAIL_", suggestion.template);
+        assert_eq!("kubectl logs", suggestion.description);
+        assert_eq!(
+            vec![
+                ("F".to_string(), "string".to_string()),
+                ("TAIL".to_string(), "number".to_string())
+            ],
+            suggestion.groups
+        );
+
+        let rsync = [
+            words("rsync -av ./build/ web:/srv/app"),
+            words("rsync -av ./dist/ web:/srv/site"),
+        ];
+        let invocations: Vec<&Argv> = rsync.iter().collect();
+        let suggestion = to_suggestion(&generalize(&invocations), 2).unwrap();
+        assert_eq!("rsync -av _AV_ _ARG2_", suggestion.template);
+        assert_eq!(
+            vec![
+                ("AV".to_string(), "path".to_string()),
+                ("ARG2".to_string(), "path".to_string())
+            ],
+            suggestion.groups
+        );
+
+        let wget = [
+            words("wget --timeout=5 https://example.com"),
+            words("wget --timeout=10 https://example.com"),
+        ];
+        let invocations: Vec<&Argv> = wget.iter().collect();
+        let suggestion = to_suggestion(&generalize(&invocations), 2).unwrap();
+        assert_eq!(
+            "wget --timeout=_TIMEOUT_ https://example.com",
+            suggestion.template
+        );
+    }
+
+    #[test]
+    fn suggest_frequent_undefined() {
+        let mut history = parse_history(BASH_HISTORY);
+        history.extend(parse_history(ZSH_HISTORY));
+        let suggestions = suggest(&history, &builtin(), 10);
+        let found: Vec<(usize, &str)> = suggestions
+            .iter()
+            .map(|s| (s.count, s.template.as_str()))
+            .collect();
+        assert_eq!(
+            vec![
+                (3, "git commit -m _M_"),
+                (3, "kubectl logs -f _F_ --tail _TAIL_"),
+                (2, "docker run --rm -it _IT_"),
+                (2, "git log --oneline -_ONELINE_"),
+                (2, "git status"),
+                (2, "rsync -av _AV_ _ARG2_"),
+                (2, "wget --timeout=_TIMEOUT_ https://example.com"),
+            ],
+            found
+        );
+        // grep is defined and single invocations are not suggested
+        assert!(suggestions.iter().all(|s| !s.template.starts_with("grep")));
+
+        assert_eq!(2, suggest(&history, &builtin(), 2).len());
+    }
+
+    #[test]
+    fn suggestion_toml_parses() {
+        let history = parse_history("make build_all\nmake build_all\necho '[x]'\necho '[x]'\n");
+        let suggestions = suggest(&history, &[], 10);
+        assert_eq!(
+            vec!["echo '\\[x\\]'", "make build\\_all"],
+            suggestions
+                .iter()
+                .map(|s| s.template.as_str())
+                .collect::<Vec<_>>()
+        );
+        for suggestion in &suggestions {
+            let commands = parser::parse_str(&suggestion.to_toml().unwrap()).unwrap();
+            assert_eq!(1, commands.len());
+        }
+    }
+}
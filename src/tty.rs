@@ -0,0 +1,267 @@
+use std::fs::File;
+use std::io::{self, stderr, stdin, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::{Mutex, Once};
+
+use anyhow::{anyhow, Result};
+use termion::clear;
+use termion::event::Event;
+use termion::input::TermRead;
+
+use crate::replay::Replay;
+use crate::view::DISABLE_BRACKETED_PASTE;
+
+/// When to read keys from /dev/tty (SNOVA_TTY=auto|always|never)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TtyMode {
+    /// Use /dev/tty only when stdin is not a terminal
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl TtyMode {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => Err(anyhow!("Unknown tty mode '{}'", value)),
+        }
+    }
+}
+
+/// Where key events are read from
+#[derive(Debug, PartialEq)]
+pub enum KeySource {
+    Stdin,
+    Tty,
+}
+
+impl KeySource {
+    /// Pick a source for key events. Stdin is used when it is a terminal,
+    /// otherwise it probably carries data and keys come from /dev/tty.
+    pub fn select(mode: TtyMode, stdin_is_tty: bool, tty_available: bool) -> Self {
+        let wants_tty = match mode {
+            TtyMode::Auto => !stdin_is_tty,
+            TtyMode::Always => true,
+            TtyMode::Never => false,
+        };
+        if wants_tty && tty_available {
+            KeySource::Tty
+        } else {
+            KeySource::Stdin
+        }
+    }
+}
+
+/// Key input with the terminal switched to raw mode for its lifetime
+pub struct Input {
+    tty: Option<File>,
+    raw: Option<RawMode>,
+    replay: Option<Replay>,
+}
+
+impl Input {
+    /// Open key input according to the mode
+    pub fn open(mode: TtyMode) -> Result<Self> {
+        let stdin_is_tty = termion::is_tty(&stdin());
+        let tty = match mode {
+            TtyMode::Never => None,
+            _ => termion::get_tty().ok(),
+        };
+        let source = KeySource::select(mode, stdin_is_tty, tty.is_some());
+        let (tty, raw) = match source {
+            KeySource::Tty => {
+                let tty = tty.expect("Tty is available");
+                let raw = RawMode::enable(tty.as_raw_fd(), tty.as_raw_fd())?;
+                (Some(tty), Some(raw))
+            }
+            // Piped input without a terminal is read as is
+            KeySource::Stdin if !stdin_is_tty => (None, None),
+            KeySource::Stdin => (
+                None,
+                Some(RawMode::enable(stdin().as_raw_fd(), stderr().as_raw_fd())?),
+            ),
+        };
+        Ok(Self {
+            tty,
+            raw,
+            replay: None,
+        })
+    }
+
+    /// Take keys from the replay instead of the selected source. The terminal
+    /// stays in raw mode so typed keys don't disturb the output.
+    pub fn replay(mut self, replay: Replay) -> Self {
+        self.replay = Some(replay);
+        self
+    }
+
+    /// Whether keys come from a terminal rather than piped input
+    pub fn is_terminal(&self) -> bool {
+        self.raw.is_some()
+    }
+
+    /// Where prompts and choices are rendered: the terminal that keys are
+    /// read from or stderr. Stdout is left for the built command.
+    pub fn ui(&self) -> Result<Box<dyn Write>> {
+        Ok(match &self.tty {
+            Some(tty) => Box::new(tty.try_clone()?),
+            None => Box::new(stderr()),
+        })
+    }
+
+    /// Key events from the replay or the selected source
+    pub fn events(&self) -> Result<Box<dyn Iterator<Item = io::Result<Event>>>> {
+        Ok(match (&self.replay, &self.tty) {
+            (Some(replay), _) => Box::new(replay.events()),
+            (None, Some(tty)) => Box::new(tty.try_clone()?.events()),
+            (None, None) => Box::new(stdin().events()),
+        })
+    }
+}
+
+/// Terminal in raw mode that the panic hook restores: the fd with its
+/// previous attributes and the fd that prompts are rendered to
+static RAW_TERMINAL: Mutex<Option<(RawFd, libc::termios, RawFd)>> = Mutex::new(None);
+static PANIC_HOOK: Once = Once::new();
+
+/// Restores terminal attributes of the fd when dropped
+struct RawMode {
+    fd: RawFd,
+    prev: libc::termios,
+    ui: RawFd,
+}
+
+impl RawMode {
+    /// Switch the fd to raw mode. Prompts are rendered to the ui fd, which
+    /// is cleared below the cursor if we panic.
+    fn enable(fd: RawFd, ui: RawFd) -> io::Result<Self> {
+        let prev = unsafe {
+            let mut ios: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(fd, &mut ios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let prev = ios;
+            libc::cfmakeraw(&mut ios);
+            if libc::tcsetattr(fd, libc::TCSANOW, &ios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            prev
+        };
+        PANIC_HOOK.call_once(install_panic_hook);
+        if let Ok(mut raw) = RAW_TERMINAL.lock() {
+            *raw = Some((fd, prev, ui));
+        }
+        Ok(Self { fd, prev, ui })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        if let Ok(mut raw) = RAW_TERMINAL.lock() {
+            *raw = None;
+        }
+        restore(self.fd, &self.prev, self.ui);
+    }
+}
+
+/// Clear what is left of the prompts below the cursor from column 0, turn off
+/// bracketed paste that an open prompt could have left on (e.g on panic) and
+/// restore terminal attributes of the fd
+fn restore(fd: RawFd, prev: &libc::termios, ui: RawFd) {
+    let reset = format!("\r{}{}", clear::AfterCursor, DISABLE_BRACKETED_PASTE);
+    unsafe {
+        libc::write(ui, reset.as_ptr() as *const libc::c_void, reset.len());
+        libc::tcsetattr(fd, libc::TCSANOW, prev);
+    }
+}
+
+/// Width of the terminal that prompts are rendered to. Stdout could be piped,
+/// e.g in `$(snova)`, so the size is read from the ui fd (stderr by default).
+pub fn ui_width() -> Option<usize> {
+    let ui = match RAW_TERMINAL.lock() {
+        Ok(raw) => raw.map(|(_, _, ui)| ui),
+        Err(_) => None,
+    };
+    fd_width(ui.unwrap_or_else(|| stderr().as_raw_fd()))
+}
+
+/// Width in columns of the terminal of the fd
+fn fd_width(fd: RawFd) -> Option<usize> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } != 0 || size.ws_col == 0 {
+        return None;
+    }
+    Some(size.ws_col as usize)
+}
+
+/// Restore the terminal before the panic message is printed: in raw mode
+/// it would be garbled and the prompt would be left on the screen.
+fn install_panic_hook() {
+    let default = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        // Hook could run while the lock is held, e.g if a Drop panics
+        let raw = RAW_TERMINAL.try_lock().ok().and_then(|mut raw| raw.take());
+        if let Some((fd, prev, ui)) = raw {
+            restore(fd, &prev, ui);
+        }
+        default(info);
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn width_of_non_terminal() {
+        let file = File::open("Cargo.toml").unwrap();
+        assert_eq!(None, fd_width(file.as_raw_fd()));
+    }
+
+    #[test]
+    fn select_auto() {
+        assert_eq!(
+            KeySource::select(TtyMode::Auto, true, true),
+            KeySource::Stdin
+        );
+        assert_eq!(
+            KeySource::select(TtyMode::Auto, false, true),
+            KeySource::Tty
+        );
+        // No terminal at all: fall back to stdin
+        assert_eq!(
+            KeySource::select(TtyMode::Auto, false, false),
+            KeySource::Stdin
+        );
+    }
+
+    #[test]
+    fn select_always() {
+        assert_eq!(
+            KeySource::select(TtyMode::Always, true, true),
+            KeySource::Tty
+        );
+        assert_eq!(
+            KeySource::select(TtyMode::Always, true, false),
+            KeySource::Stdin
+        );
+    }
+
+    #[test]
+    fn select_never() {
+        assert_eq!(
+            KeySource::select(TtyMode::Never, false, true),
+            KeySource::Stdin
+        );
+    }
+
+    #[test]
+    fn parse_mode() {
+        assert_eq!(TtyMode::parse("always").unwrap(), TtyMode::Always);
+        assert!(TtyMode::parse("sometimes").is_err());
+    }
+}
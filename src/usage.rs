@@ -0,0 +1,219 @@
+//! Counts how often commands are picked so that the picker lists frequently
+//! and recently used commands first
+
+use std::cmp::Reverse;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::cmd::Command;
+
+const USAGE_FILE: &str = "usage.toml";
+/// How many commands to keep counts for
+const USAGE_LIMIT: usize = 500;
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct UsageFile {
+    #[serde(default)]
+    commands: Vec<CommandUsage>,
+}
+
+/// How often a command was picked
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CommandUsage {
+    template: String,
+    count: u64,
+    /// Seconds since the UNIX epoch
+    last_used: u64,
+}
+
+impl CommandUsage {
+    /// Frecency: the count weighted by how recently the command was used
+    fn score(&self, now: u64) -> f64 {
+        let age = now.saturating_sub(self.last_used);
+        let weight = if age < HOUR {
+            4.0
+        } else if age < DAY {
+            2.0
+        } else if age < WEEK {
+            1.0
+        } else {
+            0.5
+        };
+        self.count as f64 * weight
+    }
+}
+
+/// Picked commands by their templates
+#[derive(Debug, Default)]
+pub struct Usage {
+    /// Where counts are stored. Nothing is persisted if not set.
+    path: Option<PathBuf>,
+    commands: Vec<CommandUsage>,
+}
+
+impl Usage {
+    /// Read counts from the directory
+    pub fn open(dir: &Path) -> Result<Self> {
+        let path = dir.join(USAGE_FILE);
+        let file = if path.is_file() {
+            let data =
+                std::fs::read_to_string(&path).context(format!("Read {}", path.display()))?;
+            toml::de::from_str(&data).context("Parse command usage")?
+        } else {
+            UsageFile::default()
+        };
+        Ok(Self {
+            path: Some(path),
+            commands: file.commands,
+        })
+    }
+
+    /// Count the command as picked. Least recently used commands are
+    /// forgotten once there are too many.
+    pub fn add(&mut self, template: &str, now: u64) {
+        match self.commands.iter_mut().find(|c| c.template == template) {
+            Some(usage) => {
+                usage.count += 1;
+                usage.last_used = now;
+            }
+            None => self.commands.push(CommandUsage {
+                template: template.to_string(),
+                count: 1,
+                last_used: now,
+            }),
+        }
+        if self.commands.len() > USAGE_LIMIT {
+            self.commands.sort_by_key(|usage| Reverse(usage.last_used));
+            self.commands.truncate(USAGE_LIMIT);
+        }
+    }
+
+    fn score(&self, template: &str, now: u64) -> f64 {
+        self.commands
+            .iter()
+            .find(|c| c.template == template)
+            .map(|usage| usage.score(now))
+            .unwrap_or_default()
+    }
+
+    /// Order commands by their score. Commands that were never picked keep
+    /// their order at the end.
+    pub fn sort(&self, commands: &mut [Command], now: u64) {
+        if self.commands.is_empty() {
+            return;
+        }
+        commands.sort_by(|a, b| {
+            self.score(&b.template, now)
+                .total_cmp(&self.score(&a.template, now))
+        });
+    }
+
+    /// Write counts to disk
+    pub fn save(&self) -> Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).context(format!("Create {}", dir.display()))?;
+        }
+        let file = UsageFile {
+            commands: self.commands.clone(),
+        };
+        let data = toml::ser::to_string(&file).context("Serialize command usage")?;
+        std::fs::write(path, data).context(format!("Write {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn commands() -> Vec<Command> {
+        parser::parse_str(
+            r#"
+[[commands]]
+template = "ls"
+description = "List files"
+groups = {}
+
+[[commands]]
+template = "pwd"
+description = "Print directory"
+groups = {}
+
+[[commands]]
+template = "date"
+description = "Print date"
+groups = {}
+
+[[commands]]
+template = "uptime"
+description = "Print uptime"
+groups = {}
+"#,
+        )
+        .unwrap()
+    }
+
+    fn templates(commands: &[Command]) -> Vec<&str> {
+        commands.iter().map(|cmd| cmd.template.as_str()).collect()
+    }
+
+    #[test]
+    fn frequent_and_recent_first() {
+        let mut usage = Usage::default();
+        // Used often but a month ago
+        for _ in 0..3 {
+            usage.add("pwd", NOW - 30 * DAY);
+        }
+        // Used twice within the hour
+        usage.add("uptime", NOW - 60);
+        usage.add("uptime", NOW);
+
+        let mut commands = commands();
+        usage.sort(&mut commands, NOW);
+        assert_eq!(vec!["uptime", "pwd", "ls", "date"], templates(&commands));
+    }
+
+    #[test]
+    fn unused_keep_order() {
+        let mut commands = commands();
+        Usage::default().sort(&mut commands, NOW);
+        assert_eq!(vec!["ls", "pwd", "date", "uptime"], templates(&commands));
+    }
+
+    #[test]
+    fn least_recent_forgotten() {
+        let mut usage = Usage::default();
+        for i in 0..=USAGE_LIMIT as u64 {
+            usage.add(&format!("cmd{}", i), NOW + i);
+        }
+        assert_eq!(USAGE_LIMIT, usage.commands.len());
+        assert_eq!(0.0, usage.score("cmd0", NOW));
+        assert!(usage.score("cmd1", NOW) > 0.0);
+    }
+
+    #[test]
+    fn save_and_open() {
+        let dir = std::env::temp_dir().join(format!("snova-usage-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut usage = Usage::open(&dir).unwrap();
+        usage.add("date", NOW);
+        usage.save().unwrap();
+
+        let mut commands = commands();
+        Usage::open(&dir).unwrap().sort(&mut commands, NOW);
+        assert_eq!(vec!["date", "ls", "pwd", "uptime"], templates(&commands));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
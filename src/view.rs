@@ -1,39 +1,180 @@
-use anyhow::{anyhow, Result};
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::io::{stdin, Write};
-use termion::event::Key;
+use termion::event::{Event, Key};
 use termion::{clear, style};
 use termion::{cursor, input::TermRead};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 use crate::cmd::ValueType;
+use crate::error::{Result, SnovaError};
+use crate::messages::Messages;
+use crate::palette::Palette;
 
 /// Size of autocomplete window
 const AUTOCOMPLETE_ROWS: u16 = 8;
+/// Number of choices visible at once (last autocomplete row shows the counter)
+const VISIBLE_CHOICES: usize = AUTOCOMPLETE_ROWS as usize - 1;
 
 pub trait Choice {
     /// Get a reference to the text
     fn text(&self) -> &str;
+
+    /// Text that user input is matched against. Defaults to the displayed text.
+    fn search_text(&self) -> Cow<'_, str> {
+        Cow::Borrowed(self.text())
+    }
+
+    /// Secondary text that is displayed dimmed when it matched user input
+    /// but the displayed text didn't.
+    fn hint(&self) -> Option<&str> {
+        None
+    }
+
+    /// Short tag that is displayed dimmed after the text, e.g where the choice comes from
+    fn badge(&self) -> Option<&str> {
+        None
+    }
+
+    /// Full details that are displayed in a preview pane when choice is selected
+    fn preview(&self) -> Option<String> {
+        None
+    }
+
+    /// How the choice is displayed in the list
+    fn style(&self) -> ChoiceStyle {
+        ChoiceStyle::Normal
+    }
+
+    /// Choice could be picked before user typed or moved the selection.
+    /// Otherwise it is never accepted with a single Enter.
+    fn preselect(&self) -> bool {
+        true
+    }
+}
+
+/// Display style of a choice
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChoiceStyle {
+    Normal,
+    /// Choice is dangerous (e.g a destructive flag)
+    Warning,
 }
 
 impl Choice for String {
     fn text(&self) -> &str {
-        &self
+        self
     }
 }
 
-impl<'a, C> Choice for &'a C
+impl<C> Choice for &C
 where
     C: Choice,
 {
     fn text(&self) -> &str {
         (*self).text()
     }
+
+    fn search_text(&self) -> Cow<'_, str> {
+        (*self).search_text()
+    }
+
+    fn hint(&self) -> Option<&str> {
+        (*self).hint()
+    }
+
+    fn badge(&self) -> Option<&str> {
+        (*self).badge()
+    }
+
+    fn preview(&self) -> Option<String> {
+        (*self).preview()
+    }
+
+    fn style(&self) -> ChoiceStyle {
+        (*self).style()
+    }
+
+    fn preselect(&self) -> bool {
+        (*self).preselect()
+    }
+}
+
+/// Error when key input ends before the prompt is done
+pub fn input_closed() -> SnovaError {
+    std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "Input closed").into()
 }
 
+/// Source of key presses and other terminal events
+type Events<'s> = Box<dyn Iterator<Item = std::io::Result<Event>> + 's>;
+
+/// Terminal sends pasted text between these sequences in bracketed paste mode
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+const ENABLE_BRACKETED_PASTE: &str = "\x1b[?2004h";
+pub(crate) const DISABLE_BRACKETED_PASTE: &str = "\x1b[?2004l";
+
+/// Number of rows in the preview pane
+const PREVIEW_ROWS: u16 = 6;
+/// Key that shows or hides the preview pane
+const PREVIEW_KEY: char = '?';
+/// Key that shows the whole help until any key is pressed
+const FULL_HELP_KEY: Key = Key::Ctrl('o');
+
+/// Max number of undo steps
+const UNDO_LIMIT: usize = 50;
+
+/// Max number of rows a multi-line input grows to
+const MULTILINE_ROWS: usize = 5;
+
 pub struct Readline<'s> {
     expect_input: Option<ValueType>,
+    /// Typed text filters the choices, so any char could be typed. The
+    /// expected type is checked by the caller once a value is submitted.
+    filtering: bool,
     prefix: String,
-    stdout: &'s mut dyn Write,
+    /// Where prompts and choices are rendered
+    ui: &'s mut dyn Write,
+    /// Events are read from stdin unless specified
+    events: Option<Events<'s>>,
+    /// Bracketed paste mode is enabled in the terminal
+    bracketed_paste: bool,
+    /// Show key bindings below user input
+    footer: bool,
+    /// Allow skipping the remaining prompts
+    finish: bool,
+    /// Esc cancels the prompt
+    cancel_on_esc: bool,
+    /// Ctrl-G goes back to picking a command
+    restart: bool,
+    /// Enter submits empty input even when a value is expected
+    allow_empty: bool,
+    /// Alt-Enter inserts a new line
+    multiline: bool,
+    /// Log dropped input to stderr
+    debug: bool,
+    /// UI strings
+    messages: Messages,
+    /// Styles that suit the terminal background
+    palette: Palette,
+    /// Terminal width to use instead of the actual one
+    width: Option<usize>,
+    /// Preview could be toggled with a key
+    preview_toggle: bool,
+    /// Show preview pane of the selected choice
+    preview: bool,
     help: Option<String>,
+    /// Max input length that is shown with the typed chars count after the help
+    counter: Option<usize>,
+    /// Value that is used when user submits an empty input
+    default: Option<String>,
+    /// Input to start with
+    initial: String,
+    /// Snapshots of input and cursor before destructive edits
+    undo: Vec<(String, usize)>,
+    /// Previously pressed key
+    last_key: Option<Key>,
     scroll_offset: usize,
     /// Cursor horizontal position
     cursor: usize,
@@ -43,25 +184,68 @@ enum AutocompleteMode<A> {
     /// Enable autocompletion
     Enabled {
         autocomplete: A,
-        allow_user_input: bool,
+        user_input: UserInput,
     },
     /// No completion
     None,
 }
 
+/// Whether typed text could be submitted instead of an option
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum UserInput {
+    /// Only options could be picked
+    Disabled,
+    /// Typed text is offered unless it equals one of the options
+    Typed,
+    /// Typed text is offered as is when it matches no option
+    Custom,
+}
+
 impl<A> AutocompleteMode<A> {
     fn enabled(&self) -> bool {
         matches!(self, AutocompleteMode::Enabled { .. })
     }
+
+    /// Key bindings available in this mode
+    fn footer<'m>(&self, messages: &'m Messages) -> &'m str {
+        messages.get(match self {
+            AutocompleteMode::Enabled { user_input, .. } => match user_input {
+                UserInput::Disabled => "footer_choice",
+                UserInput::Typed => "footer_suggest",
+                UserInput::Custom => "footer_custom",
+            },
+            AutocompleteMode::None => "footer_input",
+        })
+    }
 }
 
 impl<'s> Readline<'s> {
-    pub fn new(stdout: &'s mut dyn Write) -> Self {
+    pub fn new(ui: &'s mut dyn Write) -> Self {
         Self {
             expect_input: None,
+            filtering: false,
             prefix: "$".into(),
-            stdout,
+            ui,
+            events: None,
+            bracketed_paste: false,
+            footer: true,
+            finish: false,
+            cancel_on_esc: false,
+            restart: false,
+            allow_empty: false,
+            multiline: false,
+            debug: false,
+            messages: Messages::default(),
+            palette: Palette::default(),
+            width: None,
+            preview_toggle: false,
+            preview: false,
             help: None,
+            counter: None,
+            default: None,
+            initial: String::new(),
+            undo: vec![],
+            last_key: None,
             scroll_offset: 0,
             cursor: 0,
         }
@@ -82,82 +266,346 @@ impl<'s> Readline<'s> {
         self
     }
 
+    /// Show how many chars are typed out of the max after the help
+    pub fn counter(mut self, max: usize) -> Self {
+        self.counter = Some(max);
+        self
+    }
+
+    pub fn default(mut self, value: impl Into<String>) -> Self {
+        self.default = Some(value.into());
+        self
+    }
+
+    /// Show or hide key bindings below user input
+    pub fn footer(mut self, enabled: bool) -> Self {
+        self.footer = enabled;
+        self
+    }
+
+    /// Allow skipping the remaining prompts with a key. Reading returns
+    /// a Finished error in that case.
+    pub fn finish(mut self, enabled: bool) -> Self {
+        self.finish = enabled;
+        self
+    }
+
+    /// Cancel the prompt with Esc. Reading returns a Cancelled error in that case.
+    pub fn cancel_on_esc(mut self, enabled: bool) -> Self {
+        self.cancel_on_esc = enabled;
+        self
+    }
+
+    /// Abandon the command with Ctrl-G. Reading returns a Restart error in that case.
+    pub fn restart(mut self, enabled: bool) -> Self {
+        self.restart = enabled;
+        self
+    }
+
+    /// Submit empty input, e.g to skip an optional value
+    pub fn allow_empty(mut self, enabled: bool) -> Self {
+        self.allow_empty = enabled;
+        self
+    }
+
+    /// Allow new lines in the input. Alt-Enter inserts a new line (Ctrl-J
+    /// can't be told apart from Enter) and Enter submits.
+    pub fn multiline(mut self, enabled: bool) -> Self {
+        self.multiline = enabled;
+        self
+    }
+
+    /// Log input that was dropped (e.g escape sequences in pasted text)
+    pub fn debug(mut self, enabled: bool) -> Self {
+        self.debug = enabled;
+        self
+    }
+
+    /// Use the messages for UI strings
+    pub fn messages(mut self, messages: Messages) -> Self {
+        self.messages = messages;
+        self
+    }
+
+    /// Start with a pre-filled input
+    pub fn initial(mut self, value: impl Into<String>) -> Self {
+        self.initial = value.into();
+        self
+    }
+
+    /// Style text for the terminal background
+    pub fn palette(mut self, palette: Palette) -> Self {
+        self.palette = palette;
+        self
+    }
+
+    /// Read keys from the given source instead of stdin
+    #[cfg(test)]
+    pub fn keys(self, keys: impl Iterator<Item = std::io::Result<Key>> + 's) -> Self {
+        self.events(keys.map(|key| key.map(Event::Key)))
+    }
+
+    /// Render for a terminal of the given width
+    #[cfg(test)]
+    pub fn width(mut self, width: usize) -> Self {
+        self.width = Some(width);
+        self
+    }
+
+    /// Read events from the given source instead of stdin
+    pub fn events(mut self, events: impl Iterator<Item = std::io::Result<Event>> + 's) -> Self {
+        self.events = Some(Box::new(events));
+        self
+    }
+
     /// Return a choice from one of the autocomplete options.
     /// Returns None if input was interrupted (e.g with ctrl-d).
+    /// Fails at once with EmptyChoices if there are no options.
     pub fn choice<'c, C>(
         &mut self,
-        autocomplete: impl AutoComplete<'c, C = C>,
+        mut autocomplete: impl AutoComplete<'c, C = C>,
     ) -> Result<Option<&'c C>>
     where
         C: Choice,
     {
-        let (choice, _) = self.run(AutocompleteMode::Enabled {
+        if autocomplete.list("").is_empty() {
+            return Err(SnovaError::EmptyChoices);
+        }
+        let result = self.run(AutocompleteMode::Enabled {
             autocomplete,
-            allow_user_input: false,
+            user_input: UserInput::Disabled,
         })?;
-        Ok(choice)
+        Ok(result.and_then(|(choice, _)| choice))
+    }
+
+    /// Return a choice from one of the autocomplete options or the typed text
+    /// if it matches none of them (e.g a flag that is not defined).
+    /// Returns None if input was interrupted (e.g with ctrl-d).
+    pub fn choice_or_custom<'c, C>(
+        &mut self,
+        autocomplete: impl AutoComplete<'c, C = C>,
+    ) -> Result<Option<(Option<&'c C>, String)>>
+    where
+        C: Choice,
+    {
+        self.run(AutocompleteMode::Enabled {
+            autocomplete,
+            user_input: UserInput::Custom,
+        })
     }
 
     /// Return a choice from one of the autocomplete options and a user input.
     /// This can be used when user is not required to pick an option
-    /// but instead could provide a custom value.
+    /// but instead could provide a custom value. Without options it reads
+    /// a single line.
+    /// Returns None if input was interrupted (e.g with ctrl-d).
     pub fn suggest<'c, C>(
         &mut self,
-        autocomplete: impl AutoComplete<'c, C = C>,
-    ) -> Result<(Option<&'c C>, String)>
+        mut autocomplete: impl AutoComplete<'c, C = C>,
+    ) -> Result<Option<(Option<&'c C>, String)>>
     where
         C: Choice,
     {
+        if autocomplete.list("").is_empty() {
+            return Ok(self.line()?.map(|text| (None, text)));
+        }
         self.run(AutocompleteMode::Enabled {
             autocomplete,
-            allow_user_input: true,
+            user_input: UserInput::Typed,
         })
     }
 
-    /// Read a single line
-    pub fn line<'c>(&mut self) -> Result<String> {
-        let (_, text) = self.run(AutocompleteMode::None::<FixedComplete<'c, String>>)?;
-        Ok(text)
+    /// Read a single line.
+    /// Returns None if input was interrupted (e.g with ctrl-d).
+    pub fn line(&mut self) -> Result<Option<String>> {
+        let result = self.run(AutocompleteMode::None::<FixedComplete<String>>)?;
+        Ok(result.map(|(_, text)| text))
+    }
+
+    /// Ask user to type the word to confirm.
+    /// Returns false if user typed anything else or input was interrupted (e.g with ctrl-d).
+    pub fn confirm(&mut self, word: &str) -> Result<bool> {
+        let value = self.line()?;
+        Ok(value.as_deref().map(str::trim) == Some(word))
+    }
+
+    /// Typed chars must be valid for the expected input unless they filter choices
+    fn accepts_char(&self, c: char) -> bool {
+        match &self.expect_input {
+            Some(expect) if !self.filtering => expect.is_valid_char(c),
+            _ => true,
+        }
+    }
+
+    /// Return the default value if it is valid for the expected input
+    fn valid_default(&self) -> Option<&str> {
+        self.default
+            .as_deref()
+            .filter(|value| match &self.expect_input {
+                Some(expect) => value.chars().all(|c| expect.is_valid_char(c)),
+                None => true,
+            })
+    }
+
+    /// Terminal width in columns
+    fn term_width(&self) -> usize {
+        self.width.or_else(crate::tty::ui_width).unwrap_or(80)
+    }
+
+    /// Tell that some of the input was dropped in debug mode
+    fn log_stripped(&self, count: usize) {
+        if self.debug && count > 0 {
+            let count = count.to_string();
+            eprint!(
+                "{}\r\n",
+                self.messages.format("stripped_input", &[("count", &count)])
+            );
+        }
+    }
+
+    /// Remember input state so that it could be restored later
+    fn push_undo(&mut self, input: &str) {
+        if self.undo.len() >= UNDO_LIMIT {
+            self.undo.remove(0);
+        }
+        self.undo.push((input.to_string(), self.cursor));
+    }
+
+    /// Insert pasted text as a single edit. Pasted text is rejected if it
+    /// contains chars that are not valid for the expected input.
+    fn paste(
+        &mut self,
+        mut events: impl Iterator<Item = std::io::Result<Event>>,
+        input: &mut String,
+    ) -> Result<()> {
+        let mut raw = String::new();
+        // Keys that terminal parsed out of escape sequences (e.g arrows)
+        let mut dropped = 0;
+        loop {
+            match events.next() {
+                Some(event) => match event? {
+                    Event::Unsupported(bytes) if bytes == PASTE_END => break,
+                    Event::Key(Key::Char('\r')) => raw.push('\n'),
+                    Event::Key(Key::Char(c)) => raw.push(c),
+                    // Let sanitize strip unknown sequences
+                    Event::Unsupported(bytes) => raw.push_str(&String::from_utf8_lossy(&bytes)),
+                    _ => dropped += 1,
+                },
+                None => return Err(input_closed()),
+            }
+        }
+
+        let (mut pasted, stripped) = sanitize(&raw);
+        self.log_stripped(stripped + dropped);
+        if !self.multiline {
+            // New lines would submit the input
+            pasted = pasted.replace('\n', " ");
+        }
+
+        if !pasted.chars().all(|c| self.accepts_char(c)) {
+            return Ok(());
+        }
+
+        self.push_undo(input);
+        input.insert_str(byte_index(input, self.cursor), &pasted);
+        self.cursor += pasted.chars().count();
+        Ok(())
     }
 
     /// Mutates the input based on the keys from stdin. It then returns the key
     /// for post processing.
     fn read_key(
         &mut self,
-        keys: impl Iterator<Item = std::result::Result<Key, std::io::Error>>,
+        mut events: impl Iterator<Item = std::io::Result<Event>>,
         input: &mut String,
     ) -> Result<Key> {
-        for key in keys {
-            let key = key?;
+        while let Some(event) = events.next() {
+            let key = match event? {
+                Event::Key(key) => key,
+                Event::Unsupported(bytes) if bytes == PASTE_START => {
+                    self.paste(&mut events, input)?;
+                    self.last_key = None;
+                    return Ok(Key::Null);
+                }
+                Event::Unsupported(bytes) => {
+                    self.log_stripped(bytes.len());
+                    continue;
+                }
+                _ => continue,
+            };
+            // Control chars would corrupt rendering and end up in the command
+            let key = match key {
+                Key::Char('\t') => Key::Char(' '),
+                Key::Char(c) if c != '\n' && c.is_control() => {
+                    self.log_stripped(1);
+                    Key::Null
+                }
+                key => key,
+            };
+            let last_key = self.last_key.replace(key);
             match key {
                 Key::Ctrl('c') => {
-                    return Err(anyhow!("Terminated"));
+                    return Err(SnovaError::Interrupted);
                 }
                 Key::Ctrl('u') => {
                     // Remove chars before the cursor
-                    input.drain(0..self.cursor);
+                    self.push_undo(input);
+                    input.drain(0..byte_index(input, self.cursor));
                     self.cursor = 0;
                 }
-                Key::Char('\n') => {}
-                Key::Char(c) => match &self.expect_input {
-                    Some(expect) if !expect.is_valid_char(c) => {}
-                    _ => {
-                        input.insert(self.cursor, c);
-                        self.cursor += 1;
+                Key::Ctrl('k') => {
+                    // Remove chars after the cursor
+                    self.push_undo(input);
+                    input.truncate(byte_index(input, self.cursor));
+                }
+                Key::Ctrl('w') => {
+                    // Remove a word before the cursor
+                    self.push_undo(input);
+                    let before: Vec<char> = input.chars().take(self.cursor).collect();
+                    // Skip whitespace before the cursor and then the word itself
+                    let word_end = before
+                        .iter()
+                        .rposition(|c| !c.is_whitespace())
+                        .map_or(0, |i| i + 1);
+                    let start = before[..word_end]
+                        .iter()
+                        .rposition(|c| c.is_whitespace())
+                        .map_or(0, |i| i + 1);
+                    input.drain(byte_index(input, start)..byte_index(input, self.cursor));
+                    self.cursor = start;
+                }
+                // Ctrl-_ is reported as Ctrl-7
+                Key::Ctrl('7') | Key::Ctrl('z') => {
+                    if let Some((prev_input, prev_cursor)) = self.undo.pop() {
+                        *input = prev_input;
+                        self.cursor = prev_cursor;
                     }
-                },
-                Key::Backspace => {
-                    self.cursor = self.cursor.saturating_sub(1);
-                    if self.cursor == 0 {
-                        input.pop();
-                    } else {
-                        input.drain(self.cursor..self.cursor + 1);
+                }
+                Key::Char('\n') => {}
+                Key::Alt('\r') | Key::Alt('\n') if self.multiline => {
+                    input.insert(byte_index(input, self.cursor), '\n');
+                    self.cursor += 1;
+                }
+                Key::Char(PREVIEW_KEY) if self.preview_toggle => {
+                    self.preview = !self.preview;
+                }
+                Key::Char(c) if self.accepts_char(c) => {
+                    input.insert(byte_index(input, self.cursor), c);
+                    self.cursor += 1;
+                }
+                Key::Backspace if self.cursor > 0 => {
+                    // Consecutive backspaces are undone at once
+                    if last_key != Some(Key::Backspace) {
+                        self.push_undo(input);
                     }
+                    // Remove a char before the cursor
+                    self.cursor -= 1;
+                    input.remove(byte_index(input, self.cursor));
                 }
                 Key::Left => {
                     self.cursor = self.cursor.saturating_sub(1);
                 }
-                Key::Right if self.cursor < input.len() => {
+                Key::Right if self.cursor < input.chars().count() => {
                     self.cursor += 1;
                 }
                 _ => {}
@@ -166,90 +614,288 @@ impl<'s> Readline<'s> {
             return Ok(key);
         }
 
-        Ok(Key::Char('\n'))
+        Err(input_closed())
     }
 
     fn run<'c, A, C>(
         &mut self,
         mut autocomplete: AutocompleteMode<A>,
-    ) -> Result<(Option<&'c C>, String)>
+    ) -> Result<Option<(Option<&'c C>, String)>>
     where
         C: Choice,
         A: AutoComplete<'c, C = C>,
     {
-        let mut input = String::new();
+        let mut input = std::mem::take(&mut self.initial);
+        self.cursor = input.chars().count();
         let mut selected: usize = 0;
         let reserve_rows = {
             // User input row
             let mut rows = 1;
-            // Help row above user input
-            if self.help.is_some() {
-                rows += 1;
+            // Help rows above user input (e.g a command rendered on several lines)
+            if let Some(help) = &self.help {
+                rows += help.lines().count().max(1) as u16;
             }
             // Autocomplete rows
             if autocomplete.enabled() {
                 rows += AUTOCOMPLETE_ROWS;
             }
+            // Footer row below user input
+            if self.footer {
+                rows += 1;
+            }
             rows
         };
-        let mut choices = vec![];
+        // Cursor stays on the user input row
+        let rows_above_input = reserve_rows - 1 - self.footer as u16;
+        // Multi-line input rows rendered above the cursor row
+        let mut input_rows_above_cursor;
+        let mut preview_rows;
+        self.filtering = autocomplete.enabled();
+        // Preview is available when picking one of the options
+        self.preview_toggle = matches!(
+            autocomplete,
+            AutocompleteMode::Enabled {
+                user_input: UserInput::Disabled | UserInput::Custom,
+                ..
+            }
+        );
+        let mut choices: Vec<&'c C> = vec![];
         let mut choices_len = 0;
-        let mut keys = stdin().keys();
+        // Typed text is listed after the choices
+        let mut show_user_input = false;
+        // Default value that is one of the options is preselected instead
+        let mut default_in_choices = false;
+        // User typed or moved the selection
+        let mut touched = false;
+        let mut events = self
+            .events
+            .take()
+            .unwrap_or_else(|| Box::new(stdin().events()));
+
+        write!(self.ui, "{}", ENABLE_BRACKETED_PASTE)?;
+        self.bracketed_paste = true;
 
         // TODO: in case of error clean up always
+        // User interrupted the input
+        let mut cancelled = false;
+
         let choice = loop {
-            write!(self.stdout, "{}\r", clear::AfterCursor)?;
+            write!(self.ui, "{}\r", clear::AfterCursor)?;
 
             // Render autocomplete choices
             if let AutocompleteMode::Enabled {
                 autocomplete,
-                allow_user_input,
+                user_input,
             } = &mut autocomplete
             {
+                // Remember which option was selected before filtering
+                let prev_selected = choices.get(selected).map(|c| c.text().to_string());
+
                 choices = autocomplete.list(&input);
                 choices_len = choices.len();
 
-                if *allow_user_input && !input.is_empty() {
+                // Show user input as a separate row unless it equals one of the options
+                show_user_input = !input.is_empty()
+                    && match user_input {
+                        UserInput::Disabled => false,
+                        UserInput::Typed => !choices.iter().any(|c| c.text() == input),
+                        UserInput::Custom => choices.is_empty(),
+                    };
+                if show_user_input {
                     choices_len += 1;
                 }
 
-                if selected >= choices_len {
-                    selected = choices_len.saturating_sub(1);
-                }
-
                 let mut view_choices: Vec<&str> = choices.iter().map(|c| c.text()).collect();
-                if *allow_user_input && !input.is_empty() {
-                    view_choices.push(&input);
+                let label = match user_input {
+                    UserInput::Custom => "custom_label",
+                    _ => "use_typed_label",
+                };
+                let user_input_row = format!("{}{}", self.messages.get(label), input);
+                if show_user_input {
+                    view_choices.push(&user_input_row);
+                }
+                let prev_selected = match (prev_selected, self.valid_default()) {
+                    // Preselect the default on the first render
+                    (None, Some(default)) if input.is_empty() => {
+                        default_in_choices = view_choices.contains(&default);
+                        Some(default.to_string())
+                    }
+                    (prev, _) => prev,
+                };
+                selected = keep_selected(prev_selected.as_deref(), &view_choices, selected);
+                // Start from the first choice that could be picked right away
+                if !touched && selected == 0 && choices.len() > 1 && !choices[0].preselect() {
+                    selected = 1;
                 }
+                self.scroll_offset = scroll_to(selected, self.scroll_offset, choices_len);
 
-                self.render_choices(&view_choices, selected)?;
+                let case = autocomplete.case_matching();
+                let hints: Vec<Option<&str>> = choices
+                    .iter()
+                    .map(|c| matched_hint(*c, &input, case))
+                    .chain(std::iter::once(None))
+                    .collect();
+                let styles: Vec<ChoiceStyle> = choices
+                    .iter()
+                    .map(|c| c.style())
+                    .chain(std::iter::once(ChoiceStyle::Normal))
+                    .collect();
+                let badges: Vec<Option<&str>> = choices
+                    .iter()
+                    .map(|c| c.badge())
+                    .chain(std::iter::once(None))
+                    .collect();
+                self.render_choices(&view_choices, &hints, &badges, &styles, selected)?;
+
+                if self.preview_toggle && self.preview {
+                    let preview = choices.get(selected).and_then(|c| c.preview());
+                    self.render_preview(preview.as_deref().unwrap_or(""))?;
+                }
             }
 
             // Display help
             if let Some(ref help) = self.help {
-                write!(self.stdout, "{}\r\n", fmt_text(help))?;
+                write!(
+                    self.ui,
+                    "{}",
+                    self.palette.fmt_text(help).replace('\n', "\r\n")
+                )?;
+                if let Some(max) = self.counter {
+                    let count = input.chars().count();
+                    if count > max {
+                        write!(
+                            self.ui,
+                            "  {}{}/{}{}",
+                            self.palette.error(),
+                            count,
+                            max,
+                            style::Reset
+                        )?;
+                    } else {
+                        write!(self.ui, "  {}/{}", count, max)?;
+                    }
+                }
+                write!(self.ui, "\r\n")?;
+            }
+
+            // Display user input. Every input line occupies a single row, hence
+            // we render only a window of it that fits into the terminal.
+            let term_width = self.term_width();
+            let prefix = self.palette.fmt_text(&self.prefix);
+            let prefix_width = display_width(&prefix);
+            // Prefix, a space after it and a trailing space
+            let input_width = term_width.saturating_sub(prefix_width + 2);
+            let lines = InputLines::new(&input, self.cursor, MULTILINE_ROWS);
+            let mut cursor_window = None;
+            for (i, line) in input.split('\n').enumerate() {
+                if i < lines.start || i >= lines.end {
+                    continue;
+                }
+                // Continuation rows are aligned with the first one
+                let lead = if i == 0 {
+                    prefix.clone()
+                } else {
+                    " ".repeat(prefix_width)
+                };
+                let line_cursor = if i == lines.cursor_line {
+                    lines.cursor_col
+                } else {
+                    0
+                };
+                let window = InputWindow::new(line, line_cursor, input_width);
+                if i > lines.start {
+                    write!(self.ui, "\r\n")?;
+                }
+                write!(self.ui, "{} {} ", lead, window.text)?;
+                if i == lines.cursor_line {
+                    cursor_window = Some(window);
+                }
+            }
+            let window = cursor_window.expect("Cursor line is visible");
+            let default_hint = match self.valid_default() {
+                Some(default) if input.is_empty() && !default_in_choices => {
+                    format!("{}({}){}", style::Faint, default, style::Reset)
+                }
+                _ => String::new(),
+            };
+            write!(self.ui, "{}", default_hint)?;
+
+            // Display key bindings
+            if self.footer {
+                let mut footer = autocomplete.footer(&self.messages).to_string();
+                if self.multiline {
+                    footer.push_str(self.messages.get("footer_new_line"));
+                }
+                if self.finish {
+                    footer.push_str(self.messages.get("footer_finish"));
+                }
+                if self.cancel_on_esc {
+                    footer.push_str(self.messages.get("footer_back"));
+                }
+                if self.restart {
+                    footer.push_str(self.messages.get("footer_restart"));
+                }
+                let help_wraps = self.help.as_ref().is_some_and(|help| {
+                    help.lines()
+                        .any(|line| display_width(&self.palette.fmt_text(line)) > term_width)
+                });
+                if help_wraps {
+                    footer.push_str(self.messages.get("footer_full_help"));
+                }
+                write!(
+                    self.ui,
+                    "\r\n{}{}{}{}",
+                    style::Faint,
+                    truncate(&footer, term_width.saturating_sub(1)),
+                    style::Reset,
+                    cursor::Up(1)
+                )?;
+            }
+
+            // Move the cursor to its row and an absolute column within the row
+            let rows_below_cursor = (lines.end - 1 - lines.cursor_line) as u16;
+            if rows_below_cursor > 0 {
+                write!(self.ui, "{}", cursor::Up(rows_below_cursor))?;
+            }
+            input_rows_above_cursor = (lines.cursor_line - lines.start) as u16;
+            let before_cursor: String = window.text.chars().take(window.cursor).collect();
+            let cursor_col = prefix_width + 1 + display_width(&before_cursor);
+            write!(self.ui, "\r")?;
+            if cursor_col > 0 {
+                write!(self.ui, "{}", cursor::Right(cursor_col as u16))?;
             }
+            self.ui.flush()?;
 
-            // Display user input
-            write!(self.stdout, "{} {} ", fmt_text(&self.prefix), input,)?;
-            // Cursor position is 1 based.
-            let cursor_left = input.len().saturating_sub(self.cursor) + 1;
-            write!(self.stdout, "{}", cursor::Left(cursor_left as u16))?;
-            self.stdout.flush()?;
+            // Preview pane rows were rendered above
+            preview_rows = if self.preview_toggle && self.preview {
+                PREVIEW_ROWS
+            } else {
+                0
+            };
 
-            let key = match self.read_key(&mut keys, &mut input) {
+            let key = match self.read_key(&mut events, &mut input) {
                 Ok(key) => key,
                 Err(e) => break Err(e),
             };
+            touched |= key != Key::Char('\n');
 
             match key {
                 Key::Char('\n') => {
-                    if let AutocompleteMode::Enabled {
-                        allow_user_input, ..
-                    } = autocomplete
-                    {
-                        let choice = choices.get(selected).cloned();
-                        if allow_user_input {
+                    if let AutocompleteMode::Enabled { user_input, .. } = autocomplete {
+                        let choice = choices
+                            .get(selected)
+                            .cloned()
+                            .filter(|c| touched || c.preselect());
+                        let typed = user_input == UserInput::Typed;
+                        if typed && input.is_empty() && !default_in_choices {
+                            if let Some(default) = self.valid_default() {
+                                // Accept the default
+                                input = default.to_string();
+                                break Ok(None);
+                            }
+                        }
+
+                        if typed || (show_user_input && choice.is_none()) {
                             // It is fine not to have a choice when user can
                             // input their own value
                             break Ok(choice);
@@ -257,7 +903,12 @@ impl<'s> Readline<'s> {
                             // Require a choice when running in a strict mode
                             break Ok(choice);
                         }
-                    } else if self.expect_input.is_some() && !input.is_empty() {
+                    } else if let (true, Some(default)) = (input.is_empty(), self.valid_default()) {
+                        // Accept the default
+                        input = default.to_string();
+                        break Ok(None);
+                    } else if self.expect_input.is_some() && (!input.is_empty() || self.allow_empty)
+                    {
                         // When expecting an input require it to be non-empty
                         break Ok(None);
                     } else if self.expect_input.is_none() {
@@ -265,50 +916,143 @@ impl<'s> Readline<'s> {
                         break Ok(None);
                     }
                 }
-                Key::Up | Key::Ctrl('j') if selected > 0 => {
+                // Multi-line input without choices moves the cursor between lines
+                Key::Up if self.multiline && !autocomplete.enabled() => {
+                    self.cursor = cursor_line_up(&input, self.cursor);
+                }
+                Key::Down if self.multiline && !autocomplete.enabled() => {
+                    self.cursor = cursor_line_down(&input, self.cursor);
+                }
+                // Visible window is scrolled to the selected row on the next render
+                Key::Up | Key::Ctrl('p') if selected > 0 => {
                     selected -= 1;
-
-                    // If cursor moved outsize of visible window then scroll
-                    if selected < self.scroll_offset {
-                        self.scroll_offset -= 1;
-                    }
                 }
-                Key::Down | Key::Ctrl('k') if selected < (choices_len.saturating_sub(1)) => {
+                Key::Down | Key::Ctrl('n') if selected < (choices_len.saturating_sub(1)) => {
                     selected += 1;
-
-                    // If cursor moved outsize of visible window then scroll
-                    if selected >= AUTOCOMPLETE_ROWS as usize + self.scroll_offset {
-                        self.scroll_offset += 1;
-                    }
                 }
                 Key::Ctrl('d') => {
+                    cancelled = true;
                     break Ok(None);
                 }
+                Key::Ctrl('s') if self.finish => {
+                    break Err(SnovaError::Finished);
+                }
+                Key::Esc if self.cancel_on_esc => {
+                    break Err(SnovaError::Cancelled);
+                }
+                Key::Ctrl('g') if self.restart => {
+                    break Err(SnovaError::Restart);
+                }
+                FULL_HELP_KEY if self.help.is_some() => {
+                    let rows_up = rows_above_input + input_rows_above_cursor + preview_rows;
+                    if rows_up > 0 {
+                        write!(self.ui, "{}\r", cursor::Up(rows_up))?;
+                    }
+                    if let Err(e) = self.show_full_help(&mut events) {
+                        break Err(e);
+                    }
+                    // Cursor is back on the first row and the prompt is rendered again
+                    continue;
+                }
                 _ => {}
             }
 
-            if reserve_rows > 1 {
-                write!(self.stdout, "{}\r", cursor::Up(reserve_rows - 1))?;
+            let rows_up = rows_above_input + input_rows_above_cursor;
+            if rows_up > 0 {
+                write!(self.ui, "{}\r", cursor::Up(rows_up))?;
+            }
+            if preview_rows > 0 {
+                write!(self.ui, "{}\r", cursor::Up(preview_rows))?;
             }
         };
 
-        if reserve_rows > 1 {
-            write!(self.stdout, "{}\r", cursor::Up(reserve_rows - 1))?;
+        let rows_up = rows_above_input + input_rows_above_cursor;
+        if rows_up > 0 {
+            write!(self.ui, "{}\r", cursor::Up(rows_up))?;
         }
-        write!(self.stdout, "{}\r", clear::AfterCursor)?;
-        self.stdout.flush()?;
+        if preview_rows > 0 {
+            write!(self.ui, "{}\r", cursor::Up(preview_rows))?;
+        }
+        write!(self.ui, "{}\r", clear::AfterCursor)?;
+        write!(self.ui, "{}", DISABLE_BRACKETED_PASTE)?;
+        self.bracketed_paste = false;
+        self.ui.flush()?;
+
+        self.events = Some(events);
 
         let choice = choice?;
-        Ok((choice, input))
+        if cancelled {
+            return Ok(None);
+        }
+        Ok(Some((choice, input)))
+    }
+
+    /// Replace the prompt with the help wrapped to the terminal width until
+    /// any key is pressed. Starts and ends with the cursor on the first row.
+    fn show_full_help(&mut self, events: &mut Events<'_>) -> Result<()> {
+        let help = self.help.as_deref().unwrap_or_default();
+        let lines = wrap(help, self.term_width().saturating_sub(1));
+        write!(self.ui, "{}", clear::AfterCursor)?;
+        for line in &lines {
+            write!(
+                self.ui,
+                "{}{}\r\n",
+                self.palette.fmt_text(line),
+                style::Reset
+            )?;
+        }
+        write!(
+            self.ui,
+            "{}{}{}",
+            style::Faint,
+            self.messages.get("full_help_return"),
+            style::Reset
+        )?;
+        self.ui.flush()?;
+        match events.next() {
+            Some(Ok(Event::Key(Key::Ctrl('c')))) => return Err(SnovaError::Interrupted),
+            Some(event) => event?,
+            None => return Err(input_closed()),
+        };
+        if !lines.is_empty() {
+            write!(self.ui, "{}", cursor::Up(lines.len() as u16))?;
+        }
+        write!(self.ui, "\r{}", clear::AfterCursor)?;
+        Ok(())
+    }
+
+    /// Render preview text in a fixed number of rows
+    fn render_preview(&mut self, preview: &str) -> Result<()> {
+        let term_width = self.term_width();
+        let lines = wrap(preview, term_width.saturating_sub(3));
+        for i in 0..PREVIEW_ROWS as usize {
+            let line = lines.get(i).map(|l| l.as_str()).unwrap_or("");
+            write!(
+                self.ui,
+                "{}  {}{}\n\r",
+                clear::CurrentLine,
+                self.palette.fmt_text(line),
+                style::Reset
+            )?;
+        }
+        Ok(())
     }
 
-    fn render_choices(&mut self, choices: &[&str], selected: usize) -> Result<()> {
+    fn render_choices(
+        &mut self,
+        choices: &[&str],
+        hints: &[Option<&str>],
+        badges: &[Option<&str>],
+        styles: &[ChoiceStyle],
+        selected: usize,
+    ) -> Result<()> {
         let total = choices.len();
-        let size = AUTOCOMPLETE_ROWS - 1;
+        let size = VISIBLE_CHOICES as u16;
+        let term_width = self.term_width();
         let empty_rows = (size as isize - total as isize).max(0);
 
         for _ in 0..empty_rows {
-            write!(self.stdout, "{}\n\r", clear::CurrentLine)?;
+            write!(self.ui, "{}\n\r", clear::CurrentLine)?;
         }
 
         for (i, choice) in choices
@@ -317,109 +1061,791 @@ impl<'s> Readline<'s> {
             .skip(self.scroll_offset)
             .take(size as usize)
         {
-            write!(self.stdout, "{}", clear::CurrentLine)?;
+            write!(self.ui, "{}", clear::CurrentLine)?;
+            let color = match styles.get(i) {
+                Some(ChoiceStyle::Warning) => self.palette.warning(),
+                _ => String::new(),
+            };
+            let text = self.palette.fmt_text(choice);
             if i == selected {
                 write!(
-                    self.stdout,
-                    "> {}{}{}",
-                    style::Bold,
-                    fmt_text(choice),
+                    self.ui,
+                    "{}{}{}{}{}",
+                    self.palette.marker(),
+                    self.palette.strong(),
+                    color,
+                    text,
                     style::Reset
                 )?;
+            } else if color.is_empty() {
+                write!(self.ui, "  {}", text)?;
             } else {
-                write!(self.stdout, "  {}", fmt_text(choice))?;
+                write!(self.ui, "  {}{}{}", color, text, style::Reset)?;
+            }
+            // Hint follows the badge
+            let mut shown = choice.to_string();
+            if let Some(Some(badge)) = badges.get(i) {
+                write!(self.ui, "  {}[{}]{}", style::Faint, badge, style::Reset)?;
+                shown = format!("{}  [{}]", choice, badge);
             }
-            write!(self.stdout, "\n\r")?;
+            if let Some(Some(hint)) = hints.get(i) {
+                let hint = fit_hint(&shown, hint, term_width);
+                if !hint.is_empty() {
+                    write!(self.ui, "  {}{}{}", style::Faint, hint, style::Reset)?;
+                }
+            }
+            write!(self.ui, "\n\r")?;
         }
 
-        write!(
-            self.stdout,
-            "  {}{}/{}{}\n\r",
-            style::Italic,
-            selected + 1,
-            total,
-            style::NoItalic
-        )?;
+        let counter = self
+            .palette
+            .counter(format_args!("{}/{}", selected + 1, total));
+        write!(self.ui, "  {}\n\r", counter)?;
         Ok(())
     }
 }
 
-#[derive(Default)]
-struct FmtState {
-    /// Bold text has started
-    bold: bool,
-    /// Underline text has started
-    underline: bool,
+impl<'s> Drop for Readline<'s> {
+    fn drop(&mut self) {
+        // Leave the terminal as it was (e.g on panic)
+        if self.bracketed_paste {
+            let _ = write!(self.ui, "{}", DISABLE_BRACKETED_PASTE);
+            let _ = self.ui.flush();
+        }
+    }
 }
 
-pub fn fmt_text(text: impl AsRef<str>) -> String {
-    let text = text.as_ref();
-    let mut result = String::new();
-    let mut state = FmtState::default();
-
-    for c in text.chars() {
-        match c {
-            '*' => {
-                if state.bold {
-                    // End bold
-                    // Somehow NoBold doesn't work properly hence using Reset for now
-                    // result.push_str(style::NoBold.as_ref());
-                    result.push_str(style::Reset.as_ref());
-                } else {
-                    // Start bold
-                    result.push_str(style::Bold.as_ref());
-                }
+/// Part of a template that is edited in a single line
+#[derive(Debug, Clone)]
+pub enum TemplatePart {
+    /// Text that is kept as is
+    Text(String),
+    /// Group value that is typed in place of its placeholder
+    Value {
+        name: String,
+        expect: ValueType,
+        optional: bool,
+    },
+    /// Group that is asked after the line (e.g flags), displayed dimmed
+    Later(String),
+}
 
-                state.bold = !state.bold;
-            }
-            '_' => {
-                if state.underline {
-                    // End underline
-                    result.push_str(style::NoUnderline.as_ref());
-                } else {
-                    // Start underline
-                    result.push_str(style::Underline.as_ref());
-                }
+/// Edits the values of several groups in a single line. The template text
+/// stays fixed while Tab and Shift-Tab move between the placeholders.
+pub struct TemplateLine<'s> {
+    parts: Vec<TemplatePart>,
+    /// Typed values by part index
+    values: Vec<String>,
+    /// Where the line is rendered
+    ui: &'s mut dyn Write,
+    /// Events are read from stdin unless specified
+    events: Option<Events<'s>>,
+    /// Show key bindings below the line
+    footer: bool,
+    /// Esc cancels the line
+    cancel_on_esc: bool,
+    /// Ctrl-G goes back to picking a command
+    restart: bool,
+    /// UI strings
+    messages: Messages,
+    /// Text styles
+    palette: Palette,
+}
 
-                state.underline = !state.underline;
-            }
-            _ => {
-                result.push(c);
-            }
+impl<'s> TemplateLine<'s> {
+    pub fn new(ui: &'s mut dyn Write, parts: Vec<TemplatePart>) -> Self {
+        Self {
+            values: vec![String::new(); parts.len()],
+            parts,
+            ui,
+            events: None,
+            footer: true,
+            cancel_on_esc: false,
+            restart: false,
+            messages: Messages::default(),
+            palette: Palette::default(),
         }
     }
 
-    // Clean styles if not closed properly
-    if state.bold {
-        // Somehow NoBold doesn't work properly hence using Reset for now
-        // result.push_str(style::NoBold.as_ref());
-        result.push_str(style::Reset.as_ref());
+    /// Value of the group to start with
+    pub fn initial(mut self, name: &str, value: impl Into<String>) -> Self {
+        let index = self
+            .parts
+            .iter()
+            .position(|part| matches!(part, TemplatePart::Value { name: n, .. } if n == name));
+        if let Some(index) = index {
+            self.values[index] = value.into();
+        }
+        self
     }
 
-    if state.underline {
-        result.push_str(style::NoUnderline.as_ref());
+    pub fn footer(mut self, enabled: bool) -> Self {
+        self.footer = enabled;
+        self
     }
 
-    result
-}
+    pub fn cancel_on_esc(mut self, enabled: bool) -> Self {
+        self.cancel_on_esc = enabled;
+        self
+    }
+
+    pub fn restart(mut self, enabled: bool) -> Self {
+        self.restart = enabled;
+        self
+    }
+
+    pub fn messages(mut self, messages: Messages) -> Self {
+        self.messages = messages;
+        self
+    }
+
+    pub fn palette(mut self, palette: Palette) -> Self {
+        self.palette = palette;
+        self
+    }
+
+    pub fn events(mut self, events: impl Iterator<Item = std::io::Result<Event>> + 's) -> Self {
+        self.events = Some(Box::new(events));
+        self
+    }
+
+    /// Read keys from the given source instead of stdin
+    #[cfg(test)]
+    pub fn keys(self, keys: impl Iterator<Item = std::io::Result<Key>> + 's) -> Self {
+        self.events(keys.map(|key| key.map(Event::Key)))
+    }
+
+    /// Return the typed values by group name once every value is valid.
+    /// Skipped optional values are empty.
+    /// Returns None if input was interrupted (e.g with ctrl-d).
+    pub fn run(mut self) -> Result<Option<HashMap<String, String>>> {
+        let spans: Vec<usize> = self
+            .parts
+            .iter()
+            .enumerate()
+            .filter(|(_, part)| matches!(part, TemplatePart::Value { .. }))
+            .map(|(index, _)| index)
+            .collect();
+        let mut events = self
+            .events
+            .take()
+            .unwrap_or_else(|| Box::new(stdin().events()));
+        let mut current = 0;
+        let mut error: Option<String> = None;
+
+        let result = loop {
+            write!(self.ui, "{}\r", clear::AfterCursor)?;
+            let rows_above_line = error.is_some() as u16;
+            if let Some(error) = &error {
+                write!(
+                    self.ui,
+                    "{}{}{}\r\n",
+                    self.palette.error(),
+                    error,
+                    style::Reset
+                )?;
+            }
+
+            let mut line = String::new();
+            let mut cursor_col = 0;
+            for (index, part) in self.parts.iter().enumerate() {
+                match part {
+                    TemplatePart::Text(text) => line.push_str(text),
+                    TemplatePart::Later(name) => {
+                        line.push_str(&format!("{}{}{}", style::Faint, name, style::Reset))
+                    }
+                    TemplatePart::Value { name, .. } => {
+                        let value = &self.values[index];
+                        let shown = if value.is_empty() {
+                            format!("{}{}{}", style::Faint, name, style::Reset)
+                        } else {
+                            value.clone()
+                        };
+                        if spans.get(current) == Some(&index) {
+                            cursor_col = display_width(&line) + display_width(value);
+                            line.push_str(&format!(
+                                "{}{}{}",
+                                style::Underline,
+                                shown,
+                                style::NoUnderline
+                            ));
+                        } else {
+                            line.push_str(&shown);
+                        }
+                    }
+                }
+            }
+            write!(self.ui, "$ {}", line)?;
+
+            if self.footer {
+                let mut footer = self.messages.get("footer_template").to_string();
+                if self.cancel_on_esc {
+                    footer.push_str(self.messages.get("footer_back"));
+                }
+                if self.restart {
+                    footer.push_str(self.messages.get("footer_restart"));
+                }
+                write!(
+                    self.ui,
+                    "\r\n{}{}{}{}",
+                    style::Faint,
+                    footer,
+                    style::Reset,
+                    cursor::Up(1)
+                )?;
+            }
+            write!(self.ui, "\r{}", cursor::Right(2 + cursor_col as u16))?;
+            self.ui.flush()?;
+
+            let key = loop {
+                match events.next() {
+                    Some(Ok(Event::Key(key))) => break Ok(key),
+                    Some(Ok(_)) => continue,
+                    Some(Err(err)) => break Err(err.into()),
+                    None => break Err(input_closed()),
+                }
+            };
+            let key = match key {
+                Ok(key) => key,
+                Err(err) => break Err(err),
+            };
+            let span = spans.get(current).copied();
+            match key {
+                Key::Char('\t') if !spans.is_empty() => {
+                    current = (current + 1) % spans.len();
+                }
+                Key::BackTab if !spans.is_empty() => {
+                    current = (current + spans.len() - 1) % spans.len();
+                }
+                Key::Char('\n') => match self.check(&spans) {
+                    Ok(()) => break Ok(Some(self.named_values())),
+                    Err((invalid, err)) => {
+                        current = invalid;
+                        error = Some(err);
+                    }
+                },
+                Key::Char(c) if !c.is_control() => {
+                    if let Some(span) = span {
+                        if self.expect(span).is_some_and(|e| e.is_valid_char(c)) {
+                            self.values[span].push(c);
+                        }
+                    }
+                }
+                Key::Backspace => {
+                    if let Some(span) = span {
+                        self.values[span].pop();
+                    }
+                }
+                Key::Ctrl('u') => {
+                    if let Some(span) = span {
+                        self.values[span].clear();
+                    }
+                }
+                Key::Ctrl('d') => break Ok(None),
+                Key::Ctrl('c') => break Err(SnovaError::Interrupted),
+                Key::Esc if self.cancel_on_esc => break Err(SnovaError::Cancelled),
+                Key::Ctrl('g') if self.restart => break Err(SnovaError::Restart),
+                _ => {}
+            }
+
+            if rows_above_line > 0 {
+                write!(self.ui, "{}\r", cursor::Up(rows_above_line))?;
+            }
+        };
+
+        if error.is_some() {
+            write!(self.ui, "{}\r", cursor::Up(1))?;
+        }
+        write!(self.ui, "{}\r", clear::AfterCursor)?;
+        self.ui.flush()?;
+        result
+    }
+
+    fn expect(&self, index: usize) -> Option<&ValueType> {
+        match &self.parts[index] {
+            TemplatePart::Value { expect, .. } => Some(expect),
+            _ => None,
+        }
+    }
+
+    /// Validate the values. Returns the position of the first invalid span
+    /// with the error.
+    fn check(&self, spans: &[usize]) -> std::result::Result<(), (usize, String)> {
+        for (position, index) in spans.iter().enumerate() {
+            if let TemplatePart::Value {
+                name,
+                expect,
+                optional,
+            } = &self.parts[*index]
+            {
+                let value = &self.values[*index];
+                if value.is_empty() && *optional {
+                    continue;
+                }
+                if value.is_empty() {
+                    let err = self.messages.format("value_required", &[("prompt", name)]);
+                    return Err((position, err));
+                }
+                if let Err(err) = expect.validate(value) {
+                    return Err((position, format!("{}: {}", name, err)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn named_values(&self) -> HashMap<String, String> {
+        self.parts
+            .iter()
+            .zip(&self.values)
+            .filter_map(|(part, value)| match part {
+                TemplatePart::Value { name, .. } => Some((name.clone(), value.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Split text into lines that fit into width columns. Lines are broken
+/// between words when possible. Markup doesn't take any space.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = vec![];
+
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        let mut line_width = 0;
+
+        for word in paragraph.split(' ') {
+            let word_width = display_width(&strip_markup(word));
+            if line_width > 0 && line_width + 1 + word_width > width {
+                lines.push(std::mem::take(&mut line));
+                line_width = 0;
+            }
+            if line_width > 0 {
+                line.push(' ');
+                line_width += 1;
+            }
+
+            // Word is longer than the line: break it between graphemes
+            for g in word.graphemes(true) {
+                let g_width = if g == "*" || g == "_" {
+                    0
+                } else {
+                    grapheme_width(g)
+                };
+                if line_width + g_width > width {
+                    lines.push(std::mem::take(&mut line));
+                    line_width = 0;
+                }
+                line.push_str(g);
+                line_width += g_width;
+            }
+        }
+
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Remove ANSI escape sequences and control chars from the text. Tabs become
+/// spaces and new lines are kept. Returns the text and the number of removed chars.
+fn sanitize(text: &str) -> (String, usize) {
+    let mut result = String::with_capacity(text.len());
+    let mut removed = 0;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => {
+                removed += 1;
+                match chars.next() {
+                    // CSI sequence up to the final byte
+                    Some('[') => {
+                        removed += 1;
+                        for c in chars.by_ref() {
+                            removed += 1;
+                            if ('@'..='~').contains(&c) {
+                                break;
+                            }
+                        }
+                    }
+                    // OSC sequence up to BEL or ST
+                    Some(']') => {
+                        removed += 1;
+                        while let Some(c) = chars.next() {
+                            removed += 1;
+                            if c == '\x07' {
+                                break;
+                            }
+                            if c == '\x1b' && chars.peek() == Some(&'\\') {
+                                chars.next();
+                                removed += 1;
+                                break;
+                            }
+                        }
+                    }
+                    Some(_) => removed += 1,
+                    None => {}
+                }
+            }
+            '\t' => result.push(' '),
+            '\n' => result.push('\n'),
+            c if c.is_control() => removed += 1,
+            c => result.push(c),
+        }
+    }
+
+    (result, removed)
+}
+
+/// Cut the text so that it fits into width columns. Graphemes are never split.
+fn truncate(text: &str, width: usize) -> String {
+    let mut result = String::new();
+    let mut result_width = 0;
+    for g in text.graphemes(true) {
+        result_width += grapheme_width(g);
+        if result_width > width {
+            break;
+        }
+        result.push_str(g);
+    }
+    result
+}
+
+/// Cut the hint to the columns left in the row after the choice.
+/// The row starts with a two column marker and the hint is separated by two spaces.
+fn fit_hint(choice: &str, hint: &str, term_width: usize) -> String {
+    let used = 2 + display_width(&strip_markup(choice)) + 2;
+    truncate(&strip_markup(hint), term_width.saturating_sub(used + 1))
+}
+
+/// Convert cursor position (in chars) into a byte index within the input
+fn byte_index(input: &str, cursor: usize) -> usize {
+    input
+        .char_indices()
+        .nth(cursor)
+        .map(|(i, _)| i)
+        .unwrap_or_else(|| input.len())
+}
+
+/// Find the previously selected option in the filtered list. If it is gone
+/// then keep the same position within the list bounds.
+fn keep_selected(prev: Option<&str>, choices: &[&str], selected: usize) -> usize {
+    prev.and_then(|prev| choices.iter().position(|c| *c == prev))
+        .unwrap_or_else(|| selected.min(choices.len().saturating_sub(1)))
+}
+
+/// Return a scroll offset that keeps the selected row visible and
+/// doesn't go past the end of the list.
+fn scroll_to(selected: usize, scroll_offset: usize, total: usize) -> usize {
+    let offset = scroll_offset.min(total.saturating_sub(VISIBLE_CHOICES));
+    if selected < offset {
+        selected
+    } else if selected >= offset + VISIBLE_CHOICES {
+        selected + 1 - VISIBLE_CHOICES
+    } else {
+        offset
+    }
+}
+
+/// Number of terminal columns the text occupies. ANSI escape sequences
+/// (e.g from fmt_text) take no space.
+pub fn display_width(text: &str) -> usize {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Skip CSI sequence up to the final byte
+            if chars.next() == Some('[') {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            continue;
+        }
+        plain.push(c);
+    }
+
+    plain.graphemes(true).map(grapheme_width).sum()
+}
+
+/// Number of terminal columns a single grapheme (e.g emoji sequence) occupies.
+fn grapheme_width(g: &str) -> usize {
+    if g.chars().all(char::is_control) {
+        0
+    } else {
+        g.width()
+    }
+}
+
+/// Number of terminal columns a single char occupies.
+fn char_width(c: char) -> usize {
+    c.width().unwrap_or(0)
+}
+
+/// Marks the part of the input that doesn't fit into the row
+const ELLIPSIS: char = '…';
+
+/// Visible part of the user input
+#[derive(Debug, PartialEq)]
+struct InputWindow {
+    text: String,
+    /// Cursor position within the window text
+    cursor: usize,
+}
+
+impl InputWindow {
+    /// Take a window of the input around the cursor that fits into width columns.
+    /// Hidden parts are replaced with an ellipsis on either end.
+    fn new(input: &str, cursor: usize, width: usize) -> Self {
+        let chars: Vec<char> = input.chars().collect();
+        let widths: Vec<usize> = chars.iter().map(|c| char_width(*c)).collect();
+        let total = chars.len();
+        let cursor = cursor.min(total);
+        // We need at least one visible char between the ellipses
+        let width = width.max(3);
+
+        // Input fits as is (keep one column for the cursor at the end)
+        if widths.iter().sum::<usize>() < width {
+            return Self {
+                text: input.to_string(),
+                cursor,
+            };
+        }
+
+        let cols = |start: usize, end: usize| widths[start..end].iter().sum::<usize>();
+        // Visible chars, ellipses and a column for the cursor at the end fit
+        let fits = |start: usize, end: usize| {
+            let ellipses = (start > 0) as usize + (end < total) as usize;
+            let cursor_col = (end == total) as usize;
+            cols(start, end) + ellipses + cursor_col <= width
+        };
+
+        // Window always includes the char under the cursor
+        let cursor_end = (cursor + 1).min(total);
+        let (start, end) = if fits(0, cursor_end) {
+            // Cursor is near the start: hide the end
+            let mut end = cursor_end;
+            while end < total && fits(0, end + 1) {
+                end += 1;
+            }
+            (0, end)
+        } else if fits(cursor.saturating_sub(1), total) {
+            // Cursor is near the end: hide the start
+            let mut start = cursor;
+            while start > 0 && fits(start - 1, total) {
+                start -= 1;
+            }
+            (start, total)
+        } else {
+            // Cursor is in the middle: hide both ends and center the cursor
+            let (mut start, mut end) = (cursor, cursor_end);
+            while start > 0 && cols(start - 1, cursor) <= (width - 2) / 2 && fits(start - 1, end) {
+                start -= 1;
+            }
+            while end < total && fits(start, end + 1) {
+                end += 1;
+            }
+            while start > 0 && fits(start - 1, end) {
+                start -= 1;
+            }
+            (start, end)
+        };
+
+        let mut text = String::new();
+        let mut window_cursor = cursor - start;
+        if start > 0 {
+            text.push(ELLIPSIS);
+            window_cursor += 1;
+        }
+        text.extend(&chars[start..end]);
+        if end < total {
+            text.push(ELLIPSIS);
+        }
+
+        Self {
+            text,
+            cursor: window_cursor,
+        }
+    }
+}
+
+/// Visible rows of a multi-line input
+#[derive(Debug, PartialEq)]
+struct InputLines {
+    /// First visible line
+    start: usize,
+    /// Line after the last visible one
+    end: usize,
+    /// Line with the cursor
+    cursor_line: usize,
+    /// Cursor position (in chars) within its line
+    cursor_col: usize,
+}
+
+impl InputLines {
+    /// Pick at most max_rows lines of the input. The window follows the cursor line.
+    fn new(input: &str, cursor: usize, max_rows: usize) -> Self {
+        let total = input.split('\n').count();
+        let (cursor_line, cursor_col) = line_col(input, cursor);
+        let max_rows = max_rows.max(1);
+        let start = (cursor_line + 1).saturating_sub(max_rows);
+        Self {
+            start,
+            end: (start + max_rows).min(total),
+            cursor_line,
+            cursor_col,
+        }
+    }
+}
+
+/// Line and column (in chars) of the cursor
+fn line_col(input: &str, cursor: usize) -> (usize, usize) {
+    let before: Vec<char> = input.chars().take(cursor).collect();
+    let line = before.iter().filter(|c| **c == '\n').count();
+    let col = before.iter().rev().take_while(|c| **c != '\n').count();
+    (line, col)
+}
+
+/// Cursor position of the column within the line. Column is clamped to the line length.
+fn line_pos(input: &str, line: usize, col: usize) -> usize {
+    let mut pos = 0;
+    for (i, text) in input.split('\n').enumerate() {
+        if i == line {
+            return pos + col.min(text.chars().count());
+        }
+        pos += text.chars().count() + 1;
+    }
+    input.chars().count()
+}
+
+/// Move the cursor to the same column on the previous line
+fn cursor_line_up(input: &str, cursor: usize) -> usize {
+    match line_col(input, cursor) {
+        (0, _) => cursor,
+        (line, col) => line_pos(input, line - 1, col),
+    }
+}
+
+/// Move the cursor to the same column on the next line
+fn cursor_line_down(input: &str, cursor: usize) -> usize {
+    let (line, col) = line_col(input, cursor);
+    if line + 1 >= input.split('\n').count() {
+        cursor
+    } else {
+        line_pos(input, line + 1, col)
+    }
+}
+
+/// Remove formatting markup (see fmt_text) from the text
+pub fn strip_markup(text: &str) -> String {
+    text.replace("\\_", "\0")
+        .replace(&['*', '_'][..], "")
+        .replace('\0', "_")
+}
+
+/// How to compare letter case when matching user input
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum CaseMatching {
+    /// Ignore case unless user input contains an uppercase char
+    #[default]
+    Smart,
+    Sensitive,
+    Insensitive,
+}
+
+impl CaseMatching {
+    pub fn parse(v: &str) -> Result<CaseMatching> {
+        match v {
+            "smart" => Ok(CaseMatching::Smart),
+            "sensitive" => Ok(CaseMatching::Sensitive),
+            "insensitive" => Ok(CaseMatching::Insensitive),
+            _ => Err(SnovaError::parse(format!("Unknown case matching '{}'", v))),
+        }
+    }
+
+    fn ignore_case(&self, input: &str) -> bool {
+        match self {
+            CaseMatching::Smart => !input.chars().any(char::is_uppercase),
+            CaseMatching::Sensitive => false,
+            CaseMatching::Insensitive => true,
+        }
+    }
+}
+
+/// Check whether text matches user input. Input is split into whitespace
+/// separated tokens and every token must be found in the text in any order.
+/// Matching ignores markup.
+pub fn matches(text: &str, input: &str, case: CaseMatching) -> bool {
+    let text = strip_markup(text);
+    input
+        .split_whitespace()
+        .all(|token| matches_token(&text, token, case))
+}
+
+/// Check whether text contains a single token
+fn matches_token(text: &str, token: &str, case: CaseMatching) -> bool {
+    if case.ignore_case(token) {
+        text.to_lowercase().contains(&token.to_lowercase())
+    } else {
+        text.contains(token)
+    }
+}
+
+/// Return a choice hint if the displayed text didn't match user input
+/// and the hint did match some of it.
+pub fn matched_hint<'a, C: Choice>(
+    choice: &'a C,
+    input: &str,
+    case: CaseMatching,
+) -> Option<&'a str> {
+    if matches(choice.text(), input, case) {
+        return None;
+    }
+    choice.hint().filter(|hint| {
+        let hint = strip_markup(hint);
+        input
+            .split_whitespace()
+            .any(|token| matches_token(&hint, token, case))
+    })
+}
 
 pub trait AutoComplete<'c> {
     type C: Choice;
 
     fn list(&mut self, input: &str) -> Vec<&'c Self::C>;
+
+    /// Case matching used for filtering the options
+    fn case_matching(&self) -> CaseMatching {
+        CaseMatching::default()
+    }
 }
 
 /// Autocomplete from a fixed set of options
 pub struct FixedComplete<'c, C> {
-    options: &'c Vec<C>,
+    options: &'c [C],
+    case: CaseMatching,
 }
 
 impl<'c, C> FixedComplete<'c, C>
 where
     C: Choice,
 {
-    pub fn new(options: &'c Vec<C>) -> Self {
-        Self { options }
+    pub fn new(options: &'c [C]) -> Self {
+        Self {
+            options,
+            case: CaseMatching::default(),
+        }
+    }
+
+    pub fn case(mut self, case: CaseMatching) -> Self {
+        self.case = case;
+        self
     }
 }
 
@@ -432,31 +1858,1589 @@ where
     fn list(&mut self, input: &str) -> Vec<&'c C> {
         self.options
             .iter()
-            .filter(|o| o.text().to_lowercase().contains(&input.to_lowercase()))
+            .filter(|o| matches(&o.search_text(), input, self.case))
             .collect()
     }
+
+    fn case_matching(&self) -> CaseMatching {
+        self.case
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::palette::Background;
+    use termion::color;
+
+    /// Convert text into key presses
+    fn type_keys(text: &str) -> Vec<Key> {
+        text.chars().map(Key::Char).collect()
+    }
+
+    fn scripted(keys: Vec<Key>) -> impl Iterator<Item = std::io::Result<Key>> {
+        keys.into_iter().map(Ok)
+    }
+
+    fn head_template() -> Vec<TemplatePart> {
+        vec![
+            TemplatePart::Text("head ".into()),
+            TemplatePart::Later("OPTIONS".into()),
+            TemplatePart::Text(" -n ".into()),
+            TemplatePart::Value {
+                name: "NUM".into(),
+                expect: ValueType::Number,
+                optional: false,
+            },
+            TemplatePart::Text(" ".into()),
+            TemplatePart::Value {
+                name: "PATH".into(),
+                expect: ValueType::Path,
+                optional: false,
+            },
+            TemplatePart::Text(" ".into()),
+            TemplatePart::Value {
+                name: "NOTE".into(),
+                expect: ValueType::String,
+                optional: true,
+            },
+        ]
+    }
 
     #[test]
-    fn fmt_text_ok() {
+    fn template_line_values() {
+        let mut out = vec![];
+        // Invalid chars are not typed, Shift-Tab moves back
+        let mut keys = type_keys("1x0\tlog.tx");
+        keys.extend(vec![
+            Key::Char('\t'),
+            Key::BackTab,
+            Key::Char('t'),
+            Key::Char('\n'),
+        ]);
+        let values = TemplateLine::new(&mut out, head_template())
+            .keys(scripted(keys))
+            .run()
+            .unwrap()
+            .unwrap();
+        let mut values: Vec<(String, String)> = values.into_iter().collect();
+        values.sort();
         assert_eq!(
-            format!(
-                "Hello {}UNDERLINE{} and {}bold{}",
-                style::Underline,
-                style::NoUnderline,
-                style::Bold,
-                style::Reset
-            ),
-            fmt_text("Hello _UNDERLINE_ and *bold*")
+            vec![
+                ("NOTE".to_string(), "".to_string()),
+                ("NUM".to_string(), "10".to_string()),
+                ("PATH".to_string(), "log.txt".to_string()),
+            ],
+            values
         );
+    }
 
-        assert_eq!(
-            format!("inline={}underline{}", style::Underline, style::NoUnderline),
-            fmt_text("inline=_underline_")
-        );
+    #[test]
+    fn template_line_required() {
+        let mut out = vec![];
+        // Submit jumps to the missing value
+        let mut keys = type_keys("\t\t\n5\n");
+        keys.push(Key::Ctrl('d'));
+        let values = TemplateLine::new(&mut out, head_template())
+            .keys(scripted(keys))
+            .run()
+            .unwrap();
+        assert_eq!(None, values);
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("NUM is required"), "{}", out);
+        assert!(out.contains("PATH is required"), "{}", out);
+    }
+
+    #[test]
+    fn suggest_typed_equals_option() {
+        let options: Vec<String> = vec!["GET".into(), "POST".into()];
+        let mut out = vec![];
+        // Down does nothing as there is no user input row
+        let mut keys = type_keys("GET");
+        keys.extend(vec![Key::Down, Key::Char('\n')]);
+
+        let (choice, input) = Readline::new(&mut out)
+            .keys(scripted(keys))
+            .suggest(FixedComplete::new(&options))
+            .unwrap()
+            .unwrap();
+        assert_eq!(Some(&options[0]), choice);
+        assert_eq!("GET", input);
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(!out.contains(&format!(
+            "{}GET",
+            Messages::default().get("use_typed_label")
+        )));
+    }
+
+    #[test]
+    fn suggest_typed_custom_value() {
+        let options: Vec<String> = vec!["GET".into(), "POST".into()];
+        let mut out = vec![];
+        let mut keys = type_keys("GE");
+        keys.extend(vec![Key::Down, Key::Char('\n')]);
+
+        let (choice, input) = Readline::new(&mut out)
+            .keys(scripted(keys))
+            .suggest(FixedComplete::new(&options))
+            .unwrap()
+            .unwrap();
+        assert_eq!(None, choice);
+        assert_eq!("GE", input);
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(&format!("{}GE", Messages::default().get("use_typed_label"))));
+    }
+
+    #[test]
+    fn choice_without_options() {
+        let options: Vec<String> = vec![];
+        let mut out = vec![];
+        let choice = Readline::new(&mut out)
+            .keys(scripted(type_keys("\n")))
+            .choice(FixedComplete::new(&options));
+        assert!(matches!(choice, Err(SnovaError::EmptyChoices)));
+        // Nothing is rendered
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn suggest_without_options() {
+        let options: Vec<String> = vec![];
+        let mut out = vec![];
+        let (choice, input) = Readline::new(&mut out)
+            .keys(scripted(type_keys("abc\n")))
+            .suggest(FixedComplete::new(&options))
+            .unwrap()
+            .unwrap();
+        assert_eq!(None, choice);
+        assert_eq!("abc", input);
+        let out = String::from_utf8(out).unwrap();
+        assert!(!out.contains("1/0"));
+    }
+
+    #[test]
+    fn line_accept_default() {
+        let mut out = vec![];
+        let value = Readline::new(&mut out)
+            .prefix("PATH:")
+            .expect(ValueType::Path)
+            .default("./src")
+            .keys(scripted(vec![Key::Char('\n')]))
+            .line()
+            .unwrap()
+            .unwrap();
+        assert_eq!("./src", value);
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(&format!("PATH:  {}(./src){}", style::Faint, style::Reset)));
+    }
+
+    #[test]
+    fn line_override_default() {
+        let mut out = vec![];
+        let mut keys = type_keys("./lib");
+        keys.push(Key::Char('\n'));
+        let value = Readline::new(&mut out)
+            .expect(ValueType::Path)
+            .default("./src")
+            .keys(scripted(keys))
+            .line()
+            .unwrap()
+            .unwrap();
+        assert_eq!("./lib", value);
+    }
+
+    #[test]
+    fn line_invalid_default() {
+        let mut out = vec![];
+        // Default is not a number: Enter on empty input is ignored
+        let keys = vec![Key::Char('\n'), Key::Char('4'), Key::Char('\n')];
+        let value = Readline::new(&mut out)
+            .expect(ValueType::Number)
+            .default("four")
+            .keys(scripted(keys))
+            .line()
+            .unwrap()
+            .unwrap();
+        assert_eq!("4", value);
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(!out.contains("(four)"));
+    }
+
+    /// Suggested number with a description
+    struct Described(&'static str, &'static str);
+
+    impl Choice for Described {
+        fn text(&self) -> &str {
+            self.0
+        }
+
+        fn search_text(&self) -> Cow<'_, str> {
+            Cow::Owned(format!("{} {}", self.0, self.1))
+        }
+    }
+
+    #[test]
+    fn number_suggest_filter() {
+        let options = vec![
+            Described("3", "few lines"),
+            Described("10", "whole function"),
+        ];
+        let mut out = vec![];
+        // Letters filter the suggestions of a number value
+        let mut keys = type_keys("func");
+        keys.push(Key::Char('\n'));
+        let (choice, _) = Readline::new(&mut out)
+            .expect(ValueType::Number)
+            .keys(scripted(keys))
+            .suggest(FixedComplete::new(&options))
+            .unwrap()
+            .unwrap();
+        assert_eq!("10", choice.unwrap().0);
+
+        // Typed letters are returned to be checked by the caller
+        let (choice, input) = Readline::new(&mut out)
+            .expect(ValueType::Number)
+            .keys(scripted(type_keys("1x\n")))
+            .suggest(FixedComplete::new(&options))
+            .unwrap()
+            .unwrap();
+        assert!(choice.is_none());
+        assert_eq!("1x", input);
+
+        // Without suggestions letters are not typed
+        let value = Readline::new(&mut out)
+            .expect(ValueType::Number)
+            .keys(scripted(type_keys("1x\n")))
+            .line()
+            .unwrap();
+        assert_eq!(Some("1".to_string()), value);
+    }
+
+    #[test]
+    fn suggest_accept_default() {
+        let options: Vec<String> = vec!["GET".into(), "POST".into()];
+        let mut out = vec![];
+
+        // Default is one of the options: it is preselected
+        let (choice, _) = Readline::new(&mut out)
+            .default("POST")
+            .keys(scripted(vec![Key::Char('\n')]))
+            .suggest(FixedComplete::new(&options))
+            .unwrap()
+            .unwrap();
+        assert_eq!(Some(&options[1]), choice);
+
+        // Custom default
+        let (choice, input) = Readline::new(&mut out)
+            .default("PATCH")
+            .keys(scripted(vec![Key::Char('\n')]))
+            .suggest(FixedComplete::new(&options))
+            .unwrap()
+            .unwrap();
+        assert_eq!(None, choice);
+        assert_eq!("PATCH", input);
+    }
+
+    #[test]
+    fn initial_submit() {
+        let mut out = vec![];
+        let value = Readline::new(&mut out)
+            .initial("./src")
+            .keys(scripted(type_keys("/bin\n")))
+            .line()
+            .unwrap()
+            .unwrap();
+        assert_eq!("./src/bin", value);
+    }
+
+    #[test]
+    fn initial_clear() {
+        let mut out = vec![];
+        let keys = vec![Key::Ctrl('u'), Key::Char('x'), Key::Char('\n')];
+        let value = Readline::new(&mut out)
+            .initial("./src")
+            .keys(scripted(keys))
+            .line()
+            .unwrap()
+            .unwrap();
+        assert_eq!("x", value);
+    }
+
+    #[test]
+    fn initial_backspace_multibyte() {
+        let mut out = vec![];
+        let keys = vec![Key::Backspace, Key::Char('\n')];
+        let value = Readline::new(&mut out)
+            .initial("名前x名")
+            .keys(scripted(keys))
+            .line()
+            .unwrap()
+            .unwrap();
+        assert_eq!("名前x", value);
+
+        let mut out = vec![];
+        let keys = vec![
+            Key::Left,
+            Key::Left,
+            Key::Backspace,
+            Key::Char('é'),
+            Key::Char('\n'),
+        ];
+        let value = Readline::new(&mut out)
+            .initial("héllo")
+            .keys(scripted(keys))
+            .line()
+            .unwrap()
+            .unwrap();
+        assert_eq!("héélo", value);
+    }
+
+    #[test]
+    fn initial_filters_choices() {
+        let options: Vec<String> = vec!["GET".into(), "POST".into()];
+        let mut out = vec![];
+        let choice = Readline::new(&mut out)
+            .initial("po")
+            .keys(scripted(type_keys("\n")))
+            .choice(FixedComplete::new(&options))
+            .unwrap();
+        assert_eq!(Some(&options[1]), choice);
+    }
+
+    fn kill_to_end(initial: &str, left: usize) -> String {
+        let mut out = vec![];
+        let mut keys = vec![Key::Left; left];
+        keys.extend(vec![Key::Ctrl('k'), Key::Char('\n')]);
+        let value = Readline::new(&mut out)
+            .initial(initial)
+            .keys(scripted(keys))
+            .line()
+            .unwrap()
+            .unwrap();
+        value
+    }
+
+    #[test]
+    fn kill_to_end_cursor_at_start() {
+        assert_eq!("", kill_to_end("héllo", 5));
+    }
+
+    #[test]
+    fn kill_to_end_cursor_in_middle() {
+        assert_eq!("hé", kill_to_end("héllo", 3));
+        assert_eq!("名", kill_to_end("名前", 1));
+    }
+
+    #[test]
+    fn kill_to_end_cursor_at_end() {
+        assert_eq!("héllo", kill_to_end("héllo", 0));
+    }
+
+    #[test]
+    fn ctrl_n_moves_selection() {
+        let options: Vec<String> = vec!["GET".into(), "POST".into()];
+        let mut out = vec![];
+        let keys = vec![
+            Key::Ctrl('n'),
+            Key::Ctrl('n'),
+            Key::Ctrl('p'),
+            Key::Ctrl('n'),
+            Key::Char('\n'),
+        ];
+        let choice = Readline::new(&mut out)
+            .keys(scripted(keys))
+            .choice(FixedComplete::new(&options))
+            .unwrap();
+        assert_eq!(Some(&options[1]), choice);
+    }
+
+    fn edit(initial: &str, keys: Vec<Key>) -> String {
+        let mut out = vec![];
+        let mut keys = keys;
+        keys.push(Key::Char('\n'));
+        let value = Readline::new(&mut out)
+            .initial(initial)
+            .keys(scripted(keys))
+            .line()
+            .unwrap()
+            .unwrap();
+        value
+    }
+
+    #[test]
+    fn delete_word() {
+        assert_eq!("git config ", edit("git config user", vec![Key::Ctrl('w')]));
+        assert_eq!("git ", edit("git config  ", vec![Key::Ctrl('w')]));
+        assert_eq!("", edit("  ", vec![Key::Ctrl('w')]));
+        assert_eq!(
+            "git user",
+            edit(
+                "git config user",
+                vec![Key::Left; 4]
+                    .into_iter()
+                    .chain(vec![Key::Ctrl('w')])
+                    .collect()
+            )
+        );
+    }
+
+    #[test]
+    fn undo_delete_word() {
+        let keys = vec![Key::Ctrl('w'), Key::Ctrl('7')];
+        assert_eq!("git config user", edit("git config user", keys));
+
+        // Cursor is restored as well
+        let keys = vec![Key::Ctrl('w'), Key::Ctrl('z'), Key::Char('!')];
+        assert_eq!("git config user!", edit("git config user", keys));
+    }
+
+    #[test]
+    fn undo_multiple_steps() {
+        let keys = vec![
+            Key::Backspace,
+            Key::Backspace,
+            Key::Left,
+            Key::Ctrl('k'),
+            Key::Ctrl('u'),
+        ];
+        assert_eq!("", edit("abcdef", keys.clone()));
+
+        let mut undo_once = keys.clone();
+        undo_once.push(Key::Ctrl('z'));
+        assert_eq!("abc", edit("abcdef", undo_once));
+
+        let mut undo_twice = keys.clone();
+        undo_twice.extend(vec![Key::Ctrl('z'), Key::Ctrl('z')]);
+        assert_eq!("abcd", edit("abcdef", undo_twice));
+
+        // Backspace run is undone at once
+        let mut undo_all = keys;
+        undo_all.extend(vec![Key::Ctrl('z'); 4]);
+        assert_eq!("abcdef", edit("abcdef", undo_all));
+    }
+
+    #[test]
+    fn undo_limit() {
+        let mut keys = vec![];
+        for _ in 0..(UNDO_LIMIT + 10) {
+            keys.extend(vec![Key::Char('x'), Key::Ctrl('u')]);
+        }
+        keys.extend(vec![Key::Ctrl('z'); UNDO_LIMIT + 10]);
+        // Undo stops at the oldest remembered state
+        assert_eq!("x", edit("", keys));
+    }
+
+    /// Wrap text into bracketed paste events
+    fn paste_events(text: &str) -> Vec<Event> {
+        let mut events = vec![Event::Unsupported(PASTE_START.to_vec())];
+        events.extend(text.chars().map(|c| Event::Key(Key::Char(c))));
+        events.push(Event::Unsupported(PASTE_END.to_vec()));
+        events
+    }
+
+    fn paste(expect: Option<ValueType>, events: Vec<Event>) -> String {
+        let mut out = vec![];
+        let mut events = events;
+        events.push(Event::Key(Key::Char('\n')));
+        let mut readline = Readline::new(&mut out).events(events.into_iter().map(Ok));
+        if let Some(expect) = expect {
+            readline = readline.expect(expect);
+        }
+        readline.line().unwrap().unwrap()
+    }
+
+    #[test]
+    fn bracketed_paste_newlines() {
+        // Pasted new line doesn't submit the input
+        let value = paste(None, paste_events("{\"one\":\n1}"));
+        assert_eq!("{\"one\": 1}", value);
+    }
+
+    #[test]
+    fn bracketed_paste_at_cursor() {
+        let mut events: Vec<Event> = "ac".chars().map(|c| Event::Key(Key::Char(c))).collect();
+        events.push(Event::Key(Key::Left));
+        events.extend(paste_events("名b"));
+        events.push(Event::Key(Key::Char('!')));
+        assert_eq!("a名b!c", paste(None, events));
+    }
+
+    #[test]
+    fn bracketed_paste_undo() {
+        let mut events = paste_events("one two");
+        events.push(Event::Key(Key::Ctrl('z')));
+        assert_eq!("", paste(None, events));
+    }
+
+    #[test]
+    fn bracketed_paste_number() {
+        assert_eq!("42", paste(Some(ValueType::Number), paste_events("42")));
+        // Invalid number is rejected as a whole
+        let mut events = paste_events("4\n2");
+        events.extend(paste_events("7"));
+        assert_eq!("7", paste(Some(ValueType::Number), events));
+    }
+
+    #[test]
+    fn sanitize_text() {
+        assert_eq!(("plain".to_string(), 0), sanitize("plain"));
+        assert_eq!(("red".to_string(), 9), sanitize("\x1b[31mred\x1b[0m"));
+        assert_eq!(("title".to_string(), 6), sanitize("\x1b]0;x\x07title"));
+        assert_eq!(("a b\nc".to_string(), 2), sanitize("a\tb\x07\nc\u{9b}"));
+        assert_eq!(("ok".to_string(), 6), sanitize("ok\x1b]8;\x1b\\"));
+    }
+
+    #[test]
+    fn bracketed_paste_escape_sequences() {
+        // Terminal passes unknown sequences as is
+        let mut events = vec![Event::Unsupported(PASTE_START.to_vec())];
+        events.push(Event::Unsupported(b"\x1b[1;31m".to_vec()));
+        events.extend("error\tat".chars().map(|c| Event::Key(Key::Char(c))));
+        events.push(Event::Unsupported(b"\x1b[0m".to_vec()));
+        events.push(Event::Key(Key::Up));
+        events.push(Event::Unsupported(PASTE_END.to_vec()));
+        assert_eq!("error at", paste(None, events));
+
+        // Raw escape chars
+        assert_eq!("ls", paste(None, paste_events("\x1b[32mls\x1b[0m\x07")));
+    }
+
+    #[test]
+    fn typed_control_chars() {
+        let keys = vec![
+            Key::Char('a'),
+            Key::Char('\u{7}'),
+            Key::Char('\t'),
+            Key::Char('\u{85}'),
+            Key::Char('b'),
+        ];
+        assert_eq!("a b", edit("", keys));
+
+        let mut out = vec![];
+        let mut events: Vec<Event> = vec![Event::Unsupported(b"\x1b[999~".to_vec())];
+        events.push(Event::Key(Key::Char('x')));
+        events.push(Event::Key(Key::Char('\n')));
+        let value = Readline::new(&mut out)
+            .events(events.into_iter().map(Ok))
+            .line()
+            .unwrap();
+        assert_eq!(Some("x".to_string()), value);
+    }
+
+    #[test]
+    fn bracketed_paste_mode_toggled() {
+        let mut out = vec![];
+        Readline::new(&mut out)
+            .keys(scripted(type_keys("\n")))
+            .line()
+            .unwrap()
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with(ENABLE_BRACKETED_PASTE));
+        assert!(out.ends_with(DISABLE_BRACKETED_PASTE));
+    }
+
+    fn multiline(initial: &str, keys: Vec<Key>) -> (String, String) {
+        let mut out = vec![];
+        let mut keys = keys;
+        keys.push(Key::Char('\n'));
+        let value = Readline::new(&mut out)
+            .footer(false)
+            .multiline(true)
+            .initial(initial)
+            .keys(scripted(keys))
+            .line()
+            .unwrap()
+            .unwrap();
+        (value, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn multiline_new_line() {
+        let keys = vec![Key::Char('a'), Key::Alt('\r'), Key::Char('b')];
+        let (value, _) = multiline("", keys.clone());
+        assert_eq!("a\nb", value);
+
+        // Ignored in a single line input
+        assert_eq!("ab", edit("", keys));
+
+        // Pasted new lines are kept
+        let mut out = vec![];
+        let mut events = paste_events("{\n}");
+        events.push(Event::Key(Key::Char('\n')));
+        let value = Readline::new(&mut out)
+            .multiline(true)
+            .events(events.into_iter().map(Ok))
+            .line()
+            .unwrap()
+            .unwrap();
+        assert_eq!("{\n}", value);
+    }
+
+    #[test]
+    fn multiline_cursor_between_lines() {
+        let keys = vec![Key::Up, Key::Char('!'), Key::Down, Key::Char('?')];
+        let (value, _) = multiline("one\ntwo", keys);
+        assert_eq!("one!\ntwo?", value);
+
+        // Column is kept within the shorter line
+        let keys = vec![Key::Up, Key::Char('-')];
+        let (value, _) = multiline("a\nlonger", keys);
+        assert_eq!("a-\nlonger", value);
+
+        // Up on the first line does nothing
+        let keys = vec![Key::Up, Key::Up, Key::Up, Key::Char('-')];
+        let (value, _) = multiline("a\nb", keys);
+        assert_eq!("a-\nb", value);
+    }
+
+    #[test]
+    fn multiline_render_and_cleanup() {
+        let keys = vec![Key::Char('a'), Key::Alt('\r'), Key::Char('b')];
+        let (_, out) = multiline("", keys);
+        // Second line is aligned with the first one after the prefix
+        assert!(out.contains("$ a \r\n  b "));
+        // Grown region is erased from its first row
+        assert!(out.ends_with(&format!(
+            "{}\r{}\r{}",
+            cursor::Up(1),
+            clear::AfterCursor,
+            DISABLE_BRACKETED_PASTE
+        )));
+    }
+
+    #[test]
+    fn multiline_rows_capped() {
+        let input = "1\n2\n3\n4\n5\n6\n7";
+        let lines = InputLines::new(input, input.chars().count(), MULTILINE_ROWS);
+        assert_eq!(2, lines.start);
+        assert_eq!(7, lines.end);
+        assert_eq!((6, 1), (lines.cursor_line, lines.cursor_col));
+
+        let lines = InputLines::new(input, 0, MULTILINE_ROWS);
+        assert_eq!((0, MULTILINE_ROWS), (lines.start, lines.end));
+
+        let lines = InputLines::new("one", 1, MULTILINE_ROWS);
+        assert_eq!(
+            (0, 1, 0, 1),
+            (lines.start, lines.end, lines.cursor_line, lines.cursor_col)
+        );
+    }
+
+    #[test]
+    fn footer_hints_per_mode() {
+        let messages = Messages::default();
+        let choice = AutocompleteMode::Enabled {
+            autocomplete: (),
+            user_input: UserInput::Disabled,
+        };
+        assert!(choice.footer(&messages).contains("enter select"));
+        assert!(!choice.footer(&messages).contains("use typed"));
+
+        let suggest = AutocompleteMode::Enabled {
+            autocomplete: (),
+            user_input: UserInput::Typed,
+        };
+        assert!(suggest.footer(&messages).contains("use typed"));
+
+        let custom = AutocompleteMode::Enabled {
+            autocomplete: (),
+            user_input: UserInput::Custom,
+        };
+        assert!(custom.footer(&messages).contains("use as-is"));
+
+        let line = AutocompleteMode::<()>::None;
+        assert!(line.footer(&messages).contains("enter submit"));
+        assert!(!line.footer(&messages).contains("navigate"));
+    }
+
+    #[test]
+    fn footer_rendered() {
+        let mut out = vec![];
+        Readline::new(&mut out)
+            .keys(scripted(type_keys("\n")))
+            .line()
+            .unwrap()
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let messages = Messages::default();
+        let footer = AutocompleteMode::<()>::None.footer(&messages);
+        assert!(out.contains(&format!("\r\n{}{}{}", style::Faint, footer, style::Reset)));
+
+        let mut out = vec![];
+        Readline::new(&mut out)
+            .footer(false)
+            .keys(scripted(type_keys("\n")))
+            .line()
+            .unwrap()
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(!out.contains(footer));
+    }
+
+    #[test]
+    fn truncate_to_width() {
+        assert_eq!("enter", truncate("enter", 10));
+        assert_eq!("↑↓ nav", truncate("↑↓ navigate", 6));
+        assert_eq!("名", truncate("名前", 3));
+        assert_eq!("", truncate("名前", 0));
+    }
+
+    #[test]
+    fn wrap_words() {
+        assert_eq!(vec!["one two", "three"], wrap("one two three", 8));
+        assert_eq!(vec!["one", "", "two"], wrap("one\n\ntwo", 8));
+        // Markup takes no space
+        assert_eq!(vec!["*-A* _NUM_"], wrap("*-A* _NUM_", 6));
+        assert_eq!(Vec::<String>::new(), wrap("", 8));
+    }
+
+    #[test]
+    fn wrap_long_words() {
+        assert_eq!(vec!["abcd", "efgh", "ij"], wrap("abcdefghij", 4));
+        assert_eq!(vec!["a", "名前", "名"], wrap("a 名前名", 4));
+    }
+
+    struct Detailed(String, String);
+
+    impl Choice for Detailed {
+        fn text(&self) -> &str {
+            &self.0
+        }
+
+        fn preview(&self) -> Option<String> {
+            Some(self.1.clone())
+        }
+    }
+
+    #[test]
+    fn preview_toggle() {
+        let options = vec![
+            Detailed("grep".into(), "Find *lines*".into()),
+            Detailed("find".into(), "Find files".into()),
+        ];
+
+        let mut out = vec![];
+        let keys = vec![Key::Char('?'), Key::Down, Key::Char('\n')];
+        let choice = Readline::new(&mut out)
+            .keys(scripted(keys))
+            .choice(FixedComplete::new(&options))
+            .unwrap();
+        assert_eq!("find", choice.unwrap().text());
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(&Palette::default().fmt_text("Find *lines*")));
+        assert!(out.contains("Find files"));
+
+        // Hidden by default and toggled back
+        let mut out = vec![];
+        let keys = vec![Key::Char('?'), Key::Char('?'), Key::Down, Key::Char('\n')];
+        Readline::new(&mut out)
+            .keys(scripted(keys))
+            .choice(FixedComplete::new(&options))
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(!out.contains("Find files"));
+    }
+
+    #[test]
+    fn custom_row_when_nothing_matches() {
+        let options = vec![
+            Detailed("Follow redirects".into(), "-L".into()),
+            Detailed("Verbose logging".into(), "-v".into()),
+        ];
+        // Matching input picks an option
+        let mut out = vec![];
+        let (choice, _) = Readline::new(&mut out)
+            .keys(scripted(type_keys("verb\n")))
+            .choice_or_custom(FixedComplete::new(&options))
+            .unwrap()
+            .unwrap();
+        assert_eq!("Verbose logging", choice.unwrap().text());
+        let out = String::from_utf8(out).unwrap();
+        assert!(!out.contains(Messages::default().get("custom_label")));
+
+        let mut out = vec![];
+        let (choice, input) = Readline::new(&mut out)
+            .keys(scripted(type_keys("--compressed\n")))
+            .choice_or_custom(FixedComplete::new(&options))
+            .unwrap()
+            .unwrap();
+        assert!(choice.is_none());
+        assert_eq!("--compressed", input);
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("use as-is: --compressed"));
+    }
+
+    #[test]
+    fn preview_key_typed_in_suggest_mode() {
+        let options: Vec<String> = vec!["GET".into()];
+        let mut out = vec![];
+        let (_, input) = Readline::new(&mut out)
+            .keys(scripted(type_keys("a?b\n")))
+            .suggest(FixedComplete::new(&options))
+            .unwrap()
+            .unwrap();
+        assert_eq!("a?b", input);
+    }
+
+    #[test]
+    fn finish_key() {
+        let mut out = vec![];
+        let keys = vec![Key::Char('a'), Key::Ctrl('s')];
+        let result = Readline::new(&mut out)
+            .finish(true)
+            .keys(scripted(keys))
+            .line();
+        assert!(matches!(result.unwrap_err(), SnovaError::Finished));
+
+        // Ignored unless enabled
+        let mut out = vec![];
+        let keys = vec![Key::Char('a'), Key::Ctrl('s'), Key::Char('\n')];
+        let result = Readline::new(&mut out).keys(scripted(keys)).line();
+        assert_eq!(Some("a".to_string()), result.unwrap());
+    }
+
+    #[test]
+    fn cancel_line() {
+        let mut out = vec![];
+        let keys = vec![Key::Char('a'), Key::Ctrl('d')];
+        let value = Readline::new(&mut out).keys(scripted(keys)).line();
+        assert_eq!(None, value.unwrap());
+    }
+
+    #[test]
+    fn scripted_keys_closed() {
+        let options: Vec<String> = vec!["GET".into()];
+        let mut out = vec![];
+        let result = Readline::new(&mut out)
+            .keys(scripted(type_keys("X")))
+            .choice(FixedComplete::new(&options));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn strip_markup_ok() {
+        assert_eq!("-A NUM", strip_markup("*-A* _NUM_"));
+        assert_eq!("client_id=ID", strip_markup("client\\_id=_ID_"));
+    }
+
+    #[test]
+    fn matches_ignores_case_and_markup() {
+        let case = CaseMatching::Insensitive;
+        assert!(matches("Find lines in a file (*grep*)", "GREP", case));
+        assert!(matches("*-A* _NUM_", "-a num", case));
+        assert!(!matches("*-A* _NUM_", "-B", case));
+    }
+
+    fn list_files(case: CaseMatching, input: &str) -> Vec<&'static str> {
+        let options: Vec<String> = vec!["README.md".into(), "readme.old".into(), "src".into()];
+        let mut complete = FixedComplete::new(&options).case(case);
+        complete
+            .list(input)
+            .into_iter()
+            .map(|o| match o.as_ref() {
+                "README.md" => "README.md",
+                "readme.old" => "readme.old",
+                _ => "src",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fixed_complete_smart_case() {
+        let case = CaseMatching::Smart;
+        assert_eq!(vec!["README.md", "readme.old"], list_files(case, "readme"));
+        assert_eq!(vec!["README.md"], list_files(case, "README"));
+        assert!(list_files(case, "Read").is_empty());
+        assert!(list_files(case, "SRC").is_empty());
+    }
+
+    #[test]
+    fn fixed_complete_case_sensitive() {
+        let case = CaseMatching::Sensitive;
+        assert_eq!(vec!["readme.old"], list_files(case, "readme"));
+        assert_eq!(vec!["README.md"], list_files(case, "README"));
+    }
+
+    #[test]
+    fn fixed_complete_case_insensitive() {
+        let case = CaseMatching::Insensitive;
+        assert_eq!(vec!["README.md", "readme.old"], list_files(case, "readme"));
+        assert_eq!(vec!["README.md", "readme.old"], list_files(case, "README"));
+        assert_eq!(vec!["src"], list_files(case, "SRC"));
+    }
+
+    fn list_commands(input: &str) -> Vec<String> {
+        let options: Vec<String> = vec![
+            "Set git email address".into(),
+            "Find lines in a file (*grep*)".into(),
+            "Send an HTTP request (*curl*)".into(),
+        ];
+        let mut complete = FixedComplete::new(&options);
+        complete.list(input).into_iter().cloned().collect()
+    }
+
+    #[test]
+    fn fixed_complete_tokens_any_order() {
+        assert_eq!(vec!["Set git email address"], list_commands("email git"));
+        assert_eq!(vec!["Set git email address"], list_commands("git email"));
+        assert_eq!(
+            vec!["Find lines in a file (*grep*)"],
+            list_commands("grep file")
+        );
+        // Every token must match
+        assert!(list_commands("email curl").is_empty());
+    }
+
+    #[test]
+    fn fixed_complete_tokens_whitespace() {
+        assert_eq!(vec!["Set git email address"], list_commands("email "));
+        assert_eq!(
+            vec!["Set git email address"],
+            list_commands("  git   email  ")
+        );
+        // Only whitespace matches everything
+        assert_eq!(3, list_commands("   ").len());
+        assert_eq!(3, list_commands("").len());
+    }
+
+    #[test]
+    fn fixed_complete_tokens_smart_case() {
+        // Smart case applies to each token separately
+        assert_eq!(
+            vec!["Send an HTTP request (*curl*)"],
+            list_commands("HTTP send")
+        );
+        assert!(list_commands("Http send").is_empty());
+    }
+
+    #[test]
+    fn case_matching_parse() {
+        assert_eq!(CaseMatching::Smart, CaseMatching::parse("smart").unwrap());
+        assert_eq!(
+            CaseMatching::Sensitive,
+            CaseMatching::parse("sensitive").unwrap()
+        );
+        assert!(CaseMatching::parse("other").is_err());
+    }
+
+    #[test]
+    fn display_width_plain() {
+        assert_eq!(0, display_width(""));
+        assert_eq!(5, display_width("PATH:"));
+    }
+
+    #[test]
+    fn display_width_styled() {
+        let prefix = Palette::default().fmt_text("*-A* _NUM_:");
+        assert!(prefix.len() > "-A NUM:".len());
+        assert_eq!(7, display_width(&prefix));
+
+        let prefix = format!("{}{}hint{}", style::Italic, style::Faint, style::Reset);
+        assert_eq!(4, display_width(&prefix));
+    }
+
+    #[test]
+    fn display_width_wide_chars() {
+        assert_eq!(5, display_width("名前:"));
+        assert_eq!(7, display_width(&Palette::default().fmt_text("*名前*:ok")));
+        assert_eq!(3, display_width("🚀:"));
+        // Combining acute accent
+        assert_eq!(1, display_width("e\u{301}"));
+    }
+
+    #[test]
+    fn scroll_to_follows_selection() {
+        assert_eq!(0, scroll_to(0, 0, 20));
+        assert_eq!(0, scroll_to(VISIBLE_CHOICES - 1, 0, 20));
+        assert_eq!(1, scroll_to(VISIBLE_CHOICES, 0, 20));
+        assert_eq!(5, scroll_to(5, 10, 20));
+        // Short lists are never scrolled
+        assert_eq!(0, scroll_to(2, 0, 3));
+    }
+
+    #[test]
+    fn filter_shrinks_while_scrolled() {
+        let all: Vec<String> = (0..20).map(|i| format!("option {}", i)).collect();
+        let all: Vec<&str> = all.iter().map(|o| o.as_ref()).collect();
+        let selected = 15;
+        let scroll_offset = scroll_to(selected, 0, all.len());
+        assert_eq!(9, scroll_offset);
+
+        // Previously selected option is still present
+        let filtered = ["option 1", "option 15", "option 19"];
+        let selected = keep_selected(Some(all[15]), &filtered, selected);
+        assert_eq!(1, selected);
+        assert_eq!(0, scroll_to(selected, scroll_offset, filtered.len()));
+
+        // Previously selected option is gone
+        let filtered = ["option 1", "option 10"];
+        let selected = keep_selected(Some(all[15]), &filtered, 15);
+        assert_eq!(1, selected);
+        assert_eq!(0, scroll_to(selected, scroll_offset, filtered.len()));
+    }
+
+    #[test]
+    fn filter_grows_after_clear() {
+        let all: Vec<String> = (0..20).map(|i| format!("option {}", i)).collect();
+        let all: Vec<&str> = all.iter().map(|o| o.as_ref()).collect();
+
+        let filtered = ["option 1", "option 12"];
+        let selected = 1;
+        let scroll_offset = scroll_to(selected, 0, filtered.len());
+        assert_eq!(0, scroll_offset);
+
+        // Input was cleared and the full list is shown again
+        let selected = keep_selected(Some(filtered[selected]), &all, selected);
+        assert_eq!(12, selected);
+        let scroll_offset = scroll_to(selected, scroll_offset, all.len());
+        assert_eq!(12 + 1 - VISIBLE_CHOICES, scroll_offset);
+        assert!(selected < scroll_offset + VISIBLE_CHOICES);
+    }
+
+    #[test]
+    fn keep_selected_empty_list() {
+        assert_eq!(0, keep_selected(Some("one"), &[], 4));
+        assert_eq!(0, keep_selected(None, &[], 0));
+    }
+
+    #[test]
+    fn input_window_fits() {
+        assert_eq!(
+            InputWindow {
+                text: "hello".into(),
+                cursor: 2,
+            },
+            InputWindow::new("hello", 2, 10)
+        );
+        assert_eq!(
+            InputWindow {
+                text: "".into(),
+                cursor: 0,
+            },
+            InputWindow::new("", 0, 10)
+        );
+    }
+
+    #[test]
+    fn input_window_cursor_at_start() {
+        let window = InputWindow::new("0123456789abcdef", 0, 8);
+        assert_eq!("0123456…", window.text);
+        assert_eq!(0, window.cursor);
+
+        let window = InputWindow::new("0123456789abcdef", 6, 8);
+        assert_eq!("0123456…", window.text);
+        assert_eq!(6, window.cursor);
+    }
+
+    #[test]
+    fn input_window_cursor_in_middle() {
+        let window = InputWindow::new("0123456789abcdef", 8, 8);
+        assert_eq!("…56789a…", window.text);
+        assert_eq!(4, window.cursor);
+        assert_eq!(Some('8'), window.text.chars().nth(window.cursor));
+    }
+
+    #[test]
+    fn input_window_cursor_at_end() {
+        // Cursor after the last char
+        let window = InputWindow::new("0123456789abcdef", 16, 8);
+        assert_eq!("…abcdef", window.text);
+        assert_eq!(7, window.cursor);
+
+        // Cursor on the last char
+        let window = InputWindow::new("0123456789abcdef", 15, 8);
+        assert_eq!("…abcdef", window.text);
+        assert_eq!(Some('f'), window.text.chars().nth(window.cursor));
+    }
+
+    #[test]
+    fn input_window_never_exceeds_width() {
+        let input = "0123456789abcdef";
+        for width in 3..20 {
+            for cursor in 0..=input.len() {
+                let window = InputWindow::new(input, cursor, width);
+                let len = window.text.chars().count();
+                assert!(len <= width, "width={} cursor={}", width, cursor);
+                assert!(window.cursor <= len);
+                if window.cursor == len {
+                    // Keep one column for the cursor at the end of the input
+                    assert!(len < width, "width={} cursor={}", width, cursor);
+                }
+
+                // Cursor points at the same char as in the input
+                let expected = input.chars().nth(cursor);
+                let actual = window.text.chars().nth(window.cursor);
+                assert_eq!(expected, actual, "width={} cursor={}", width, cursor);
+            }
+        }
+    }
+
+    #[test]
+    fn input_window_terminal_resize() {
+        let input = "https://example.com/some/long/path";
+        let cursor = input.len();
+
+        let narrow = InputWindow::new(input, cursor, 10);
+        assert_eq!("…ong/path", narrow.text);
+        assert_eq!(9, narrow.cursor);
+
+        // Whole input is visible once terminal is wide enough
+        let wide = InputWindow::new(input, cursor, 80);
+        assert_eq!(input, wide.text);
+        assert_eq!(cursor, wide.cursor);
+    }
+
+    #[test]
+    fn confirm_accept() {
+        let mut out = vec![];
+        let confirmed = Readline::new(&mut out)
+            .keys(scripted(type_keys("yes\n")))
+            .confirm("yes")
+            .unwrap();
+        assert!(confirmed);
+    }
+
+    #[test]
+    fn confirm_decline() {
+        let mut out = vec![];
+        // Enter alone doesn't confirm
+        let confirmed = Readline::new(&mut out)
+            .keys(scripted(type_keys("\n")))
+            .confirm("yes")
+            .unwrap();
+        assert!(!confirmed);
+
+        let confirmed = Readline::new(&mut out)
+            .keys(scripted(vec![Key::Ctrl('d')]))
+            .confirm("yes")
+            .unwrap();
+        assert!(!confirmed);
+    }
+
+    #[test]
+    fn confirm_terminate() {
+        let mut out = vec![];
+        let result = Readline::new(&mut out)
+            .keys(scripted(
+                type_keys("ye")
+                    .into_iter()
+                    .chain(vec![Key::Ctrl('c')])
+                    .collect(),
+            ))
+            .confirm("yes");
+        assert!(result.is_err());
+    }
+
+    /// Choice that could be preselected or not
+    struct Later(&'static str, bool);
+
+    impl Choice for Later {
+        fn text(&self) -> &str {
+            self.0
+        }
+
+        fn preselect(&self) -> bool {
+            self.1
+        }
+    }
+
+    #[test]
+    fn choice_not_preselected() {
+        let options = vec![Later("clipboard", false), Later("other", true)];
+        let mut out = vec![];
+        // Selection starts from the second choice
+        let (choice, _) = Readline::new(&mut out)
+            .keys(scripted(type_keys("\n")))
+            .suggest(FixedComplete::new(&options))
+            .unwrap()
+            .unwrap();
+        assert_eq!("other", choice.unwrap().text());
+
+        // Single choice is not accepted without moving to it
+        let options = vec![Later("clipboard", false)];
+        let (choice, input) = Readline::new(&mut out)
+            .keys(scripted(type_keys("\n")))
+            .suggest(FixedComplete::new(&options))
+            .unwrap()
+            .unwrap();
+        assert!(choice.is_none());
+        assert_eq!("", input);
+
+        let keys = vec![Key::Up, Key::Char('\n')];
+        let (choice, _) = Readline::new(&mut out)
+            .keys(scripted(keys))
+            .suggest(FixedComplete::new(&options))
+            .unwrap()
+            .unwrap();
+        assert_eq!("clipboard", choice.unwrap().text());
+    }
+
+    struct Styled(&'static str, ChoiceStyle);
+
+    impl Choice for Styled {
+        fn text(&self) -> &str {
+            self.0
+        }
+
+        fn style(&self) -> ChoiceStyle {
+            self.1
+        }
+    }
+
+    #[test]
+    fn render_warning_style() {
+        let options = vec![
+            Styled("Verbose", ChoiceStyle::Normal),
+            Styled("Force", ChoiceStyle::Warning),
+        ];
+        let mut out = vec![];
+        Readline::new(&mut out)
+            .keys(scripted(type_keys("\n")))
+            .choice(FixedComplete::new(&options))
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let yellow = color::Fg(color::Yellow).to_string();
+        assert!(out.contains(&format!("  {}Force", yellow)));
+        assert!(!out.contains(&format!("{}Verbose", yellow)));
+    }
+
+    #[test]
+    fn render_light_background() {
+        let options = vec![
+            Styled("Verbose", ChoiceStyle::Normal),
+            Styled("Force", ChoiceStyle::Warning),
+        ];
+        let mut out = vec![];
+        Readline::new(&mut out)
+            .palette(Palette::new(Background::Light))
+            .keys(scripted(type_keys("\n")))
+            .choice(FixedComplete::new(&options))
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let blue = color::Fg(color::Blue);
+        assert!(out.contains(&format!("{}> {}{}Verbose", blue, style::Bold, blue)));
+        assert!(out.contains(&format!("  {}Force", color::Fg(color::Magenta))));
+        assert!(out.contains(&format!("{}{}1/2", style::Italic, blue)));
+        assert!(!out.contains(&color::Fg(color::Yellow).to_string()));
+    }
+
+    struct Badged(&'static str, Option<&'static str>);
+
+    impl Choice for Badged {
+        fn text(&self) -> &str {
+            self.0
+        }
+
+        fn badge(&self) -> Option<&str> {
+            self.1
+        }
+    }
+
+    #[test]
+    fn render_badge() {
+        let options = vec![
+            Badged("Find files", Some("project")),
+            Badged("List files", None),
+        ];
+        let mut out = vec![];
+        Readline::new(&mut out)
+            .keys(scripted(type_keys("\n")))
+            .choice(FixedComplete::new(&options))
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let badge = format!("  {}[project]{}", style::Faint, style::Reset);
+        assert!(out.contains(&format!("Find files{}{}", style::Reset, badge)));
+        assert!(out.contains("List files\n\r"));
+    }
+
+    #[test]
+    fn display_width_graphemes() {
+        // Woman technologist (ZWJ sequence) and a flag
+        assert_eq!(2, display_width("👩\u{200d}💻"));
+        assert_eq!(2, display_width("🇫🇮"));
+        assert_eq!(10, display_width("ls 日本/🚀"));
+    }
+
+    #[test]
+    fn truncate_graphemes() {
+        assert_eq!("👩\u{200d}💻", truncate("👩\u{200d}💻ok", 2));
+        assert_eq!("", truncate("👩\u{200d}💻ok", 1));
+        assert_eq!("e\u{301}", truncate("e\u{301}x", 1));
+        assert_eq!("a日", truncate("a日本", 4));
+    }
+
+    #[test]
+    fn wrap_graphemes() {
+        assert_eq!(vec!["🇫🇮", "🇫🇮"], wrap("🇫🇮🇫🇮", 3));
+    }
+
+    #[test]
+    fn fit_hint_wide_chars() {
+        // Marker, choice and separator take 2 + 9 + 2 columns
+        let hint = fit_hint("Copy 日本", "backup 🚀 files", 20);
+        assert_eq!("backup", hint);
+        assert_eq!(
+            19,
+            2 + display_width("Copy 日本") + 2 + display_width(&hint)
+        );
+
+        // Emoji doesn't fit into a single remaining column
+        assert_eq!("backup ", fit_hint("Copy 日本", "backup 🚀 files", 22));
+
+        let hint = fit_hint("Copy 日本", "backup 🚀 files", 23);
+        assert_eq!("backup 🚀", hint);
+        assert_eq!(
+            22,
+            2 + display_width("Copy 日本") + 2 + display_width(&hint)
+        );
+
+        assert_eq!("", fit_hint("日本語のファイル名", "hint", 20));
+        assert_eq!("hint", fit_hint("*Copy*", "_hint_", 80));
+    }
+
+    #[test]
+    fn input_window_wide_chars() {
+        let input = "名前名前名前名前";
+
+        let window = InputWindow::new(input, 0, 8);
+        assert_eq!("名前名…", window.text);
+        assert_eq!(0, window.cursor);
+
+        let window = InputWindow::new(input, 8, 8);
+        assert_eq!("…前名前", window.text);
+        assert_eq!(4, window.cursor);
+        // Cursor column after the last char
+        assert_eq!(7, display_width(&window.text));
+
+        let window = InputWindow::new(input, 4, 8);
+        assert_eq!("…前名前…", window.text);
+        assert_eq!(Some('名'), window.text.chars().nth(window.cursor));
+    }
+
+    #[test]
+    fn input_window_mixed_never_exceeds_width() {
+        let input = "a名🚀b前c👍日d";
+        let total = input.chars().count();
+        for width in 3..20 {
+            for cursor in 0..=total {
+                let window = InputWindow::new(input, cursor, width);
+                let columns = display_width(&window.text);
+                let len = window.text.chars().count();
+                // A single wide char between ellipses may not fit the narrowest window
+                assert!(columns <= width.max(4), "width={} cursor={}", width, cursor);
+                if window.cursor == len && width > 3 {
+                    assert!(columns < width, "width={} cursor={}", width, cursor);
+                }
+
+                let expected = input.chars().nth(cursor);
+                let actual = window.text.chars().nth(window.cursor);
+                assert_eq!(expected, actual, "width={} cursor={}", width, cursor);
+            }
+        }
+    }
+
+    #[test]
+    fn render_cursor_column_wide_chars() {
+        let mut out = vec![];
+        let mut keys = type_keys("日本");
+        keys.push(Key::Left);
+        keys.push(Key::Char('\n'));
+        let value = Readline::new(&mut out)
+            .prefix("名前:")
+            .keys(scripted(keys))
+            .line()
+            .unwrap();
+        assert_eq!(Some("日本".to_string()), value);
+        let out = String::from_utf8(out).unwrap();
+        // Prefix (5 columns), a space and the first char (2 columns)
+        assert!(out.contains(&format!("\r{}", cursor::Right(8))));
+        // After typing both chars the cursor was at the end
+        assert!(out.contains(&format!("\r{}", cursor::Right(10))));
+    }
+
+    /// Replace escape sequences with readable tags, e.g `<UP:3>` or `<FG:1>`
+    fn normalize(out: &[u8]) -> String {
+        let out = String::from_utf8_lossy(out);
+        let mut result = String::new();
+        let mut chars = out.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\x1b' if chars.peek() == Some(&'[') => {
+                    chars.next();
+                    let mut params = String::new();
+                    let mut end = None;
+                    for c in chars.by_ref() {
+                        if c.is_ascii_alphabetic() || c == '~' {
+                            end = Some(c);
+                            break;
+                        }
+                        params.push(c);
+                    }
+                    result.push_str(&csi_tag(&params, end.unwrap_or('?')));
+                }
+                '\x1b' => result.push_str("<ESC>"),
+                '\r' => result.push_str("<CR>"),
+                c => result.push(c),
+            }
+        }
+        result
+    }
+
+    fn csi_tag(params: &str, end: char) -> String {
+        match (params, end) {
+            ("" | "0", 'm') => "<RESET>".to_string(),
+            ("1", 'm') => "<BOLD>".to_string(),
+            ("2", 'm') => "<FAINT>".to_string(),
+            ("3", 'm') => "<ITALIC>".to_string(),
+            ("4", 'm') => "<UNDERLINE>".to_string(),
+            ("7", 'm') => "<INVERT>".to_string(),
+            ("24", 'm') => "<NO_UNDERLINE>".to_string(),
+            ("23", 'm') => "<NO_ITALIC>".to_string(),
+            (color, 'm') if color.starts_with("38;5;") => format!("<FG:{}>", &color[5..]),
+            (color, 'm') if color.starts_with("48;5;") => format!("<BG:{}>", &color[5..]),
+            ("2", 'K') => "<CLR_LINE>".to_string(),
+            ("" | "0", 'K') => "<CLR_EOL>".to_string(),
+            ("" | "0", 'J') => "<CLR_DOWN>".to_string(),
+            ("2", 'J') => "<CLR_ALL>".to_string(),
+            (n, 'A') => format!("<UP:{}>", n),
+            (n, 'B') => format!("<DOWN:{}>", n),
+            (n, 'C') => format!("<RIGHT:{}>", n),
+            (n, 'D') => format!("<LEFT:{}>", n),
+            (n, 'G') => format!("<COL:{}>", n),
+            ("?25", 'l') => "<HIDE>".to_string(),
+            ("?25", 'h') => "<SHOW>".to_string(),
+            ("?2004", 'h') => "<PASTE_ON>".to_string(),
+            ("?2004", 'l') => "<PASTE_OFF>".to_string(),
+            (params, end) => format!("<CSI:{}{}>", params, end),
+        }
+    }
+
+    #[test]
+    fn normalize_sequences() {
+        let out = format!(
+            "{}{}a{}{}\r\n{}{}",
+            clear::CurrentLine,
+            color::Fg(color::Red),
+            style::Reset,
+            cursor::Up(3),
+            cursor::Right(2),
+            clear::AfterCursor
+        );
+        assert_eq!(
+            "<CLR_LINE><FG:1>a<RESET><UP:3><CR>\n<RIGHT:2><CLR_DOWN>",
+            normalize(out.as_bytes())
+        );
+    }
+
+    #[test]
+    fn snapshot_line_input() {
+        let mut out = vec![];
+        Readline::new(&mut out)
+            .width(40)
+            .prefix("NAME:")
+            .help("Who to greet")
+            .keys(scripted(type_keys("hi\n")))
+            .line()
+            .unwrap();
+        insta::assert_snapshot!(normalize(&out));
+    }
+
+    #[test]
+    fn snapshot_multiline_help() {
+        let mut out = vec![];
+        Readline::new(&mut out)
+            .width(40)
+            .prefix("NAME:")
+            .help("docker run \\\n  -v a:b \\\n  image")
+            .keys(scripted(type_keys("hi\n")))
+            .line()
+            .unwrap();
+        insta::assert_snapshot!(normalize(&out));
+    }
+
+    #[test]
+    fn snapshot_full_help() {
+        let mut out = vec![];
+        let mut keys = vec![Key::Char('a'), FULL_HELP_KEY, Key::Char('x')];
+        keys.extend(type_keys("\n"));
+        let input = Readline::new(&mut out)
+            .width(30)
+            .prefix("OPTIONS:")
+            .help("curl -X POST -H 'Accept: application/json' --data-binary @body.json https://example.com")
+            .keys(scripted(keys))
+            .line()
+            .unwrap();
+        // Key that returns from the full help is not typed
+        assert_eq!(Some("a".to_string()), input);
+        insta::assert_snapshot!(normalize(&out));
+    }
+
+    #[test]
+    fn length_counter() {
+        let mut out = vec![];
+        Readline::new(&mut out)
+            .help("Subject")
+            .counter(3)
+            .keys(scripted(type_keys("aöcd\n")))
+            .line()
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Subject  0/3\r\n"), "{:?}", out);
+        assert!(out.contains("Subject  3/3\r\n"), "{:?}", out);
+        assert!(
+            out.contains(&format!(
+                "Subject  {}4/3{}\r\n",
+                color::Fg(color::Red),
+                style::Reset
+            )),
+            "{:?}",
+            out
+        );
+    }
+
+    #[test]
+    fn snapshot_choice_scrolling() {
+        let options: Vec<String> = (1..=12).map(|i| format!("option {}", i)).collect();
+        let mut keys = vec![Key::Down; 9];
+        keys.push(Key::Char('\n'));
+        let mut out = vec![];
+        let choice = Readline::new(&mut out)
+            .width(40)
+            .keys(scripted(keys))
+            .choice(FixedComplete::new(&options))
+            .unwrap();
+        assert_eq!(Some(&options[9]), choice);
+        insta::assert_snapshot!(normalize(&out));
+    }
+
+    #[test]
+    fn snapshot_suggest_user_input_row() {
+        let options: Vec<String> = vec!["GET".into(), "GETALL".into()];
+        let mut out = vec![];
+        Readline::new(&mut out)
+            .width(40)
+            .prefix("METHOD:")
+            .keys(scripted(type_keys("GEX\n")))
+            .suggest(FixedComplete::new(&options))
+            .unwrap();
+        insta::assert_snapshot!(normalize(&out));
+    }
+
+    #[test]
+    fn snapshot_cancel_cleanup() {
+        let options: Vec<String> = vec!["one".into(), "two".into()];
+        let mut out = vec![];
+        let choice = Readline::new(&mut out)
+            .width(40)
+            .keys(scripted(vec![Key::Char('t'), Key::Ctrl('d')]))
+            .choice(FixedComplete::new(&options))
+            .unwrap();
+        assert_eq!(None, choice);
+        insta::assert_snapshot!(normalize(&out));
     }
 }
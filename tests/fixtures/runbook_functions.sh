@@ -0,0 +1,13 @@
+#!/bin/bash
+
+restart() {
+    # snova: description = "Restart a deployment"
+    kubectl rollout restart deploy/_NAME_ -n _NAMESPACE_  # snova-groups: NAME, NAMESPACE
+}
+
+logs() {
+    if [ -n "$1" ]; then
+        # snova: description = "Follow logs of a deployment"
+        kubectl logs -f deploy/_NAME_ # snova-groups: NAME
+    fi
+}
@@ -0,0 +1,22 @@
+#!/bin/sh
+
+# snova: description = "Unclosed
+echo broken _A_ # snova-groups: A
+
+# snova: description = "Check the health of a host"
+curl -fsS https://_HOST_/health # snova-groups: HOST
+# snova: description = "Nothing below"
+
+# snova: description = "Ping a host"
+# snova: long_description = "Sends a single packet"
+ping -c 1 _HOST_ # snova-groups: HOST, PORT
+
+# A stray annotation
+uptime # snova-groups: HOST
+
+# snova: description = "Twice"
+# snova: description = "Twice again"
+uptime
+
+# snova: description = "Clean logs"
+rm -f /var/log/app/*.log
@@ -0,0 +1,15 @@
+#!/bin/sh
+# Runbook of the API service
+set -e
+
+# snova: description = "Restart the API"
+# snova: keywords = ["systemd", "deploy"]
+systemctl restart api@_ENV_.service && journalctl -u api_svc -n _LINES_ # snova-groups: ENV, LINES:number
+
+# snova: description = "Back up a directory"
+# snova: confirm = true
+tar -czf backup-{date}.tar.gz \
+    _DIR_ # snova-groups: DIR:path
+
+# snova: description = "Check that a directory exists"
+[ -d _DIR_ ] && echo exists # snova-groups: DIR:path
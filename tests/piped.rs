@@ -0,0 +1,401 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+/// Run snova with keys piped into stdin
+fn run_piped(args: &[&str], keys: &[u8]) -> Output {
+    run_piped_in(&std::env::temp_dir().join("snova-piped"), args, keys)
+}
+
+/// Run snova with keys piped into stdin and values stored in the data dir
+fn run_piped_in(data_dir: &Path, args: &[&str], keys: &[u8]) -> Output {
+    run_with_input(snova(data_dir).args(args).env("TERM", "xterm"), keys)
+}
+
+fn snova(data_dir: &Path) -> Command {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_snova"));
+    cmd.env("SNOVA_TTY", "never")
+        .env("SNOVA_FOOTER", "off")
+        .env("SNOVA_CLIPBOARD", "off")
+        .env("SNOVA_CONFIG_DIR", env!("CARGO_MANIFEST_DIR"))
+        .env("SNOVA_DATA_DIR", data_dir);
+    cmd
+}
+
+fn run_with_input(cmd: &mut Command, input: &[u8]) -> Output {
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Snova could exit before reading its input, e.g on invalid commands
+    let _ = child.stdin.take().unwrap().write_all(input);
+    child.wait_with_output().unwrap()
+}
+
+const GIT_EMAIL_KEYS: &[u8] = b"git email\nme@example.com\n\x04";
+
+/// Keys come from piped stdin when there is no terminal to read from.
+/// Prompts go to stderr and stdout has only the command.
+#[test]
+fn piped_stdin_without_tty() {
+    let output = run_piped(&[], GIT_EMAIL_KEYS);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success());
+    assert_eq!(1, stdout.lines().count(), "{}", stdout);
+    assert!(!stdout.contains('\x1b'), "{}", stdout);
+    assert!(
+        stdout.trim_end().ends_with("user.email me@example.com"),
+        "{}",
+        stdout
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("me@example.com"), "{}", stderr);
+}
+
+/// Dumb terminals get numbered commands and line prompts
+#[test]
+fn plain_prompts_on_dumb_terminal() {
+    let data_dir = std::env::temp_dir().join(format!("snova-plain-{}", std::process::id()));
+    // Closed input cancels at the command number
+    let output = run_with_input(snova(&data_dir).env("TERM", "dumb"), b"");
+    assert_eq!(Some(130), output.status.code());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains('\x1b'), "{}", stderr);
+    let number = stderr
+        .lines()
+        .find(|line| line.contains("Set git email address"))
+        .and_then(|line| line.trim().split(')').next())
+        .unwrap()
+        .to_string();
+
+    let keys = format!("{}\nme@example.com\n\n", number);
+    let output = run_with_input(snova(&data_dir).env("TERM", "dumb"), keys.as_bytes());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(
+        stdout.trim_end().ends_with("user.email me@example.com"),
+        "{}",
+        stdout
+    );
+    let _ = std::fs::remove_dir_all(&data_dir);
+}
+
+/// Only the printed command ends with the chosen terminator
+#[test]
+fn print0_and_no_newline() {
+    let output = run_piped(&["--print0"], GIT_EMAIL_KEYS);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success());
+    assert!(
+        stdout.ends_with("user.email me@example.com\0"),
+        "{:?}",
+        stdout
+    );
+    assert!(!stdout.contains('\n'), "{:?}", stdout);
+
+    let output = run_piped(&["-n"], GIT_EMAIL_KEYS);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success());
+    assert!(
+        stdout.ends_with("user.email me@example.com"),
+        "{:?}",
+        stdout
+    );
+
+    let output = run_piped(&["--print0", "-x"], GIT_EMAIL_KEYS);
+    assert_eq!(Some(1), output.status.code());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn format_json() {
+    let output = run_piped(&["--format", "json"], GIT_EMAIL_KEYS);
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let argv = json["argv"].as_array().unwrap();
+    assert_eq!(Some("me@example.com"), argv.last().unwrap().as_str());
+    assert!(json["command"]
+        .as_str()
+        .unwrap()
+        .ends_with("user.email me@example.com"));
+}
+
+/// Keys come from the replay file and stdin is left alone
+#[test]
+fn replay_keys_file() {
+    let dir = std::env::temp_dir().join(format!("snova-replay-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let keys = dir.join("keys.txt");
+    std::fs::write(
+        &keys,
+        "# Set git email\n\"git email\" enter 1s\n\"me@example.com\" enter ^d\n",
+    )
+    .unwrap();
+
+    let args = ["--replay", keys.to_str().unwrap(), "--replay-speed", "100"];
+    let output = run_piped(&args, b"");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success());
+    assert!(
+        stdout.trim_end().ends_with("user.email me@example.com"),
+        "{}",
+        stdout
+    );
+}
+
+fn out_file(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("snova-out-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join("cmd")
+}
+
+#[test]
+fn out_file_and_stdout() {
+    let file = out_file("stdout");
+    let output = run_piped(&["--out", file.to_str().unwrap()], GIT_EMAIL_KEYS);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.trim_end().ends_with("user.email me@example.com"));
+    let written = std::fs::read_to_string(&file).unwrap();
+    assert!(
+        written.ends_with("user.email me@example.com\n"),
+        "{}",
+        written
+    );
+}
+
+#[test]
+fn out_file_quiet() {
+    let file = out_file("quiet");
+    let output = run_piped(
+        &["--out", file.to_str().unwrap(), "--quiet"],
+        GIT_EMAIL_KEYS,
+    );
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        !stdout.trim_end().ends_with("user.email me@example.com"),
+        "{}",
+        stdout
+    );
+    let written = std::fs::read_to_string(&file).unwrap();
+    assert!(written.ends_with("user.email me@example.com\n"));
+}
+
+#[test]
+fn out_file_failed() {
+    let file = out_file("failed").join("missing/cmd");
+    let output = run_piped(
+        &["--out", file.to_str().unwrap(), "--quiet"],
+        GIT_EMAIL_KEYS,
+    );
+    // Command is printed anyway
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.trim_end().ends_with("user.email me@example.com"));
+}
+
+#[test]
+fn presets_save_run_list_rm() {
+    let dir = std::env::temp_dir().join(format!("snova-presets-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let output = run_piped_in(&dir, &["--save-preset", "work"], GIT_EMAIL_KEYS);
+    assert!(output.status.success());
+
+    let output = run_piped_in(&dir, &["preset", "list"], b"");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!("work: git config [_OPTIONS_] user.email _EMAIL_\n", stdout);
+
+    // Pre-filled email is accepted with Enter
+    let output = run_piped_in(
+        &dir,
+        &["run", "Set git email address (git)", "--preset", "work"],
+        b"\n\x04",
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.trim_end().ends_with("user.email me@example.com"),
+        "{}",
+        stdout
+    );
+
+    let output = run_piped_in(
+        &dir,
+        &["preset", "rm", "Set git email address (git)", "work"],
+        b"",
+    );
+    assert!(output.status.success());
+    let output = run_piped_in(&dir, &["preset", "list"], b"");
+    assert!(output.stdout.is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Cancelling and failing are told apart by the exit code
+#[test]
+fn exit_codes() {
+    let output = run_piped(&[], b"\x04");
+    assert_eq!(Some(130), output.status.code());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("Failed"), "{}", stderr);
+
+    let dir = out_file("exit-codes").parent().unwrap().to_path_buf();
+    std::fs::write(dir.join("commands.toml"), "[[commands]]\ntemplate = 1\n").unwrap();
+    let output = run_piped(&["--config", dir.to_str().unwrap()], b"\x04");
+    assert_eq!(Some(1), output.status.code());
+
+    let output = run_piped(&["no-such-subcommand"], b"");
+    assert_eq!(Some(1), output.status.code());
+}
+
+/// Commands are listed without reading keys or touching the terminal
+#[test]
+fn list_without_terminal() {
+    let output = run_piped(&["--list"], b"");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Find lines in a file (grep) [builtin]\n  grep [OPTIONS] PATTERN PATH\n"),
+        "{}",
+        stdout
+    );
+    assert!(!stdout.contains('\x1b'), "{}", stdout);
+}
+
+/// Built commands are printed again from the history
+#[test]
+fn last_and_history() {
+    let data_dir = std::env::temp_dir().join(format!("snova-piped-history-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&data_dir);
+    let output = run_piped_in(&data_dir, &["--last"], b"");
+    assert_eq!(Some(1), output.status.code());
+
+    run_piped_in(
+        &data_dir,
+        &["fill", "git email", "EMAIL=a@example.com"],
+        b"",
+    );
+    run_piped_in(
+        &data_dir,
+        &["fill", "git email", "EMAIL=b@example.com"],
+        b"",
+    );
+    let output = run_piped_in(&data_dir, &["--last"], b"");
+    assert_eq!(
+        "git config user.email b@example.com\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    // Most recent command comes first
+    let output = run_piped_in(&data_dir, &["--history"], b"\x1b[B\n");
+    assert!(output.status.success());
+    assert_eq!(
+        "git config user.email a@example.com\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+    std::fs::remove_dir_all(&data_dir).unwrap();
+}
+
+/// Command is built from arguments without reading any keys
+#[test]
+fn fill_from_arguments() {
+    let output = run_piped(&["fill", "git email", "EMAIL=me@example.com"], b"");
+    assert!(output.status.success());
+    assert_eq!(
+        "git config user.email me@example.com\n",
+        String::from_utf8_lossy(&output.stdout)
+    );
+}
+
+/// Built command runs in a shell and its exit code is snova's
+#[test]
+fn execute_command() {
+    let dir = out_file("execute").parent().unwrap().to_path_buf();
+    std::fs::write(
+        dir.join("commands.toml"),
+        r#"
+[[commands]]
+template = "echo _TEXT_; exit 3"
+description = "Print and fail"
+groups.TEXT.expect = "string"
+"#,
+    )
+    .unwrap();
+    let config = dir.to_str().unwrap();
+    let output = run_piped(
+        &["--config", config, "--execute"],
+        b"print and fail
+hello
+",
+    );
+    assert_eq!(Some(3), output.status.code());
+    assert_eq!("hello\n", String::from_utf8_lossy(&output.stdout));
+
+    // Nothing runs when cancelled
+    let output = run_piped(&["--config", config, "-x"], b"\x04");
+    assert_eq!(Some(130), output.status.code());
+    assert!(output.stdout.is_empty());
+}
+
+/// Hook gets the command on stdin and its failure doesn't change the exit code
+#[test]
+fn post_build_hook() {
+    let dir = out_file("hook").parent().unwrap().to_path_buf();
+    let captured = dir.join("hook.txt");
+    std::fs::write(
+        dir.join("settings.toml"),
+        format!(
+            "post_build = \"cat > '{0}'; echo \\\"$SNOVA_TEMPLATE\\\" >> '{0}'; exit 1\"\n",
+            captured.display()
+        ),
+    )
+    .unwrap();
+    let output = run_piped(&["--config", dir.to_str().unwrap()], GIT_EMAIL_KEYS);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.trim_end().ends_with("user.email me@example.com"));
+    let captured = std::fs::read_to_string(&captured).unwrap();
+    assert_eq!(
+        "git config user.email me@example.com\ngit config [_OPTIONS_] user.email _EMAIL_\n",
+        captured
+    );
+}
+
+/// Commands of a project file found up the tree never run a hook
+#[test]
+fn project_post_build_never_runs() {
+    let root = std::env::temp_dir().join(format!("snova-project-hook-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    let nested = root.join("src").join("bin");
+    std::fs::create_dir_all(&nested).unwrap();
+    let marker = root.join("hook-ran");
+    std::fs::write(
+        root.join(".snova.toml"),
+        format!(
+            "[[commands]]\ntemplate = \"make _TARGET_\"\ndescription = \"Make a target\"\n\
+             post_build = \"touch '{}'\"\ngroups.TARGET.expect = \"string\"\n",
+            marker.display()
+        ),
+    )
+    .unwrap();
+
+    let output = run_with_input(
+        snova(&root.join("data"))
+            .current_dir(&nested)
+            .env("TERM", "xterm"),
+        b"make a target\nall\n",
+    );
+    assert_eq!(Some(1), output.status.code(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("post_build is only allowed in user commands"),
+        "{}",
+        stderr
+    );
+    assert!(!marker.exists());
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,220 @@
+//! End-to-end tests that run snova in a pseudo-terminal. They are ignored by
+//! default, run them with `cargo test --test pty -- --ignored`.
+
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use portable_pty::{native_pty_system, Child, CommandBuilder, MasterPty, PtySize};
+
+const ROWS: u16 = 24;
+const COLS: u16 = 80;
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Snova running in a pseudo-terminal with its output fed into a terminal emulator
+struct Session {
+    master: Box<dyn MasterPty + Send>,
+    writer: Box<dyn Write + Send>,
+    child: Box<dyn Child + Send + Sync>,
+    screen: Arc<Mutex<vt100::Parser>>,
+}
+
+impl Session {
+    fn spawn() -> Self {
+        let pair = native_pty_system()
+            .openpty(PtySize {
+                rows: ROWS,
+                cols: COLS,
+                pixel_width: 0,
+                pixel_height: 0,
+            })
+            .unwrap();
+        let mut cmd = CommandBuilder::new(env!("CARGO_BIN_EXE_snova"));
+        cmd.env("TERM", "xterm");
+        // Keep error reports to a single line
+        cmd.env_remove("RUST_BACKTRACE");
+        cmd.env_remove("RUST_LIB_BACKTRACE");
+        cmd.env("SNOVA_CLIPBOARD", "off");
+        cmd.env("SNOVA_CONFIG_DIR", env!("CARGO_MANIFEST_DIR"));
+        cmd.env(
+            "SNOVA_DATA_DIR",
+            std::env::temp_dir().join(format!("snova-pty-{}", std::process::id())),
+        );
+        let child = pair.slave.spawn_command(cmd).unwrap();
+        // Reader gets EOF once the child exits only if we don't hold the slave
+        drop(pair.slave);
+
+        let screen = Arc::new(Mutex::new(vt100::Parser::new(ROWS, COLS, 0)));
+        let mut reader = pair.master.try_clone_reader().unwrap();
+        let parser = Arc::clone(&screen);
+        std::thread::spawn(move || {
+            let mut buf = [0; 1024];
+            while let Ok(n) = reader.read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+                parser.lock().unwrap().process(&buf[..n]);
+            }
+        });
+        let writer = pair.master.take_writer().unwrap();
+        Self {
+            master: pair.master,
+            writer,
+            child,
+            screen,
+        }
+    }
+
+    fn contents(&self) -> String {
+        self.screen.lock().unwrap().screen().contents()
+    }
+
+    /// Wait until the screen shows the text
+    fn expect(&self, text: &str) {
+        let start = Instant::now();
+        while !self.contents().contains(text) {
+            assert!(
+                start.elapsed() < TIMEOUT,
+                "Timed out waiting for '{}', screen:\n{}",
+                text,
+                self.contents()
+            );
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    fn send(&mut self, keys: &str) {
+        self.writer.write_all(keys.as_bytes()).unwrap();
+        self.writer.flush().unwrap();
+    }
+
+    /// Wait for the process to exit and the remaining output to be drawn
+    fn wait(&mut self) -> portable_pty::ExitStatus {
+        let start = Instant::now();
+        loop {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                std::thread::sleep(Duration::from_millis(100));
+                return status;
+            }
+            assert!(
+                start.elapsed() < TIMEOUT,
+                "Timed out waiting for exit, screen:\n{}",
+                self.contents()
+            );
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    /// Non-empty lines left on the screen
+    fn lines(&self) -> Vec<String> {
+        self.contents()
+            .lines()
+            .map(|line| line.trim_end().to_string())
+            .filter(|line| !line.is_empty())
+            .collect()
+    }
+
+    /// Assert that raw mode, bracketed paste and the hidden cursor were undone
+    fn assert_restored(&self) {
+        let parser = self.screen.lock().unwrap();
+        let screen = parser.screen();
+        assert!(!screen.bracketed_paste(), "Bracketed paste is still on");
+        assert!(!screen.hide_cursor(), "Cursor is hidden");
+        let fd = self.master.as_raw_fd().unwrap();
+        let ios = unsafe {
+            let mut ios: libc::termios = std::mem::zeroed();
+            assert_eq!(0, libc::tcgetattr(fd, &mut ios));
+            ios
+        };
+        assert_ne!(0, ios.c_lflag & libc::ICANON, "Terminal is in raw mode");
+        assert_ne!(0, ios.c_lflag & libc::ECHO, "Echo is off");
+    }
+
+    /// Cursor row and column
+    fn cursor(&self) -> (u16, u16) {
+        self.screen.lock().unwrap().screen().cursor_position()
+    }
+}
+
+#[test]
+#[ignore]
+fn grep_full_flow() {
+    let mut session = Session::spawn();
+    session.expect("Pick a command:");
+    session.send("grep");
+    session.expect("Find lines in a file (grep)");
+    session.send("\r");
+    session.expect("PATTERN:");
+    session.send("foo\r");
+    session.expect("PATH:");
+    session.send("src\r");
+    session.expect("Case insensitive matching");
+    // Pick -i, then filter down to -r and pick it too
+    session.send("\r");
+    session.expect("grep -i foo src");
+    session.send("recursive");
+    session.expect("1/1");
+    session.send("\r");
+    session.expect("grep -i -r foo src");
+    session.send("\x13");
+    // Print the command from the final menu
+    session.expect("enter print");
+    session.send("\r");
+
+    let status = session.wait();
+    assert!(status.success(), "{:?}", status);
+    // Prompts, choices and footers are all cleared
+    assert_eq!(
+        vec!["Command: grep [OPTIONS] PATTERN PATH", "grep -i -r foo src"],
+        session.lines()
+    );
+    // Cursor is at the start of the line below the command
+    assert_eq!((2, 0), session.cursor());
+    session.assert_restored();
+}
+
+#[test]
+#[ignore]
+fn ctrl_c_mid_prompt() {
+    let mut session = Session::spawn();
+    session.expect("Pick a command:");
+    session.send("grep\r");
+    session.expect("PATTERN:");
+    session.send("fo");
+    session.expect("PATTERN: fo");
+    session.send("\x03");
+
+    let status = session.wait();
+    assert_eq!(130, status.exit_code(), "{:?}", status);
+    assert_eq!(
+        vec!["Command: grep [OPTIONS] PATTERN PATH", "Nothing selected."],
+        session.lines()
+    );
+    assert_eq!((2, 0), session.cursor());
+    session.assert_restored();
+}
+
+#[test]
+#[ignore]
+fn cancel_final_menu() {
+    let mut session = Session::spawn();
+    session.expect("Pick a command:");
+    session.send("grep\r");
+    session.expect("PATTERN:");
+    session.send("foo\r");
+    session.expect("PATH:");
+    session.send("src\r");
+    session.expect("Case insensitive matching");
+    session.send("\x13");
+    session.expect("enter print");
+    session.send("\x04");
+
+    let status = session.wait();
+    assert_eq!(130, status.exit_code(), "{:?}", status);
+    // Menu is erased and no command is printed
+    assert_eq!(
+        vec!["Command: grep [OPTIONS] PATTERN PATH", "Nothing selected."],
+        session.lines()
+    );
+    session.assert_restored();
+}